@@ -0,0 +1,49 @@
+(load "/lib/lisp/core.lsp")
+
+# Draw with the left button of the mouse, press "c" to clear the screen and
+# "q" to quit
+
+(var size (gfx/size))
+(var width (first size))
+(var height (second size))
+(var color 0xFFFFFF)
+(var last-x nil)
+(var last-y nil)
+(var running true)
+
+(def (clear)
+  (do
+    (gfx/clear 0x202020)
+    (gfx/rect 0 0 width 20 0x3070C0 true)
+    (gfx/text 4 2 "Paint - c: clear, q: quit" 0xFFFFFF)
+    (gfx/flush)))
+
+(def (on-mouse x y buttons)
+  (if (= (% buttons 2) 1)
+    (do
+      (if (nil? last-x)
+        (gfx/pixel x y color)
+        (gfx/line last-x last-y x y color))
+      (set last-x x)
+      (set last-y y)
+      (gfx/flush))
+    (do
+      (set last-x nil)
+      (set last-y nil))))
+
+(def (on-key key)
+  (cond
+    ((equal? key "c") (clear))
+    ((equal? key "q") (set running false))
+    (true nil)))
+
+(clear)
+(while running
+  (do
+    (var event (gfx/event))
+    (cond
+      ((equal? (first event) "mouse")
+        (on-mouse (second event) (third event) (last event)))
+      ((equal? (first event) "key")
+        (on-key (second event)))
+      (true nil))))
@@ -1,24 +1,72 @@
 use crate::hlt_loop;
 use crate::api::process::ExitCode;
 use crate::api::syscall;
+use crate::api::syscall::MapFlag;
 
 use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::{self, NonNull};
+use linked_list_allocator::LockedHeap;
 
-pub struct UserspaceAllocator;
+const HEAP_GROWTH: usize = 64 << 10; // 64 KB
+
+// The memory is allocated in the heap of the process by the kernel, then in a
+// second heap made of pages mapped after each other when the first is full.
+pub struct UserspaceAllocator {
+    heap: LockedHeap,
+}
+
+impl UserspaceAllocator {
+    const fn new() -> Self {
+        Self { heap: LockedHeap::empty() }
+    }
+}
 
 unsafe impl GlobalAlloc for UserspaceAllocator {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        syscall::alloc(layout.size(), layout.align())
+        let ptr = syscall::alloc(layout.size(), layout.align());
+        if !ptr.is_null() {
+            return ptr;
+        }
+
+        let mut heap = self.heap.lock();
+        if let Ok(ptr) = heap.allocate_first_fit(layout) {
+            return ptr.as_ptr();
+        }
+
+        // Grow the heap with enough pages for the allocation
+        let size = (layout.size() + layout.align()).max(HEAP_GROWTH);
+        let size = size.div_ceil(4096) * 4096;
+        let flags = MapFlag::Read | MapFlag::Write;
+        if heap.size() == 0 {
+            match syscall::mmap(0, size, flags) {
+                Some(addr) => heap.init(addr as *mut u8, size),
+                None => return ptr::null_mut(),
+            }
+        } else {
+            let top = heap.top() as usize;
+            if syscall::mmap(top, size, flags) != Some(top) {
+                return ptr::null_mut();
+            }
+            heap.extend(size);
+        }
+        heap.allocate_first_fit(layout).map_or(ptr::null_mut(), |ptr| {
+            ptr.as_ptr()
+        })
     }
 
     unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
-        syscall::free(ptr, layout.size(), layout.align());
+        let mut heap = self.heap.lock();
+        if heap.bottom() <= ptr && ptr < heap.top() {
+            heap.deallocate(NonNull::new_unchecked(ptr), layout);
+        } else {
+            syscall::free(ptr, layout.size(), layout.align());
+        }
     }
 }
 
 #[allow(dead_code)]
 #[cfg_attr(feature = "userspace", global_allocator)]
-static ALLOCATOR: UserspaceAllocator = UserspaceAllocator;
+static ALLOCATOR: UserspaceAllocator = UserspaceAllocator::new();
 
 #[allow(dead_code)]
 #[cfg_attr(feature = "userspace", alloc_error_handler)]
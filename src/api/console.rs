@@ -1,9 +1,13 @@
-use crate::sys;
+use crate::api::process;
+use crate::api::vga;
 
 use alloc::string::ToString;
 use core::fmt;
 
-pub use crate::sys::console::{EOT_KEY, ETX_KEY};
+pub const BS_KEY: char = '\x08'; // Backspace
+pub const EOT_KEY: char = '\x04'; // End of Transmission
+pub const ESC_KEY: char = '\x1B'; // Escape
+pub const ETX_KEY: char = '\x03'; // End of Text
 
 #[derive(Clone, Copy)]
 pub struct Style {
@@ -103,7 +107,7 @@ pub fn is_printable(c: char) -> bool {
     if cfg!(feature = "video") {
         // Check if the char can be converted to ASCII or Extended ASCII before
         // asking the VGA driver if it's printable.
-        ((c as u32) < 0xFF) && sys::vga::is_printable(c as u8)
+        ((c as u32) < 0xFF) && vga::is_printable(c as u8)
     } else {
         true // TODO
     }
@@ -113,10 +117,10 @@ pub fn is_printable(c: char) -> bool {
 
 pub fn cols() -> usize {
     let n = 80; // chars
-    sys::process::env("COLS").unwrap_or(n.to_string()).parse().unwrap_or(n)
+    process::env("COLS").unwrap_or(n.to_string()).parse().unwrap_or(n)
 }
 
 pub fn rows() -> usize {
     let n = 25; // lines
-    sys::process::env("ROWS").unwrap_or(n.to_string()).parse().unwrap_or(n)
+    process::env("ROWS").unwrap_or(n.to_string()).parse().unwrap_or(n)
 }
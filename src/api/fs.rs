@@ -1,13 +1,12 @@
+use crate::api::process;
 use crate::api::syscall;
-use crate::sys;
-use crate::sys::fs::OpenFlag;
 
 use alloc::format;
 use alloc::string::{String, ToString};
 use alloc::vec;
 use alloc::vec::Vec;
-
-pub use crate::sys::fs::{DeviceType, FileInfo};
+use core::convert::{TryFrom, TryInto};
+use core::ops::BitOr;
 
 #[derive(Clone, Copy)]
 pub enum IO {
@@ -22,6 +21,200 @@ pub trait FileIO {
     fn poll(&mut self, event: IO) -> bool;
 }
 
+#[derive(Clone, Copy)]
+#[repr(u8)]
+pub enum OpenFlag {
+    Read     = 1,
+    Write    = 2,
+    Append   = 4,
+    Create   = 8,
+    Truncate = 16,
+    Dir      = 32,
+    Device   = 64,
+}
+
+impl OpenFlag {
+    pub fn is_set(&self, flags: u8) -> bool {
+        flags & (*self as u8) != 0
+    }
+}
+
+impl BitOr for OpenFlag {
+   type Output = u8;
+
+   fn bitor(self, rhs: Self) -> Self::Output {
+       (self as u8) | (rhs as u8)
+   }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FileType {
+    Dir = 0,
+    File = 1,
+    Device = 2,
+    Pipe = 3,
+}
+
+impl TryFrom<usize> for FileType {
+    type Error = ();
+
+    fn try_from(num: usize) -> Result<Self, Self::Error> {
+        match num {
+             0 => Ok(FileType::Dir),
+             1 => Ok(FileType::File),
+             2 => Ok(FileType::Device),
+             3 => Ok(FileType::Pipe),
+             _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct FileInfo {
+    pub(crate) kind: FileType,
+    pub(crate) size: u32,
+    pub(crate) time: u64,
+    pub(crate) name: String,
+}
+
+impl FileInfo {
+    pub fn new() -> Self {
+        Self {
+            kind: FileType::File,
+            name: String::new(),
+            size: 0,
+            time: 0,
+        }
+    }
+
+    // Info of a file generated by the kernel instead of being on the disk
+    pub fn synthetic(kind: FileType, name: &str, size: u32, time: u64) -> Self {
+        let name = String::from(name);
+        Self {
+            kind,
+            name,
+            size,
+            time,
+        }
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    pub fn time(&self) -> u64 {
+        self.time
+    }
+
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    pub fn kind(&self) -> FileType {
+        self.kind
+    }
+
+    // TODO: Duplicated from dir entry
+    pub fn is_dir(&self) -> bool {
+        self.kind == FileType::Dir
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.kind == FileType::File
+    }
+
+    pub fn is_device(&self) -> bool {
+        self.kind == FileType::Device
+    }
+
+    // TODO: Use bincode?
+    pub fn as_bytes(&self) -> Vec<u8> {
+        debug_assert!(self.name.len() < 256);
+        let mut res = Vec::new();
+        res.push(self.kind as u8);
+        res.extend_from_slice(&self.size.to_be_bytes());
+        res.extend_from_slice(&self.time.to_be_bytes());
+        res.push(self.name.len() as u8);
+        res.extend_from_slice(self.name.as_bytes());
+        res
+    }
+}
+
+impl From<&[u8]> for FileInfo {
+    fn from(buf: &[u8]) -> Self {
+        let kind = (buf[0] as usize).try_into().unwrap();
+        let size = u32::from_be_bytes(buf[1..5].try_into().unwrap());
+        let time = u64::from_be_bytes(buf[5..13].try_into().unwrap());
+        let i = 14 + buf[13] as usize;
+        let name = String::from_utf8_lossy(&buf[14..i]).into();
+        Self {
+            kind,
+            name,
+            size,
+            time,
+        }
+    }
+}
+
+#[derive(PartialEq, Eq, Clone, Copy)]
+#[repr(u8)]
+pub enum DeviceType {
+    Null       = 0,
+    File       = 1,
+    Console    = 2,
+    Random     = 3,
+    BootTime   = 4,
+    EpochTime  = 5,
+    RTC        = 6,
+    TcpSocket  = 7,
+    UdpSocket  = 8,
+    Drive      = 9,
+    VgaBuffer  = 10,
+    VgaFont    = 11,
+    VgaMode    = 12,
+    VgaPalette = 13,
+    Speaker    = 14,
+    NetGw      = 15,
+    NetIp      = 16,
+    NetMac     = 17,
+    NetUsage   = 18,
+    GpuBuffer  = 19,
+    GpuMode    = 20,
+    Mouse      = 21,
+}
+
+impl TryFrom<&[u8]> for DeviceType {
+    type Error = ();
+
+    fn try_from(buf: &[u8]) -> Result<Self, Self::Error> {
+        match buf.first().ok_or(())? {
+             0 => Ok(DeviceType::Null),
+             1 => Ok(DeviceType::File),
+             2 => Ok(DeviceType::Console),
+             3 => Ok(DeviceType::Random),
+             4 => Ok(DeviceType::BootTime),
+             5 => Ok(DeviceType::EpochTime),
+             6 => Ok(DeviceType::RTC),
+             7 => Ok(DeviceType::TcpSocket),
+             8 => Ok(DeviceType::UdpSocket),
+             9 => Ok(DeviceType::Drive),
+            10 => Ok(DeviceType::VgaBuffer),
+            11 => Ok(DeviceType::VgaFont),
+            12 => Ok(DeviceType::VgaMode),
+            13 => Ok(DeviceType::VgaPalette),
+            14 => Ok(DeviceType::Speaker),
+            15 => Ok(DeviceType::NetGw),
+            16 => Ok(DeviceType::NetIp),
+            17 => Ok(DeviceType::NetMac),
+            18 => Ok(DeviceType::NetUsage),
+            19 => Ok(DeviceType::GpuBuffer),
+            20 => Ok(DeviceType::GpuMode),
+            21 => Ok(DeviceType::Mouse),
+             _ => Err(()),
+        }
+    }
+}
+
 pub fn dirname(pathname: &str) -> &str {
     let pathname = if pathname.len() > 1 {
         pathname.trim_end_matches('/')
@@ -55,7 +248,7 @@ pub fn realpath(pathname: &str) -> String {
     if pathname.starts_with('/') {
         pathname.into()
     } else {
-        let dirname = sys::process::dir();
+        let dirname = process::dir();
         let sep = if dirname.ends_with('/') { "" } else { "/" };
         format!("{}{}{}", dirname, sep, pathname)
     }
@@ -127,6 +320,9 @@ pub fn open_device(path: &str) -> Option<usize> {
     syscall::open(path, flags)
 }
 
+// The buffer of a device depends on the state of its driver, which is only
+// known by the kernel
+#[cfg(not(feature = "userspace"))]
 pub fn create_device(path: &str, name: &str) -> Option<usize> {
     if let Ok(buf) = device_buffer(name) {
         let flags = OpenFlag::Create | OpenFlag::Device;
@@ -138,6 +334,7 @@ pub fn create_device(path: &str, name: &str) -> Option<usize> {
     None
 }
 
+#[cfg(not(feature = "userspace"))]
 fn device_buffer(name: &str) -> Result<Vec<u8>, ()> {
     let arg = if name.starts_with("ata-") { "ata" } else { name };
     let dev = device_type(arg)?;
@@ -155,6 +352,7 @@ fn device_buffer(name: &str) -> Result<Vec<u8>, ()> {
 }
 
 // TODO: Move this to sys::fs::device
+#[cfg(not(feature = "userspace"))]
 fn device_type(name: &str) -> Result<DeviceType, ()> {
     match name {
         "null"        => Ok(DeviceType::Null),
@@ -174,6 +372,9 @@ fn device_type(name: &str) -> Result<DeviceType, ()> {
         "vga-font"    => Ok(DeviceType::VgaFont),
         "vga-mode"    => Ok(DeviceType::VgaMode),
         "vga-palette" => Ok(DeviceType::VgaPalette),
+        "gpu-buffer"  => Ok(DeviceType::GpuBuffer),
+        "gpu-mode"    => Ok(DeviceType::GpuMode),
+        "mouse"       => Ok(DeviceType::Mouse),
         "speaker"     => Ok(DeviceType::Speaker),
         "ata"         => Ok(DeviceType::Drive),
         _             => Err(()),
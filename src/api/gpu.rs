@@ -0,0 +1,97 @@
+use crate::api::fs;
+use crate::api::syscall;
+
+use alloc::vec::Vec;
+use core::str;
+
+pub const LEFT_BUTTON: u8 = 1 << 0;
+pub const RIGHT_BUTTON: u8 = 1 << 1;
+pub const MIDDLE_BUTTON: u8 = 1 << 2;
+
+// Position of the pointer in screen coordinates and pressed buttons
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MouseState {
+    pub x: u32,
+    pub y: u32,
+    pub buttons: u8,
+}
+
+impl MouseState {
+    pub fn is_left_pressed(&self) -> bool {
+        self.buttons & LEFT_BUTTON != 0
+    }
+
+    pub fn is_right_pressed(&self) -> bool {
+        self.buttons & RIGHT_BUTTON != 0
+    }
+
+    pub fn is_middle_pressed(&self) -> bool {
+        self.buttons & MIDDLE_BUTTON != 0
+    }
+}
+
+fn read_device(path: &str, buf: &mut [u8]) -> Option<usize> {
+    if !fs::is_device(path) {
+        return None;
+    }
+    let handle = fs::open_device(path)?;
+    let res = syscall::read(handle, buf);
+    syscall::close(handle);
+    res
+}
+
+// Returns the resolution of the VirtIO GPU display if there is one
+pub fn get_resolution() -> Option<(u32, u32)> {
+    let mut buf = [0; 16];
+    let n = read_device("/dev/gpu/mode", &mut buf)?;
+    let mode = str::from_utf8(&buf[0..n]).ok()?;
+    let (width, height) = mode.split_once('x')?;
+    Some((width.parse().ok()?, height.parse().ok()?))
+}
+
+// Returns the state of the VirtIO mouse if there is one
+pub fn mouse_state() -> Option<MouseState> {
+    let mut buf = [0; 32];
+    let n = read_device("/dev/mouse", &mut buf)?;
+    let text = str::from_utf8(&buf[0..n]).ok()?;
+    let fields: Vec<&str> = text.split(' ').collect();
+    if fields.len() != 3 {
+        return None;
+    }
+    Some(MouseState {
+        x: fields[0].parse().ok()?,
+        y: fields[1].parse().ok()?,
+        buttons: fields[2].parse().ok()?,
+    })
+}
+
+// Handle on the framebuffer of the VirtIO GPU. Successive writes continue
+// where the previous one stopped, wrapping around at the end of the screen.
+pub struct Buffer {
+    handle: usize,
+}
+
+impl Buffer {
+    pub fn open() -> Option<Self> {
+        let path = "/dev/gpu/buffer";
+        if !fs::is_device(path) {
+            return None;
+        }
+        fs::open_device(path).map(|handle| Self { handle })
+    }
+
+    // Writes pixels in 0xAARRGGBB format and refreshes the display
+    pub fn write(&mut self, pixels: &[u32]) -> bool {
+        let mut buf = Vec::with_capacity(pixels.len() * 4);
+        for pixel in pixels {
+            buf.extend_from_slice(&pixel.to_le_bytes());
+        }
+        syscall::write(self.handle, &buf) == Some(buf.len())
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        syscall::close(self.handle);
+    }
+}
@@ -1,8 +1,9 @@
+use crate::api::fs::FileType;
 use crate::api::syscall;
-use crate::sys::fs::FileType;
 
 use alloc::string::{String, ToString};
 use alloc::vec;
+use alloc::vec::Vec;
 
 pub struct Stdin;
 pub struct Stdout;
@@ -34,6 +35,19 @@ impl Stdin {
             String::new()
         }
     }
+
+    // Read until EOF, which is only sent when stdin is redirected
+    pub fn read_to_string(&self) -> String {
+        let mut res = Vec::new();
+        let mut buf = vec![0; 256];
+        while let Some(bytes) = syscall::read(0, &mut buf) {
+            if bytes == 0 {
+                break;
+            }
+            res.extend_from_slice(&buf[0..bytes]);
+        }
+        String::from_utf8_lossy(&res).to_string()
+    }
 }
 
 impl Stdout {
@@ -70,7 +84,7 @@ pub fn stderr() -> Stderr {
 
 pub fn is_redirected(handle: usize) -> bool {
     match syscall::kind(handle) {
-        Some(FileType::File) => true,
+        Some(FileType::File) | Some(FileType::Pipe) => true,
         _ => false,
     }
 }
@@ -1,18 +1,26 @@
+// The size of the process can be given after the path of the main function
+// to override the default 10 MB window used for the code, the heap, and the
+// stack of the program.
 #[cfg(not(test))]
 #[macro_export]
 macro_rules! entry_point {
-    ($path:path) => {
-        #[panic_handler]
-        fn panic(_info: &core::panic::PanicInfo) -> ! {
-            let msg = b"\x1b[91mError:\x1b[m An exception occured\n";
-            $crate::api::syscall::write(2, msg);
-            let code = $crate::api::process::ExitCode::ExecError;
-            $crate::api::syscall::exit(code);
-            loop {}
-        }
+    ($path:path, $size:expr) => {
+        #[used]
+        #[link_section = ".proc_size"]
+        static PROC_SIZE: u64 = $size;
 
+        // The section is read at startup to keep it in the binary
+        $crate::entry_point!(@start $path, {
+            core::ptr::read_volatile(core::ptr::addr_of!(PROC_SIZE));
+        });
+    };
+    ($path:path) => {
+        $crate::entry_point!(@start $path, {});
+    };
+    (@start $path:path, $init:block) => {
         #[export_name = "_start"]
         pub unsafe extern "sysv64" fn __impl_start(ptr: u64, len: usize) {
+            $init
             let args = core::slice::from_raw_parts(ptr as *const _, len);
             let f: fn(&[&str]) = $path;
             f(args);
@@ -88,13 +96,17 @@ pub mod clock;
 pub mod console;
 pub mod font;
 pub mod fs;
+pub mod gpu;
+pub mod hex;
 pub mod io;
 pub mod power;
 pub mod process;
 pub mod prompt;
 pub mod rng;
 pub mod regex;
+pub mod sync;
 pub mod syscall;
+pub mod thread;
 pub mod time;
 pub mod unit;
 pub mod vga;
@@ -1,17 +1,21 @@
 use crate::api::syscall;
 
+use alloc::string::String;
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 #[repr(u8)]
 pub enum ExitCode {
-    Success        =   0,
-    Failure        =   1,
-    UsageError     =  64,
-    DataError      =  65,
-    OpenError      = 128,
-    ReadError      = 129,
-    ExecError      = 130,
-    PageFaultError = 200,
-    ShellExit      = 255,
+    Success              =   0,
+    Failure              =   1,
+    UsageError           =  64,
+    DataError            =  65,
+    OpenError            = 128,
+    ReadError            = 129,
+    ExecError            = 130,
+    Killed               = 137,
+    PageFaultError       = 200,
+    ProtectionFaultError = 201,
+    ShellExit            = 255,
 }
 
 impl From<usize> for ExitCode {
@@ -23,13 +27,52 @@ impl From<usize> for ExitCode {
             128 => ExitCode::OpenError,
             129 => ExitCode::ReadError,
             130 => ExitCode::ExecError,
+            137 => ExitCode::Killed,
             200 => ExitCode::PageFaultError,
+            201 => ExitCode::ProtectionFaultError,
             255 => ExitCode::ShellExit,
               _ => ExitCode::Failure,
         }
     }
 }
 
+pub const SIGINT:  usize = 2;
+pub const SIGKILL: usize = 9;
+pub const SIGUSR1: usize = 10;
+pub const SIGUSR2: usize = 12;
+pub const SIGTERM: usize = 15;
+pub const SIGCHLD: usize = 17;
+
+// Special handlers to take the default action of a signal or to ignore it
+pub const SIG_DFL: usize = 0;
+pub const SIG_IGN: usize = 1;
+
+// Operations of the futex syscall
+pub const FUTEX_WAIT: usize = 0;
+pub const FUTEX_WAKE: usize = 1;
+
+// The environment and the directory of a process are kept in the kernel and
+// are not yet given to the programs running in userspace
+#[cfg(not(feature = "userspace"))]
+pub fn env(key: &str) -> Option<String> {
+    crate::sys::process::env(key)
+}
+
+#[cfg(feature = "userspace")]
+pub fn env(_key: &str) -> Option<String> {
+    None
+}
+
+#[cfg(not(feature = "userspace"))]
+pub fn dir() -> String {
+    crate::sys::process::dir()
+}
+
+#[cfg(feature = "userspace")]
+pub fn dir() -> String {
+    String::from("/")
+}
+
 pub fn spawn(path: &str, args: &[&str]) -> Result<(), ExitCode> {
     if syscall::info(path).is_some() {
         match syscall::spawn(path, args) {
@@ -40,3 +83,57 @@ pub fn spawn(path: &str, args: &[&str]) -> Result<(), ExitCode> {
         Err(ExitCode::OpenError)
     }
 }
+
+// Start a process in the background and return its PID
+pub fn start(path: &str, args: &[&str]) -> Result<usize, ExitCode> {
+    if syscall::info(path).is_some() {
+        syscall::start(path, args)
+    } else {
+        Err(ExitCode::OpenError)
+    }
+}
+
+// Wait for a child process to exit and return its exit code
+pub fn wait(pid: usize) -> Result<ExitCode, ()> {
+    syscall::wait(pid, true).map(|code| code.unwrap_or(ExitCode::Failure))
+}
+
+// Return the exit code of a child process if it has exited
+pub fn try_wait(pid: usize) -> Result<Option<ExitCode>, ()> {
+    syscall::wait(pid, false)
+}
+
+pub fn kill(pid: usize, signal: usize) -> Result<(), ()> {
+    syscall::kill(pid, signal)
+}
+
+// Call a function when the process receives a signal
+pub fn signal(signal: usize, handler: fn(usize)) -> Result<(), ()> {
+    let trampoline = signal_trampoline as usize;
+    syscall::sigaction(signal, handler as usize, trampoline).map(|_| ())
+}
+
+pub fn ignore_signal(signal: usize) -> Result<(), ()> {
+    syscall::sigaction(signal, SIG_IGN, 0).map(|_| ())
+}
+
+pub fn reset_signal(signal: usize) -> Result<(), ()> {
+    syscall::sigaction(signal, SIG_DFL, 0).map(|_| ())
+}
+
+// Keep a signal pending until it is unblocked
+pub fn block_signal(signal: usize) -> Result<(), ()> {
+    syscall::sigprocmask(0, 1 << signal).map(|_| ())
+}
+
+pub fn unblock_signal(signal: usize) -> Result<(), ()> {
+    syscall::sigprocmask(1, 1 << signal).map(|_| ())
+}
+
+// The kernel switches to this function to handle a signal, and it goes back
+// to the interrupted code when the handler returns
+extern "sysv64" fn signal_trampoline(signal: usize, handler: usize) {
+    let handler: fn(usize) = unsafe { core::mem::transmute(handler) };
+    handler(signal);
+    syscall::sigreturn();
+}
@@ -0,0 +1,131 @@
+use crate::api::syscall;
+
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+use core::sync::atomic::{AtomicU32, Ordering};
+
+// States of a mutex
+const UNLOCKED: u32 = 0;
+const LOCKED: u32 = 1;
+const CONTENDED: u32 = 2; // Locked with threads waiting for it
+
+// A mutex blocking the threads waiting for it with the futex syscall instead
+// of spinning, which only calls the kernel when it is contended
+pub struct Mutex<T> {
+    state: AtomicU32,
+    data: UnsafeCell<T>,
+}
+
+unsafe impl<T: Send> Send for Mutex<T> {}
+unsafe impl<T: Send> Sync for Mutex<T> {}
+
+impl<T> Mutex<T> {
+    pub const fn new(data: T) -> Self {
+        Self {
+            state: AtomicU32::new(UNLOCKED),
+            data: UnsafeCell::new(data),
+        }
+    }
+
+    pub fn lock(&self) -> MutexGuard<'_, T> {
+        let res = self.state.compare_exchange(
+            UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed
+        );
+        if res.is_err() {
+            // The state stays contended until the mutex is unlocked, which
+            // could wake up a thread that doesn't need it
+            while self.state.swap(CONTENDED, Ordering::Acquire) != UNLOCKED {
+                syscall::futex_wait(&self.state, CONTENDED).ok();
+            }
+        }
+        MutexGuard { mutex: self }
+    }
+
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        self.state.compare_exchange(
+            UNLOCKED, LOCKED, Ordering::Acquire, Ordering::Relaxed
+        ).ok().map(|_| MutexGuard { mutex: self })
+    }
+
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+}
+
+pub struct MutexGuard<'a, T> {
+    mutex: &'a Mutex<T>,
+}
+
+impl<T> Deref for MutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.mutex.data.get() }
+    }
+}
+
+impl<T> DerefMut for MutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.mutex.data.get() }
+    }
+}
+
+impl<T> Drop for MutexGuard<'_, T> {
+    fn drop(&mut self) {
+        if self.mutex.state.swap(UNLOCKED, Ordering::Release) == CONTENDED {
+            syscall::futex_wake(&self.mutex.state, 1);
+        }
+    }
+}
+
+// A condition variable where the threads wait for a change of a counter
+// incremented by each notification
+pub struct Condvar {
+    counter: AtomicU32,
+}
+
+impl Condvar {
+    pub const fn new() -> Self {
+        Self { counter: AtomicU32::new(0) }
+    }
+
+    // Unlock the mutex and block the current thread until it is notified,
+    // then lock the mutex again. The thread could also be woken up without
+    // a notification so the condition must be checked in a loop.
+    pub fn wait<'a, T>(&self, guard: MutexGuard<'a, T>) -> MutexGuard<'a, T> {
+        let counter = self.counter.load(Ordering::Relaxed);
+        let mutex = guard.mutex;
+        drop(guard);
+        syscall::futex_wait(&self.counter, counter).ok();
+        mutex.lock()
+    }
+
+    pub fn notify_one(&self) {
+        self.counter.fetch_add(1, Ordering::Release);
+        syscall::futex_wake(&self.counter, 1);
+    }
+
+    pub fn notify_all(&self) {
+        self.counter.fetch_add(1, Ordering::Release);
+        syscall::futex_wake(&self.counter, usize::MAX);
+    }
+}
+
+impl Default for Condvar {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[test_case]
+fn test_mutex() {
+    let mutex = Mutex::new(1);
+    *mutex.lock() += 1;
+    assert_eq!(*mutex.lock(), 2);
+    {
+        let _guard = mutex.lock();
+        assert!(mutex.try_lock().is_none());
+    }
+    assert!(mutex.try_lock().is_some());
+    assert_eq!(mutex.into_inner(), 2);
+}
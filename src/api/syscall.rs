@@ -1,14 +1,42 @@
-use crate::api::fs::IO;
-use crate::api::process::ExitCode;
-use crate::sys::fs::{FileInfo, FileType};
-use crate::sys::syscall::number::*;
+pub mod number;
+
+use crate::api::fs::{FileInfo, FileType, IO};
+use crate::api::process::{ExitCode, FUTEX_WAIT, FUTEX_WAKE};
 use crate::syscall;
+use number::*;
 
+use core::arch::asm;
 use core::convert::TryInto;
 use core::convert::TryFrom;
-use core::sync::atomic::{fence, Ordering};
+use core::ops::BitOr;
+use core::sync::atomic::{fence, AtomicU32, Ordering};
 use smoltcp::wire::{IpAddress, Ipv4Address};
 
+// Protection of the pages mapped by a process, which can be filled with the
+// content of a file if they are not writable
+#[derive(Clone, Copy)]
+#[repr(u8)]
+pub enum MapFlag {
+    Read  = 1,
+    Write = 2,
+    Exec  = 4,
+    File  = 8,
+}
+
+impl MapFlag {
+    pub fn is_set(&self, flags: u8) -> bool {
+        flags & (*self as u8) != 0
+    }
+}
+
+impl BitOr for MapFlag {
+    type Output = u8;
+
+    fn bitor(self, rhs: Self) -> Self::Output {
+        (self as u8) | (rhs as u8)
+    }
+}
+
 pub fn exit(code: ExitCode) {
     unsafe { syscall!(EXIT, code as usize) };
 }
@@ -70,6 +98,19 @@ pub fn dup(old_handle: usize, new_handle: usize) -> Result<(), ()> {
     }
 }
 
+// Returns the reading and writing handles of a new pipe, using the default
+// capacity when `capacity` is 0
+pub fn pipe(capacity: usize) -> Option<(usize, usize)> {
+    let mut handles = [0; 2];
+    let ptr = handles.as_mut_ptr() as usize;
+    let res = unsafe { syscall!(PIPE, capacity, ptr) } as isize;
+    if res >= 0 {
+        Some((handles[0], handles[1]))
+    } else {
+        None
+    }
+}
+
 pub fn read(handle: usize, buf: &mut [u8]) -> Option<usize> {
     let ptr = buf.as_ptr() as usize;
     let len = buf.len();
@@ -112,6 +153,80 @@ pub fn spawn(path: &str, args: &[&str]) -> ExitCode {
     ExitCode::from(res)
 }
 
+pub fn start(path: &str, args: &[&str]) -> Result<usize, ExitCode> {
+    let path_ptr = path.as_ptr() as usize;
+    let args_ptr = args.as_ptr() as usize;
+    let path_len = path.len();
+    let args_len = args.len();
+    let res = unsafe {
+        syscall!(START, path_ptr, path_len, args_ptr, args_len)
+    } as isize;
+    if res >= 0 {
+        Ok(res as usize)
+    } else {
+        Err(ExitCode::from(-res as usize))
+    }
+}
+
+// Return the PID of the child in the parent and 0 in the child
+pub fn fork() -> Option<usize> {
+    let res = unsafe { syscall!(FORK) } as isize;
+    if res >= 0 {
+        Some(res as usize)
+    } else {
+        None
+    }
+}
+
+pub fn wait(pid: usize, block: bool) -> Result<Option<ExitCode>, ()> {
+    let res = unsafe { syscall!(WAIT, pid, block as usize) } as isize;
+    match res {
+        -2 => Ok(None),
+        -1 => Err(()),
+        _ => Ok(Some(ExitCode::from(res as usize))),
+    }
+}
+
+pub fn kill(pid: usize, signal: usize) -> Result<(), ()> {
+    let res = unsafe { syscall!(KILL, pid, signal) } as isize;
+    if res >= 0 {
+        Ok(())
+    } else {
+        Err(())
+    }
+}
+
+// Set the handler of a signal and return the previous one
+pub fn sigaction(
+    signal: usize,
+    handler: usize,
+    trampoline: usize
+) -> Result<usize, ()> {
+    let res = unsafe {
+        syscall!(SIGACTION, signal, handler, trampoline)
+    } as isize;
+    if res >= 0 {
+        Ok(res as usize)
+    } else {
+        Err(())
+    }
+}
+
+// Block (0), unblock (1), or set (2) the mask of blocked signals and return
+// the previous one
+pub fn sigprocmask(how: usize, mask: u32) -> Result<u32, ()> {
+    let res = unsafe { syscall!(SIGPROCMASK, how, mask) } as isize;
+    if res >= 0 {
+        Ok(res as u32)
+    } else {
+        Err(())
+    }
+}
+
+pub fn sigreturn() {
+    unsafe { syscall!(SIGRETURN) };
+}
+
 pub fn stop(code: usize) {
     unsafe { syscall!(STOP, code) };
 }
@@ -175,6 +290,171 @@ pub fn free(ptr: *mut u8, size: usize, align: usize) {
     }
 }
 
+pub fn mmap(addr: usize, size: usize, flags: u8) -> Option<usize> {
+    let res = unsafe { syscall!(MMAP, addr, size, flags, 0) } as isize;
+    if res >= 0 {
+        Some(res as usize)
+    } else {
+        None
+    }
+}
+
+pub fn mmap_file(handle: usize, size: usize) -> Option<usize> {
+    let flags = MapFlag::Read | MapFlag::File;
+    let res = unsafe { syscall!(MMAP, 0, size, flags, handle) } as isize;
+    if res >= 0 {
+        Some(res as usize)
+    } else {
+        None
+    }
+}
+
+pub fn munmap(addr: usize, size: usize) -> Result<(), ()> {
+    let res = unsafe { syscall!(MUNMAP, addr, size) } as isize;
+    if res >= 0 {
+        Ok(())
+    } else {
+        Err(())
+    }
+}
+
+// Start a thread calling the function at the entry point with the argument
+// on the given stack, and return its TID
+pub fn thread(entry: usize, stack: usize, arg: usize) -> Result<usize, ()> {
+    let res = unsafe { syscall!(THREAD, entry, stack, arg) } as isize;
+    if res >= 0 {
+        Ok(res as usize)
+    } else {
+        Err(())
+    }
+}
+
+pub fn join(tid: usize) -> Result<ExitCode, ()> {
+    let res = unsafe { syscall!(JOIN, tid) } as isize;
+    if res >= 0 {
+        Ok(ExitCode::from(res as usize))
+    } else {
+        Err(())
+    }
+}
+
+pub fn exit_thread(code: ExitCode) {
+    unsafe { syscall!(EXIT_THREAD, code as usize) };
+}
+
+// Block the current thread while the futex has the given value, until
+// another thread wakes it up
+pub fn futex_wait(futex: &AtomicU32, val: u32) -> Result<(), ()> {
+    let addr = futex as *const AtomicU32 as usize;
+    let res = unsafe { syscall!(FUTEX, addr, FUTEX_WAIT, val) } as isize;
+    if res >= 0 {
+        Ok(())
+    } else {
+        Err(())
+    }
+}
+
+// Wake up to `n` threads waiting on the futex and return how many were woken
+pub fn futex_wake(futex: &AtomicU32, n: usize) -> usize {
+    let addr = futex as *const AtomicU32 as usize;
+    unsafe { syscall!(FUTEX, addr, FUTEX_WAKE, n) }
+}
+
+#[doc(hidden)]
+pub unsafe fn syscall0(n: usize) -> usize {
+    let res: usize;
+    asm!(
+        "int 0x80", in("rax") n,
+        lateout("rax") res
+    );
+    res
+}
+
+#[doc(hidden)]
+pub unsafe fn syscall1(n: usize, arg1: usize) -> usize {
+    let res: usize;
+    asm!(
+        "int 0x80", in("rax") n,
+        in("rdi") arg1,
+        lateout("rax") res
+    );
+    res
+}
+
+#[doc(hidden)]
+pub unsafe fn syscall2(n: usize, arg1: usize, arg2: usize) -> usize {
+    let res: usize;
+    asm!(
+        "int 0x80", in("rax") n,
+        in("rdi") arg1, in("rsi") arg2,
+        lateout("rax") res
+    );
+    res
+}
+
+#[doc(hidden)]
+pub unsafe fn syscall3(
+    n: usize,
+    arg1: usize,
+    arg2: usize,
+    arg3: usize
+) -> usize {
+    let res: usize;
+    asm!(
+        "int 0x80", in("rax") n,
+        in("rdi") arg1, in("rsi") arg2, in("rdx") arg3,
+        lateout("rax") res
+    );
+    res
+}
+
+#[doc(hidden)]
+pub unsafe fn syscall4(
+    n: usize,
+    arg1: usize,
+    arg2: usize,
+    arg3: usize,
+    arg4: usize
+) -> usize {
+    let res: usize;
+    asm!(
+        "int 0x80", in("rax") n,
+        in("rdi") arg1, in("rsi") arg2, in("rdx") arg3, in("r8") arg4,
+        lateout("rax") res
+    );
+    res
+}
+
+#[macro_export]
+macro_rules! syscall {
+    ($n:expr) => {
+        $crate::api::syscall::syscall0($n as usize)
+    };
+    ($n:expr, $a1:expr) => {
+        $crate::api::syscall::syscall1($n as usize, $a1 as usize)
+    };
+    ($n:expr, $a1:expr, $a2:expr) => {
+        $crate::api::syscall::syscall2($n as usize, $a1 as usize, $a2 as usize)
+    };
+    ($n:expr, $a1:expr, $a2:expr, $a3:expr) => {
+        $crate::api::syscall::syscall3(
+            $n as usize,
+            $a1 as usize,
+            $a2 as usize,
+            $a3 as usize,
+        )
+    };
+    ($n:expr, $a1:expr, $a2:expr, $a3:expr, $a4:expr) => {
+        $crate::api::syscall::syscall4(
+            $n as usize,
+            $a1 as usize,
+            $a2 as usize,
+            $a3 as usize,
+            $a4 as usize,
+        )
+    };
+}
+
 #[test_case]
 fn test_file() {
     use crate::sys::fs::{dismount, format_mem, mount_mem, OpenFlag};
@@ -16,3 +16,17 @@ pub const ACCEPT:  usize = 0xF;
 pub const ALLOC:   usize = 0x10;
 pub const FREE:    usize = 0x11;
 pub const KIND:    usize = 0x12;
+pub const START:   usize = 0x13;
+pub const WAIT:    usize = 0x14;
+pub const KILL:    usize = 0x15;
+pub const PIPE:    usize = 0x16;
+pub const MMAP:    usize = 0x17;
+pub const MUNMAP:  usize = 0x18;
+pub const FORK:    usize = 0x19;
+pub const SIGACTION: usize = 0x1A;
+pub const SIGPROCMASK: usize = 0x1B;
+pub const SIGRETURN: usize = 0x1C;
+pub const THREAD:  usize = 0x1D;
+pub const JOIN:    usize = 0x1E;
+pub const EXIT_THREAD: usize = 0x1F;
+pub const FUTEX:   usize = 0x20;
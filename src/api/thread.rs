@@ -0,0 +1,65 @@
+use crate::api::process::ExitCode;
+use crate::api::syscall;
+use crate::api::syscall::MapFlag;
+
+use alloc::boxed::Box;
+
+const STACK_SIZE: usize = 128 << 10; // 128 KB
+
+// A thread running in the address space of the process, with its own stack
+// mapped by the process
+pub struct JoinHandle {
+    tid: usize,
+    stack: usize,
+}
+
+impl JoinHandle {
+    pub fn id(&self) -> usize {
+        self.tid
+    }
+
+    // Wait for the thread to exit and return its exit code
+    pub fn join(self) -> Result<ExitCode, ()> {
+        let code = syscall::join(self.tid)?;
+        syscall::munmap(self.stack, STACK_SIZE).ok();
+        Ok(code)
+    }
+}
+
+// Start a thread calling the closure, which shares the memory and the
+// handles of the process
+pub fn spawn<F>(f: F) -> Result<JoinHandle, ()>
+where
+    F: FnOnce() + Send + 'static,
+{
+    let flags = MapFlag::Read | MapFlag::Write;
+    let stack = syscall::mmap(0, STACK_SIZE, flags).ok_or(())?;
+
+    // The closure is boxed twice to be given as a thin pointer, and the top
+    // of the stack is aligned as if the entry point had been called
+    let f: Box<Box<dyn FnOnce()>> = Box::new(Box::new(f));
+    let arg = Box::into_raw(f) as usize;
+    let entry = thread_start as usize;
+    let top = stack + STACK_SIZE - 8;
+    match syscall::thread(entry, top, arg) {
+        Ok(tid) => Ok(JoinHandle { tid, stack }),
+        Err(()) => {
+            drop(unsafe { Box::from_raw(arg as *mut Box<dyn FnOnce()>) });
+            syscall::munmap(stack, STACK_SIZE).ok();
+            Err(())
+        }
+    }
+}
+
+// Terminate the current thread, or the process from its main thread
+pub fn exit(code: ExitCode) {
+    syscall::exit_thread(code);
+}
+
+// The kernel starts a thread with this function, which exits the thread when
+// the closure returns
+extern "sysv64" fn thread_start(arg: usize) {
+    let f = unsafe { Box::from_raw(arg as *mut Box<dyn FnOnce()>) };
+    f();
+    syscall::exit_thread(ExitCode::Success);
+}
@@ -1,5 +1,5 @@
 use crate::api::clock;
-use crate::sys;
+use crate::api::process;
 
 use time::{Duration, OffsetDateTime, UtcOffset};
 
@@ -24,7 +24,7 @@ pub fn from_timestamp_utc(ts: i64) -> OffsetDateTime {
 }
 
 fn offset() -> UtcOffset {
-    if let Some(tz) = sys::process::env("TZ") {
+    if let Some(tz) = process::env("TZ") {
         if let Ok(offset) = tz.parse::<i32>() {
             return UtcOffset::seconds(offset);
         }
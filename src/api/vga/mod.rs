@@ -1,5 +1,14 @@
 use crate::api::fs;
 
+// ASCII Printable
+// Backspace
+// New Line
+// Carriage Return
+// Extended ASCII Printable
+pub fn is_printable(c: u8) -> bool {
+    matches!(c, 0x20..=0x7E | 0x08 | 0x0A | 0x0D | 0x80..=0xFF)
+}
+
 pub fn graphic_mode() {
     let dev = "/dev/vga/mode";
     if fs::is_device(dev) {
@@ -3,10 +3,14 @@
 
 extern crate alloc;
 
-use moros::{print, println};
+use alloc::vec::Vec;
+
+use moros::{error, eprint, eprintln, print, println};
 use moros::api::console::Style;
-use moros::api::fs;
+use moros::api::fs::{self, IO};
+use moros::api::gpu;
 use moros::api::io;
+use moros::api::syscall;
 use moros::api::vga;
 use moros::entry_point;
 
@@ -15,6 +19,7 @@ entry_point!(main);
 const WIDTH: usize = 320;
 const HEIGHT: usize = 200;
 const COLORS: usize = 256;
+const BAND_HEIGHT: usize = 16;
 
 struct Point {
     x: f64,
@@ -48,59 +53,97 @@ fn palette(config: &Config) -> [u8; COLORS * 3] {
     palette
 }
 
-fn mandelbrot(buffer: &mut [u8], config: &Config) {
-    let w = WIDTH as f64;
-    let h = HEIGHT as f64;
+// Returns the color index of a pixel on a screen of the given size
+fn mandelbrot(px: usize, py: usize, w: usize, h: usize, config: &Config) -> u8 {
+    let (x0, y0) = complex(px, py, w, h, config);
+
+    // Compute whether the point is in the Mandelbrot Set
+    let mut x = 0.0;
+    let mut y = 0.0;
+    let mut x2 = 0.0;
+    let mut y2 = 0.0;
+    let mut i = 0;
+
+    // Cardioid check
+    let q = libm::pow(x0 - 0.25, 2.0) + libm::pow(y0, 2.0);
+    if q * (q + (x0 - 0.25)) <= 0.25 * libm::pow(y0, 2.0) {
+        return black(config) as u8;
+    }
+
+    // Period-2 bulb check
+    if libm::pow(x0 + 1.0, 2.0) + libm::pow(y0, 2.0) <= 0.0625 {
+        return black(config) as u8;
+    }
+
+    while i < config.n {
+        y = 2.0 * x * y + y0;
+        x = x2 - y2 + x0;
+        x2 = x * x;
+        y2 = y * y;
+
+        if x2 + y2 > 4.0 {
+            break;
+        }
+
+        i += 1;
+    }
+
+    if i < config.n {
+        // Color the pixel based on the number of iterations
+        (i % COLORS) as u8
+    } else {
+        // Or black for points that are in the set
+        black(config) as u8
+    }
+}
+
+// Map pixel position to complex plane
+fn complex(px: usize, py: usize, w: usize, h: usize, config: &Config) -> (f64, f64) {
+    let w = w as f64;
+    let h = h as f64;
     let x_scale = 3.0 / (config.zoom * w);
     let y_scale = 2.0 / (config.zoom * h);
+    let x = config.offset.x + ((px as f64) - w / 2.0) * x_scale;
+    let y = config.offset.y + ((py as f64) - h / 2.0) * y_scale;
+    (x, y)
+}
+
+fn render_vga(buffer: &mut [u8], config: &Config) {
     for py in 0..HEIGHT {
         for px in 0..WIDTH {
-            // Map pixel position to complex plane
-            let x0 = config.offset.x + ((px as f64) - w / 2.0) * x_scale;
-            let y0 = config.offset.y + ((py as f64) - h / 2.0) * y_scale;
-
-            // Compute whether the point is in the Mandelbrot Set
-            let mut x = 0.0;
-            let mut y = 0.0;
-            let mut x2 = 0.0;
-            let mut y2 = 0.0;
-            let mut i = 0;
-
-            // Cardioid check
-            let q = libm::pow(x0 - 0.25, 2.0) + libm::pow(y0, 2.0);
-            if q * (q + (x0 - 0.25)) <= 0.25 * libm::pow(y0, 2.0) {
-                buffer[py * WIDTH + px] = black(config) as u8;
-                continue;
-            }
-
-            // Period-2 bulb check
-            if libm::pow(x0 + 1.0, 2.0) + libm::pow(y0, 2.0) <= 0.0625 {
-                buffer[py * WIDTH + px] = black(config) as u8;
-                continue;
-            }
-
-            while i < config.n {
-                y = 2.0 * x * y + y0;
-                x = x2 - y2 + x0;
-                x2 = x * x;
-                y2 = y * y;
-
-                if x2 + y2 > 4.0 {
-                    break;
-                }
+            buffer[py * WIDTH + px] = mandelbrot(px, py, WIDTH, HEIGHT, config);
+        }
+    }
+    fs::write("/dev/vga/buffer", buffer).ok();
+}
 
-                i += 1;
+// Render the whole screen in bands of rows to limit the memory used and to
+// show the progress of the computation.
+fn render_gpu(buffer: &mut gpu::Buffer, palette: &[u8], w: usize, h: usize, config: &Config) -> bool {
+    let mut band = Vec::with_capacity(w * BAND_HEIGHT);
+    for py in 0..h {
+        for px in 0..w {
+            let i = mandelbrot(px, py, w, h, config) as usize * 3;
+            let (r, g, b) = (palette[i], palette[i + 1], palette[i + 2]);
+            band.push(0xFF000000 | (r as u32) << 16 | (g as u32) << 8 | b as u32);
+        }
+        if band.len() == band.capacity() || py == h - 1 {
+            if !buffer.write(&band) {
+                return false;
             }
-
-            buffer[py * WIDTH + px] = if i < config.n {
-                // Color the pixel based on the number of iterations
-                (i % COLORS) as u8
-            } else {
-                // Or black for points that are in the set
-                black(config) as u8
-            };
+            band.clear();
         }
     }
+    true
+}
+
+// Zoom in at the position of the pointer
+fn zoom_at(mouse: gpu::MouseState, w: u32, h: u32, config: &mut Config) {
+    let (w, h) = (w as usize, h as usize);
+    let (px, py) = (mouse.x as usize, mouse.y as usize);
+    let (x, y) = complex(px, py, w, h, config);
+    config.offset = Point { x, y };
+    config.zoom *= 1.5;
 }
 
 fn black(config: &Config) -> usize {
@@ -150,15 +193,54 @@ fn main(args: &[&str]) {
         i += 1;
     }
 
-    vga::graphic_mode();
-    fs::write("/dev/vga/palette", &palette(&config)).ok();
+    let palette = palette(&config);
+    let resolution = gpu::get_resolution();
+    let mut gpu_buffer = None;
+    if resolution.is_some() {
+        gpu_buffer = gpu::Buffer::open();
+    }
+    if gpu_buffer.is_none() {
+        vga::graphic_mode();
+        fs::write("/dev/vga/palette", &palette).ok();
+    }
 
     let mut escape = false;
     let mut csi = false;
     let mut buffer = [0; WIDTH * HEIGHT];
+    let mut was_pressed = false;
+    let mut is_dirty = true;
+    print!("\x1b[12l"); // Disable echo
     loop {
-        mandelbrot(&mut buffer, &config);
-        fs::write("/dev/vga/buffer", &buffer).ok();
+        if is_dirty {
+            match (&mut gpu_buffer, resolution) {
+                (Some(gpu_buffer), Some((w, h))) => {
+                    let (w, h) = (w as usize, h as usize);
+                    if !render_gpu(gpu_buffer, &palette, w, h, &config) {
+                        error!("Could not write to the GPU buffer");
+                        break;
+                    }
+                }
+                _ => {
+                    render_vga(&mut buffer, &config);
+                }
+            }
+            is_dirty = false;
+        }
+
+        // Zoom in at the pointer when the left button is clicked
+        if let (Some(mouse), Some((w, h))) = (gpu::mouse_state(), resolution) {
+            let is_pressed = mouse.is_left_pressed();
+            if is_pressed && !was_pressed {
+                zoom_at(mouse, w, h, &mut config);
+                is_dirty = true;
+            }
+            was_pressed = is_pressed;
+        }
+
+        if syscall::poll(&[(0, IO::Read)]).is_none() {
+            syscall::sleep(0.02);
+            continue;
+        }
         let c = io::stdin().read_char().unwrap_or('\0');
         match c {
             'q' | '\x11' | '\x03' => { // Ctrl Q or Ctrl C
@@ -193,11 +275,20 @@ fn main(args: &[&str]) {
             '\x08' => { // Backspace: zoom out
                 config.zoom /= 1.5;
             }
+            '\n' => { // Enter: zoom in at the mouse pointer
+                if let (Some(mouse), Some((w, h))) = (gpu::mouse_state(), resolution) {
+                    zoom_at(mouse, w, h, &mut config);
+                }
+            }
             _ => {}
         }
+        is_dirty = true;
     }
+    print!("\x1b[12h"); // Enable echo
 
-    vga::text_mode();
+    if gpu_buffer.is_none() {
+        vga::text_mode();
+    }
 }
 
 fn help() {
@@ -0,0 +1,200 @@
+use crate::api::font::Font;
+use crate::gpu;
+
+use alloc::vec;
+use alloc::vec::Vec;
+use core::convert::TryFrom;
+use lazy_static::lazy_static;
+
+pub const FONT_WIDTH: u32 = 8;
+pub const FONT_HEIGHT: u32 = 16;
+
+lazy_static! {
+    // Font used to render text, embedded to be available without a disk
+    static ref FONT: Font = {
+        let buf = include_bytes!("../dsk/ini/fonts/zap-light-8x16.psf");
+        Font::try_from(&buf[..]).expect("Could not parse embedded font")
+    };
+}
+
+// Off-screen buffer of pixels in 0xAARRGGBB format.
+// Coordinates are signed so that shapes can be partially outside of the
+// canvas, every drawing operation is clipped to its bounds.
+#[derive(Clone)]
+pub struct Canvas {
+    width: u32,
+    height: u32,
+    pixels: Vec<u32>,
+}
+
+impl Canvas {
+    pub fn new(width: u32, height: u32) -> Self {
+        let pixels = vec![0xFF000000; (width * height) as usize];
+        Self { width, height, pixels }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[u32] {
+        &self.pixels
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [u32] {
+        &mut self.pixels
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if 0 <= x && x < self.width as i32 && 0 <= y && y < self.height as i32 {
+            Some((y as u32 * self.width + x as u32) as usize)
+        } else {
+            None
+        }
+    }
+
+    pub fn clear(&mut self, color: u32) {
+        self.pixels.fill(color);
+    }
+
+    pub fn pixel(&self, x: i32, y: i32) -> Option<u32> {
+        self.index(x, y).map(|i| self.pixels[i])
+    }
+
+    pub fn set_pixel(&mut self, x: i32, y: i32, color: u32) {
+        if let Some(i) = self.index(x, y) {
+            self.pixels[i] = color;
+        }
+    }
+
+    pub fn fill_rect(&mut self, x: i32, y: i32, w: u32, h: u32, color: u32) {
+        let x0 = x.clamp(0, self.width as i32);
+        let y0 = y.clamp(0, self.height as i32);
+        let x1 = x.saturating_add(w as i32).clamp(0, self.width as i32);
+        let y1 = y.saturating_add(h as i32).clamp(0, self.height as i32);
+        for py in y0..y1 {
+            let i = (py as u32 * self.width) as usize;
+            self.pixels[i + x0 as usize..i + x1 as usize].fill(color);
+        }
+    }
+
+    pub fn rect(&mut self, x: i32, y: i32, w: u32, h: u32, color: u32) {
+        if w == 0 || h == 0 {
+            return;
+        }
+        self.fill_rect(x, y, w, 1, color);
+        self.fill_rect(x, y + h as i32 - 1, w, 1, color);
+        self.fill_rect(x, y, 1, h, color);
+        self.fill_rect(x + w as i32 - 1, y, 1, h, color);
+    }
+
+    // Bresenham's line algorithm
+    pub fn line(&mut self, x0: i32, y0: i32, x1: i32, y1: i32, color: u32) {
+        let dx = (x1 - x0).abs();
+        let dy = -(y1 - y0).abs();
+        let sx = if x0 < x1 { 1 } else { -1 };
+        let sy = if y0 < y1 { 1 } else { -1 };
+        let mut err = dx + dy;
+        let (mut x, mut y) = (x0, y0);
+        loop {
+            self.set_pixel(x, y, color);
+            if x == x1 && y == y1 {
+                break;
+            }
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+        }
+    }
+
+    // Draws a single character with the embedded 8x16 font, leaving the
+    // background pixels untouched.
+    pub fn char(&mut self, x: i32, y: i32, c: char, color: u32) {
+        self.scaled_char(x, y, c, color, 1);
+    }
+
+    // Draws a single character with each pixel of the font enlarged to a
+    // square of `scale` pixels.
+    pub fn scaled_char(&mut self, x: i32, y: i32, c: char, color: u32, scale: u32) {
+        let code = c as usize;
+        let code = if code < FONT.size as usize { code } else { '?' as usize };
+        let h = FONT.height as usize;
+        let s = scale as i32;
+        for row in 0..h {
+            let bits = FONT.data[code * h + row];
+            for col in 0..8 {
+                if bits & (0x80 >> col) != 0 {
+                    let px = x + col * s;
+                    let py = y + row as i32 * s;
+                    if scale == 1 {
+                        self.set_pixel(px, py, color);
+                    } else {
+                        self.fill_rect(px, py, scale, scale, color);
+                    }
+                }
+            }
+        }
+    }
+
+    // Draws a line of text, `\n` is not interpreted.
+    pub fn text(&mut self, x: i32, y: i32, s: &str, color: u32) {
+        for (i, c) in s.chars().enumerate() {
+            self.char(x + (i as u32 * FONT_WIDTH) as i32, y, c, color);
+        }
+    }
+
+    // Copies another canvas at the given position, clipping what is outside.
+    pub fn blit(&mut self, src: &Canvas, x: i32, y: i32) {
+        let x0 = x.max(0);
+        let x1 = x.saturating_add(src.width as i32).min(self.width as i32);
+        if x0 >= x1 {
+            return;
+        }
+        let y0 = y.max(0);
+        let y1 = y.saturating_add(src.height as i32).min(self.height as i32);
+        for py in y0..y1 {
+            let s = ((py - y) as u32 * src.width + (x0 - x) as u32) as usize;
+            let d = (py as u32 * self.width + x0 as u32) as usize;
+            let n = (x1 - x0) as usize;
+            self.pixels[d..d + n].copy_from_slice(&src.pixels[s..s + n]);
+        }
+    }
+
+    // Draws the canvas on the screen at the given position and flushes the
+    // display to make the changes visible.
+    pub fn present(&self, x: u32, y: u32) -> bool {
+        gpu::draw_buffer(&self.pixels, self.width, self.height, x, y)
+            && gpu::flush_display()
+    }
+}
+
+#[test_case]
+fn test_canvas_clipping() {
+    let mut canvas = Canvas::new(4, 4);
+    canvas.clear(0);
+    canvas.fill_rect(-2, -2, 4, 4, 1);
+    assert_eq!(canvas.pixel(0, 0), Some(1));
+    assert_eq!(canvas.pixel(1, 1), Some(1));
+    assert_eq!(canvas.pixel(2, 2), Some(0));
+    assert_eq!(canvas.pixel(4, 0), None);
+
+    let mut src = Canvas::new(2, 2);
+    src.clear(2);
+    canvas.blit(&src, 3, 3);
+    assert_eq!(canvas.pixel(3, 3), Some(2));
+    assert_eq!(canvas.pixel(2, 3), Some(0));
+
+    canvas.line(0, 3, 3, 0, 3);
+    assert_eq!(canvas.pixel(0, 3), Some(3));
+    assert_eq!(canvas.pixel(3, 0), Some(3));
+}
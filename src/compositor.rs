@@ -0,0 +1,344 @@
+use crate::canvas::{Canvas, FONT_HEIGHT, FONT_WIDTH};
+use crate::gpu;
+use crate::mouse::MouseState;
+
+use alloc::collections::VecDeque;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use lazy_static::lazy_static;
+use spin::Mutex;
+
+pub const TITLE_BAR_HEIGHT: u32 = FONT_HEIGHT + 4;
+
+const BACKGROUND_COLOR: u32 = 0xFF204060;
+const BORDER_COLOR: u32 = 0xFF000000;
+const TITLE_COLOR: u32 = 0xFF808080;
+const FOCUSED_TITLE_COLOR: u32 = 0xFF3070C0;
+const TITLE_TEXT_COLOR: u32 = 0xFFFFFFFF;
+
+// Minimum number of pixels of the title bar kept on screen when a window is
+// dragged, so that it can always be grabbed again.
+const GRAB_MARGIN: i32 = 32;
+
+// Maximum number of keys waiting to be read by a window
+const MAX_KEYS: usize = 256;
+
+lazy_static! {
+    pub static ref COMPOSITOR: Mutex<Compositor> = Mutex::new(Compositor::new());
+    static ref SCREEN: Mutex<Option<Canvas>> = Mutex::new(None);
+}
+
+// A window is a title bar above a content area backed by an off-screen
+// canvas that the owner of the window draws into.
+pub struct Window {
+    id: usize,
+    title: String,
+    x: i32,
+    y: i32,
+    canvas: Canvas,
+    keys: VecDeque<char>,
+}
+
+impl Window {
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    pub fn title(&self) -> &str {
+        &self.title
+    }
+
+    pub fn set_title(&mut self, title: &str) {
+        self.title = title.to_string();
+    }
+
+    // Position of the top-left corner of the title bar
+    pub fn position(&self) -> (i32, i32) {
+        (self.x, self.y)
+    }
+
+    // Size of the content area
+    pub fn size(&self) -> (u32, u32) {
+        (self.canvas.width(), self.canvas.height())
+    }
+
+    pub fn canvas(&self) -> &Canvas {
+        &self.canvas
+    }
+
+    pub fn canvas_mut(&mut self) -> &mut Canvas {
+        &mut self.canvas
+    }
+
+    pub fn read_key(&mut self) -> Option<char> {
+        self.keys.pop_front()
+    }
+
+    fn contains(&self, x: i32, y: i32) -> bool {
+        let w = self.canvas.width() as i32;
+        let h = (self.canvas.height() + TITLE_BAR_HEIGHT) as i32;
+        self.x <= x && x < self.x + w && self.y <= y && y < self.y + h
+    }
+
+    fn is_in_title_bar(&self, x: i32, y: i32) -> bool {
+        self.contains(x, y) && y < self.y + TITLE_BAR_HEIGHT as i32
+    }
+
+    fn draw(&self, screen: &mut Canvas, is_focused: bool) {
+        let w = self.canvas.width();
+        let h = self.canvas.height() + TITLE_BAR_HEIGHT;
+        let color = if is_focused { FOCUSED_TITLE_COLOR } else { TITLE_COLOR };
+        screen.fill_rect(self.x, self.y, w, TITLE_BAR_HEIGHT, color);
+
+        // Truncate the title to the width of the window
+        let n = (w.saturating_sub(8) / FONT_WIDTH) as usize;
+        let title: String = self.title.chars().take(n).collect();
+        screen.text(self.x + 4, self.y + 2, &title, TITLE_TEXT_COLOR);
+
+        screen.blit(&self.canvas, self.x, self.y + TITLE_BAR_HEIGHT as i32);
+        screen.rect(self.x - 1, self.y - 1, w + 2, h + 2, BORDER_COLOR);
+    }
+}
+
+struct Drag {
+    id: usize,
+    dx: i32,
+    dy: i32,
+}
+
+// Manages a stack of windows ordered from the bottom to the top. The window
+// at the top of the stack has the focus and receives the keyboard events.
+pub struct Compositor {
+    windows: Vec<Window>,
+    next_id: usize,
+    drag: Option<Drag>,
+    buttons: u8,
+}
+
+impl Compositor {
+    pub fn new() -> Self {
+        Self {
+            windows: Vec::new(),
+            next_id: 1,
+            drag: None,
+            buttons: 0,
+        }
+    }
+
+    // Creates a window with a content area of the given size, on top of the
+    // others, and returns its id.
+    pub fn create_window(
+        &mut self,
+        title: &str,
+        x: i32,
+        y: i32,
+        width: u32,
+        height: u32
+    ) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.windows.push(Window {
+            id,
+            title: title.to_string(),
+            x,
+            y,
+            canvas: Canvas::new(width, height),
+            keys: VecDeque::new(),
+        });
+        id
+    }
+
+    pub fn close_window(&mut self, id: usize) -> bool {
+        if let Some(i) = self.index(id) {
+            self.windows.remove(i);
+            if self.drag.as_ref().is_some_and(|drag| drag.id == id) {
+                self.drag = None;
+            }
+            true
+        } else {
+            false
+        }
+    }
+
+    fn index(&self, id: usize) -> Option<usize> {
+        self.windows.iter().position(|w| w.id == id)
+    }
+
+    pub fn window(&self, id: usize) -> Option<&Window> {
+        self.windows.iter().find(|w| w.id == id)
+    }
+
+    pub fn window_mut(&mut self, id: usize) -> Option<&mut Window> {
+        self.windows.iter_mut().find(|w| w.id == id)
+    }
+
+    // Returns the windows from the bottom to the top of the stack
+    pub fn windows(&self) -> &[Window] {
+        &self.windows
+    }
+
+    pub fn focused(&self) -> Option<usize> {
+        self.windows.last().map(|w| w.id)
+    }
+
+    // Gives the focus to a window by raising it to the top of the stack
+    pub fn focus(&mut self, id: usize) -> bool {
+        if let Some(i) = self.index(id) {
+            let window = self.windows.remove(i);
+            self.windows.push(window);
+            true
+        } else {
+            false
+        }
+    }
+
+    // Gives the focus to the window at the bottom of the stack
+    pub fn focus_next(&mut self) {
+        if self.windows.len() > 1 {
+            let window = self.windows.remove(0);
+            self.windows.push(window);
+        }
+    }
+
+    pub fn move_window(&mut self, id: usize, x: i32, y: i32) -> bool {
+        if let Some(window) = self.window_mut(id) {
+            window.x = x;
+            window.y = y;
+            true
+        } else {
+            false
+        }
+    }
+
+    // Returns the topmost window at the given position
+    pub fn window_at(&self, x: i32, y: i32) -> Option<usize> {
+        self.windows.iter().rev().find(|w| w.contains(x, y)).map(|w| w.id)
+    }
+
+    // Routes a key to the focused window
+    pub fn handle_key(&mut self, key: char) -> bool {
+        if let Some(window) = self.windows.last_mut() {
+            if window.keys.len() < MAX_KEYS {
+                window.keys.push_back(key);
+                return true;
+            }
+        }
+        false
+    }
+
+    // Updates the windows from the state of the mouse and returns true if
+    // the screen needs to be composed again.
+    //
+    // A left click gives the focus to the window under the pointer, and
+    // starts dragging it if the click happened in its title bar.
+    pub fn handle_mouse(&mut self, state: MouseState) -> bool {
+        let x = state.x as i32;
+        let y = state.y as i32;
+        let was_pressed = self.buttons & crate::mouse::LEFT_BUTTON != 0;
+        self.buttons = state.buttons;
+
+        match (was_pressed, state.is_left_pressed()) {
+            (false, true) => {
+                if let Some(id) = self.window_at(x, y) {
+                    let is_drag = self.window(id).unwrap().is_in_title_bar(x, y);
+                    let changed = self.focused() != Some(id);
+                    self.focus(id);
+                    if is_drag {
+                        let (wx, wy) = self.window(id).unwrap().position();
+                        self.drag = Some(Drag { id, dx: x - wx, dy: y - wy });
+                    }
+                    return changed;
+                }
+                false
+            }
+            (true, true) => {
+                if let Some(Drag { id, dx, dy }) = self.drag {
+                    let (w, _) = self.window(id).map_or((0, 0), |w| w.size());
+                    let min_x = GRAB_MARGIN - w as i32;
+                    let max_x = match gpu::get_resolution() {
+                        Some((sw, _)) => sw as i32 - GRAB_MARGIN,
+                        None => i32::MAX,
+                    };
+                    let nx = (x - dx).clamp(min_x, max_x.max(min_x));
+                    let ny = (y - dy).max(0);
+                    return self.move_window(id, nx, ny);
+                }
+                false
+            }
+            (true, false) => {
+                self.drag = None;
+                false
+            }
+            (false, false) => false,
+        }
+    }
+
+    // Draws the background and every window from the bottom to the top
+    pub fn compose(&self, screen: &mut Canvas) {
+        screen.clear(BACKGROUND_COLOR);
+        let focused = self.focused();
+        for window in &self.windows {
+            window.draw(screen, Some(window.id) == focused);
+        }
+    }
+}
+
+impl Default for Compositor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Composes the windows of the global compositor on the screen and flushes
+// the display.
+pub fn render() -> bool {
+    let (width, height) = match gpu::get_resolution() {
+        Some(resolution) => resolution,
+        None => return false,
+    };
+    let mut screen_guard = SCREEN.lock();
+    let is_resized = screen_guard.as_ref().is_none_or(|screen|
+        screen.width() != width || screen.height() != height
+    );
+    if is_resized {
+        *screen_guard = Some(Canvas::new(width, height));
+    }
+    let screen = screen_guard.as_mut().unwrap();
+    COMPOSITOR.lock().compose(screen);
+    screen.present(0, 0)
+}
+
+#[test_case]
+fn test_compositor() {
+    let mut compositor = Compositor::new();
+    let a = compositor.create_window("a", 0, 0, 100, 100);
+    let b = compositor.create_window("b", 50, 50, 100, 100);
+    assert_eq!(compositor.focused(), Some(b));
+    assert_eq!(compositor.window_at(60, 60), Some(b));
+    assert_eq!(compositor.window_at(10, 10), Some(a));
+    assert_eq!(compositor.window_at(200, 200), None);
+
+    // Keys are routed to the focused window
+    compositor.handle_key('x');
+    assert_eq!(compositor.window_mut(b).unwrap().read_key(), Some('x'));
+    assert_eq!(compositor.window_mut(a).unwrap().read_key(), None);
+
+    // Clicking on a window raises it
+    let click = |x, y, buttons| MouseState { x, y, buttons };
+    compositor.handle_mouse(click(10, 10, crate::mouse::LEFT_BUTTON));
+    compositor.handle_mouse(click(10, 10, 0));
+    assert_eq!(compositor.focused(), Some(a));
+    assert_eq!(compositor.window_at(60, 60), Some(a));
+
+    // Dragging the title bar moves the window
+    compositor.handle_mouse(click(120, 55, crate::mouse::LEFT_BUTTON));
+    assert_eq!(compositor.focused(), Some(b));
+    compositor.handle_mouse(click(130, 75, crate::mouse::LEFT_BUTTON));
+    compositor.handle_mouse(click(130, 75, 0));
+    assert_eq!(compositor.window(b).unwrap().position(), (60, 70));
+
+    compositor.focus_next();
+    assert_eq!(compositor.focused(), Some(a));
+    assert!(compositor.close_window(a));
+    assert_eq!(compositor.focused(), Some(b));
+}
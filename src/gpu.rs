@@ -14,6 +14,9 @@ use virtio_drivers::transport::Transport;
 use crate::{debug, error, warning, eprint, eprintln};
 use crate::sys::pci;
 use crate::hal;
+use crate::api::fs::{FileIO, IO};
+
+use alloc::format;
 
 lazy_static! {
     static ref GPU_DRIVER: Mutex<Option<VirtIOGpu<hal::MyKernelHal, PciTransport>>> = Mutex::new(None);
@@ -136,7 +139,10 @@ pub fn init_and_setup_gpu() {
                             *driver_guard = Some(temp_gpu_driver);
                             let gpu_driver_static_ref: &'static mut VirtIOGpu<hal::MyKernelHal, PciTransport>;
                             unsafe {
-                                gpu_driver_static_ref = mem::transmute(driver_guard.as_mut().unwrap());
+                                gpu_driver_static_ref = mem::transmute::<
+                                    &mut VirtIOGpu<hal::MyKernelHal, PciTransport>,
+                                    &'static mut VirtIOGpu<hal::MyKernelHal, PciTransport>,
+                                >(driver_guard.as_mut().unwrap());
                             }
 
                             // Get resolution by 'static mutable reference before setup_framebuffer
@@ -216,7 +222,7 @@ where
         let width = FRAMEBUFFER_WIDTH.load(Ordering::SeqCst);
         let height = FRAMEBUFFER_HEIGHT.load(Ordering::SeqCst);
         // Pass the mutable framebuffer slice and dimensions to the closure.
-        f(*fb, width, height);
+        f(fb, width, height);
         true
     } else {
         error!("Framebuffer slice not available (was init successful?).");
@@ -427,4 +433,274 @@ pub fn draw_image<const W_PIXELS: usize, const H_PIXELS: usize>(
             }
         }
     })
-}
\ No newline at end of file
+}
+
+// Draws a buffer of pixels at a specified position.
+// `pixels`: Row-major buffer of `width * height` pixels in 0xAARRGGBB format.
+// `dest_x`, `dest_y`: Top-left corner coordinates on the screen.
+// Rows and columns falling outside of the screen are clipped.
+pub fn draw_buffer(
+    pixels: &[u32],
+    width: u32,
+    height: u32,
+    dest_x: u32,
+    dest_y: u32,
+) -> bool {
+    if !GPU_INITIALIZED.load(Ordering::Acquire) {
+        error!("GPU driver not initialized. Cannot draw buffer.");
+        return false;
+    }
+
+    if pixels.len() != (width * height) as usize {
+        error!("draw_buffer: `pixels` length ({}) does not match expected size for {}x{} buffer.",
+            pixels.len(), width, height);
+        return false;
+    }
+
+    with_framebuffer_do(|framebuffer, fb_w, fb_h| {
+        if dest_x >= fb_w || dest_y >= fb_h {
+            return;
+        }
+        let end_x = dest_x.saturating_add(width).min(fb_w);
+        let end_y = dest_y.saturating_add(height).min(fb_h);
+        let n = (end_x - dest_x) as usize;
+
+        for screen_y in dest_y..end_y {
+            let src = ((screen_y - dest_y) * width) as usize;
+            let dst = ((screen_y * fb_w) + dest_x) as usize * 4;
+            if dst + n * 4 > framebuffer.len() {
+                error!("draw_buffer: Row {} out of bounds (framebuffer len: {}).", screen_y, framebuffer.len());
+                return;
+            }
+            let row = &mut framebuffer[dst..dst + n * 4];
+            for (bgra, color_code) in row.chunks_exact_mut(4).zip(&pixels[src..src + n]) {
+                // 0xAARRGGBB stored little endian is BGRA
+                bgra.copy_from_slice(&color_code.to_le_bytes());
+            }
+        }
+    })
+}
+
+// Device file giving access to the framebuffer from userspace.
+//
+// Pixels are written as 0xAARRGGBB values in little endian, starting from
+// the position of a cursor that is advanced by each write and wraps around
+// at the end of the frame, so a frame can be sent in multiple bands of rows
+// through the same handle. The display is flushed after each write.
+#[derive(Debug, Clone, Default)]
+pub struct GpuBuffer {
+    offset: usize,
+}
+
+impl GpuBuffer {
+    pub fn new() -> Self {
+        Self { offset: 0 }
+    }
+}
+
+impl FileIO for GpuBuffer {
+    fn read(&mut self, _buf: &mut [u8]) -> Result<usize, ()> {
+        Err(())
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize, ()> {
+        if buf.len() % 4 != 0 || get_resolution().is_none() {
+            return Err(());
+        }
+        let mut res = Err(());
+        let offset = &mut self.offset;
+        with_framebuffer_do(|framebuffer, width, height| {
+            let size = (width * height * 4) as usize;
+            if buf.len() > size || size > framebuffer.len() {
+                return;
+            }
+            let mut i = 0;
+            while i < buf.len() {
+                if *offset >= size {
+                    *offset = 0;
+                }
+                let n = (buf.len() - i).min(size - *offset);
+                framebuffer[*offset..*offset + n].copy_from_slice(&buf[i..i + n]);
+                *offset += n;
+                i += n;
+            }
+            res = Ok(buf.len());
+        });
+        if res.is_ok() && !flush_display() {
+            return Err(());
+        }
+        res
+    }
+
+    fn close(&mut self) {}
+
+    fn poll(&mut self, event: IO) -> bool {
+        match event {
+            IO::Read => false,
+            IO::Write => get_resolution().is_some(),
+        }
+    }
+}
+
+// Device file returning the resolution of the display as "<width>x<height>"
+#[derive(Debug, Clone, Default)]
+pub struct GpuMode;
+
+impl GpuMode {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn size() -> usize {
+        16
+    }
+}
+
+impl FileIO for GpuMode {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+        let (width, height) = get_resolution().ok_or(())?;
+        let mode = format!("{}x{}", width, height);
+        let n = mode.len();
+        if buf.len() < n {
+            return Err(());
+        }
+        buf[0..n].copy_from_slice(mode.as_bytes());
+        Ok(n)
+    }
+
+    fn write(&mut self, _buf: &[u8]) -> Result<usize, ()> {
+        Err(())
+    }
+
+    fn close(&mut self) {}
+
+    fn poll(&mut self, event: IO) -> bool {
+        match event {
+            IO::Read => get_resolution().is_some(),
+            IO::Write => false,
+        }
+    }
+}
+
+// Returns the SHA-256 digest of a region of the framebuffer in hexadecimal,
+// or None if the GPU is not available or the region is not on the screen.
+#[cfg(test)]
+fn framebuffer_digest(x: u32, y: u32, width: u32, height: u32) -> Option<alloc::string::String> {
+    use sha2::{Digest, Sha256};
+
+    let mut res = None;
+    with_framebuffer_do(|framebuffer, fb_w, fb_h| {
+        if x + width > fb_w || y + height > fb_h {
+            return;
+        }
+        let mut hasher = Sha256::new();
+        for row in y..(y + height) {
+            let i = ((row * fb_w + x) * 4) as usize;
+            hasher.update(&framebuffer[i..i + (width * 4) as usize]);
+        }
+        let hex = hasher.finalize().iter().map(|byte|
+            format!("{:02x}", byte)
+        ).collect();
+        res = Some(hex);
+    });
+    res
+}
+
+#[cfg(test)]
+fn clear_region(x: u32, y: u32, width: u32, height: u32) {
+    let pixels = alloc::vec![0xFF000000; (width * height) as usize];
+    assert!(draw_buffer(&pixels, width, height, x, y));
+}
+
+// The following tests are skipped when QEMU is started without a VirtIO GPU.
+// The expected digests cover regions placed relative to the edges of the
+// screen, so they don't depend on its resolution.
+
+#[test_case]
+fn test_gpu_pixel_format() {
+    if get_resolution().is_none() {
+        return;
+    }
+    assert!(draw_square(0, 0, 0xFF112233));
+    with_framebuffer_do(|framebuffer, _, _| {
+        // 0xAARRGGBB is stored as BGRA
+        assert_eq!(framebuffer[0..4], [0x33, 0x22, 0x11, 0xFF]);
+    });
+}
+
+#[test_case]
+fn test_gpu_squares() {
+    if get_resolution().is_none() {
+        return;
+    }
+    clear_region(0, 0, 32, 32);
+    assert!(draw_square(0, 0, 0xFFFF0000));
+    assert!(draw_square(8, 8, 0xFF00FF00));
+    assert!(draw_square(16, 16, 0xFF0000FF));
+    assert!(draw_square(28, 28, 0xFFFFFFFF));
+    assert!(flush_display());
+    assert_eq!(
+        framebuffer_digest(0, 0, 32, 32).as_deref(),
+        Some("c3f5a09c6a7db3b63aedbf389785e45b8d40fda85134ca19aba4450ac80cc30d")
+    );
+}
+
+#[test_case]
+fn test_gpu_image() {
+    if get_resolution().is_none() {
+        return;
+    }
+    let image = [
+        [0xFFFF0000, 0xFF00FF00, 0xFF0000FF, 0xFFFFFFFF],
+        [0xFF112233, 0xFF445566, 0xFF778899, 0xFFAABBCC],
+    ];
+    clear_region(0, 0, 16, 16);
+    assert!(draw_image(&image, 4, 4));
+    assert!(flush_display());
+    assert_eq!(
+        framebuffer_digest(0, 0, 16, 16).as_deref(),
+        Some("afa066f9b78c56c775100f72ad19ea3512a8428d35e55381d593cb645cab7694")
+    );
+}
+
+#[test_case]
+fn test_gpu_clipping() {
+    let (w, h) = match get_resolution() {
+        Some(resolution) => resolution,
+        None => return,
+    };
+    clear_region(w - 16, h - 16, 16, 16);
+    let before = framebuffer_digest(w - 16, h - 16, 16, 16);
+
+    // Nothing is drawn outside of the screen
+    assert!(!draw_square(w, h, 0xFFFF0000));
+    assert!(!draw_image(&[[0xFFFF0000; 2]; 2], w, 0));
+    assert_eq!(framebuffer_digest(w - 16, h - 16, 16, 16), before);
+
+    // Shapes overlapping the edges are clipped
+    assert!(draw_square(w - 4, h - 4, 0xFFFF0000));
+    let pixels: alloc::vec::Vec<u32> = (0..64).map(|i| 0xFF00FF00 + i).collect();
+    assert!(draw_buffer(&pixels, 8, 8, w - 12, h - 3));
+    assert!(draw_image(&[[0xFF0000FF; 6]; 2], w - 2, h - 12));
+    assert!(flush_display());
+    assert_eq!(
+        framebuffer_digest(w - 16, h - 16, 16, 16).as_deref(),
+        Some("c5d5a80738fd0f3f5b71aa7a98b4208fb660d119d1c771101a8d6397dd725d51")
+    );
+}
+
+#[test_case]
+fn test_gpu_canvas() {
+    if get_resolution().is_none() {
+        return;
+    }
+    let mut canvas = crate::canvas::Canvas::new(16, 16);
+    canvas.clear(0xFF000000);
+    canvas.fill_rect(4, 4, 8, 8, 0xFF808080);
+    canvas.rect(0, 0, 16, 16, 0xFFFFFF00);
+    assert!(canvas.present(0, 0));
+    assert_eq!(
+        framebuffer_digest(0, 0, 16, 16).as_deref(),
+        Some("81442e93b3012d33d95494925591627580e3f5381d700b4bf00d10211290fd7b")
+    );
+}
@@ -27,7 +27,7 @@ unsafe impl Hal for MyKernelHal {
         //debug!("dma_alloc: Attempting to allocate {} bytes ({} pages) for DMA.", size, pages);
 
         // Special allocation for framebuffer which is greater than 1MB
-        if size > 1 * 1024 * 1024 && !IS_FB_ALLOCATED.load(Ordering::Relaxed) {
+        if size > 1024 * 1024 && !IS_FB_ALLOCATED.load(Ordering::Relaxed) {
             // Access static DMA_FRAMEBUFFER_REGION
             let fb_dma_buf = crate::sys::mem::dma_framebuffer();
             // Ensure the requested size fits within the pre-allocated 8MB buffer.
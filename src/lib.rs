@@ -15,14 +15,32 @@ extern crate alloc;
 pub mod api;
 
 #[macro_use]
+#[cfg(not(feature = "userspace"))]
 pub mod sys;
 
+#[cfg(not(feature = "userspace"))]
 pub mod usr;
 
+// Modified by shshi102
+#[cfg(not(feature = "userspace"))]
+pub mod hal; // src/hal.rs Hardware Abstraction Layer for VirtIO Driver
+#[cfg(not(feature = "userspace"))]
+pub mod gpu; // src/gpu.rs Graphic API Using VirtIO Driver
+#[cfg(not(feature = "userspace"))]
+pub mod mouse; // src/mouse.rs VirtIO mouse driver
+#[cfg(not(feature = "userspace"))]
+pub mod canvas; // src/canvas.rs Off-screen drawing buffer
+#[cfg(not(feature = "userspace"))]
+pub mod compositor; // src/compositor.rs Window manager on the framebuffer
+
+#[cfg(not(feature = "userspace"))]
 use bootloader::BootInfo;
+use core::panic::PanicInfo;
 
+#[cfg(not(feature = "userspace"))]
 const KERNEL_SIZE: usize = 4 << 20; // 4 MB
 
+#[cfg(not(feature = "userspace"))]
 pub fn init(boot_info: &'static BootInfo) {
     sys::vga::init();
     sys::gdt::init();
@@ -44,12 +62,13 @@ pub fn init(boot_info: &'static BootInfo) {
     sys::net::init(); // Require PCI
     sys::ata::init();
     sys::fs::init(); // Require ATA
+    sys::backtrace::init(); // Require FS
 
     log!("RTC {}", sys::clk::date());
 }
 
-#[allow(dead_code)]
-#[cfg_attr(not(feature = "userspace"), alloc_error_handler)]
+#[cfg(not(feature = "userspace"))]
+#[alloc_error_handler]
 fn alloc_error_handler(layout: alloc::alloc::Layout) -> ! {
     let csi_color = api::console::Style::color("red");
     let csi_reset = api::console::Style::reset();
@@ -107,11 +126,27 @@ pub fn hlt_loop() -> ! {
     }
 }
 
-#[cfg(test)]
-use bootloader::entry_point;
+// The panic handlers are defined in the library, which can be linked
+// dynamically to the user programs
+#[cfg(all(not(test), not(feature = "userspace")))]
+#[panic_handler]
+fn panic(info: &PanicInfo) -> ! {
+    debug!("{}", info);
+    sys::backtrace::print_backtrace();
+    hlt_loop();
+}
+
+#[cfg(all(not(test), feature = "userspace"))]
+#[panic_handler]
+fn panic(_info: &PanicInfo) -> ! {
+    let msg = b"\x1b[91mError:\x1b[m An exception occured\n";
+    api::syscall::write(2, msg);
+    api::syscall::exit(api::process::ExitCode::ExecError);
+    loop {}
+}
 
 #[cfg(test)]
-use core::panic::PanicInfo;
+use bootloader::entry_point;
 
 #[cfg(test)]
 entry_point!(test_kernel_main);
@@ -119,6 +154,7 @@ entry_point!(test_kernel_main);
 #[cfg(test)]
 fn test_kernel_main(boot_info: &'static BootInfo) -> ! {
     init(boot_info);
+    gpu::init_and_setup_gpu();
     test_main();
     hlt_loop();
 }
@@ -130,6 +166,7 @@ fn panic(info: &PanicInfo) -> ! {
     let csi_reset = api::console::Style::reset();
     println!("{}failed{}\n", csi_color, csi_reset);
     println!("{}\n", info);
+    sys::backtrace::print_backtrace();
     exit_qemu(QemuExitCode::Failed);
     hlt_loop();
 }
@@ -3,16 +3,13 @@
 
 extern crate alloc;
 
-// Modified by shshi102
-mod hal; // src/hal.rs Hardware Abstraction Layer for VirtIO Driver
-mod gpu; // src/gpu.rs Graphic API Using VirtIO Driver
 mod picture_data; // image/picture.rs test image data
 
 use bootloader::{entry_point, BootInfo};
-use core::panic::PanicInfo;
 use moros::{
     debug, error, warning, hlt_loop, eprint, eprintln, print, println, sys, usr
 };
+use moros::{gpu, mouse};
 
 // Modified by shshi102
 use moros::sys::console; // src/sys/console.rs keyboard input
@@ -26,6 +23,7 @@ fn main(boot_info: &'static BootInfo) -> ! {
 
     // Initialize GPU, Modified by shshi102
     gpu::init_and_setup_gpu();
+    mouse::init_and_setup_mouse();
     debug!("Starting VirtIO GPU public API tests...");
 
     // TEST gpu::get_resolution(), Modified by shshi102
@@ -87,78 +85,90 @@ fn main(boot_info: &'static BootInfo) -> ! {
     // TEST gpu::draw_square, Modified by shshi102
     println!("Use WASD to draw, 'C' to reset drawing, 'SPACE' to reset drawing and position, 'Q' to quit.");
     let move_step: u32 = 8;
-    'keyboard_drawing_loop: loop {
-        let input_char = console::read_char();
-        match input_char {
-            'w' | 'W' => {
-                square_y = square_y.saturating_sub(move_step);
-                //println!("Move Up at ({}, {})", square_x, square_y);
-            },
-            's' | 'S' => {
-                square_y = square_y.saturating_add(move_step).min(screen_height.saturating_sub(square_size));
-                //println!("Move Down at ({}, {})", square_x, square_y);
-            },
-            'a' | 'A' => {
-                square_x = square_x.saturating_sub(move_step);
-                //println!("Move Left at ({}, {})", square_x, square_y);
-            },
-            'd' | 'D' => {
-                square_x = square_x.saturating_add(move_step).min(screen_width.saturating_sub(square_size));
-                //println!("Move Right at ({}, {})", square_x, square_y);
-            },
-            'c' | 'C' => { // Clear screen and redraw picture, keep position
-                println!("'C' pressed. Resetting screen and redisplaying main picture (keeping position).");
-                
-                // Clear the entire screen, Modified by shshi102
-                for y_coord in (0..screen_height).step_by(square_size as usize) {
-                    for x_coord in (0..screen_width).step_by(square_size as usize) {
-                        gpu::draw_square(x_coord, y_coord, 0xFF000000); // Directly use u32 for black
+    // Redraw at a steady frame rate while handling the keys without blocking
+    let mut scheduler = sys::clk::FrameScheduler::new(30.0);
+    console::disable_echo();
+    console::enable_raw();
+    let stats = scheduler.run(|_frame| {
+        while let Some(input_char) = console::try_read_char() {
+            match input_char {
+                'w' | 'W' => {
+                    square_y = square_y.saturating_sub(move_step);
+                    //println!("Move Up at ({}, {})", square_x, square_y);
+                },
+                's' | 'S' => {
+                    square_y = square_y.saturating_add(move_step).min(screen_height.saturating_sub(square_size));
+                    //println!("Move Down at ({}, {})", square_x, square_y);
+                },
+                'a' | 'A' => {
+                    square_x = square_x.saturating_sub(move_step);
+                    //println!("Move Left at ({}, {})", square_x, square_y);
+                },
+                'd' | 'D' => {
+                    square_x = square_x.saturating_add(move_step).min(screen_width.saturating_sub(square_size));
+                    //println!("Move Right at ({}, {})", square_x, square_y);
+                },
+                'c' | 'C' => { // Clear screen and redraw picture, keep position
+                    println!("'C' pressed. Resetting screen and redisplaying main picture (keeping position).");
+                    
+                    // Clear the entire screen, Modified by shshi102
+                    for y_coord in (0..screen_height).step_by(square_size as usize) {
+                        for x_coord in (0..screen_width).step_by(square_size as usize) {
+                            gpu::draw_square(x_coord, y_coord, 0xFF000000); // Directly use u32 for black
+                        }
                     }
-                }
-                gpu::flush_display();
-                let current_picture_data_width = PICTURE_DATA[0].len() as u32;
-                let current_picture_data_height = PICTURE_DATA.len() as u32;
-                let current_picture_start_x = (screen_width / 2).saturating_sub(current_picture_data_width / 2);
-                let current_picture_start_y = (screen_height / 2).saturating_sub(current_picture_data_height / 2);
-                gpu::draw_image(&PICTURE_DATA, current_picture_start_x, current_picture_start_y);
-                gpu::flush_display();
-            },
-            ' ' => {
-                println!("'SPACE' pressed. Resetting screen, redisplaying main picture, and centering position.");
-
-                // Clear the entire screen, Modified by shshi102
-                for y_coord in (0..screen_height).step_by(square_size as usize) {
-                    for x_coord in (0..screen_width).step_by(square_size as usize) {
-                        gpu::draw_square(x_coord, y_coord, 0xFF000000); // Directly use u32 for black
+                    gpu::flush_display();
+                    let current_picture_data_width = PICTURE_DATA[0].len() as u32;
+                    let current_picture_data_height = PICTURE_DATA.len() as u32;
+                    let current_picture_start_x = (screen_width / 2).saturating_sub(current_picture_data_width / 2);
+                    let current_picture_start_y = (screen_height / 2).saturating_sub(current_picture_data_height / 2);
+                    gpu::draw_image(&PICTURE_DATA, current_picture_start_x, current_picture_start_y);
+                    gpu::flush_display();
+                },
+                ' ' => {
+                    println!("'SPACE' pressed. Resetting screen, redisplaying main picture, and centering position.");
+
+                    // Clear the entire screen, Modified by shshi102
+                    for y_coord in (0..screen_height).step_by(square_size as usize) {
+                        for x_coord in (0..screen_width).step_by(square_size as usize) {
+                            gpu::draw_square(x_coord, y_coord, 0xFF000000); // Directly use u32 for black
+                        }
                     }
-                }
-                gpu::flush_display();
-                let current_picture_data_width = PICTURE_DATA[0].len() as u32;
-                let current_picture_data_height = PICTURE_DATA.len() as u32;
-                let current_picture_start_x = (screen_width / 2).saturating_sub(current_picture_data_width / 2);
-                let current_picture_start_y = (screen_height / 2).saturating_sub(current_picture_data_height / 2);
-                gpu::draw_image(&PICTURE_DATA, current_picture_start_x, current_picture_start_y);
-                gpu::flush_display();
-
-                // Reset square position to center, Modified by shshi102
-                square_x = (screen_width / 2).saturating_sub(square_size / 2);
-                square_y = (screen_height / 2).saturating_sub(square_size / 2);
-            },
-            'q' | 'Q' => {
-                println!("'Q' pressed. Exiting graphics test.");
-                println!("Return to command line interface.");
-                break 'keyboard_drawing_loop;
-            },
-            key => {
-                debug!("Key pressed: {}", key);
-            },
+                    gpu::flush_display();
+                    let current_picture_data_width = PICTURE_DATA[0].len() as u32;
+                    let current_picture_data_height = PICTURE_DATA.len() as u32;
+                    let current_picture_start_x = (screen_width / 2).saturating_sub(current_picture_data_width / 2);
+                    let current_picture_start_y = (screen_height / 2).saturating_sub(current_picture_data_height / 2);
+                    gpu::draw_image(&PICTURE_DATA, current_picture_start_x, current_picture_start_y);
+                    gpu::flush_display();
+
+                    // Reset square position to center, Modified by shshi102
+                    square_x = (screen_width / 2).saturating_sub(square_size / 2);
+                    square_y = (screen_height / 2).saturating_sub(square_size / 2);
+                },
+                'q' | 'Q' => {
+                    println!("'Q' pressed. Exiting graphics test.");
+                    println!("Return to command line interface.");
+                    return false;
+                },
+                key => {
+                    debug!("Key pressed: {}", key);
+                },
+            }
         }
 
         // Flush drawing and move cursor, Modified by shshi102
         gpu::draw_square(square_x, square_y, 0xFFFF0000);
         gpu::move_pointer(square_x + square_size / 2, square_y + square_size / 2);
         gpu::flush_display();
-    }
+        true
+    });
+    console::enable_echo();
+    console::disable_raw();
+    println!(
+        "{} frames, {} dropped, {:.2} ms per frame",
+        stats.frames, stats.dropped, stats.average_frame_time * 1000.0
+    );
 
     loop {
         if let Some(cmd) = option_env!("MOROS_CMD") {
@@ -185,9 +195,3 @@ fn user_boot() {
         usr::shell::main(&["shell"]).ok();
     }
 }
-
-#[panic_handler]
-fn panic(info: &PanicInfo) -> ! {
-    debug!("{}", info);
-    hlt_loop();
-}
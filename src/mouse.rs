@@ -0,0 +1,227 @@
+use spin::Mutex;
+use core::sync::atomic::{AtomicBool, AtomicI32, AtomicU8, Ordering};
+use lazy_static::lazy_static;
+use virtio_drivers::device::input::VirtIOInput;
+use virtio_drivers::transport::pci::{
+    PciTransport,
+    bus::{PciRoot, DeviceFunction},
+};
+use virtio_drivers::transport::{DeviceType, Transport};
+
+use crate::{debug, error, warning, eprint, eprintln};
+use crate::gpu::{self, MorosPciConfigAccess};
+use crate::sys::pci;
+use crate::hal;
+use crate::api::fs::{FileIO, IO};
+
+use alloc::format;
+
+lazy_static! {
+    static ref MOUSE_DRIVER: Mutex<Option<VirtIOInput<hal::MyKernelHal, PciTransport>>> = Mutex::new(None);
+}
+
+// Boolean whether the mouse has been successfully initialized.
+static MOUSE_INITIALIZED: AtomicBool = AtomicBool::new(false);
+
+// Pointer position in screen coordinates and pressed buttons
+static MOUSE_X: AtomicI32 = AtomicI32::new(0);
+static MOUSE_Y: AtomicI32 = AtomicI32::new(0);
+static MOUSE_BUTTONS: AtomicU8 = AtomicU8::new(0);
+
+// Linux evdev event types and codes used by virtio-input
+const EV_SYN: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const EV_REL: u16 = 0x02;
+const REL_X: u16 = 0x00;
+const REL_Y: u16 = 0x01;
+const BTN_LEFT: u16 = 0x110;
+const BTN_RIGHT: u16 = 0x111;
+const BTN_MIDDLE: u16 = 0x112;
+
+pub const LEFT_BUTTON: u8 = 1 << 0;
+pub const RIGHT_BUTTON: u8 = 1 << 1;
+pub const MIDDLE_BUTTON: u8 = 1 << 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MouseState {
+    pub x: u32,
+    pub y: u32,
+    pub buttons: u8,
+}
+
+impl MouseState {
+    pub fn is_left_pressed(&self) -> bool {
+        self.buttons & LEFT_BUTTON != 0
+    }
+
+    pub fn is_right_pressed(&self) -> bool {
+        self.buttons & RIGHT_BUTTON != 0
+    }
+
+    pub fn is_middle_pressed(&self) -> bool {
+        self.buttons & MIDDLE_BUTTON != 0
+    }
+}
+
+// Initializes VirtIO mouse driver
+// Find PCI device, sets up the transport and the input driver.
+pub fn init_and_setup_mouse() {
+    debug!("Searching for VirtIO mouse...");
+
+    // Find the VirtIO input device (Vendor ID: 0x1af4, Device ID: 0x1052).
+    let mouse_device_config = pci::find_device(0x1af4, 0x1052);
+
+    if let Some(mut device) = mouse_device_config {
+        debug!("Found VirtIO input at PCI BDF: {}:{}.{}", device.bus, device.device, device.function);
+        device.enable_bus_mastering();
+
+        let pci_config_access = MorosPciConfigAccess::new(
+            device.bus, device.device, device.function,
+        );
+        let mut pci_root = PciRoot::new(pci_config_access);
+        let device_function = DeviceFunction {
+            bus: device.bus, device: device.device, function: device.function,
+        };
+
+        match PciTransport::new::<hal::MyKernelHal, MorosPciConfigAccess>(
+            &mut pci_root,
+            device_function
+        ) {
+            Ok(transport) if transport.device_type() == DeviceType::Input => {
+                match VirtIOInput::<hal::MyKernelHal, PciTransport>::new(transport) {
+                    Ok(driver) => {
+                        debug!("VirtIO Mouse Driver Initialized");
+                        *MOUSE_DRIVER.lock() = Some(driver);
+
+                        // Start with the pointer at the center of the screen
+                        if let Some((w, h)) = gpu::get_resolution() {
+                            MOUSE_X.store((w / 2) as i32, Ordering::SeqCst);
+                            MOUSE_Y.store((h / 2) as i32, Ordering::SeqCst);
+                        }
+                        MOUSE_INITIALIZED.store(true, Ordering::Release);
+                    }
+                    Err(e) => error!("Failed to initialize VirtIO mouse driver: {:?}", e),
+                }
+            }
+            Ok(transport) => {
+                warning!("Found VirtIO PCI device, but it's not an input. Type: {:?}", transport.device_type());
+            }
+            Err(e) => error!("Failed to create PciTransport: {:?}", e),
+        }
+    } else {
+        warning!("No VirtIO mouse found.");
+    }
+}
+
+pub fn is_initialized() -> bool {
+    MOUSE_INITIALIZED.load(Ordering::Acquire)
+}
+
+// Processes the pending input events of the device and returns true if the
+// state of the mouse changed.
+// The position is clamped to the screen resolution when the GPU is available.
+pub fn poll() -> bool {
+    if !is_initialized() {
+        return false;
+    }
+    let (max_x, max_y) = match gpu::get_resolution() {
+        Some((w, h)) => (w as i32 - 1, h as i32 - 1),
+        None => (i32::MAX, i32::MAX),
+    };
+    let mut changed = false;
+    let mut driver_guard = MOUSE_DRIVER.lock();
+    if let Some(driver) = driver_guard.as_mut() {
+        driver.ack_interrupt();
+        while let Some(event) = driver.pop_pending_event() {
+            match (event.event_type, event.code) {
+                (EV_REL, REL_X) => {
+                    let x = MOUSE_X.load(Ordering::SeqCst);
+                    let x = x.saturating_add(event.value as i32).clamp(0, max_x);
+                    MOUSE_X.store(x, Ordering::SeqCst);
+                }
+                (EV_REL, REL_Y) => {
+                    let y = MOUSE_Y.load(Ordering::SeqCst);
+                    let y = y.saturating_add(event.value as i32).clamp(0, max_y);
+                    MOUSE_Y.store(y, Ordering::SeqCst);
+                }
+                (EV_KEY, BTN_LEFT | BTN_RIGHT | BTN_MIDDLE) => {
+                    let mask = match event.code {
+                        BTN_LEFT => LEFT_BUTTON,
+                        BTN_RIGHT => RIGHT_BUTTON,
+                        _ => MIDDLE_BUTTON,
+                    };
+                    if event.value != 0 {
+                        MOUSE_BUTTONS.fetch_or(mask, Ordering::SeqCst);
+                    } else {
+                        MOUSE_BUTTONS.fetch_and(!mask, Ordering::SeqCst);
+                    }
+                }
+                (EV_SYN, _) => {}
+                _ => continue,
+            }
+            changed = true;
+        }
+    }
+    changed
+}
+
+// Returns the current state of the mouse without processing pending events.
+pub fn state() -> MouseState {
+    MouseState {
+        x: MOUSE_X.load(Ordering::SeqCst) as u32,
+        y: MOUSE_Y.load(Ordering::SeqCst) as u32,
+        buttons: MOUSE_BUTTONS.load(Ordering::SeqCst),
+    }
+}
+
+// Moves the pointer and the hardware cursor to a new position.
+pub fn set_position(x: u32, y: u32) {
+    MOUSE_X.store(x as i32, Ordering::SeqCst);
+    MOUSE_Y.store(y as i32, Ordering::SeqCst);
+    gpu::move_pointer(x, y);
+}
+
+// Device file returning the state of the mouse as "<x> <y> <buttons>"
+#[derive(Debug, Clone, Default)]
+pub struct MouseDevice;
+
+impl MouseDevice {
+    pub fn new() -> Self {
+        Self
+    }
+
+    pub fn size() -> usize {
+        // Must be at least 10 + 1 + 10 + 1 + 3 bytes
+        32
+    }
+}
+
+impl FileIO for MouseDevice {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+        if !is_initialized() {
+            return Err(());
+        }
+        poll();
+        let state = state();
+        let text = format!("{} {} {}", state.x, state.y, state.buttons);
+        let n = text.len();
+        if buf.len() < n {
+            return Err(());
+        }
+        buf[0..n].copy_from_slice(text.as_bytes());
+        Ok(n)
+    }
+
+    fn write(&mut self, _buf: &[u8]) -> Result<usize, ()> {
+        Err(())
+    }
+
+    fn close(&mut self) {}
+
+    fn poll(&mut self, event: IO) -> bool {
+        match event {
+            IO::Read => is_initialized(),
+            IO::Write => false,
+        }
+    }
+}
@@ -1,6 +1,8 @@
 use crate::sys;
 
-use acpi::{AcpiHandler, AcpiTables, PhysicalMapping};
+use acpi::{
+    AcpiHandler, AcpiTables, HpetInfo, InterruptModel, PhysicalMapping,
+};
 use acpi::platform::{Processor, ProcessorState};
 use alloc::boxed::Box;
 use aml::value::AmlValue;
@@ -18,12 +20,28 @@ pub fn init() {
     match res {
         Ok(acpi) => {
             if let Ok(info) = acpi.platform_info() {
-                if let Some(info) = info.processor_info {
-                    log_cpu(&info.boot_processor);
-                    for processor in info.application_processors.iter() {
+                if let Some(processors) = &info.processor_info {
+                    log_cpu(&processors.boot_processor);
+                    for processor in processors.application_processors.iter() {
                         log_cpu(&processor);
                     }
                 }
+                if let InterruptModel::Apic(apic) = &info.interrupt_model {
+                    sys::lapic::init(apic.local_apic_address);
+                    sys::ioapic::init(
+                        &apic.io_apics,
+                        &apic.interrupt_source_overrides,
+                    );
+                    if let Some(processors) = &info.processor_info {
+                        sys::smp::init(
+                            &processors.boot_processor,
+                            &processors.application_processors,
+                        );
+                    }
+                }
+            }
+            if let Ok(hpet) = HpetInfo::new(&acpi) {
+                sys::clk::init_hpet(hpet.base_address as u64);
             }
             if let Ok(fadt) = acpi.find_table::<acpi::fadt::Fadt>() {
                 if let Ok(block) = fadt.pm1a_control_block() {
@@ -0,0 +1,175 @@
+use crate::api::fs;
+use crate::sys;
+use crate::KERNEL_SIZE;
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::arch::asm;
+use core::fmt;
+use core::ptr::addr_of;
+use spin::Once;
+use x86_64::structures::paging::Translate;
+use x86_64::VirtAddr;
+
+// Symbols of the kernel in the format of `nm --numeric-sort`, written by
+// `make image` at the end of the area of the disk reserved for the kernel,
+// before a last block giving their size, or copied to a file after a rebuild
+const SYMBOLS_SIGNATURE: &str = "MOROS SYM ";
+const SYMBOLS_PATH: &str = "/boot/kernel.sym";
+const MAX_FRAMES: usize = 32;
+
+static SYMBOLS: Once<Vec<(u64, String)>> = Once::new();
+
+// Defined by the linker at the end of the code
+extern "C" {
+    static etext: u8;
+}
+
+/// Loads the symbol table of the kernel if it is found on the disk.
+pub fn init() {
+    let contents = read_disk().or_else(|| {
+        fs::read_to_string(SYMBOLS_PATH).ok()
+    });
+    if let Some(contents) = contents {
+        let symbols = parse(&contents);
+        if symbols.is_empty() {
+            return;
+        }
+        log!("SYM {} kernel symbols loaded", symbols.len());
+        SYMBOLS.call_once(|| symbols);
+    }
+}
+
+fn read_disk() -> Option<String> {
+    let block_size = sys::fs::BLOCK_SIZE;
+    let addr = (KERNEL_SIZE / block_size - 1) as u32;
+    let buf = sys::fs::read_kernel_blocks(addr, 1)?;
+    let header = buf.split(|b| *b == b'\n').next()?;
+    let header = core::str::from_utf8(header).ok()?;
+    let size: usize = header.strip_prefix(SYMBOLS_SIGNATURE)?.parse().ok()?;
+
+    let count = size.div_ceil(block_size);
+    let start = addr.checked_sub(count as u32)?;
+    let mut contents = sys::fs::read_kernel_blocks(start, count)?;
+    contents.truncate(size);
+    String::from_utf8(contents).ok()
+}
+
+/// Prints the return addresses of the call stack of the caller, walking the
+/// chain of frame pointers.
+#[inline(never)]
+pub fn print_backtrace() {
+    let rbp: u64;
+    unsafe {
+        asm!("mov {}, rbp", out(reg) rbp);
+    }
+    print_frames(rbp);
+}
+
+/// Prints the address of the instruction that caused an exception and the
+/// call stack of the interrupted code. It must be called by the handler of
+/// the exception.
+#[inline(never)]
+pub fn print_exception(addr: u64) {
+    print(format_args!("Exception at {}\n", Location(addr, addr)));
+    let rbp: u64;
+    unsafe {
+        asm!("mov {}, rbp", out(reg) rbp);
+    }
+    // Skip the frames of this function and of the handler
+    if let Some(rbp) = read(rbp).and_then(read) {
+        print_frames(rbp);
+    }
+}
+
+// The saved frame pointer of the caller is followed by the return address
+// in each frame, and the stack grows down
+fn print_frames(mut rbp: u64) {
+    print(format_args!("Backtrace:\n"));
+    for _ in 0..MAX_FRAMES {
+        let (next, addr) = match (read(rbp), read(rbp + 8)) {
+            (Some(next), Some(addr)) if addr != 0 => (next, addr),
+            _ => break,
+        };
+        // The return address is after the call instruction, which could be
+        // the last instruction of the calling function
+        print(format_args!("  {}\n", Location(addr, addr - 1)));
+        if next <= rbp {
+            break;
+        }
+        rbp = next;
+    }
+}
+
+// Read a word of the stack without faulting on a corrupted frame pointer
+fn read(addr: u64) -> Option<u64> {
+    if addr == 0 || addr % 8 != 0 {
+        return None;
+    }
+    let virt_addr = VirtAddr::try_new(addr).ok()?;
+    sys::mem::mapper().translate_addr(virt_addr)?;
+    Some(unsafe { core::ptr::read_volatile(addr as *const u64) })
+}
+
+fn print(args: fmt::Arguments) {
+    sys::console::print_fmt(args);
+    if cfg!(feature = "video") {
+        sys::serial::print_fmt(args);
+    }
+}
+
+// An address with the function found by looking up another address
+struct Location(u64, u64);
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#018X}", self.0)?;
+        if let Some((name, addr)) = resolve(self.1) {
+            write!(f, " {}+{:#X}", name, self.0 - addr)?;
+        }
+        Ok(())
+    }
+}
+
+// Return the name and the address of the function containing the address,
+// which must be in the code of the kernel and not after its last function
+fn resolve(addr: u64) -> Option<(&'static str, u64)> {
+    let end = addr_of!(etext) as u64;
+    if addr >= end {
+        return None;
+    }
+    let symbols = SYMBOLS.get()?;
+    let i = symbols.partition_point(|(a, _)| *a <= addr).checked_sub(1)?;
+    let (a, name) = &symbols[i];
+    Some((name, *a))
+}
+
+// Keep the text symbols sorted by address
+fn parse(contents: &str) -> Vec<(u64, String)> {
+    let mut res: Vec<(u64, String)> = contents.lines().filter_map(|line| {
+        let mut fields = line.splitn(3, ' ');
+        let addr = u64::from_str_radix(fields.next()?, 16).ok()?;
+        let kind = fields.next()?;
+        let name = fields.next()?;
+        if kind.eq_ignore_ascii_case("t") {
+            Some((addr, name.into()))
+        } else {
+            None
+        }
+    }).collect();
+    res.sort_by_key(|(addr, _)| *addr);
+    res
+}
+
+#[test_case]
+fn test_parse() {
+    let contents = "\
+        0000000000201000 T _start\n\
+        0000000000200000 t moros::init\n\
+        0000000000300000 R moros::VERSION\n\
+    ";
+    let symbols = parse(contents);
+    assert_eq!(symbols.len(), 2);
+    assert_eq!(symbols[0], (0x200000, "moros::init".into()));
+    assert_eq!(symbols[1], (0x201000, "_start".into()));
+}
@@ -1,3 +1,4 @@
+use super::clocksource;
 use super::timer;
 
 use crate::api::fs::{FileIO, IO};
@@ -46,9 +47,12 @@ impl FileIO for BootTime {
 
 /// Returns the number of seconds since boot.
 ///
-/// This clock is monotonic.
+/// This clock is monotonic, with the resolution of the clocksource if there
+/// is one or of the timer interrupt otherwise.
 pub fn boot_time() -> f64 {
-    timer::time_between_ticks() * timer::ticks() as f64
+    clocksource::uptime().unwrap_or_else(||
+        timer::time_between_ticks() * timer::ticks() as f64
+    )
 }
 
 #[test_case]
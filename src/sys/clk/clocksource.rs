@@ -0,0 +1,116 @@
+use super::hpet;
+use super::timer;
+
+use crate::sys::smp::IrqMutex;
+
+use raw_cpuid::CpuId;
+
+// Calibration time of the TSC against the HPET
+const CALIBRATION_TIME: u64 = 10_000_000; // 10 ms
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Counter {
+    Tsc,
+    Hpet,
+}
+
+#[derive(Clone, Copy)]
+struct Clocksource {
+    counter: Counter,
+    frequency: u64,
+    counter_base: u64,
+    time_base: f64,
+}
+
+static CLOCKSOURCE: IrqMutex<Option<Clocksource>> = IrqMutex::new(None);
+
+/// Selects the invariant TSC as the clocksource when it is available.
+///
+/// Its frequency is calibrated against the PIT at this point, and against
+/// the HPET later when it is found by ACPI.
+pub fn init() {
+    if has_invariant_tsc() {
+        let frequency = timer::tsc_frequency() * 1_000_000;
+        select(Counter::Tsc, frequency);
+    }
+}
+
+/// Calibrates the TSC against the HPET and uses one of them as the
+/// clocksource instead of the PIT.
+pub fn init_hpet(phys_addr: u64) {
+    if hpet::init(phys_addr).is_err() {
+        return;
+    }
+    if has_invariant_tsc() {
+        let a = (hpet::nanoseconds(), timer::tsc());
+        while hpet::nanoseconds() - a.0 < CALIBRATION_TIME {
+            core::hint::spin_loop();
+        }
+        let b = (hpet::nanoseconds(), timer::tsc());
+        let frequency = (b.1 - a.1) * 1_000_000_000 / (b.0 - a.0);
+        timer::set_tsc_frequency(frequency);
+        select(Counter::Tsc, frequency);
+    } else {
+        select(Counter::Hpet, hpet::frequency());
+    }
+}
+
+/// Returns the counter used as the clocksource.
+pub fn counter() -> Option<Counter> {
+    CLOCKSOURCE.lock().map(|clocksource| clocksource.counter)
+}
+
+/// Returns the number of seconds since boot, with the resolution of the
+/// clocksource, or `None` if only the PIT is available.
+pub fn uptime() -> Option<f64> {
+    let clocksource = (*CLOCKSOURCE.lock())?;
+    let n = read(clocksource.counter).saturating_sub(clocksource.counter_base);
+    Some(clocksource.time_base + n as f64 / clocksource.frequency as f64)
+}
+
+// Start counting from the current time to keep the clock monotonic
+fn select(counter: Counter, frequency: u64) {
+    if frequency == 0 {
+        return;
+    }
+    let mut clocksource = CLOCKSOURCE.lock();
+    let time_base = match *clocksource {
+        Some(c) => {
+            let n = read(c.counter).saturating_sub(c.counter_base);
+            c.time_base + n as f64 / c.frequency as f64
+        }
+        None => timer::time_between_ticks() * timer::ticks() as f64,
+    };
+    let counter_base = read(counter);
+    *clocksource = Some(Clocksource {
+        counter,
+        frequency,
+        counter_base,
+        time_base,
+    });
+    drop(clocksource); // The log uses the clocksource
+    let name = match counter {
+        Counter::Tsc => "TSC",
+        Counter::Hpet => "HPET",
+    };
+    log!("CLK {} {:.3} MHz", name, frequency as f64 / 1e6);
+}
+
+fn read(counter: Counter) -> u64 {
+    match counter {
+        Counter::Tsc => timer::tsc(),
+        Counter::Hpet => hpet::counter(),
+    }
+}
+
+fn has_invariant_tsc() -> bool {
+    CpuId::new().get_advanced_power_mgmt_info().
+        is_some_and(|info| info.has_invariant_tsc())
+}
+
+#[test_case]
+fn test_uptime() {
+    if let Some(a) = uptime() {
+        assert!(a <= uptime().unwrap());
+    }
+}
@@ -0,0 +1,166 @@
+use super::boot;
+use super::sync;
+use super::timer;
+
+/// Returns the number of seconds since boot with the precision of the TSC,
+/// or of the PIT until the TSC has been calibrated.
+fn now() -> f64 {
+    let frequency = timer::tsc_frequency(); // Cycles per microsecond
+    if frequency > 0 {
+        timer::tsc() as f64 / (frequency as f64 * 1e6)
+    } else {
+        boot::boot_time()
+    }
+}
+
+/// Information given to the render callback of a frame.
+#[derive(Debug, Clone, Copy)]
+pub struct Frame {
+    /// Number of the frame, starting from 0
+    pub number: usize,
+    /// Seconds elapsed since the previous frame
+    pub delta: f64,
+    /// Seconds elapsed since the first frame
+    pub time: f64,
+}
+
+/// Statistics of a frame scheduler.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FrameStats {
+    /// Number of frames rendered
+    pub frames: usize,
+    /// Number of frames skipped because the previous ones took too long
+    pub dropped: usize,
+    /// Average number of seconds between two frames
+    pub average_frame_time: f64,
+    /// Average number of seconds spent in the render callback
+    pub average_render_time: f64,
+}
+
+/// Schedules frames at a steady rate.
+///
+/// Frames that could not be rendered on time are dropped instead of being
+/// rendered late in a burst, so that an animation keeps the same speed when
+/// the system is too slow for the target frame rate.
+pub struct FrameScheduler {
+    interval: f64,
+    next: f64,
+    first: f64,
+    last: f64,
+    frames: usize,
+    dropped: usize,
+    render_time: f64,
+}
+
+impl FrameScheduler {
+    /// Creates a scheduler for the target number of frames per second, the
+    /// first frame being due immediately.
+    pub fn new(fps: f64) -> Self {
+        let t = now();
+        Self {
+            interval: 1.0 / fps,
+            next: t,
+            first: t,
+            last: t,
+            frames: 0,
+            dropped: 0,
+            render_time: 0.0,
+        }
+    }
+
+    /// Returns the next frame if it is due without waiting.
+    ///
+    /// This can be called from a loop that does something else between the
+    /// frames.
+    pub fn poll(&mut self) -> Option<Frame> {
+        let t = now();
+        if t < self.next {
+            return None;
+        }
+        let missed = ((t - self.next) / self.interval) as usize;
+        self.dropped += missed;
+        self.next += (missed + 1) as f64 * self.interval;
+        if self.frames == 0 {
+            self.first = t;
+            self.last = t;
+        }
+        let frame = Frame {
+            number: self.frames,
+            delta: t - self.last,
+            time: t - self.first,
+        };
+        self.frames += 1;
+        self.last = t;
+        Some(frame)
+    }
+
+    /// Waits for the next frame.
+    ///
+    /// The CPU is halted between timer interrupts, so keyboard and mouse
+    /// interrupts are still handled while waiting.
+    pub fn wait(&mut self) -> Frame {
+        loop {
+            if let Some(frame) = self.poll() {
+                return frame;
+            }
+            sync::block();
+        }
+    }
+
+    /// Calls the render callback for each frame until it returns false, then
+    /// returns the statistics of the scheduler.
+    pub fn run<F>(&mut self, mut render: F) -> FrameStats
+    where
+        F: FnMut(&Frame) -> bool,
+    {
+        loop {
+            let frame = self.wait();
+            let started_at = now();
+            let is_running = render(&frame);
+            self.render_time += now() - started_at;
+            if !is_running {
+                break;
+            }
+        }
+        self.stats()
+    }
+
+    pub fn stats(&self) -> FrameStats {
+        let average_frame_time = if self.frames > 1 {
+            (self.last - self.first) / (self.frames - 1) as f64
+        } else {
+            0.0
+        };
+        let average_render_time = if self.frames > 0 {
+            self.render_time / self.frames as f64
+        } else {
+            0.0
+        };
+        FrameStats {
+            frames: self.frames,
+            dropped: self.dropped,
+            average_frame_time,
+            average_render_time,
+        }
+    }
+}
+
+#[test_case]
+fn test_frame_scheduler() {
+    let fps = 100.0;
+    let mut scheduler = FrameScheduler::new(fps);
+    let stats = scheduler.run(|frame| frame.number < 4);
+    assert_eq!(stats.frames, 5);
+    assert!(stats.average_frame_time > 0.5 / fps);
+
+    // A slow frame drops the frames that should have been rendered during it
+    let mut scheduler = FrameScheduler::new(fps);
+    let stats = scheduler.run(|frame| {
+        if frame.number == 0 {
+            sync::sleep(5.0 / fps);
+        }
+        frame.number < 1
+    });
+    assert_eq!(stats.frames, 2);
+    assert!(stats.dropped >= 3);
+}
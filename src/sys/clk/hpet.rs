@@ -0,0 +1,83 @@
+use crate::sys;
+
+use core::sync::atomic::{AtomicU64, Ordering};
+use x86_64::{PhysAddr, VirtAddr};
+
+// Registers of the High Precision Event Timer
+const CAPABILITIES: u64 = 0x00;
+const CONFIGURATION: u64 = 0x10;
+const MAIN_COUNTER: u64 = 0xF0;
+
+const COUNTER_64_BITS: u64 = 1 << 13;
+const ENABLE: u64 = 1;
+
+// The period of the counter must be at most 100 ns
+const MAX_PERIOD: u64 = 100_000_000; // Femtoseconds
+
+// The registers are mapped after the registers of the I/O APIC
+const VIRT_ADDR: u64 = 0xFFFF_FF00_8000_2000;
+
+static ADDR: AtomicU64 = AtomicU64::new(0);
+static PERIOD: AtomicU64 = AtomicU64::new(0);
+
+/// Enables the main counter of the HPET found at the given address.
+pub fn init(phys_addr: u64) -> Result<(), ()> {
+    let res = unsafe {
+        sys::mem::map_contiguous_physical_region(
+            sys::mem::mapper(),
+            PhysAddr::new(phys_addr),
+            VirtAddr::new(VIRT_ADDR),
+            4096,
+        )
+    };
+    if res.is_err() {
+        debug!("HPET: Could not map registers at {:#X}", phys_addr);
+        return Err(());
+    }
+    let capabilities = read(VIRT_ADDR, CAPABILITIES);
+    let period = capabilities >> 32;
+    if period == 0 || period > MAX_PERIOD {
+        return Err(());
+    }
+    // A 32-bit counter would overflow every few minutes
+    if capabilities & COUNTER_64_BITS == 0 {
+        return Err(());
+    }
+    let config = read(VIRT_ADDR, CONFIGURATION);
+    write(VIRT_ADDR, CONFIGURATION, config | ENABLE);
+
+    PERIOD.store(period, Ordering::SeqCst);
+    ADDR.store(VIRT_ADDR, Ordering::SeqCst);
+    log!("HPET {:.3} MHz", frequency() as f64 / 1e6);
+    Ok(())
+}
+
+/// Returns the frequency of the main counter in hertz.
+pub fn frequency() -> u64 {
+    match PERIOD.load(Ordering::SeqCst) {
+        0 => 0,
+        period => 1_000_000_000_000_000 / period,
+    }
+}
+
+/// Returns the value of the main counter.
+pub fn counter() -> u64 {
+    match ADDR.load(Ordering::SeqCst) {
+        0 => 0,
+        addr => read(addr, MAIN_COUNTER),
+    }
+}
+
+/// Returns the number of nanoseconds since the counter was enabled.
+pub fn nanoseconds() -> u64 {
+    let period = PERIOD.load(Ordering::SeqCst) as u128;
+    (counter() as u128 * period / 1_000_000) as u64
+}
+
+fn read(addr: u64, reg: u64) -> u64 {
+    unsafe { core::ptr::read_volatile((addr + reg) as *const u64) }
+}
+
+fn write(addr: u64, reg: u64, value: u64) {
+    unsafe { core::ptr::write_volatile((addr + reg) as *mut u64, value) }
+}
@@ -1,15 +1,24 @@
 mod cmos;
 mod boot;
+mod clocksource;
 mod epoch;
+mod frame;
+mod hpet;
 mod rtc;
 mod sync;
 mod timer;
+mod wheel;
 
 pub use boot::{boot_time, BootTime};
+pub use clocksource::{init_hpet, Counter};
 pub use epoch::{epoch_time, EpochTime};
+pub use frame::{Frame, FrameScheduler, FrameStats};
 pub use rtc::RTC;
-pub use sync::{halt, sleep, wait};
-pub use timer::{ticks, pit_frequency, set_pit_frequency};
+pub use sync::{block, halt, sleep, wait};
+pub use timer::{
+    ticks, cpu_ticks, pit_frequency, set_speaker_frequency, preempt
+};
+pub use wheel::{add_timer, cancel_timer, is_pending, TimerHandle};
 
 use crate::api;
 
@@ -18,6 +27,12 @@ use time::{Duration, OffsetDateTime};
 
 pub fn init() {
     timer::init();
+    clocksource::init();
+}
+
+/// Returns the counter used as the clocksource, if any.
+pub fn clocksource() -> Option<Counter> {
+    clocksource::counter()
 }
 
 /// Returns the current date and time.
@@ -1,8 +1,19 @@
 use super::boot;
+use super::clocksource;
 use super::timer;
+use super::wheel;
 
+use crate::sys;
+use crate::sys::process::ProcessState;
+use crate::sys::smp::MAX_CPUS;
+
+use core::sync::atomic::{AtomicBool, Ordering};
 use x86_64::instructions::interrupts;
 
+static IDLE: [AtomicBool; MAX_CPUS] = [
+    const { AtomicBool::new(false) }; MAX_CPUS
+];
+
 /// Halts the CPU until the next interrupt.
 ///
 /// This function preserves interrupt state.
@@ -14,14 +25,74 @@ pub fn halt() {
     }
 }
 
+/// Halts the CPU until the next interrupt at a blocking point where the
+/// scheduler can switch to another process.
+///
+/// The caller must not hold any lock because another process could then
+/// spin on it in the kernel without ever being preempted.
+///
+/// The process could be resumed on another CPU after the halt.
+pub fn block() {
+    IDLE[sys::smp::cpu_id()].store(true, Ordering::Relaxed);
+    halt();
+    IDLE[sys::smp::cpu_id()].store(false, Ordering::Relaxed);
+}
+
+/// Returns true if the current CPU was halted by the kernel at a blocking
+/// point, where it does not hold any lock.
+pub fn is_idle() -> bool {
+    IDLE[sys::smp::cpu_id()].load(Ordering::Relaxed)
+}
+
+// The process switched to by the scheduler is not at the blocking point of
+// the previous one, it could resume in userspace
+pub(super) fn clear_idle() {
+    IDLE[sys::smp::cpu_id()].store(false, Ordering::Relaxed);
+}
+
 /// Sleeps for the specified number of seconds.
 ///
-/// This function works by repeatedly halting the CPU until the time is
-/// elapsed.
+/// This function blocks the current process until a timer expires on the
+/// last tick before the deadline, and then waits for the deadline with the
+/// resolution of the clocksource.
 pub fn sleep(seconds: f64) {
-    let start = boot::boot_time();
-    while boot::boot_time() - start < seconds {
-        halt();
+    let deadline = boot::boot_time() + seconds;
+    let interval = timer::time_between_ticks();
+    if seconds > 2.0 * interval {
+        let pid = sys::process::id();
+        let delay = seconds - interval;
+        if let Ok(handle) = wheel::add_timer(delay, None, wake, pid) {
+            loop {
+                // The timer could expire before the process is blocked
+                sys::process::set_state(ProcessState::Blocked);
+                if !wheel::is_pending(handle) {
+                    sys::process::set_state(ProcessState::Running);
+                    break;
+                }
+                block();
+            }
+        }
+    }
+    halt_until(deadline);
+}
+
+// Wait for the deadline without blocking the current process
+pub(super) fn halt_until(deadline: f64) {
+    while boot::boot_time() < deadline {
+        if clocksource::counter().is_some() {
+            core::hint::spin_loop();
+        } else {
+            block();
+        }
+    }
+}
+
+// Unblock the process or try again on the next tick if the process table is
+// locked by the interrupted code
+fn wake(pid: usize) {
+    if sys::process::wake(pid).is_err() {
+        let interval = timer::time_between_ticks();
+        wheel::add_timer(interval, None, wake, pid).ok();
     }
 }
 
@@ -1,11 +1,16 @@
 use super::sync;
 use super::cmos::CMOS;
+use super::wheel;
 
 use crate::sys;
+use crate::sys::process::Registers;
+use crate::sys::smp::MAX_CPUS;
 
 use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use x86_64::instructions::interrupts;
 use x86_64::instructions::port::Port;
+use x86_64::structures::idt::InterruptStackFrameValue;
+use x86_64::PrivilegeLevel;
 
 // At boot the PIT starts with a frequency divider of 0 (equivalent to 65536)
 // which will result in about 54.926 ms between ticks.
@@ -15,7 +20,14 @@ const PIT_FREQUENCY: f64 = 3_579_545.0 / 3.0; // 1_193_181.666 Hz
 const PIT_DIVIDER: u16 = 1193;
 const PIT_INTERVAL: f64 = (PIT_DIVIDER as f64) / PIT_FREQUENCY;
 
+// Number of ticks a process can run before the scheduler gives the CPU to the
+// next ready process.
+const QUANTUM: usize = 10;
+
 static PIT_TICKS: AtomicUsize = AtomicUsize::new(0);
+static CPU_TICKS: [AtomicUsize; MAX_CPUS] = [
+    const { AtomicUsize::new(0) }; MAX_CPUS
+];
 static LAST_RTC_UPDATE: AtomicUsize = AtomicUsize::new(0);
 static TSC_FREQUENCY: AtomicU64 = AtomicU64::new(0);
 
@@ -23,6 +35,12 @@ pub fn ticks() -> usize {
     PIT_TICKS.load(Ordering::Relaxed)
 }
 
+// Number of ticks of the timer of a CPU, which are counted by the scheduler
+// of the CPU
+pub fn cpu_ticks(id: usize) -> usize {
+    CPU_TICKS.get(id).map_or(0, |ticks| ticks.load(Ordering::Relaxed))
+}
+
 pub fn time_between_ticks() -> f64 {
     PIT_INTERVAL
 }
@@ -36,7 +54,7 @@ pub fn pit_frequency() -> f64 {
 }
 
 // The frequency divider must be between 0 and 65535, with 0 acting as 65536
-pub fn set_pit_frequency(divider: u16, channel: u8) {
+fn set_pit_frequency(divider: u16, channel: u8) {
     interrupts::without_interrupts(|| {
         let bytes = divider.to_le_bytes();
         let mut cmd: Port<u8> = Port::new(0x43);
@@ -51,6 +69,14 @@ pub fn set_pit_frequency(divider: u16, channel: u8) {
     });
 }
 
+// The channel 0 of the PIT is only set during init because the timer of the
+// Local APIC is calibrated on its interval, so the other devices can only
+// use the channel 2 connected to the PC speaker
+pub fn set_speaker_frequency(divider: u16) {
+    let channel = 2;
+    set_pit_frequency(divider, channel);
+}
+
 // Time Stamp Counter
 pub fn tsc() -> u64 {
     unsafe {
@@ -59,12 +85,42 @@ pub fn tsc() -> u64 {
     }
 }
 
+// Number of cycles per microsecond
 pub fn tsc_frequency() -> u64 {
     TSC_FREQUENCY.load(Ordering::Relaxed)
 }
 
+pub fn set_tsc_frequency(hertz: u64) {
+    TSC_FREQUENCY.store(hertz / 1_000_000, Ordering::Relaxed);
+}
+
 pub fn pit_interrupt_handler() {
-    PIT_TICKS.fetch_add(1, Ordering::Relaxed);
+    let ticks = PIT_TICKS.fetch_add(1, Ordering::Relaxed) + 1;
+    wheel::run_timers(ticks);
+}
+
+// Called by the PIT interrupt handler with the context of the interrupted code
+// to return the context of the next process when the quantum of the current
+// one has expired. The kernel can only be preempted at a blocking point.
+pub fn preempt(
+    stack_frame: InterruptStackFrameValue,
+    regs: Registers
+) -> Option<(InterruptStackFrameValue, Registers)> {
+    // A blocked process gives the CPU back without waiting for the end of
+    // its quantum
+    let ticks = CPU_TICKS[sys::smp::cpu_id()].fetch_add(1, Ordering::Relaxed);
+    if (ticks + 1) % QUANTUM != 0 && !sys::process::is_blocked() {
+        return None;
+    }
+    let is_userspace = stack_frame.code_segment.rpl() == PrivilegeLevel::Ring3;
+    if !is_userspace && !sync::is_idle() {
+        return None;
+    }
+    let next = sys::process::schedule(stack_frame, regs);
+    if next.is_some() {
+        sync::clear_idle();
+    }
+    next
 }
 
 pub fn rtc_interrupt_handler() {
@@ -85,8 +141,10 @@ pub fn init() {
 
     // TSC timmer
     let calibration_time = 250_000; // 0.25 seconds
+    let deadline = super::boot_time() + calibration_time as f64 / 1e6;
     let a = tsc();
-    sync::sleep(calibration_time as f64 / 1e6);
+    // The process table can't be used to sleep before the heap is ready
+    sync::halt_until(deadline);
     let b = tsc();
     TSC_FREQUENCY.store((b - a) / calibration_time, Ordering::Relaxed);
 }
@@ -0,0 +1,227 @@
+use super::timer;
+
+use crate::sys::smp::IrqMutex;
+
+// The timers are kept in a fixed pool because they are run by the timer
+// interrupt, where allocating memory could deadlock with the interrupted
+// code. They are linked in the slot of the wheel corresponding to the tick of
+// their expiration modulo the size of the wheel.
+const WHEEL_SIZE: usize = 256;
+const MAX_TIMERS: usize = 256;
+
+#[derive(Clone, Copy)]
+struct Timer {
+    expires: usize,
+    period: usize,
+    callback: fn(usize),
+    arg: usize,
+    generation: u32,
+    is_active: bool,
+    next: Option<usize>,
+}
+
+impl Timer {
+    const fn new() -> Self {
+        Self {
+            expires: 0,
+            period: 0,
+            callback: |_| {},
+            arg: 0,
+            generation: 0,
+            is_active: false,
+            next: None,
+        }
+    }
+}
+
+/// Handle returned by `add_timer` to cancel a timer.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimerHandle {
+    index: usize,
+    generation: u32,
+}
+
+struct Wheel {
+    slots: [Option<usize>; WHEEL_SIZE],
+    timers: [Timer; MAX_TIMERS],
+    last_tick: usize,
+}
+
+impl Wheel {
+    const fn new() -> Self {
+        Self {
+            slots: [None; WHEEL_SIZE],
+            timers: [Timer::new(); MAX_TIMERS],
+            last_tick: 0,
+        }
+    }
+
+    fn link(&mut self, index: usize) {
+        let slot = self.timers[index].expires % WHEEL_SIZE;
+        self.timers[index].next = self.slots[slot];
+        self.slots[slot] = Some(index);
+    }
+
+    fn unlink(&mut self, index: usize) {
+        let slot = self.timers[index].expires % WHEEL_SIZE;
+        let next = self.timers[index].next;
+        if self.slots[slot] == Some(index) {
+            self.slots[slot] = next;
+            return;
+        }
+        let mut cur = self.slots[slot];
+        while let Some(i) = cur {
+            if self.timers[i].next == Some(index) {
+                self.timers[i].next = next;
+                return;
+            }
+            cur = self.timers[i].next;
+        }
+    }
+
+    fn is_valid(&self, handle: TimerHandle) -> bool {
+        let timer = &self.timers[handle.index];
+        timer.is_active && timer.generation == handle.generation
+    }
+
+    fn deactivate(&mut self, index: usize) {
+        let timer = &mut self.timers[index];
+        timer.is_active = false;
+        timer.generation = timer.generation.wrapping_add(1);
+    }
+
+    // Remove the next timer expired at the given tick from the wheel,
+    // adding it back if it is periodic, and return its callback
+    fn pop_expired(&mut self, now: usize) -> Option<(fn(usize), usize)> {
+        while self.last_tick < now {
+            let tick = self.last_tick + 1;
+            let mut cur = self.slots[tick % WHEEL_SIZE];
+            while let Some(i) = cur {
+                let timer = self.timers[i];
+                if timer.expires <= now {
+                    self.unlink(i);
+                    if timer.period > 0 {
+                        let expires = timer.expires + timer.period;
+                        self.timers[i].expires = expires.max(now + 1);
+                        self.link(i);
+                    } else {
+                        self.deactivate(i);
+                    }
+                    return Some((timer.callback, timer.arg));
+                }
+                cur = timer.next;
+            }
+            self.last_tick = tick;
+        }
+        None
+    }
+}
+
+static WHEEL: IrqMutex<Wheel> = IrqMutex::new(Wheel::new());
+
+/// Calls `callback` with `arg` after `delay` seconds, and then every
+/// `period` seconds if it is given, until the timer is cancelled.
+///
+/// The callbacks are called by the timer interrupt on the BSP so they should
+/// be short and must not block.
+pub fn add_timer(
+    delay: f64,
+    period: Option<f64>,
+    callback: fn(usize),
+    arg: usize,
+) -> Result<TimerHandle, ()> {
+    let delay = to_ticks(delay);
+    let period = period.map_or(0, to_ticks);
+    let mut wheel = WHEEL.lock();
+    let index = wheel.timers.iter().position(|t| !t.is_active).ok_or(())?;
+    let expires = wheel.last_tick.max(timer::ticks()) + delay;
+    let timer = &mut wheel.timers[index];
+    timer.expires = expires;
+    timer.period = period;
+    timer.callback = callback;
+    timer.arg = arg;
+    timer.is_active = true;
+    let generation = timer.generation;
+    wheel.link(index);
+    Ok(TimerHandle { index, generation })
+}
+
+/// Cancels a timer, returning false if it has already expired.
+pub fn cancel_timer(handle: TimerHandle) -> bool {
+    let mut wheel = WHEEL.lock();
+    if !wheel.is_valid(handle) {
+        return false;
+    }
+    wheel.unlink(handle.index);
+    wheel.deactivate(handle.index);
+    true
+}
+
+/// Returns true if the timer has not expired and has not been cancelled.
+pub fn is_pending(handle: TimerHandle) -> bool {
+    WHEEL.lock().is_valid(handle)
+}
+
+// Called by the timer interrupt handler on each tick
+pub fn run_timers(now: usize) {
+    loop {
+        // The wheel is unlocked before calling the callback of each timer
+        let expired = WHEEL.lock().pop_expired(now);
+        match expired {
+            Some((callback, arg)) => callback(arg),
+            None => break,
+        }
+    }
+}
+
+// Round up to the next tick
+fn to_ticks(seconds: f64) -> usize {
+    let ticks = libm::ceil(seconds / timer::time_between_ticks()) as usize;
+    ticks.max(1)
+}
+
+#[test_case]
+fn test_wheel() {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    fn count(n: usize) {
+        COUNT.fetch_add(n, Ordering::SeqCst);
+    }
+
+    let mut wheel = Wheel::new();
+    let mut add = |expires, period| {
+        let i = wheel.timers.iter().position(|t| !t.is_active).unwrap();
+        wheel.timers[i] = Timer {
+            expires, period, callback: count, arg: 1, is_active: true,
+            ..Timer::new()
+        };
+        wheel.link(i);
+        i
+    };
+    let a = add(3, 0);
+    let b = add(3 + WHEEL_SIZE, 0);
+    let c = add(2, 5);
+    let handle = TimerHandle { index: b, generation: 0 };
+    assert!(wheel.is_valid(handle));
+
+    let run = |wheel: &mut Wheel, now| {
+        while let Some((callback, arg)) = wheel.pop_expired(now) {
+            callback(arg);
+        }
+    };
+    run(&mut wheel, 1);
+    assert_eq!(COUNT.load(Ordering::SeqCst), 0);
+    run(&mut wheel, 3);
+    assert_eq!(COUNT.load(Ordering::SeqCst), 2); // a and c
+    assert!(!wheel.timers[a].is_active);
+    assert_eq!(wheel.timers[c].expires, 7);
+    run(&mut wheel, 10);
+    assert_eq!(COUNT.load(Ordering::SeqCst), 3); // c
+    assert!(wheel.is_valid(handle));
+    wheel.unlink(b);
+    wheel.deactivate(b);
+    assert!(!wheel.is_valid(handle));
+    run(&mut wheel, 3 + WHEEL_SIZE);
+    assert_eq!(COUNT.load(Ordering::SeqCst), 3 + 50); // c every 5 ticks
+}
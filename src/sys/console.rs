@@ -1,21 +1,18 @@
+pub use crate::api::console::{BS_KEY, EOT_KEY, ESC_KEY, ETX_KEY};
+
 use crate::api::fs::{FileIO, IO};
 use crate::sys;
+use crate::sys::smp::IrqMutex;
 use alloc::string::String;
 use alloc::string::ToString;
 use core::fmt;
 use core::sync::atomic::{AtomicBool, Ordering};
-use spin::Mutex;
 use x86_64::instructions::interrupts;
 
-pub static STDIN: Mutex<String> = Mutex::new(String::new());
+pub static STDIN: IrqMutex<String> = IrqMutex::new(String::new());
 pub static ECHO: AtomicBool = AtomicBool::new(true);
 pub static RAW: AtomicBool = AtomicBool::new(false);
 
-pub const BS_KEY: char = '\x08'; // Backspace
-pub const EOT_KEY: char = '\x04'; // End of Transmission
-pub const ESC_KEY: char = '\x1B'; // Escape
-pub const ETX_KEY: char = '\x03'; // End of Text
-
 #[derive(Debug, Clone)]
 pub struct Console;
 
@@ -51,9 +48,18 @@ impl FileIO for Console {
 
     fn close(&mut self) {}
 
+    // A line is available when it has been entered, but a program that has
+    // disabled the echo reads the keys one by one as soon as they are typed
     fn poll(&mut self, event: IO) -> bool {
         match event {
-            IO::Read => STDIN.lock().contains('\n'),
+            IO::Read => {
+                let stdin = STDIN.lock();
+                if is_echo_enabled() {
+                    stdin.contains('\n')
+                } else {
+                    !stdin.is_empty()
+                }
+            }
             IO::Write => true,
         }
     }
@@ -107,6 +113,11 @@ pub fn key_handle(key: char) {
                 print_fmt(format_args!("{}", BS_KEY.to_string().repeat(n)));
             }
         }
+    } else if key == ETX_KEY && !is_raw_enabled() && sys::process::interrupt() {
+        // Ctrl-C interrupts the foreground process instead of being read
+        if is_echo_enabled() {
+            print_fmt(format_args!("^C"));
+        }
     } else {
         let key = if (key as u32) < 0xFF {
             (key as u8) as char
@@ -141,16 +152,8 @@ pub fn read_char() -> char {
     sys::console::disable_echo();
     sys::console::enable_raw();
     loop {
-        sys::clk::halt();
-        let res = interrupts::without_interrupts(|| {
-            let mut stdin = STDIN.lock();
-            if !stdin.is_empty() {
-                Some(stdin.remove(0))
-            } else {
-                None
-            }
-        });
-        if let Some(c) = res {
+        sys::clk::block();
+        if let Some(c) = try_read_char() {
             sys::console::enable_echo();
             sys::console::disable_raw();
             return c;
@@ -158,9 +161,25 @@ pub fn read_char() -> char {
     }
 }
 
+// Non-blocking version of `read_char` that leaves echo and raw mode as they
+// are, the caller is expected to manage them.
+pub fn try_read_char() -> Option<char> {
+    interrupts::without_interrupts(|| {
+        let mut stdin = STDIN.lock();
+        if !stdin.is_empty() {
+            Some(stdin.remove(0))
+        } else {
+            None
+        }
+    })
+}
+
 pub fn read_line() -> String {
     loop {
-        sys::clk::halt();
+        sys::clk::block();
+        if sys::process::has_signal() {
+            return String::new();
+        }
         let res = interrupts::without_interrupts(|| {
             let mut stdin = STDIN.lock();
             match stdin.chars().next_back() {
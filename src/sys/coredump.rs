@@ -0,0 +1,225 @@
+use crate::sys;
+use crate::sys::fs::{File, FileIO};
+use crate::sys::process::Registers;
+
+use alloc::format;
+use alloc::vec::Vec;
+use x86_64::registers::control::Cr3;
+use x86_64::structures::idt::InterruptStackFrameValue;
+use x86_64::structures::paging::{OffsetPageTable, Translate};
+use x86_64::VirtAddr;
+
+// The notes use the layout of the structs of Linux to be readable by the
+// tools of the host like `readelf --notes` or `gdb -c`
+pub const NT_PRSTATUS: u32 = 1;
+pub const NT_PRPSINFO: u32 = 3;
+pub const NT_SIGINFO: u32 = 0x53494749;
+
+pub const PRSTATUS_SIZE: usize = 336;
+pub const PRSTATUS_REG: usize = 112; // Offset of the registers
+pub const PRPSINFO_SIZE: usize = 136;
+pub const PRPSINFO_PSARGS: usize = 56; // Offset of the command line
+pub const SIGINFO_SIZE: usize = 128;
+pub const SIGINFO_ADDR: usize = 16; // Offset of the faulting address
+
+pub const SIGSEGV: u32 = 11;
+
+// Names of the registers in the order of `user_regs_struct`
+pub const REGISTERS: [&str; 27] = [
+    "r15", "r14", "r13", "r12", "rbp", "rbx", "r11", "r10", "r9", "r8", "rax",
+    "rcx", "rdx", "rsi", "rdi", "orig_rax", "rip", "cs", "eflags", "rsp", "ss",
+    "fs_base", "gs_base", "ds", "es", "fs", "gs",
+];
+
+const EHDR_SIZE: usize = 64;
+const PHDR_SIZE: usize = 56;
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+const ET_CORE: u16 = 4;
+const EM_X86_64: u16 = 62;
+const PAGE_SIZE: usize = 4096;
+
+/// Writes the state of the current process killed by an exception to
+/// `/tmp/core.<pid>` before its memory is freed. It must be called by the
+/// process itself, whose memory is written page by page from its own address
+/// space.
+pub fn write(
+    sf: &InterruptStackFrameValue,
+    regs: &Registers,
+    addr: u64
+) -> Result<(), ()> {
+    let pid = sys::process::id();
+    let proc = sys::process::snapshot(pid).ok_or(())?;
+    let segments = segments(&proc.regions);
+    let notes = notes(&proc, sf, regs, addr);
+
+    let phnum = segments.len() + 1;
+    let mut offset = EHDR_SIZE + phnum * PHDR_SIZE;
+    let mut buf = Vec::new();
+    header(&mut buf, phnum as u16);
+    phdr(&mut buf, PT_NOTE, 0, offset, 0, notes.len());
+    offset += notes.len();
+    for (start, size, flags) in segments.iter() {
+        offset = offset.div_ceil(PAGE_SIZE) * PAGE_SIZE;
+        phdr(&mut buf, PT_LOAD, *flags, offset, *start, *size);
+        offset += size;
+    }
+    buf.extend_from_slice(&notes);
+
+    let path = format!("/tmp/core.{}", pid);
+    File::delete(&path).ok(); // The PID could have been used before
+    let mut file = File::create(&path).ok_or(())?;
+    file.write(&buf)?;
+    let mut offset = buf.len();
+    let zeros = [0; PAGE_SIZE];
+    for (start, size, _) in segments.iter() {
+        let padding = offset.div_ceil(PAGE_SIZE) * PAGE_SIZE - offset;
+        file.write(&zeros[0..padding])?;
+        offset += padding;
+        for page in (*start..*start + *size as u64).step_by(PAGE_SIZE) {
+            let ptr = page as *const u8;
+            let data = unsafe { core::slice::from_raw_parts(ptr, PAGE_SIZE) };
+            file.write(data)?;
+            offset += PAGE_SIZE;
+        }
+    }
+    Ok(())
+}
+
+// Return the runs of mapped pages of the memory window of the process, which
+// is still the active address space
+fn segments(regions: &[sys::process::Region]) -> Vec<(u64, usize, u32)> {
+    let (frame, _) = Cr3::read();
+    let offset = VirtAddr::new(sys::mem::phys_mem_offset());
+    let table = unsafe { sys::mem::create_page_table(frame) };
+    let mapper = unsafe { OffsetPageTable::new(table, offset) };
+    let mut res: Vec<(u64, usize, u32)> = Vec::new();
+    for region in regions.iter().filter(|r| r.mapped > 0) {
+        let flags = if region.name == "code" { 7 } else { 6 }; // RWX or RW
+        let end = region.addr + region.size as u64;
+        for addr in (region.addr..end).step_by(4096) {
+            if mapper.translate_addr(VirtAddr::new(addr)).is_none() {
+                continue;
+            }
+            match res.last_mut() {
+                Some((start, size, f))
+                    if *start + *size as u64 == addr && *f == flags => {
+                    *size += 4096;
+                }
+                _ => res.push((addr, 4096, flags)),
+            }
+        }
+    }
+    res
+}
+
+fn notes(
+    proc: &sys::process::ProcessInfo,
+    sf: &InterruptStackFrameValue,
+    regs: &Registers,
+    addr: u64
+) -> Vec<u8> {
+    let mut prstatus = [0; PRSTATUS_SIZE];
+    prstatus[0..4].copy_from_slice(&SIGSEGV.to_le_bytes());
+    prstatus[12..14].copy_from_slice(&(SIGSEGV as u16).to_le_bytes());
+    prstatus[32..36].copy_from_slice(&(proc.id as u32).to_le_bytes());
+    prstatus[36..40].copy_from_slice(&(proc.parent_id as u32).to_le_bytes());
+    let values = [
+        regs.r15, regs.r14, regs.r13, regs.r12, regs.rbp, regs.rbx, regs.r11,
+        regs.r10, regs.r9, regs.r8, regs.rax, regs.rcx, regs.rdx, regs.rsi,
+        regs.rdi, usize::MAX,
+        sf.instruction_pointer.as_u64() as usize,
+        sf.code_segment.0 as usize,
+        sf.cpu_flags.bits() as usize,
+        sf.stack_pointer.as_u64() as usize,
+        sf.stack_segment.0 as usize,
+    ];
+    for (i, val) in values.iter().enumerate() {
+        let j = PRSTATUS_REG + i * 8;
+        prstatus[j..j + 8].copy_from_slice(&(*val as u64).to_le_bytes());
+    }
+
+    let mut prpsinfo = [0; PRPSINFO_SIZE];
+    prpsinfo[1] = b'R';
+    prpsinfo[24..28].copy_from_slice(&(proc.id as u32).to_le_bytes());
+    prpsinfo[28..32].copy_from_slice(&(proc.parent_id as u32).to_le_bytes());
+    let fname = sys::fs::filename(&proc.name).as_bytes();
+    let n = fname.len().min(15);
+    prpsinfo[40..40 + n].copy_from_slice(&fname[0..n]);
+    let psargs = proc.name.as_bytes();
+    let n = psargs.len().min(PRPSINFO_SIZE - PRPSINFO_PSARGS - 1);
+    prpsinfo[PRPSINFO_PSARGS..PRPSINFO_PSARGS + n]
+        .copy_from_slice(&psargs[0..n]);
+
+    let mut siginfo = [0; SIGINFO_SIZE];
+    siginfo[0..4].copy_from_slice(&SIGSEGV.to_le_bytes());
+    siginfo[SIGINFO_ADDR..SIGINFO_ADDR + 8]
+        .copy_from_slice(&addr.to_le_bytes());
+
+    let mut buf = Vec::new();
+    note(&mut buf, NT_PRSTATUS, &prstatus);
+    note(&mut buf, NT_PRPSINFO, &prpsinfo);
+    note(&mut buf, NT_SIGINFO, &siginfo);
+    buf
+}
+
+fn note(buf: &mut Vec<u8>, kind: u32, desc: &[u8]) {
+    buf.extend_from_slice(&5u32.to_le_bytes());
+    buf.extend_from_slice(&(desc.len() as u32).to_le_bytes());
+    buf.extend_from_slice(&kind.to_le_bytes());
+    buf.extend_from_slice(b"CORE\0\0\0\0"); // Padded to 4 bytes
+    buf.extend_from_slice(desc);
+    buf.resize(buf.len().div_ceil(4) * 4, 0);
+}
+
+fn header(buf: &mut Vec<u8>, phnum: u16) {
+    buf.extend_from_slice(&[0x7F, b'E', b'L', b'F', 2, 1, 1, 0]);
+    buf.extend_from_slice(&[0; 8]);
+    buf.extend_from_slice(&ET_CORE.to_le_bytes());
+    buf.extend_from_slice(&EM_X86_64.to_le_bytes());
+    buf.extend_from_slice(&1u32.to_le_bytes()); // Version
+    buf.extend_from_slice(&0u64.to_le_bytes()); // Entry
+    buf.extend_from_slice(&(EHDR_SIZE as u64).to_le_bytes()); // Phdrs
+    buf.extend_from_slice(&0u64.to_le_bytes()); // Shdrs
+    buf.extend_from_slice(&0u32.to_le_bytes()); // Flags
+    buf.extend_from_slice(&(EHDR_SIZE as u16).to_le_bytes());
+    buf.extend_from_slice(&(PHDR_SIZE as u16).to_le_bytes());
+    buf.extend_from_slice(&phnum.to_le_bytes());
+    buf.extend_from_slice(&[0; 6]); // No section headers
+}
+
+fn phdr(
+    buf: &mut Vec<u8>,
+    kind: u32,
+    flags: u32,
+    offset: usize,
+    addr: u64,
+    size: usize
+) {
+    let align: u64 = if kind == PT_LOAD { 4096 } else { 4 };
+    buf.extend_from_slice(&kind.to_le_bytes());
+    buf.extend_from_slice(&flags.to_le_bytes());
+    buf.extend_from_slice(&(offset as u64).to_le_bytes());
+    buf.extend_from_slice(&addr.to_le_bytes()); // Virtual address
+    buf.extend_from_slice(&addr.to_le_bytes()); // Physical address
+    buf.extend_from_slice(&(size as u64).to_le_bytes()); // Size in file
+    buf.extend_from_slice(&(size as u64).to_le_bytes()); // Size in memory
+    buf.extend_from_slice(&align.to_le_bytes());
+}
+
+#[test_case]
+fn test_note() {
+    let mut buf = Vec::new();
+    note(&mut buf, NT_SIGINFO, &[1, 2, 3]);
+    assert_eq!(buf.len(), 12 + 8 + 4);
+    assert_eq!(&buf[0..4], &5u32.to_le_bytes());
+    assert_eq!(&buf[4..8], &3u32.to_le_bytes());
+    assert_eq!(&buf[12..17], b"CORE\0");
+    assert_eq!(&buf[20..24], &[1, 2, 3, 0]);
+
+    let mut buf = Vec::new();
+    header(&mut buf, 2);
+    assert_eq!(buf.len(), EHDR_SIZE);
+    phdr(&mut buf, PT_LOAD, 6, 4096, 0x4000_0000, 4096);
+    assert_eq!(buf.len(), EHDR_SIZE + PHDR_SIZE);
+}
@@ -3,6 +3,7 @@ use super::dir::Dir;
 use super::super_block::SuperBlock;
 
 use crate::sys;
+use crate::KERNEL_SIZE;
 
 use alloc::vec;
 use alloc::vec::Vec;
@@ -170,8 +171,12 @@ impl BlockDeviceIO for AtaBlockDevice {
         self.dev.block_size() as usize
     }
 
+    // The filesystem ends before the area of the shared library
     fn block_count(&self) -> usize {
-        self.dev.block_count() as usize
+        match library_area(self.dev.bus, self.dev.dsk) {
+            Some((addr, _)) => addr as usize,
+            None => self.dev.block_count() as usize,
+        }
     }
 }
 
@@ -194,6 +199,68 @@ pub fn format_ata() {
     }
 }
 
+// Read blocks of the area reserved for the kernel at the beginning of the
+// mounted disk, before its superblock
+pub fn read_kernel_blocks(addr: u32, count: usize) -> Option<Vec<u8>> {
+    if addr as usize + count > KERNEL_SIZE / super::BLOCK_SIZE {
+        return None;
+    }
+    if let Some(BlockDevice::Ata(ref dev)) = *BLOCK_DEVICE.lock() {
+        return read_ata_blocks(dev.dev.bus, dev.dev.dsk, addr, count);
+    }
+    None
+}
+
+// The shared library of the API is written by `make image` at the end of the
+// disk, before a last block giving its size, and is kept out of the
+// filesystem to be installed from there
+const LIBRARY_SIGNATURE: &str = "MOROS LIB ";
+
+fn library_area(bus: u8, dsk: u8) -> Option<(u32, usize)> {
+    let drive = sys::ata::Drive::open(bus, dsk)?;
+    let header = drive.block_count().checked_sub(1)?;
+    let buf = read_ata_blocks(bus, dsk, header, 1)?;
+    let line = buf.split(|b| *b == b'\n').next()?;
+    let line = core::str::from_utf8(line).ok()?;
+    let size: usize = line.strip_prefix(LIBRARY_SIGNATURE)?.parse().ok()?;
+
+    let count = size.div_ceil(super::BLOCK_SIZE);
+    let addr = header.checked_sub(count as u32)?;
+    if (addr as usize) < KERNEL_SIZE / super::BLOCK_SIZE {
+        return None;
+    }
+    Some((addr, size))
+}
+
+// Read the shared library from the first ATA drive having one
+pub fn read_library() -> Option<Vec<u8>> {
+    for bus in 0..2 {
+        for dsk in 0..2 {
+            if let Some((addr, size)) = library_area(bus, dsk) {
+                let count = size.div_ceil(super::BLOCK_SIZE);
+                let mut buf = read_ata_blocks(bus, dsk, addr, count)?;
+                buf.truncate(size);
+                return Some(buf);
+            }
+        }
+    }
+    None
+}
+
+// Read blocks outside of the filesystem, bypassing the cache
+fn read_ata_blocks(
+    bus: u8,
+    dsk: u8,
+    addr: u32,
+    count: usize,
+) -> Option<Vec<u8>> {
+    let mut buf = vec![0; count * super::BLOCK_SIZE];
+    for (i, chunk) in buf.chunks_mut(super::BLOCK_SIZE).enumerate() {
+        sys::ata::read(bus, dsk, addr + i as u32, chunk).ok()?;
+    }
+    Some(buf)
+}
+
 pub fn is_mounted() -> bool {
     BLOCK_DEVICE.lock().is_some()
 }
@@ -1,7 +1,7 @@
 use super::block::LinkedBlock;
 use super::dir::Dir;
 use super::file::File;
-use super::{dirname, filename, realpath, FileIO, IO};
+use super::{dirname, filename, realpath, DeviceType, FileIO, IO};
 
 use crate::sys::ata::Drive;
 use crate::sys::clk::{RTC, EpochTime, BootTime};
@@ -15,65 +15,14 @@ use crate::sys::net::socket::udp::UdpSocket;
 use crate::sys::rng::Random;
 use crate::sys::speaker::Speaker;
 use crate::sys::vga::{VgaFont, VgaMode, VgaPalette, VgaBuffer};
+use crate::gpu::{GpuBuffer, GpuMode};
+use crate::mouse::MouseDevice;
 
 use alloc::vec;
 use alloc::vec::Vec;
 use core::convert::TryFrom;
 use core::convert::TryInto;
 
-#[derive(PartialEq, Eq, Clone, Copy)]
-#[repr(u8)]
-pub enum DeviceType {
-    Null       = 0,
-    File       = 1,
-    Console    = 2,
-    Random     = 3,
-    BootTime   = 4,
-    EpochTime  = 5,
-    RTC        = 6,
-    TcpSocket  = 7,
-    UdpSocket  = 8,
-    Drive      = 9,
-    VgaBuffer  = 10,
-    VgaFont    = 11,
-    VgaMode    = 12,
-    VgaPalette = 13,
-    Speaker    = 14,
-    NetGw      = 15,
-    NetIp      = 16,
-    NetMac     = 17,
-    NetUsage   = 18,
-}
-
-impl TryFrom<&[u8]> for DeviceType {
-    type Error = ();
-
-    fn try_from(buf: &[u8]) -> Result<Self, Self::Error> {
-        match buf.first().ok_or(())? {
-             0 => Ok(DeviceType::Null),
-             1 => Ok(DeviceType::File),
-             2 => Ok(DeviceType::Console),
-             3 => Ok(DeviceType::Random),
-             4 => Ok(DeviceType::BootTime),
-             5 => Ok(DeviceType::EpochTime),
-             6 => Ok(DeviceType::RTC),
-             7 => Ok(DeviceType::TcpSocket),
-             8 => Ok(DeviceType::UdpSocket),
-             9 => Ok(DeviceType::Drive),
-            10 => Ok(DeviceType::VgaBuffer),
-            11 => Ok(DeviceType::VgaFont),
-            12 => Ok(DeviceType::VgaMode),
-            13 => Ok(DeviceType::VgaPalette),
-            14 => Ok(DeviceType::Speaker),
-            15 => Ok(DeviceType::NetGw),
-            16 => Ok(DeviceType::NetIp),
-            17 => Ok(DeviceType::NetMac),
-            18 => Ok(DeviceType::NetUsage),
-             _ => Err(()),
-        }
-    }
-}
-
 impl DeviceType {
     // Return a buffer for the file representing the device in the filesystem.
     // The first byte is the device type. The remaining bytes can be used to
@@ -94,6 +43,8 @@ impl DeviceType {
             DeviceType::NetIp      => NetIp::size(),
             DeviceType::NetMac     => NetMac::size(),
             DeviceType::NetUsage   => NetUsage::size(),
+            DeviceType::GpuMode    => GpuMode::size(),
+            DeviceType::Mouse      => MouseDevice::size(),
             _                      => 1,
         };
         let mut res = vec![0; len];
@@ -123,6 +74,9 @@ pub enum Device {
     NetIp(NetIp),
     NetMac(NetMac),
     NetUsage(NetUsage),
+    GpuBuffer(GpuBuffer),
+    GpuMode(GpuMode),
+    Mouse(MouseDevice),
 }
 
 impl TryFrom<&[u8]> for Device {
@@ -148,6 +102,9 @@ impl TryFrom<&[u8]> for Device {
             DeviceType::NetIp      => Ok(Device::NetIp(NetIp::new())),
             DeviceType::NetMac     => Ok(Device::NetMac(NetMac::new())),
             DeviceType::NetUsage   => Ok(Device::NetUsage(NetUsage::new())),
+            DeviceType::GpuBuffer  => Ok(Device::GpuBuffer(GpuBuffer::new())),
+            DeviceType::GpuMode    => Ok(Device::GpuMode(GpuMode::new())),
+            DeviceType::Mouse      => Ok(Device::Mouse(MouseDevice::new())),
             DeviceType::Drive if buf.len() > 2 => {
                 let bus = buf[1];
                 let dsk = buf[2];
@@ -216,6 +173,9 @@ impl FileIO for Device {
             Device::NetIp(io)      => io.read(buf),
             Device::NetMac(io)     => io.read(buf),
             Device::NetUsage(io)   => io.read(buf),
+            Device::GpuBuffer(io)  => io.read(buf),
+            Device::GpuMode(io)    => io.read(buf),
+            Device::Mouse(io)      => io.read(buf),
         }
     }
 
@@ -240,6 +200,9 @@ impl FileIO for Device {
             Device::NetIp(io)      => io.write(buf),
             Device::NetMac(io)     => io.write(buf),
             Device::NetUsage(io)   => io.write(buf),
+            Device::GpuBuffer(io)  => io.write(buf),
+            Device::GpuMode(io)    => io.write(buf),
+            Device::Mouse(io)      => io.write(buf),
         }
     }
 
@@ -264,6 +227,9 @@ impl FileIO for Device {
             Device::NetIp(io)      => io.close(),
             Device::NetMac(io)     => io.close(),
             Device::NetUsage(io)   => io.close(),
+            Device::GpuBuffer(io)  => io.close(),
+            Device::GpuMode(io)    => io.close(),
+            Device::Mouse(io)      => io.close(),
         }
     }
 
@@ -288,6 +254,9 @@ impl FileIO for Device {
             Device::NetIp(io)      => io.poll(event),
             Device::NetMac(io)     => io.poll(event),
             Device::NetUsage(io)   => io.poll(event),
+            Device::GpuBuffer(io)  => io.poll(event),
+            Device::GpuMode(io)    => io.poll(event),
+            Device::Mouse(io)      => io.poll(event),
         }
     }
 }
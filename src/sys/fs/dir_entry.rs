@@ -1,9 +1,7 @@
 use super::dir::Dir;
-use super::{dirname, filename, realpath, FileType};
+use super::{dirname, filename, realpath, FileInfo, FileType};
 
 use alloc::string::String;
-use alloc::vec::Vec;
-use core::convert::TryInto;
 
 #[derive(Clone)]
 pub struct DirEntry {
@@ -105,24 +103,9 @@ impl DirEntry {
     }
 }
 
-#[derive(Debug)]
-pub struct FileInfo {
-    kind: FileType,
-    size: u32,
-    time: u64,
-    name: String,
-}
-
+// The info of the root dir is given by the kernel, which is the only one
+// knowing its size
 impl FileInfo {
-    pub fn new() -> Self {
-        Self {
-            kind: FileType::File,
-            name: String::new(),
-            size: 0,
-            time: 0,
-        }
-    }
-
     pub fn root() -> Self {
         let kind = FileType::Dir;
         let name = String::new();
@@ -135,61 +118,4 @@ impl FileInfo {
             time,
         }
     }
-
-    pub fn size(&self) -> u32 {
-        self.size
-    }
-
-    pub fn time(&self) -> u64 {
-        self.time
-    }
-
-    pub fn name(&self) -> String {
-        self.name.clone()
-    }
-
-    pub fn kind(&self) -> FileType {
-        self.kind
-    }
-
-    // TODO: Duplicated from dir entry
-    pub fn is_dir(&self) -> bool {
-        self.kind == FileType::Dir
-    }
-
-    pub fn is_file(&self) -> bool {
-        self.kind == FileType::File
-    }
-
-    pub fn is_device(&self) -> bool {
-        self.kind == FileType::Device
-    }
-
-    // TODO: Use bincode?
-    pub fn as_bytes(&self) -> Vec<u8> {
-        debug_assert!(self.name.len() < 256);
-        let mut res = Vec::new();
-        res.push(self.kind as u8);
-        res.extend_from_slice(&self.size.to_be_bytes());
-        res.extend_from_slice(&self.time.to_be_bytes());
-        res.push(self.name.len() as u8);
-        res.extend_from_slice(self.name.as_bytes());
-        res
-    }
-}
-
-impl From<&[u8]> for FileInfo {
-    fn from(buf: &[u8]) -> Self {
-        let kind = (buf[0] as usize).try_into().unwrap();
-        let size = u32::from_be_bytes(buf[1..5].try_into().unwrap());
-        let time = u64::from_be_bytes(buf[5..13].try_into().unwrap());
-        let i = 14 + buf[13] as usize;
-        let name = String::from_utf8_lossy(&buf[14..i]).into();
-        Self {
-            kind,
-            name,
-            size,
-            time,
-        }
-    }
 }
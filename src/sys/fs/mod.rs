@@ -5,60 +5,40 @@ mod device;
 mod dir;
 mod dir_entry;
 mod file;
+mod pipe;
+mod proc;
 mod read_dir;
 mod super_block;
 
 use crate::sys;
 
-pub use crate::api::fs::{dirname, filename, realpath, FileIO, IO};
+pub use crate::api::fs::{
+    dirname, filename, realpath, DeviceType, FileIO, FileInfo, FileType, IO,
+    OpenFlag,
+};
 pub use crate::sys::ata::BLOCK_SIZE;
 pub use bitmap_block::BITMAP_SIZE;
 pub use block_device::{
-    dismount, format_ata, format_mem, is_mounted, mount_ata, mount_mem
+    dismount, format_ata, format_mem, is_mounted, mount_ata, mount_mem,
+    read_kernel_blocks, read_library,
 };
-pub use device::{Device, DeviceType};
+pub use device::Device;
 pub use dir::Dir;
-pub use dir_entry::FileInfo;
 pub use file::{File, SeekFrom};
+pub use pipe::{Pipe, PIPE_SIZE};
+pub use proc::{ProcFile, PROC_DIR};
 
 use dir_entry::DirEntry;
 use super_block::SuperBlock;
 
 use alloc::string::{String, ToString};
-use core::convert::TryFrom;
-use core::ops::BitOr;
 
 pub const VERSION: u8 = 2;
 
-// TODO: Move that to API
-#[derive(Clone, Copy)]
-#[repr(u8)]
-pub enum OpenFlag {
-    Read     = 1,
-    Write    = 2,
-    Append   = 4,
-    Create   = 8,
-    Truncate = 16,
-    Dir      = 32,
-    Device   = 64,
-}
-
-impl OpenFlag {
-    fn is_set(&self, flags: u8) -> bool {
-        flags & (*self as u8) != 0
-    }
-}
-
-impl BitOr for OpenFlag {
-   type Output = u8;
-
-   fn bitor(self, rhs: Self) -> Self::Output {
-       (self as u8) | (rhs as u8)
-   }
-}
-
 pub fn open(path: &str, flags: u8) -> Option<Resource> {
-    if OpenFlag::Dir.is_set(flags) {
+    if proc::is_proc_path(path) {
+        ProcFile::open(path).map(Resource::Proc)
+    } else if OpenFlag::Dir.is_set(flags) {
         let res = Dir::open(path);
         if res.is_none() && OpenFlag::Create.is_set(flags) {
             Dir::create(path)
@@ -88,6 +68,9 @@ pub fn open(path: &str, flags: u8) -> Option<Resource> {
 }
 
 pub fn delete(path: &str) -> Result<(), ()> {
+    if proc::is_proc_path(path) {
+        return Err(());
+    }
     if let Some(info) = info(path) {
         if info.is_dir() {
             return Dir::delete(path);
@@ -102,27 +85,10 @@ pub fn info(pathname: &str) -> Option<FileInfo> {
     if pathname == "/" {
         return Some(FileInfo::root());
     }
-    DirEntry::open(pathname).map(|e| e.info())
-}
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum FileType {
-    Dir = 0,
-    File = 1,
-    Device = 2,
-}
-
-impl TryFrom<usize> for FileType {
-    type Error = ();
-
-    fn try_from(num: usize) -> Result<Self, Self::Error> {
-        match num {
-             0 => Ok(FileType::Dir),
-             1 => Ok(FileType::File),
-             2 => Ok(FileType::Device),
-             _ => Err(()),
-        }
+    if proc::is_proc_path(pathname) {
+        return proc::info(pathname);
     }
+    DirEntry::open(pathname).map(|e| e.info())
 }
 
 #[derive(Debug, Clone)]
@@ -130,6 +96,8 @@ pub enum Resource {
     Dir(Dir),
     File(File),
     Device(Device),
+    Pipe(Pipe),
+    Proc(ProcFile),
 }
 
 impl Resource {
@@ -138,6 +106,8 @@ impl Resource {
             Resource::Dir(_) => FileType::Dir,
             Resource::File(_) => FileType::File,
             Resource::Device(_) => FileType::Device,
+            Resource::Pipe(_) => FileType::Pipe,
+            Resource::Proc(io) => io.kind(),
         }
     }
 }
@@ -148,6 +118,8 @@ impl FileIO for Resource {
             Resource::Dir(io) => io.read(buf),
             Resource::File(io) => io.read(buf),
             Resource::Device(io) => io.read(buf),
+            Resource::Pipe(io) => io.read(buf),
+            Resource::Proc(io) => io.read(buf),
         }
     }
 
@@ -156,6 +128,8 @@ impl FileIO for Resource {
             Resource::Dir(io) => io.write(buf),
             Resource::File(io) => io.write(buf),
             Resource::Device(io) => io.write(buf),
+            Resource::Pipe(io) => io.write(buf),
+            Resource::Proc(io) => io.write(buf),
         }
     }
 
@@ -164,6 +138,8 @@ impl FileIO for Resource {
             Resource::Dir(io) => io.close(),
             Resource::File(io) => io.close(),
             Resource::Device(io) => io.close(),
+            Resource::Pipe(io) => io.close(),
+            Resource::Proc(io) => io.close(),
         }
     }
 
@@ -172,6 +148,8 @@ impl FileIO for Resource {
             Resource::Dir(io) => io.poll(event),
             Resource::File(io) => io.poll(event),
             Resource::Device(io) => io.poll(event),
+            Resource::Pipe(io) => io.poll(event),
+            Resource::Proc(io) => io.poll(event),
         }
     }
 }
@@ -0,0 +1,134 @@
+use super::{FileIO, IO};
+use crate::sys;
+
+use alloc::collections::vec_deque::VecDeque;
+use alloc::sync::{Arc, Weak};
+use spin::Mutex;
+
+pub const PIPE_SIZE: usize = 4096;
+
+// A pipe is a bounded ring buffer shared by a reading end and a writing end.
+// Each end holds a token that the other end can watch to know when all the
+// copies of its peer have been dropped: the reader gets an EOF when there is
+// no writer left and the buffer is empty, and the writer gets an error when
+// there is no reader left.
+#[derive(Debug, Clone)]
+pub struct Pipe {
+    buffer: Arc<Mutex<VecDeque<u8>>>,
+    capacity: usize,
+    is_writer: bool,
+    _token: Arc<()>,
+    peer: Weak<()>,
+}
+
+impl Pipe {
+    // Returns the reading and writing ends of a new pipe
+    pub fn new(capacity: usize) -> (Self, Self) {
+        let capacity = if capacity > 0 { capacity } else { PIPE_SIZE };
+        let buffer = Arc::new(Mutex::new(VecDeque::new()));
+        let reader_token = Arc::new(());
+        let writer_token = Arc::new(());
+        let reader_peer = Arc::downgrade(&writer_token);
+        let writer_peer = Arc::downgrade(&reader_token);
+        let reader = Self {
+            buffer: buffer.clone(),
+            capacity,
+            is_writer: false,
+            peer: reader_peer,
+            _token: reader_token,
+        };
+        let writer = Self {
+            buffer,
+            capacity,
+            is_writer: true,
+            peer: writer_peer,
+            _token: writer_token,
+        };
+        (reader, writer)
+    }
+
+    fn is_peer_closed(&self) -> bool {
+        self.peer.strong_count() == 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.lock().is_empty()
+    }
+}
+
+impl FileIO for Pipe {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+        if self.is_writer {
+            return Err(());
+        }
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            {
+                let mut buffer = self.buffer.lock();
+                if !buffer.is_empty() {
+                    let n = buf.len().min(buffer.len());
+                    for (i, byte) in buffer.drain(..n).enumerate() {
+                        buf[i] = byte;
+                    }
+                    return Ok(n);
+                }
+                if self.is_peer_closed() {
+                    return Ok(0);
+                }
+            }
+            sys::clk::block();
+        }
+    }
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize, ()> {
+        if !self.is_writer {
+            return Err(());
+        }
+        let mut i = 0;
+        while i < buf.len() {
+            if self.is_peer_closed() {
+                return Err(());
+            }
+            {
+                let mut buffer = self.buffer.lock();
+                let free = self.capacity.saturating_sub(buffer.len());
+                let n = (buf.len() - i).min(free);
+                buffer.extend(&buf[i..(i + n)]);
+                i += n;
+            }
+            if i < buf.len() {
+                sys::clk::block();
+            }
+        }
+        Ok(i)
+    }
+
+    fn close(&mut self) {}
+
+    fn poll(&mut self, event: IO) -> bool {
+        let len = self.buffer.lock().len();
+        let is_closed = self.is_peer_closed();
+        match event {
+            IO::Read => !self.is_writer && (len > 0 || is_closed),
+            IO::Write => self.is_writer && (len < self.capacity || is_closed),
+        }
+    }
+}
+
+#[test_case]
+fn test_pipe() {
+    let (mut reader, mut writer) = Pipe::new(0);
+    assert_eq!(writer.write(b"hello"), Ok(5));
+    assert!(reader.poll(IO::Read));
+
+    let mut buf = [0; 8];
+    assert_eq!(reader.read(&mut buf), Ok(5));
+    assert_eq!(&buf[0..5], b"hello");
+    assert!(!reader.poll(IO::Read));
+
+    drop(writer);
+    assert!(reader.poll(IO::Read));
+    assert_eq!(reader.read(&mut buf), Ok(0));
+}
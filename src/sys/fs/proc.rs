@@ -0,0 +1,211 @@
+use super::{realpath, Device, FileIO, FileInfo, FileType, Resource, IO};
+use crate::sys;
+use crate::sys::process::ProcessInfo;
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt::Write;
+
+pub const PROC_DIR: &str = "/proc";
+
+const FILES: [&str; 5] = ["status", "env", "dir", "handles", "maps"];
+
+// The files of the /proc directory are generated from the process table
+// when they are opened, and they keep this snapshot until they are closed.
+// The directories are read like the directories on the disk.
+#[derive(Debug, Clone)]
+pub struct ProcFile {
+    kind: FileType,
+    buf: Vec<u8>,
+    offset: usize,
+}
+
+impl ProcFile {
+    pub fn open(path: &str) -> Option<Self> {
+        let (kind, buf) = generate(path)?;
+        Some(Self { kind, buf, offset: 0 })
+    }
+
+    pub fn kind(&self) -> FileType {
+        self.kind
+    }
+}
+
+impl FileIO for ProcFile {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
+        let n = buf.len().min(self.buf.len() - self.offset);
+        buf[0..n].copy_from_slice(&self.buf[self.offset..self.offset + n]);
+        self.offset += n;
+        Ok(n)
+    }
+
+    fn write(&mut self, _buf: &[u8]) -> Result<usize, ()> {
+        Err(())
+    }
+
+    fn close(&mut self) {}
+
+    fn poll(&mut self, event: IO) -> bool {
+        match event {
+            IO::Read => self.offset < self.buf.len(),
+            IO::Write => false,
+        }
+    }
+}
+
+pub fn is_proc_path(path: &str) -> bool {
+    let path = realpath(path);
+    path == PROC_DIR || path.starts_with("/proc/")
+}
+
+pub fn info(path: &str) -> Option<FileInfo> {
+    let path = realpath(path);
+    let (kind, buf) = generate(&path)?;
+    let name = super::filename(&path);
+    let time = sys::clk::epoch_time() as u64;
+    Some(FileInfo::synthetic(kind, name, buf.len() as u32, time))
+}
+
+// Return the type and the contents of the file at the given path
+fn generate(path: &str) -> Option<(FileType, Vec<u8>)> {
+    let path = realpath(path);
+    let path = path.trim_end_matches('/');
+    let parts: Vec<&str> = path.split('/').skip(2).collect();
+    match parts.as_slice() {
+        [] => {
+            let names = sys::process::pids().iter().map(|pid| {
+                pid.to_string()
+            }).collect();
+            Some((FileType::Dir, dir_entries(path, names)))
+        }
+        [pid] => {
+            sys::process::snapshot(pid.parse().ok()?)?;
+            let names = FILES.iter().map(|name| name.to_string()).collect();
+            Some((FileType::Dir, dir_entries(path, names)))
+        }
+        [pid, name] => {
+            let proc = sys::process::snapshot(pid.parse().ok()?)?;
+            let contents = match *name {
+                "status" => status(&proc),
+                "env" => env(&proc),
+                "dir" => format!("{}\n", proc.dir),
+                "handles" => handles(&proc),
+                "maps" => maps(&proc),
+                _ => return None,
+            };
+            Some((FileType::File, contents.into_bytes()))
+        }
+        _ => None,
+    }
+}
+
+// Serialize the info of the entries like `Dir::read`
+fn dir_entries(path: &str, names: Vec<String>) -> Vec<u8> {
+    let time = sys::clk::epoch_time() as u64;
+    let mut res = Vec::new();
+    for name in names {
+        let child = format!("{}/{}", path, name);
+        if let Some((kind, buf)) = generate(&child) {
+            let info = FileInfo::synthetic(kind, &name, buf.len() as u32, time);
+            res.extend(info.as_bytes());
+        }
+    }
+    res
+}
+
+fn status(proc: &ProcessInfo) -> String {
+    let memory: usize = proc.regions.iter().map(|r| r.mapped).sum();
+    let mut res = String::new();
+    writeln!(res, "pid: {}", proc.id).ok();
+    writeln!(res, "parent: {}", proc.parent_id).ok();
+    writeln!(res, "name: {}", proc.name).ok();
+    writeln!(res, "state: {:?}", proc.state).ok();
+    if let Some(user) = &proc.user {
+        writeln!(res, "user: {}", user).ok();
+    }
+    writeln!(res, "size: {}", proc.size).ok();
+    writeln!(res, "memory: {}", memory).ok();
+    writeln!(res, "heap: {}", proc.heap_size).ok();
+    writeln!(res, "heap_used: {}", proc.heap_used).ok();
+    writeln!(res, "handles: {}", proc.handles.len()).ok();
+    res
+}
+
+fn env(proc: &ProcessInfo) -> String {
+    let mut res = String::new();
+    for (key, val) in proc.env.iter() {
+        writeln!(res, "{}={}", key, val).ok();
+    }
+    res
+}
+
+fn handles(proc: &ProcessInfo) -> String {
+    let mut res = String::new();
+    for (handle, resource) in proc.handles.iter() {
+        writeln!(res, "{} {}", handle, resource_name(resource)).ok();
+    }
+    res
+}
+
+fn maps(proc: &ProcessInfo) -> String {
+    let mut res = String::new();
+    for r in proc.regions.iter() {
+        let (addr, end) = (r.addr, r.addr + r.size as u64);
+        writeln!(res, "{:#X}-{:#X} {:>9} {}", addr, end, r.mapped, r.name).ok();
+    }
+    res
+}
+
+fn resource_name(res: &Resource) -> &'static str {
+    match res {
+        Resource::Dir(_) => "dir",
+        Resource::File(_) => "file",
+        Resource::Pipe(_) => "pipe",
+        Resource::Proc(_) => "proc",
+        Resource::Device(dev) => match dev {
+            Device::Null => "device:null",
+            Device::File(_) => "device:file",
+            Device::Console(_) => "device:console",
+            Device::Random(_) => "device:random",
+            Device::BootTime(_) => "device:boot-time",
+            Device::EpochTime(_) => "device:epoch-time",
+            Device::RTC(_) => "device:rtc",
+            Device::TcpSocket(_) => "device:tcp",
+            Device::UdpSocket(_) => "device:udp",
+            Device::Drive(_) => "device:ata",
+            Device::VgaBuffer(_) => "device:vga-buffer",
+            Device::VgaFont(_) => "device:vga-font",
+            Device::VgaMode(_) => "device:vga-mode",
+            Device::VgaPalette(_) => "device:vga-palette",
+            Device::Speaker(_) => "device:speaker",
+            Device::NetGw(_) => "device:net-gw",
+            Device::NetIp(_) => "device:net-ip",
+            Device::NetMac(_) => "device:net-mac",
+            Device::NetUsage(_) => "device:net-usage",
+            Device::GpuBuffer(_) => "device:gpu-buffer",
+            Device::GpuMode(_) => "device:gpu-mode",
+            Device::Mouse(_) => "device:mouse",
+        },
+    }
+}
+
+#[test_case]
+fn test_proc() {
+    assert!(is_proc_path("/proc"));
+    assert!(is_proc_path("/proc/0/status"));
+    assert!(!is_proc_path("/process"));
+
+    let info = info("/proc/0").unwrap();
+    assert!(info.is_dir());
+    assert_eq!(info.name(), "0");
+
+    let mut file = ProcFile::open("/proc/0/status").unwrap();
+    let mut buf = [0; 256];
+    let n = file.read(&mut buf).unwrap();
+    assert!(buf[0..n].starts_with(b"pid: 0\nparent: 0\nname: kernel\n"));
+    assert!(file.write(b"pid: 1").is_err());
+
+    assert!(ProcFile::open("/proc/0/unknown").is_none());
+    assert!(ProcFile::open(&format!("/proc/{}", usize::MAX)).is_none());
+}
@@ -0,0 +1,413 @@
+use crate::sys;
+use crate::sys::process::Registers;
+
+use alloc::collections::btree_map::{BTreeMap, Entry};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt::Write;
+use core::sync::atomic::{AtomicBool, Ordering};
+use lazy_static::lazy_static;
+use spin::Mutex;
+use uart_16550::SerialPort;
+use x86_64::instructions::interrupts;
+use x86_64::registers::control::Cr3;
+use x86_64::registers::rflags::RFlags;
+use x86_64::structures::idt::InterruptStackFrameValue;
+use x86_64::structures::paging::{OffsetPageTable, Translate};
+use x86_64::VirtAddr;
+
+pub const COM1: u16 = 0x3F8;
+pub const COM2: u16 = 0x2F8;
+
+const SIGTRAP: u8 = 5;
+const BREAKPOINT: u8 = 0xCC;
+const INTERRUPT: u8 = 0x03;
+
+// The registers sent by `g` in the order of the default x86-64 target of GDB:
+// 17 registers of 64 bits followed by 7 registers of 32 bits
+const REGISTERS: usize = 24;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+    static ref STUB: Mutex<Option<Stub>> = Mutex::new(None);
+}
+
+// GDB Remote Serial Protocol
+// See: https://sourceware.org/gdb/current/onlinedocs/gdb.html/Remote-Protocol.html
+struct Stub {
+    port: SerialPort,
+    // Original bytes of the instructions replaced by a breakpoint
+    breakpoints: BTreeMap<u64, u8>,
+}
+
+impl Stub {
+    fn new(addr: u16) -> Self {
+        let mut port = unsafe { SerialPort::new(addr) };
+        port.init();
+        Self { port, breakpoints: BTreeMap::new() }
+    }
+
+    fn read_packet(&mut self) -> Vec<u8> {
+        loop {
+            while self.port.receive() != b'$' {}
+            let mut buf = Vec::new();
+            loop {
+                match self.port.receive() {
+                    b'#' => break,
+                    b => buf.push(b),
+                }
+            }
+            let hi = self.port.receive();
+            let lo = self.port.receive();
+            if parse_hex(&[hi, lo]) == Some(checksum(&buf) as u64) {
+                self.port.send_raw(b'+');
+                return buf;
+            }
+            self.port.send_raw(b'-');
+        }
+    }
+
+    fn write_packet(&mut self, data: &str) {
+        loop {
+            self.port.send_raw(b'$');
+            for b in data.bytes() {
+                self.port.send_raw(b);
+            }
+            let mut cs = String::new();
+            write!(cs, "#{:02x}", checksum(data.as_bytes())).ok();
+            for b in cs.bytes() {
+                self.port.send_raw(b);
+            }
+            if self.port.receive() != b'-' {
+                break;
+            }
+        }
+    }
+
+    fn set_breakpoint(&mut self, addr: u64) -> Result<(), ()> {
+        if let Entry::Vacant(entry) = self.breakpoints.entry(addr) {
+            let mut buf = [0];
+            read_memory(addr, &mut buf)?;
+            write_memory(addr, &[BREAKPOINT])?;
+            entry.insert(buf[0]);
+        }
+        Ok(())
+    }
+
+    fn clear_breakpoint(&mut self, addr: u64) -> Result<(), ()> {
+        let byte = self.breakpoints.remove(&addr).ok_or(())?;
+        write_memory(addr, &[byte])
+    }
+
+    fn clear_breakpoints(&mut self) {
+        while let Some((addr, _)) = self.breakpoints.first_key_value() {
+            self.clear_breakpoint(*addr).ok();
+        }
+    }
+}
+
+/// Takes over the serial port at the given address to let a remote debugger
+/// connect to the stub.
+pub fn init(addr: u16) {
+    *STUB.lock() = Some(Stub::new(addr));
+
+    // Replace the handler of the serial console when COM1 is taken over
+    sys::idt::set_irq_handler(if addr == COM1 { 4 } else { 3 }, irq_handler);
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::SeqCst)
+}
+
+// Stop the system when the debugger sends an interrupt request (Ctrl-C)
+fn irq_handler() {
+    let b = match STUB.lock().as_mut() {
+        Some(stub) => stub.port.receive(),
+        None => return,
+    };
+    if b == INTERRUPT {
+        interrupts::int3();
+    }
+}
+
+/// Reports an exception to the debugger and executes its commands until it
+/// resumes the execution with the returned context.
+pub fn handle_exception(
+    mut sf: InterruptStackFrameValue,
+    mut regs: Registers,
+) -> (InterruptStackFrameValue, Registers) {
+    let mut guard = STUB.lock();
+    let stub = match guard.as_mut() {
+        Some(stub) => stub,
+        None => return (sf, regs),
+    };
+    sf.cpu_flags.remove(RFlags::TRAP_FLAG);
+    stub.write_packet(&stop_reply());
+    loop {
+        let packet = stub.read_packet();
+        let packet = core::str::from_utf8(&packet).unwrap_or("");
+        let (cmd, args) = packet.split_at(packet.len().min(1));
+        let res = match cmd {
+            "?" => stop_reply(),
+            "g" => {
+                let mut res = String::new();
+                for i in 0..REGISTERS {
+                    res.push_str(&encode_register(i, &sf, &regs));
+                }
+                res
+            }
+            "G" => {
+                let mut args = args;
+                for i in 0..REGISTERS {
+                    let n = register_size(i) * 2;
+                    if args.len() < n {
+                        break;
+                    }
+                    let (val, rest) = args.split_at(n);
+                    if let Some(val) = decode_le(val) {
+                        set_register(i, val, &mut sf, &mut regs);
+                    }
+                    args = rest;
+                }
+                "OK".into()
+            }
+            "p" => match parse_hex(args.as_bytes()) {
+                Some(i) if (i as usize) < REGISTERS => {
+                    encode_register(i as usize, &sf, &regs)
+                }
+                _ => "E01".into(),
+            },
+            "P" => {
+                let mut it = args.splitn(2, '=');
+                let i = it.next().and_then(|s| parse_hex(s.as_bytes()));
+                let val = it.next().and_then(decode_le);
+                match (i, val) {
+                    (Some(i), Some(val)) if (i as usize) < REGISTERS => {
+                        set_register(i as usize, val, &mut sf, &mut regs);
+                        "OK".into()
+                    }
+                    _ => "E01".into(),
+                }
+            }
+            "m" => match parse_range(args) {
+                Some((addr, len)) => {
+                    let mut buf = alloc::vec![0; len];
+                    if read_memory(addr, &mut buf).is_ok() {
+                        encode_hex(&buf)
+                    } else {
+                        "E14".into()
+                    }
+                }
+                None => "E01".into(),
+            },
+            "M" => {
+                let mut it = args.splitn(2, ':');
+                let range = it.next().and_then(parse_range);
+                let data = it.next().and_then(decode_hex);
+                match (range, data) {
+                    (Some((addr, len)), Some(data)) if data.len() == len => {
+                        if write_memory(addr, &data).is_ok() {
+                            "OK".into()
+                        } else {
+                            "E14".into()
+                        }
+                    }
+                    _ => "E01".into(),
+                }
+            }
+            "Z" | "z" => {
+                let mut it = args.splitn(3, ',');
+                let kind = it.next();
+                let addr = it.next().and_then(|s| parse_hex(s.as_bytes()));
+                match (kind, addr) {
+                    (Some("0"), Some(addr)) => {
+                        let res = if cmd == "Z" {
+                            stub.set_breakpoint(addr)
+                        } else {
+                            stub.clear_breakpoint(addr)
+                        };
+                        if res.is_ok() { "OK".into() } else { "E14".into() }
+                    }
+                    _ => String::new(), // Unsupported breakpoint type
+                }
+            }
+            "c" | "s" => {
+                if let Some(addr) = parse_hex(args.as_bytes()) {
+                    sf.instruction_pointer = VirtAddr::new(addr);
+                }
+                if cmd == "s" {
+                    sf.cpu_flags.insert(RFlags::TRAP_FLAG);
+                }
+                return (sf, regs);
+            }
+            "D" | "k" => {
+                stub.clear_breakpoints();
+                if cmd == "D" {
+                    stub.write_packet("OK");
+                }
+                return (sf, regs);
+            }
+            "H" => "OK".into(),
+            "q" if args.starts_with("Supported") => "PacketSize=1000".into(),
+            "q" if args == "Attached" => "1".into(),
+            _ => String::new(), // Unsupported command
+        };
+        stub.write_packet(&res);
+    }
+}
+
+fn stop_reply() -> String {
+    let mut res = String::new();
+    write!(res, "S{:02x}", SIGTRAP).ok();
+    res
+}
+
+fn register_size(i: usize) -> usize {
+    if i < 17 { 8 } else { 4 }
+}
+
+fn register(i: usize, sf: &InterruptStackFrameValue, regs: &Registers) -> u64 {
+    (match i {
+        0 => regs.rax,
+        1 => regs.rbx,
+        2 => regs.rcx,
+        3 => regs.rdx,
+        4 => regs.rsi,
+        5 => regs.rdi,
+        6 => regs.rbp,
+        7 => sf.stack_pointer.as_u64() as usize,
+        8 => regs.r8,
+        9 => regs.r9,
+        10 => regs.r10,
+        11 => regs.r11,
+        12 => regs.r12,
+        13 => regs.r13,
+        14 => regs.r14,
+        15 => regs.r15,
+        16 => sf.instruction_pointer.as_u64() as usize,
+        17 => sf.cpu_flags.bits() as usize,
+        18 => sf.code_segment.0 as usize,
+        19 => sf.stack_segment.0 as usize,
+        _ => 0, // The data segments are not used in long mode
+    }) as u64
+}
+
+fn set_register(
+    i: usize,
+    val: u64,
+    sf: &mut InterruptStackFrameValue,
+    regs: &mut Registers
+) {
+    let v = val as usize;
+    match i {
+        0 => regs.rax = v,
+        1 => regs.rbx = v,
+        2 => regs.rcx = v,
+        3 => regs.rdx = v,
+        4 => regs.rsi = v,
+        5 => regs.rdi = v,
+        6 => regs.rbp = v,
+        7 => sf.stack_pointer = VirtAddr::new_truncate(val),
+        8 => regs.r8 = v,
+        9 => regs.r9 = v,
+        10 => regs.r10 = v,
+        11 => regs.r11 = v,
+        12 => regs.r12 = v,
+        13 => regs.r13 = v,
+        14 => regs.r14 = v,
+        15 => regs.r15 = v,
+        16 => sf.instruction_pointer = VirtAddr::new_truncate(val),
+        17 => sf.cpu_flags = RFlags::from_bits_truncate(val),
+        _ => {} // The segments can't be changed
+    }
+}
+
+fn encode_register(
+    i: usize,
+    sf: &InterruptStackFrameValue,
+    regs: &Registers
+) -> String {
+    let bytes = register(i, sf, regs).to_le_bytes();
+    encode_hex(&bytes[0..register_size(i)])
+}
+
+// Access the memory mapped by the page table of the interrupted code through
+// the physical memory mapping, which allows breakpoints in read-only pages
+fn translate(addr: u64) -> Option<VirtAddr> {
+    let (frame, _) = Cr3::read();
+    let offset = VirtAddr::new(sys::mem::phys_mem_offset());
+    let table = unsafe { sys::mem::create_page_table(frame) };
+    let mapper = unsafe { OffsetPageTable::new(table, offset) };
+    let phys_addr = mapper.translate_addr(VirtAddr::try_new(addr).ok()?)?;
+    Some(sys::mem::phys_to_virt(phys_addr))
+}
+
+fn read_memory(addr: u64, buf: &mut [u8]) -> Result<(), ()> {
+    for (i, b) in buf.iter_mut().enumerate() {
+        let ptr = translate(addr + i as u64).ok_or(())?.as_ptr();
+        *b = unsafe { core::ptr::read_volatile(ptr) };
+    }
+    Ok(())
+}
+
+fn write_memory(addr: u64, buf: &[u8]) -> Result<(), ()> {
+    for (i, b) in buf.iter().enumerate() {
+        let ptr = translate(addr + i as u64).ok_or(())?.as_mut_ptr();
+        unsafe { core::ptr::write_volatile(ptr, *b) };
+    }
+    Ok(())
+}
+
+fn checksum(buf: &[u8]) -> u8 {
+    buf.iter().fold(0, |sum, b| sum.wrapping_add(*b))
+}
+
+fn parse_hex(buf: &[u8]) -> Option<u64> {
+    let s = core::str::from_utf8(buf).ok()?;
+    u64::from_str_radix(s, 16).ok()
+}
+
+fn parse_range(s: &str) -> Option<(u64, usize)> {
+    let (addr, len) = s.split_once(',')?;
+    let addr = parse_hex(addr.as_bytes())?;
+    let len = parse_hex(len.as_bytes())? as usize;
+    Some((addr, len))
+}
+
+fn encode_hex(buf: &[u8]) -> String {
+    let mut res = String::new();
+    for b in buf {
+        write!(res, "{:02x}", b).ok();
+    }
+    res
+}
+
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if s.len() % 2 != 0 {
+        return None;
+    }
+    s.as_bytes().chunks(2).map(|c| parse_hex(c).map(|b| b as u8)).collect()
+}
+
+// The values of the registers are sent in the byte order of the target
+fn decode_le(s: &str) -> Option<u64> {
+    let buf = decode_hex(s)?;
+    if buf.len() > 8 {
+        return None;
+    }
+    let mut bytes = [0; 8];
+    bytes[0..buf.len()].copy_from_slice(&buf);
+    Some(u64::from_le_bytes(bytes))
+}
+
+#[test_case]
+fn test_hex() {
+    assert_eq!(checksum(b"OK"), 0x9A);
+    assert_eq!(encode_hex(&[0x00, 0xCC, 0x12]), "00cc12");
+    assert_eq!(decode_hex("00cc12"), Some(alloc::vec![0x00, 0xCC, 0x12]));
+    assert_eq!(decode_hex("0"), None);
+    assert_eq!(decode_le("3412000000000000"), Some(0x1234));
+    assert_eq!(parse_range("ffff8000,10"), Some((0xFFFF8000, 16)));
+}
@@ -1,6 +1,12 @@
-use core::ptr::addr_of;
+use crate::sys;
+use crate::sys::smp::MAX_CPUS;
+
+use alloc::boxed::Box;
+use alloc::vec;
+use core::ptr::{addr_of, addr_of_mut, null_mut};
+use core::sync::atomic::{AtomicPtr, AtomicU64, Ordering};
 use lazy_static::lazy_static;
-use x86_64::instructions::segmentation::{Segment, CS, DS};
+use x86_64::instructions::segmentation::{Segment, CS, DS, SS};
 use x86_64::instructions::tables::load_tss;
 use x86_64::structures::gdt::{
     Descriptor, GlobalDescriptorTable, SegmentSelector
@@ -12,35 +18,28 @@ const STACK_SIZE: usize = 1024 * 8 * 16;
 pub const DOUBLE_FAULT_IST: u16 = 0;
 pub const PAGE_FAULT_IST: u16 = 1;
 pub const GENERAL_PROTECTION_FAULT_IST: u16 = 2;
+pub const TIMER_IST: u16 = 3;
 
-lazy_static! {
-    static ref TSS: TaskStateSegment = {
-        let mut tss = TaskStateSegment::new();
-        tss.privilege_stack_table[0] = {
-            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
-            VirtAddr::from_ptr(addr_of!(STACK)) + STACK_SIZE as u64
-        };
-        tss.interrupt_stack_table[DOUBLE_FAULT_IST as usize] = {
-            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
-            VirtAddr::from_ptr(addr_of!(STACK)) + STACK_SIZE as u64
-        };
-        tss.interrupt_stack_table[PAGE_FAULT_IST as usize] = {
-            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
-            VirtAddr::from_ptr(addr_of!(STACK)) + STACK_SIZE as u64
-        };
-        tss.interrupt_stack_table[GENERAL_PROTECTION_FAULT_IST as usize] = {
-            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
-            VirtAddr::from_ptr(addr_of!(STACK)) + STACK_SIZE as u64
-        };
-        tss
-    };
-}
+// The TSS is mutable because its privilege stack is replaced by the kernel
+// stack of the current process on each context switch.
+static mut TSS: TaskStateSegment = TaskStateSegment::new();
+static mut KERNEL_STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
+
+// The TSS and the default kernel stack of each AP
+static AP_TSS: [AtomicPtr<TaskStateSegment>; MAX_CPUS] = [
+    const { AtomicPtr::new(null_mut()) }; MAX_CPUS
+];
+static AP_KERNEL_STACKS: [AtomicU64; MAX_CPUS] = [
+    const { AtomicU64::new(0) }; MAX_CPUS
+];
 
 lazy_static! {
     pub static ref GDT: (GlobalDescriptorTable, Selectors) = {
         let mut gdt = GlobalDescriptorTable::new();
 
-        let tss = gdt.append(Descriptor::tss_segment(&TSS));
+        let tss = gdt.append(unsafe {
+            Descriptor::tss_segment_unchecked(addr_of!(TSS))
+        });
         let code = gdt.append(Descriptor::kernel_code_segment());
         let data = gdt.append(Descriptor::kernel_data_segment());
         let user_code = gdt.append(Descriptor::user_code_segment());
@@ -61,13 +60,34 @@ lazy_static! {
 
 pub struct Selectors {
     tss: SegmentSelector,
-    code: SegmentSelector,
-    data: SegmentSelector,
+    pub code: SegmentSelector,
+    pub data: SegmentSelector,
     pub user_code: SegmentSelector,
     pub user_data: SegmentSelector,
 }
 
 pub fn init() {
+    unsafe {
+        let tss = &mut *addr_of_mut!(TSS);
+        tss.interrupt_stack_table[DOUBLE_FAULT_IST as usize] = {
+            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
+            VirtAddr::from_ptr(addr_of!(STACK)) + STACK_SIZE as u64
+        };
+        tss.interrupt_stack_table[PAGE_FAULT_IST as usize] = {
+            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
+            VirtAddr::from_ptr(addr_of!(STACK)) + STACK_SIZE as u64
+        };
+        tss.interrupt_stack_table[GENERAL_PROTECTION_FAULT_IST as usize] = {
+            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
+            VirtAddr::from_ptr(addr_of!(STACK)) + STACK_SIZE as u64
+        };
+        tss.interrupt_stack_table[TIMER_IST as usize] = {
+            static mut STACK: [u8; STACK_SIZE] = [0; STACK_SIZE];
+            VirtAddr::from_ptr(addr_of!(STACK)) + STACK_SIZE as u64
+        };
+    }
+    set_kernel_stack(None);
+
     GDT.0.load();
     unsafe {
         CS::set_reg(GDT.1.code);
@@ -75,3 +95,60 @@ pub fn init() {
         load_tss(GDT.1.tss);
     }
 }
+
+// Each AP has its own TSS with its own stacks, and its own GDT with the same
+// selectors as the BSP, that are never freed.
+pub fn init_ap(id: usize) {
+    let mut tss = TaskStateSegment::new();
+    for i in 0..4 {
+        tss.interrupt_stack_table[i] = alloc_stack();
+    }
+    let stack = alloc_stack();
+    tss.privilege_stack_table[0] = stack;
+    let tss: *mut TaskStateSegment = Box::leak(Box::new(tss));
+    AP_KERNEL_STACKS[id].store(stack.as_u64(), Ordering::SeqCst);
+    AP_TSS[id].store(tss, Ordering::SeqCst);
+
+    let mut gdt = GlobalDescriptorTable::new();
+    let tss = gdt.append(unsafe { Descriptor::tss_segment_unchecked(tss) });
+    let code = gdt.append(Descriptor::kernel_code_segment());
+    let data = gdt.append(Descriptor::kernel_data_segment());
+    gdt.append(Descriptor::user_code_segment());
+    gdt.append(Descriptor::user_data_segment());
+    let gdt: &'static GlobalDescriptorTable = Box::leak(Box::new(gdt));
+
+    gdt.load();
+    unsafe {
+        CS::set_reg(code);
+        DS::set_reg(data);
+        SS::set_reg(data);
+        load_tss(tss);
+    }
+}
+
+fn alloc_stack() -> VirtAddr {
+    let stack = Box::leak(vec![0u8; STACK_SIZE].into_boxed_slice());
+    VirtAddr::from_ptr(stack.as_ptr()) + STACK_SIZE as u64
+}
+
+// Set the stack used by the current CPU when an interrupt or a syscall
+// happens in userspace, or restore its default kernel stack.
+pub fn set_kernel_stack(addr: Option<VirtAddr>) {
+    let id = sys::smp::cpu_id();
+    let tss = AP_TSS[id].load(Ordering::SeqCst);
+    if !tss.is_null() {
+        let addr = addr.unwrap_or_else(|| {
+            VirtAddr::new(AP_KERNEL_STACKS[id].load(Ordering::SeqCst))
+        });
+        unsafe {
+            (*tss).privilege_stack_table[0] = addr;
+        }
+        return;
+    }
+    let addr = addr.unwrap_or_else(|| {
+        VirtAddr::from_ptr(addr_of!(KERNEL_STACK)) + STACK_SIZE as u64
+    });
+    unsafe {
+        (*addr_of_mut!(TSS)).privilege_stack_table[0] = addr;
+    }
+}
@@ -1,20 +1,21 @@
 use crate::sys::mem::phys_mem_offset;
 use crate::api::process::ExitCode;
-use crate::sys::process::Registers;
+use crate::sys::process::{ProcessState, Registers};
+use crate::sys::smp::IrqMutex;
 use crate::{api, hlt_loop, sys};
 
 use core::arch::naked_asm;
 use lazy_static::lazy_static;
-use spin::Mutex;
 use x86_64::instructions::interrupts;
 use x86_64::instructions::port::Port;
 use x86_64::registers::control::Cr2;
 use x86_64::structures::idt::{
-    InterruptDescriptorTable, InterruptStackFrame, InterruptStackFrameValue,
-    PageFaultErrorCode,
+    HandlerFunc, HandlerFuncWithErrCode, InterruptDescriptorTable,
+    InterruptStackFrame, InterruptStackFrameValue, PageFaultErrorCode,
+    PageFaultHandlerFunc,
 };
-use x86_64::structures::paging::OffsetPageTable;
-use x86_64::VirtAddr;
+use x86_64::structures::paging::{OffsetPageTable, PageTableFlags};
+use x86_64::{PrivilegeLevel, VirtAddr};
 
 const PIC1: u16 = 0x21;
 const PIC2: u16 = 0xA1;
@@ -31,32 +32,57 @@ fn interrupt_index(irq: u8) -> u8 {
 fn default_handler() {}
 
 lazy_static! {
-    static ref IRQ_HANDLERS: Mutex<[fn(); 16]> = {
-        Mutex::new([default_handler; 16])
+    static ref IRQ_HANDLERS: IrqMutex<[fn(); 16]> = {
+        IrqMutex::new([default_handler; 16])
     };
 
     static ref IDT: InterruptDescriptorTable = {
         let mut idt = InterruptDescriptorTable::new();
-        idt.breakpoint.set_handler_fn(breakpoint_handler);
         idt.stack_segment_fault.set_handler_fn(stack_segment_fault_handler);
         idt.segment_not_present.set_handler_fn(segment_not_present_handler);
         unsafe {
             idt.double_fault.
                 set_handler_fn(double_fault_handler).
                 set_stack_index(sys::gdt::DOUBLE_FAULT_IST);
+
+            // The registers of a process killed by a fault are saved in its
+            // core file
+            let f = wrapped_page_fault_handler as *mut fn();
+            let f = core::mem::transmute::<*mut fn(), PageFaultHandlerFunc>(f);
             idt.page_fault.
-                set_handler_fn(page_fault_handler).
+                set_handler_fn(f).
                 set_stack_index(sys::gdt::PAGE_FAULT_IST);
+            let f = wrapped_general_protection_fault_handler as *mut fn();
+            let f: HandlerFuncWithErrCode = core::mem::transmute(f);
             idt.general_protection_fault.
-                set_handler_fn(general_protection_fault_handler).
+                set_handler_fn(f).
                 set_stack_index(sys::gdt::GENERAL_PROTECTION_FAULT_IST);
 
+            // The breakpoints of the user programs are handled by the stub
+            // of the debugger, which needs to change the saved registers
+            let f = wrapped_breakpoint_handler as *mut fn();
+            let f = core::mem::transmute::<*mut fn(), HandlerFunc>(f);
+            idt.breakpoint.
+                set_handler_fn(f).
+                set_privilege_level(x86_64::PrivilegeLevel::Ring3);
+            let f = wrapped_debug_handler as *mut fn();
+            let f = core::mem::transmute::<*mut fn(), HandlerFunc>(f);
+            idt.debug.set_handler_fn(f);
+
             let f = wrapped_syscall_handler as *mut fn();
             idt[0x80].
                 set_handler_fn(core::mem::transmute(f)).
                 set_privilege_level(x86_64::PrivilegeLevel::Ring3);
         }
-        idt[interrupt_index(0)].set_handler_fn(irq0_handler);
+        unsafe {
+            // The timer has its own stack to switch to a process that could
+            // resume on another CPU the kernel stack of the current one
+            let f = wrapped_pit_handler as *mut fn();
+            let f = core::mem::transmute::<*mut fn(), HandlerFunc>(f);
+            idt[interrupt_index(0)].
+                set_handler_fn(f).
+                set_stack_index(sys::gdt::TIMER_IST);
+        }
         idt[interrupt_index(1)].set_handler_fn(irq1_handler);
         idt[interrupt_index(2)].set_handler_fn(irq2_handler);
         idt[interrupt_index(3)].set_handler_fn(irq3_handler);
@@ -72,6 +98,7 @@ lazy_static! {
         idt[interrupt_index(13)].set_handler_fn(irq13_handler);
         idt[interrupt_index(14)].set_handler_fn(irq14_handler);
         idt[interrupt_index(15)].set_handler_fn(irq15_handler);
+        idt[sys::lapic::SPURIOUS_VECTOR].set_handler_fn(spurious_handler);
         idt
     };
 }
@@ -81,16 +108,11 @@ macro_rules! irq_handler {
         pub extern "x86-interrupt" fn $handler(_: InterruptStackFrame) {
             let handlers = IRQ_HANDLERS.lock();
             handlers[$irq]();
-            unsafe {
-                sys::pic::PICS.lock().notify_end_of_interrupt(
-                    interrupt_index($irq)
-                );
-            }
+            sys::pic::notify_end_of_interrupt($irq);
         }
     };
 }
 
-irq_handler!(irq0_handler, 0);
 irq_handler!(irq1_handler, 1);
 irq_handler!(irq2_handler, 2);
 irq_handler!(irq3_handler, 3);
@@ -107,11 +129,7 @@ irq_handler!(irq13_handler, 13);
 irq_handler!(irq14_handler, 14);
 irq_handler!(irq15_handler, 15);
 
-extern "x86-interrupt" fn breakpoint_handler(stack_frame: InterruptStackFrame) {
-    debug!("EXCEPTION: BREAKPOINT");
-    debug!("Stack Frame: {:#?}", stack_frame);
-    panic!();
-}
+extern "x86-interrupt" fn spurious_handler(_: InterruptStackFrame) {}
 
 extern "x86-interrupt" fn double_fault_handler(
     stack_frame: InterruptStackFrame,
@@ -119,14 +137,17 @@ extern "x86-interrupt" fn double_fault_handler(
 ) -> ! {
     debug!("EXCEPTION: DOUBLE FAULT");
     debug!("Stack Frame: {:#?}", stack_frame);
+    sys::backtrace::print_exception(stack_frame.instruction_pointer.as_u64());
     debug!("Error: {:?}", error_code);
     panic!();
 }
 
-extern "x86-interrupt" fn page_fault_handler(
-    _stack_frame: InterruptStackFrame,
-    error_code: PageFaultErrorCode,
+extern "sysv64" fn page_fault_handler(
+    stack_frame: &mut InterruptStackFrame,
+    regs: &mut Registers,
+    error_code: u64,
 ) {
+    let error_code = PageFaultErrorCode::from_bits_truncate(error_code);
     let csi_color = api::console::Style::color("red");
     let csi_reset = api::console::Style::reset();
     let addr = Cr2::read().unwrap().as_u64();
@@ -137,52 +158,75 @@ extern "x86-interrupt" fn page_fault_handler(
         OffsetPageTable::new(page_table, VirtAddr::new(phys_mem_offset()))
     };
 
-    if error_code.contains(PageFaultErrorCode::CAUSED_BY_WRITE) {
-        if sys::mem::alloc_pages(&mut mapper, addr, 1).is_err() {
-            printk!(
-                "{}Error:{} Could not allocate page at {:#X}\n",
-                csi_color, csi_reset, addr
-            );
-            if error_code.contains(PageFaultErrorCode::USER_MODE) {
-                api::syscall::exit(ExitCode::PageFaultError);
-            } else {
-                hlt_loop();
-            }
+    // The pages of the heap and the stack of a process are allocated on
+    // demand, but the access to a page that is present was not allowed
+    let is_present = error_code.contains(
+        PageFaultErrorCode::PROTECTION_VIOLATION
+    );
+    // The pages shared by a fork are copied when they are written to, which
+    // can also be done by the kernel during a syscall
+    let is_write = error_code.contains(PageFaultErrorCode::CAUSED_BY_WRITE);
+    if is_present && is_write {
+        let res = sys::mem::copy_on_write(&mut mapper, addr);
+        if res.is_ok() {
+            return;
         }
-    } else if error_code.contains(PageFaultErrorCode::USER_MODE) {
-        // TODO: This should be removed when the process page table is no
-        // longer a simple clone of the kernel page table. Currently a process
-        // is executed from its kernel address that is shared with the process.
-        let start = (addr / 4096) * 4096;
-        if sys::mem::alloc_pages(&mut mapper, start, 4096).is_ok() {
-            if sys::process::is_userspace(start) {
-                let code_addr = sys::process::code_addr();
-                let src = (code_addr + start) as *mut u8;
-                let dst = start as *mut u8;
-                unsafe {
-                    core::ptr::copy_nonoverlapping(src, dst, 4096);
-                }
-            }
+    }
+
+    if !is_present && sys::process::is_demand_paged(addr) {
+        let flags = PageTableFlags::PRESENT
+                  | PageTableFlags::WRITABLE
+                  | PageTableFlags::USER_ACCESSIBLE
+                  | PageTableFlags::NO_EXECUTE;
+        if sys::mem::map_pages(&mut mapper, addr, 1, flags).is_ok() {
+            return;
         }
+        printk!(
+            "{}Error:{} Could not allocate page at {:#X}\n",
+            csi_color, csi_reset, addr
+        );
     } else {
         printk!(
             "{}Error:{} Page fault exception at {:#X}\n",
             csi_color, csi_reset, addr
         );
-        if error_code.contains(PageFaultErrorCode::USER_MODE) {
-            api::syscall::exit(ExitCode::PageFaultError);
-        } else {
-            hlt_loop();
-        }
+    }
+    if error_code.contains(PageFaultErrorCode::USER_MODE) {
+        let code = ExitCode::PageFaultError;
+        crash_process(stack_frame, regs, addr, code, true);
+    } else if sys::process::is_user() {
+        // The kernel was given a bad pointer in a syscall of a user process
+        let addr = stack_frame.instruction_pointer.as_u64();
+        sys::backtrace::print_exception(addr);
+        let code = ExitCode::PageFaultError;
+        crash_process(stack_frame, regs, addr, code, false);
+    } else {
+        let addr = stack_frame.instruction_pointer.as_u64();
+        sys::backtrace::print_exception(addr);
+        hlt_loop();
     }
 }
 
-extern "x86-interrupt" fn general_protection_fault_handler(
-    stack_frame: InterruptStackFrame,
+extern "sysv64" fn general_protection_fault_handler(
+    stack_frame: &mut InterruptStackFrame,
+    regs: &mut Registers,
     error_code: u64,
 ) {
+    if stack_frame.code_segment.rpl() == PrivilegeLevel::Ring3 {
+        let csi_color = api::console::Style::color("red");
+        let csi_reset = api::console::Style::reset();
+        let addr = stack_frame.instruction_pointer.as_u64();
+        printk!(
+            "{}Error:{} General protection fault at {:#X}\n",
+            csi_color, csi_reset, addr
+        );
+        let code = ExitCode::ProtectionFaultError;
+        crash_process(stack_frame, regs, 0, code, true);
+        return;
+    }
     debug!("EXCEPTION: GENERAL PROTECTION FAULT");
     debug!("Stack Frame: {:#?}", stack_frame);
+    sys::backtrace::print_exception(stack_frame.instruction_pointer.as_u64());
     debug!("Error: {:?}", error_code);
     panic!();
 }
@@ -193,6 +237,7 @@ extern "x86-interrupt" fn stack_segment_fault_handler(
 ) {
     debug!("EXCEPTION: STACK SEGMENT FAULT");
     debug!("Stack Frame: {:#?}", stack_frame);
+    sys::backtrace::print_exception(stack_frame.instruction_pointer.as_u64());
     debug!("Error: {:?}", error_code);
     panic!();
 }
@@ -203,11 +248,36 @@ extern "x86-interrupt" fn segment_not_present_handler(
 ) {
     debug!("EXCEPTION: SEGMENT NOT PRESENT");
     debug!("Stack Frame: {:#?}", stack_frame);
+    sys::backtrace::print_exception(stack_frame.instruction_pointer.as_u64());
     debug!("Error: {:?}", error_code);
     panic!();
 }
 
-// Naked function wrapper saving all scratch registers to the stack
+// Resume the process raising an exception in the kernel to write its core
+// file and exit, or terminate it without a core file if it has no stack
+fn crash_process(
+    stack_frame: &mut InterruptStackFrame,
+    regs: &mut Registers,
+    addr: u64,
+    code: ExitCode,
+    dump: bool
+) {
+    let crash = sys::process::Crash {
+        stack_frame: **stack_frame,
+        registers: *regs,
+        addr,
+        code,
+        dump,
+    };
+    if let Some((sf, r)) = sys::process::crash(crash) {
+        set_context(stack_frame, regs, sf, r);
+    } else {
+        sys::process::exit(code);
+        switch_from_zombie(stack_frame, regs);
+    }
+}
+
+// Naked function wrapper saving all general purpose registers to the stack
 // See: https://os.phil-opp.com/returning-from-exceptions/
 macro_rules! wrap {
     ($fn: ident => $w:ident) => {
@@ -215,26 +285,38 @@ macro_rules! wrap {
         pub unsafe extern "sysv64" fn $w() {
             naked_asm!(
                 "push rax",
+                "push rbx",
                 "push rcx",
                 "push rdx",
                 "push rsi",
                 "push rdi",
+                "push rbp",
                 "push r8",
                 "push r9",
                 "push r10",
                 "push r11",
+                "push r12",
+                "push r13",
+                "push r14",
+                "push r15",
                 "mov rsi, rsp", // Arg #2: register list
                 "mov rdi, rsp", // Arg #1: interupt frame
-                "add rdi, 9 * 8", // 9 registers * 8 bytes
+                "add rdi, 15 * 8", // 15 registers * 8 bytes
                 "call {}",
+                "pop r15",
+                "pop r14",
+                "pop r13",
+                "pop r12",
                 "pop r11",
                 "pop r10",
                 "pop r9",
                 "pop r8",
+                "pop rbp",
                 "pop rdi",
                 "pop rsi",
                 "pop rdx",
                 "pop rcx",
+                "pop rbx",
                 "pop rax",
                 "iretq",
                 sym $fn
@@ -243,7 +325,107 @@ macro_rules! wrap {
     };
 }
 
+// Naked function wrapper for the exceptions pushing an error code after the
+// interrupt frame, given as a third argument and removed before returning
+macro_rules! wrap_with_error_code {
+    ($fn: ident => $w:ident) => {
+        /// # Safety
+        ///
+        /// This function must only be called by the CPU as the handler of an
+        /// exception pushing an error code.
+        #[naked]
+        pub unsafe extern "sysv64" fn $w() {
+            naked_asm!(
+                "push rax",
+                "push rbx",
+                "push rcx",
+                "push rdx",
+                "push rsi",
+                "push rdi",
+                "push rbp",
+                "push r8",
+                "push r9",
+                "push r10",
+                "push r11",
+                "push r12",
+                "push r13",
+                "push r14",
+                "push r15",
+                "mov rsi, rsp", // Arg #2: register list
+                "mov rdi, rsp", // Arg #1: interupt frame
+                "add rdi, 16 * 8", // 15 registers + error code * 8 bytes
+                "mov rdx, [rsp + 15 * 8]", // Arg #3: error code
+                "sub rsp, 8", // Align the stack to 16 bytes
+                "call {}",
+                "add rsp, 8",
+                "pop r15",
+                "pop r14",
+                "pop r13",
+                "pop r12",
+                "pop r11",
+                "pop r10",
+                "pop r9",
+                "pop r8",
+                "pop rbp",
+                "pop rdi",
+                "pop rsi",
+                "pop rdx",
+                "pop rcx",
+                "pop rbx",
+                "pop rax",
+                "add rsp, 8", // Error code
+                "iretq",
+                sym $fn
+            );
+        }
+    };
+}
+
 wrap!(syscall_handler => wrapped_syscall_handler);
+wrap!(pit_handler => wrapped_pit_handler);
+wrap!(breakpoint_handler => wrapped_breakpoint_handler);
+wrap!(debug_handler => wrapped_debug_handler);
+wrap_with_error_code!(page_fault_handler => wrapped_page_fault_handler);
+wrap_with_error_code!(
+    general_protection_fault_handler => wrapped_general_protection_fault_handler
+);
+
+extern "sysv64" fn breakpoint_handler(
+    stack_frame: &mut InterruptStackFrame,
+    regs: &mut Registers
+) {
+    if sys::gdb::is_enabled() {
+        let (sf, r) = sys::gdb::handle_exception(**stack_frame, *regs);
+        set_context(stack_frame, regs, sf, r);
+        return;
+    }
+    let addr = stack_frame.instruction_pointer.as_u64();
+    if stack_frame.code_segment.rpl() == PrivilegeLevel::Ring3 {
+        let csi_color = api::console::Style::color("red");
+        let csi_reset = api::console::Style::reset();
+        printk!(
+            "{}Error:{} Breakpoint exception at {:#X}\n",
+            csi_color, csi_reset, addr - 1
+        );
+        crash_process(stack_frame, regs, addr, ExitCode::Failure, false);
+        return;
+    }
+    debug!("EXCEPTION: BREAKPOINT");
+    debug!("Stack Frame: {:#?}", stack_frame);
+    sys::backtrace::print_exception(addr);
+    panic!();
+}
+
+// Raised after each instruction when the trap flag is set by the debugger
+extern "sysv64" fn debug_handler(
+    stack_frame: &mut InterruptStackFrame,
+    regs: &mut Registers
+) {
+    if sys::gdb::is_enabled() {
+        let (sf, r) = sys::gdb::handle_exception(**stack_frame, *regs);
+        set_context(stack_frame, regs, sf, r);
+    }
+}
 
 // NOTE: We can't use "x86-interrupt" for syscall_handler because we need to
 // return a result in the RAX register and it will be overwritten when the
@@ -260,31 +442,95 @@ extern "sysv64" fn syscall_handler(
     let arg3 = regs.rdx;
     let arg4 = regs.r8;
 
-    // Backup CPU context before spawning a process
-    if n == sys::syscall::number::SPAWN {
+    // The context of the process is copied to its child when it forks
+    if n == sys::syscall::number::FORK {
         sys::process::set_stack_frame(**stack_frame);
         sys::process::set_registers(*regs);
     }
 
     let res = sys::syscall::dispatcher(n, arg1, arg2, arg3, arg4);
 
-    // Restore CPU context before exiting a process
-    if n == sys::syscall::number::EXIT {
-        let sf = sys::process::stack_frame();
-        unsafe {
-            // FIXME: the following line should replace the next ones
-            //stack_frame.as_mut().write(sf);
-            let inner = stack_frame.as_mut().extract_inner();
-            let ptr = inner as *mut InterruptStackFrameValue;
-            core::ptr::write_volatile(ptr, sf);
+    // Switch to the next process when the current one has exited or has
+    // been killed
+    if sys::process::state() == ProcessState::Zombie {
+        switch_from_zombie(stack_frame, regs);
+    } else {
+        regs.rax = res;
 
-            core::ptr::write_volatile(regs, sys::process::registers());
+        // Go back to the context saved before the signal handler
+        if n == sys::syscall::number::SIGRETURN {
+            if let Some((sf, r)) = sys::process::sigreturn() {
+                set_context(stack_frame, regs, sf, r);
+            }
         }
+        handle_signals(stack_frame, regs);
+    }
+}
+
+// NOTE: The PIT interrupt is not handled like the other IRQs because the
+// scheduler needs the context of the interrupted code to switch it with the
+// context of the next process. The timers of the APs use the same vector
+// but only the BSP updates the clock.
+extern "sysv64" fn pit_handler(
+    stack_frame: &mut InterruptStackFrame,
+    regs: &mut Registers
+) {
+    if sys::smp::is_bsp() {
+        let handlers = IRQ_HANDLERS.lock();
+        handlers[0]();
+    }
+    sys::pic::notify_end_of_interrupt(0);
+
+    if let Some((sf, r)) = sys::clk::preempt(**stack_frame, *regs) {
+        set_context(stack_frame, regs, sf, r);
+    }
+    handle_signals(stack_frame, regs);
+}
+
+// Deliver the next signal of the current process before it goes back to user
+// mode, and switch to the next process if the signal has terminated it
+fn handle_signals(stack_frame: &mut InterruptStackFrame, regs: &mut Registers) {
+    let is_userspace = stack_frame.code_segment.rpl() == PrivilegeLevel::Ring3;
+    if !is_userspace {
+        return;
     }
+    if let Some((sf, r)) = sys::process::deliver_signal(**stack_frame, *regs) {
+        set_context(stack_frame, regs, sf, r);
+    } else if sys::process::state() == ProcessState::Zombie {
+        switch_from_zombie(stack_frame, regs);
+    }
+}
 
-    regs.rax = res;
+// Switch to the next process after the exit of the current one, which can
+// always be done once the process table is no longer locked by another CPU
+fn switch_from_zombie(
+    stack_frame: &mut InterruptStackFrame,
+    regs: &mut Registers
+) {
+    loop {
+        if let Some((sf, r)) = sys::process::schedule(**stack_frame, *regs) {
+            set_context(stack_frame, regs, sf, r);
+            return;
+        }
+        core::hint::spin_loop();
+    }
+}
 
-    unsafe { sys::pic::PICS.lock().notify_end_of_interrupt(0x80) };
+// Replace the context that will be restored by the interrupt handler
+fn set_context(
+    stack_frame: &mut InterruptStackFrame,
+    regs: &mut Registers,
+    sf: InterruptStackFrameValue,
+    r: Registers
+) {
+    unsafe {
+        // FIXME: the following line should replace the next ones
+        //stack_frame.as_mut().write(sf);
+        let inner = stack_frame.as_mut().extract_inner();
+        let ptr = inner as *mut InterruptStackFrameValue;
+        core::ptr::write_volatile(ptr, sf);
+        core::ptr::write_volatile(regs, r);
+    }
 }
 
 pub fn set_irq_handler(irq: u8, handler: fn()) {
@@ -297,6 +543,9 @@ pub fn set_irq_handler(irq: u8, handler: fn()) {
 }
 
 pub fn set_irq_mask(irq: u8) {
+    if sys::ioapic::is_enabled() {
+        return sys::ioapic::set_mask(irq, true);
+    }
     let mut port: Port<u8> = Port::new(if irq < 8 { PIC1 } else { PIC2 });
     unsafe {
         let value = port.read() | (1 << (if irq < 8 { irq } else { irq - 8 }));
@@ -305,6 +554,9 @@ pub fn set_irq_mask(irq: u8) {
 }
 
 pub fn clear_irq_mask(irq: u8) {
+    if sys::ioapic::is_enabled() {
+        return sys::ioapic::set_mask(irq, false);
+    }
     let mut port: Port<u8> = Port::new(if irq < 8 { PIC1 } else { PIC2 });
     unsafe {
         let value = port.read() & !(1 << if irq < 8 { irq } else { irq - 8 });
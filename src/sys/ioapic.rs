@@ -0,0 +1,173 @@
+use crate::sys;
+use crate::sys::smp::IrqMutex;
+
+use acpi::platform::interrupt::{
+    InterruptSourceOverride, IoApic, Polarity, TriggerMode,
+};
+use core::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use x86_64::instructions::interrupts;
+use x86_64::{PhysAddr, VirtAddr};
+
+// Registers of the I/O APIC
+const IOREGSEL: u64 = 0x00;
+const IOWIN: u64 = 0x10;
+const IOAPICVER: u32 = 0x01;
+const IOREDTBL: u32 = 0x10; // Redirection table
+
+const ACTIVE_LOW: u64 = 1 << 13;
+const LEVEL_TRIGGERED: u64 = 1 << 15;
+const MASKED: u64 = 1 << 16;
+
+// The registers are mapped after the registers of the Local APIC
+const VIRT_ADDR: u64 = 0xFFFF_FF00_8000_1000;
+
+const ISA_IRQS: usize = 16;
+
+#[derive(Clone, Copy)]
+struct Route {
+    gsi: u32,
+    flags: u64,
+}
+
+static ADDR: AtomicU64 = AtomicU64::new(0);
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static GSI_BASE: AtomicU64 = AtomicU64::new(0);
+static ROUTES: IrqMutex<[Option<Route>; ISA_IRQS]> = IrqMutex::new(
+    [None; ISA_IRQS]
+);
+
+// Route the ISA IRQs to the vectors used with the PIC, on the BSP, with the
+// interrupt source overrides of the MADT, and replace the PIT interrupt by
+// the timer of the Local APIC.
+pub fn init(ioapics: &[IoApic], overrides: &[InterruptSourceOverride]) {
+    if !sys::lapic::is_enabled() {
+        return;
+    }
+    // Only the first I/O APIC is used for the ISA IRQs
+    let ioapic = match ioapics.first() {
+        Some(ioapic) => ioapic,
+        None => return,
+    };
+    let res = unsafe {
+        sys::mem::map_contiguous_physical_region(
+            sys::mem::mapper(),
+            PhysAddr::new(ioapic.address as u64),
+            VirtAddr::new(VIRT_ADDR),
+            4096,
+        )
+    };
+    if res.is_err() {
+        debug!("IOAPIC: Could not map registers at {:#X}", ioapic.address);
+        return;
+    }
+    ADDR.store(VIRT_ADDR, Ordering::SeqCst);
+    let gsi_base = ioapic.global_system_interrupt_base;
+    GSI_BASE.store(gsi_base as u64, Ordering::SeqCst);
+
+    let mut routes = [None; ISA_IRQS];
+    for ovr in overrides.iter() {
+        let irq = ovr.isa_source as usize;
+        if irq >= ISA_IRQS {
+            continue;
+        }
+        let mut flags = 0;
+        if ovr.polarity == Polarity::ActiveLow {
+            flags |= ACTIVE_LOW;
+        }
+        if ovr.trigger_mode == TriggerMode::Level {
+            flags |= LEVEL_TRIGGERED;
+        }
+        routes[irq] = Some(Route { gsi: ovr.global_system_interrupt, flags });
+    }
+    // The other IRQs are identity mapped unless their GSI is already used
+    // by an override, like the cascade IRQ 2 often replaced by the PIT
+    for irq in 0..ISA_IRQS {
+        let gsi = irq as u32;
+        let is_used = routes.iter().flatten().any(|r| r.gsi == gsi);
+        if routes[irq].is_none() && !is_used {
+            routes[irq] = Some(Route { gsi, flags: 0 });
+        }
+    }
+    let max_entry = (read(IOAPICVER) >> 16) & 0xFF;
+    let is_valid = |r: &Route| {
+        r.gsi >= gsi_base && r.gsi - gsi_base <= max_entry
+    };
+    if !routes.iter().flatten().all(is_valid) {
+        debug!("IOAPIC: Could not route the ISA IRQs");
+        return;
+    }
+
+    sys::lapic::calibrate_timer(50);
+
+    interrupts::without_interrupts(|| {
+        // Keep the IRQs that were masked on the PIC masked on the I/O APIC
+        let masks = sys::pic::disable();
+        let masks = (masks[0] as u16) | (masks[1] as u16) << 8;
+        let bsp = sys::lapic::id() as u64;
+        for (irq, route) in routes.iter().enumerate() {
+            if let Some(route) = route {
+                let vector = (sys::pic::PIC_1_OFFSET + irq as u8) as u64;
+                let mut entry = (bsp << 56) | route.flags | vector;
+
+                // The PIT is replaced by the timer of the Local APIC
+                if irq == 0 || irq == 2 || masks & (1 << irq) != 0 {
+                    entry |= MASKED;
+                }
+                write_entry(route.gsi - gsi_base, entry);
+            }
+        }
+        *ROUTES.lock() = routes;
+        ENABLED.store(true, Ordering::SeqCst);
+        if sys::lapic::start_timer(sys::pic::PIC_1_OFFSET).is_err() {
+            // Fall back to the PIT routed through the I/O APIC
+            set_mask(0, false);
+        }
+    });
+    log!("APIC IRQs routed through the IOAPIC");
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::SeqCst)
+}
+
+pub fn set_mask(irq: u8, masked: bool) {
+    // The lock is kept to select and access the registers of the entry
+    let routes = ROUTES.lock();
+    if let Some(route) = routes[irq as usize] {
+        let gsi_base = GSI_BASE.load(Ordering::SeqCst) as u32;
+        let n = route.gsi - gsi_base;
+        let entry = read_entry(n);
+        if masked {
+            write_entry(n, entry | MASKED);
+        } else {
+            write_entry(n, entry & !MASKED);
+        }
+    }
+}
+
+fn read_entry(n: u32) -> u64 {
+    let lo = read(IOREDTBL + 2 * n) as u64;
+    let hi = read(IOREDTBL + 2 * n + 1) as u64;
+    (hi << 32) | lo
+}
+
+fn write_entry(n: u32, entry: u64) {
+    write(IOREDTBL + 2 * n, entry as u32);
+    write(IOREDTBL + 2 * n + 1, (entry >> 32) as u32);
+}
+
+fn read(reg: u32) -> u32 {
+    let addr = ADDR.load(Ordering::SeqCst);
+    unsafe {
+        core::ptr::write_volatile((addr + IOREGSEL) as *mut u32, reg);
+        core::ptr::read_volatile((addr + IOWIN) as *const u32)
+    }
+}
+
+fn write(reg: u32, value: u32) {
+    let addr = ADDR.load(Ordering::SeqCst);
+    unsafe {
+        core::ptr::write_volatile((addr + IOREGSEL) as *mut u32, reg);
+        core::ptr::write_volatile((addr + IOWIN) as *mut u32, value);
+    }
+}
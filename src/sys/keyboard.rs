@@ -1,12 +1,14 @@
 use crate::api;
 use crate::sys;
 
+use alloc::collections::VecDeque;
 use core::sync::atomic::{AtomicBool, Ordering};
 use pc_keyboard::{
     layouts, DecodedKey, Error, HandleControl, KeyCode, KeyEvent, KeyState,
     Keyboard, ScancodeSet1,
 };
 use spin::Mutex;
+use x86_64::instructions::interrupts;
 use x86_64::instructions::port::Port;
 
 pub static KEYBOARD: Mutex<Option<KeyboardLayout>> = Mutex::new(None);
@@ -15,6 +17,13 @@ pub static ALT: AtomicBool = AtomicBool::new(false);
 pub static CTRL: AtomicBool = AtomicBool::new(false);
 pub static SHIFT: AtomicBool = AtomicBool::new(false);
 
+// Raw key events are only queued while a program like a game loop needs to
+// know when keys are pressed and released, in addition to the characters
+// sent to the console.
+static EVENTS: Mutex<VecDeque<KeyEvent>> = Mutex::new(VecDeque::new());
+static EVENTS_ENABLED: AtomicBool = AtomicBool::new(false);
+const MAX_EVENTS: usize = 64;
+
 pub enum KeyboardLayout {
     Azerty(Keyboard<layouts::Azerty, ScancodeSet1>),
     Dvorak(Keyboard<layouts::Dvorak104Key, ScancodeSet1>),
@@ -74,6 +83,30 @@ pub fn init() {
     sys::idt::set_irq_handler(1, interrupt_handler);
 }
 
+pub fn enable_events() {
+    EVENTS_ENABLED.store(true, Ordering::SeqCst);
+}
+
+pub fn disable_events() {
+    EVENTS_ENABLED.store(false, Ordering::SeqCst);
+    interrupts::without_interrupts(|| EVENTS.lock().clear());
+}
+
+// Returns the oldest key event without waiting
+pub fn poll_event() -> Option<KeyEvent> {
+    interrupts::without_interrupts(|| EVENTS.lock().pop_front())
+}
+
+fn push_event(event: &KeyEvent) {
+    if EVENTS_ENABLED.load(Ordering::Relaxed) {
+        let mut events = EVENTS.lock();
+        if events.len() == MAX_EVENTS {
+            events.pop_front(); // Drop the oldest event
+        }
+        events.push_back(event.clone());
+    }
+}
+
 fn read_scancode() -> u8 {
     let mut port = Port::new(0x60);
     unsafe { port.read() }
@@ -95,6 +128,7 @@ fn interrupt_handler() {
     if let Some(ref mut keyboard) = *KEYBOARD.lock() {
         let scancode = read_scancode();
         if let Ok(Some(event)) = keyboard.add_byte(scancode) {
+            push_event(&event);
             let ord = Ordering::Relaxed;
             match event.code {
                 KeyCode::LAlt | KeyCode::RAltGr => {
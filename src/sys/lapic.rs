@@ -0,0 +1,182 @@
+use crate::sys;
+
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use raw_cpuid::CpuId;
+use x86_64::registers::model_specific::Msr;
+use x86_64::{PhysAddr, VirtAddr};
+
+// Registers of the Local APIC
+const ID: u64 = 0x20;
+const EOI: u64 = 0xB0;
+const SVR: u64 = 0xF0; // Spurious Interrupt Vector Register
+const ICR_LOW: u64 = 0x300; // Interrupt Command Register
+const ICR_HIGH: u64 = 0x310;
+const LVT_TIMER: u64 = 0x320;
+const TIMER_INITIAL: u64 = 0x380;
+const TIMER_CURRENT: u64 = 0x390;
+const TIMER_DIVIDE: u64 = 0x3E0;
+
+const ICR_INIT: u32 = 0x500;
+const ICR_STARTUP: u32 = 0x600;
+const ICR_LEVEL_ASSERT: u32 = 1 << 14;
+const ICR_DELIVERY_PENDING: u32 = 1 << 12;
+
+const LVT_MASKED: u32 = 1 << 16;
+const LVT_PERIODIC: u32 = 1 << 17;
+const DIVIDE_BY_16: u32 = 0x3;
+
+// In x2APIC mode the registers are accessed with MSRs instead of MMIO
+const APIC_BASE_MSR: u32 = 0x1B;
+const APIC_BASE_ENABLE: u64 = 1 << 11;
+const APIC_BASE_X2APIC: u64 = 1 << 10;
+const X2APIC_MSR: u32 = 0x800;
+
+pub const SPURIOUS_VECTOR: u8 = 0xFF;
+
+// The registers are mapped with caching disabled after the framebuffer
+const VIRT_ADDR: u64 = 0xFFFF_FF00_8000_0000;
+
+static ADDR: AtomicU64 = AtomicU64::new(0);
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static X2APIC: AtomicBool = AtomicBool::new(false);
+static TIMER_COUNT: AtomicU32 = AtomicU32::new(0);
+
+pub fn init(phys_addr: u64) {
+    let has_x2apic = CpuId::new().get_feature_info().
+        is_some_and(|info| info.has_x2apic());
+    if has_x2apic {
+        X2APIC.store(true, Ordering::SeqCst);
+    } else {
+        let res = unsafe {
+            sys::mem::map_contiguous_physical_region(
+                sys::mem::mapper(),
+                PhysAddr::new(phys_addr),
+                VirtAddr::new(VIRT_ADDR),
+                4096,
+            )
+        };
+        if res.is_err() {
+            debug!("LAPIC: Could not map registers at {:#X}", phys_addr);
+            return;
+        }
+        ADDR.store(VIRT_ADDR, Ordering::SeqCst);
+    }
+    ENABLED.store(true, Ordering::SeqCst);
+    enable();
+    let mode = if has_x2apic { "x2APIC" } else { "xAPIC" };
+    log!("APIC {} enabled", mode);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::SeqCst)
+}
+
+fn is_x2apic() -> bool {
+    X2APIC.load(Ordering::SeqCst)
+}
+
+// Enable the Local APIC of the current CPU
+pub fn enable() {
+    if is_x2apic() {
+        let mut msr = Msr::new(APIC_BASE_MSR);
+        unsafe {
+            let base = msr.read();
+            msr.write(base | APIC_BASE_ENABLE | APIC_BASE_X2APIC);
+        }
+    }
+    let svr = read(SVR);
+    write(SVR, svr | 0x100 | SPURIOUS_VECTOR as u32);
+}
+
+// Return the ID of the Local APIC of the current CPU
+pub fn id() -> u32 {
+    if !is_enabled() {
+        0
+    } else if is_x2apic() {
+        read(ID)
+    } else {
+        read(ID) >> 24
+    }
+}
+
+pub fn eoi() {
+    write(EOI, 0);
+}
+
+pub fn send_init(apic_id: u32) {
+    send(apic_id, ICR_INIT | ICR_LEVEL_ASSERT);
+}
+
+// Start an AP at the address of the given page of the low memory
+pub fn send_startup(apic_id: u32, page: u8) {
+    send(apic_id, ICR_STARTUP | ICR_LEVEL_ASSERT | page as u32);
+}
+
+// Count the ticks of the timer of the Local APIC during the given number of
+// PIT ticks to use it with the same interval
+pub fn calibrate_timer(pit_ticks: usize) {
+    write(TIMER_DIVIDE, DIVIDE_BY_16);
+    write(LVT_TIMER, LVT_MASKED);
+
+    // Wait for the beginning of a PIT tick
+    let start = sys::clk::ticks();
+    while sys::clk::ticks() == start {
+        sys::clk::halt();
+    }
+
+    write(TIMER_INITIAL, u32::MAX);
+    let start = sys::clk::ticks();
+    while sys::clk::ticks() - start < pit_ticks {
+        sys::clk::halt();
+    }
+    let count = u32::MAX - read(TIMER_CURRENT);
+    write(TIMER_INITIAL, 0);
+
+    TIMER_COUNT.store(count / pit_ticks as u32, Ordering::SeqCst);
+}
+
+// Start the periodic timer of the current CPU with the calibrated interval
+pub fn start_timer(vector: u8) -> Result<(), ()> {
+    let count = TIMER_COUNT.load(Ordering::SeqCst);
+    if count == 0 {
+        return Err(());
+    }
+    write(TIMER_DIVIDE, DIVIDE_BY_16);
+    write(LVT_TIMER, LVT_PERIODIC | vector as u32);
+    write(TIMER_INITIAL, count);
+    Ok(())
+}
+
+fn send(apic_id: u32, command: u32) {
+    if is_x2apic() {
+        let mut msr = Msr::new(X2APIC_MSR + (ICR_LOW >> 4) as u32);
+        unsafe {
+            msr.write(((apic_id as u64) << 32) | command as u64);
+        }
+        return;
+    }
+    write(ICR_HIGH, apic_id << 24);
+    write(ICR_LOW, command);
+    while read(ICR_LOW) & ICR_DELIVERY_PENDING != 0 {
+        core::hint::spin_loop();
+    }
+}
+
+fn read(reg: u64) -> u32 {
+    if is_x2apic() {
+        let msr = Msr::new(X2APIC_MSR + (reg >> 4) as u32);
+        return unsafe { msr.read() as u32 };
+    }
+    let addr = ADDR.load(Ordering::SeqCst) + reg;
+    unsafe { core::ptr::read_volatile(addr as *const u32) }
+}
+
+fn write(reg: u64, value: u32) {
+    if is_x2apic() {
+        let mut msr = Msr::new(X2APIC_MSR + (reg >> 4) as u32);
+        unsafe { msr.write(value as u64) };
+        return;
+    }
+    let addr = ADDR.load(Ordering::SeqCst) + reg;
+    unsafe { core::ptr::write_volatile(addr as *mut u32, value) }
+}
@@ -0,0 +1,415 @@
+use crate::sys;
+use crate::sys::fs::FileIO;
+
+use alloc::collections::btree_map::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use object::elf::{
+    DT_NEEDED, PF_W, PF_X, PT_TLS, R_X86_64_64, R_X86_64_DTPMOD64,
+    R_X86_64_DTPOFF64, R_X86_64_GLOB_DAT, R_X86_64_JUMP_SLOT,
+    R_X86_64_RELATIVE, R_X86_64_TPOFF64,
+};
+use object::read::elf::{Dyn, ElfFile64, ProgramHeader};
+use object::{
+    Object, ObjectKind, ObjectSegment, ObjectSymbol, ObjectSymbolTable,
+    RelocationFlags, RelocationTarget, SegmentFlags,
+};
+use x86_64::structures::paging::{OffsetPageTable, PageTableFlags, Translate};
+use x86_64::VirtAddr;
+
+const ELF_MAGIC: [u8; 4] = [0x7F, b'E', b'L', b'F'];
+
+// The shared libraries needed by a program are searched in this directory
+const LIB_DIR: &str = "/lib";
+const MAX_LIBS: usize = 16;
+
+// Size of the thread control block at the thread pointer, which only holds
+// a pointer to itself
+const TCB_SIZE: u64 = 8;
+
+/// Addresses of a program loaded with its libraries.
+pub struct Image {
+    pub entry: u64,
+    pub end: u64,
+    pub fs_base: u64,
+    pub tls: Tls,
+}
+
+/// Initialization image of the TLS blocks of the modules of a program, which
+/// are copied below the thread pointer of each of its threads.
+#[derive(Clone, Debug, Default)]
+pub struct Tls {
+    pub image: Vec<u8>,
+    pub align: u64,
+}
+
+impl Tls {
+    pub fn is_empty(&self) -> bool {
+        self.image.is_empty()
+    }
+
+    /// Returns the size of the pages holding the TLS blocks and the TCB.
+    pub fn size(&self) -> usize {
+        let size = self.image.len() as u64;
+        (size + self.align.max(16) + TCB_SIZE).next_multiple_of(4096) as usize
+    }
+}
+
+// The executable or one of its libraries, loaded at an offset from the
+// addresses of its segments
+struct Module<'a> {
+    elf: ElfFile64<'a>,
+    bias: u64,
+    tls_offset: u64,
+}
+
+// A symbol defined by a module, with an offset in the TLS block of the module
+// instead of an address for a thread-local variable
+#[derive(Clone, Copy)]
+struct Definition {
+    module: usize,
+    value: u64,
+}
+
+/// Reads the shared libraries needed by a binary and by its libraries, in
+/// the breadth-first order used to look up their symbols.
+pub fn libraries(bin: &[u8]) -> Result<Vec<Vec<u8>>, ()> {
+    let mut names = needed(bin)?;
+    let mut libs = Vec::new();
+    while libs.len() < names.len() {
+        if libs.len() == MAX_LIBS {
+            return Err(());
+        }
+        let path = format!("{}/{}", LIB_DIR, names[libs.len()]);
+        let mut file = sys::fs::File::open(&path).ok_or(())?;
+        let mut lib = vec![0; file.size()];
+        let n = file.read(&mut lib)?;
+        lib.resize(n, 0);
+        for name in needed(&lib)? {
+            if !names.contains(&name) {
+                names.push(name);
+            }
+        }
+        libs.push(lib);
+    }
+    Ok(libs)
+}
+
+/// Returns the size of the memory used after a program by its libraries and
+/// by the TLS blocks of its modules.
+pub fn libraries_size(bin: &[u8], libs: &[Vec<u8>]) -> Result<usize, ()> {
+    let mut size = 0;
+    let mut sizes = Vec::new();
+    let bufs = core::iter::once(bin).chain(libs.iter().map(|l| l.as_slice()));
+    for (i, buf) in bufs.enumerate() {
+        let elf = ElfFile64::parse(buf).map_err(|_| ())?;
+        if i > 0 {
+            size += span(&elf);
+        }
+        if let Some((_, memsz, align)) = tls_segment(&elf) {
+            sizes.push((memsz, align));
+        }
+    }
+    let (_, tls_size) = tls_offsets(&sizes);
+    if tls_size > 0 {
+        let align = sizes.iter().map(|(_, align)| *align).max().unwrap_or(1);
+        size += (tls_size + align.max(16) + TCB_SIZE).next_multiple_of(4096);
+    }
+    Ok(size as usize)
+}
+
+/// Loads an ELF binary in the memory of a process with the shared libraries
+/// it needs, applies their relocations, and sets up the TLS block of the
+/// initial thread. The segment of the interpreter is ignored.
+pub fn load(
+    mapper: &mut OffsetPageTable,
+    code_addr: u64,
+    bin: &[u8],
+    libs: &[Vec<u8>],
+) -> Result<Image, ()> {
+    // A position-independent executable is linked at address zero like the
+    // libraries, which are loaded after it
+    let mut modules = Vec::new();
+    let mut end = 0;
+    let bufs = core::iter::once(bin).chain(libs.iter().map(|l| l.as_slice()));
+    for (i, buf) in bufs.enumerate() {
+        let elf = ElfFile64::parse(buf).map_err(|_| ())?;
+        let bias = match (i, elf.kind()) {
+            (0, ObjectKind::Dynamic) => code_addr,
+            (0, _) => 0,
+            (_, ObjectKind::Dynamic) => end,
+            (_, _) => return Err(()),
+        };
+        end = bias + span(&elf);
+        modules.push(Module { elf, bias, tls_offset: 0 });
+    }
+    for module in modules.iter() {
+        load_segments(mapper, module)?;
+    }
+    let (fs_base, tls) = load_tls(mapper, &mut modules, &mut end)?;
+
+    let mut symbols = BTreeMap::new();
+    for (i, module) in modules.iter().enumerate() {
+        for sym in module.elf.dynamic_symbols() {
+            if sym.is_undefined() || !(sym.is_global() || sym.is_weak()) {
+                continue;
+            }
+            if let Ok(name) = sym.name_bytes() {
+                let def = Definition { module: i, value: sym.address() };
+                symbols.entry(name).or_insert(def);
+            }
+        }
+    }
+    for i in 0..modules.len() {
+        relocate(mapper, &modules, i, &symbols)?;
+    }
+
+    let exe = &modules[0];
+    Ok(Image { entry: exe.bias + exe.elf.entry(), end, fs_base, tls })
+}
+
+// NOTE: The size of a segment in memory can be larger than on the disk
+// because the object can contain uninitialized sections like ".bss" that has
+// a length but no data.
+fn load_segments(
+    mapper: &mut OffsetPageTable,
+    module: &Module,
+) -> Result<(), ()> {
+    for segment in module.elf.segments() {
+        if let Ok(data) = segment.data() {
+            let addr = module.bias + segment.address();
+            let size = segment.size() as usize;
+            let flags = segment_flags(segment.flags());
+            load_binary(mapper, addr, size, data, flags)?;
+        }
+    }
+    Ok(())
+}
+
+// The TLS blocks of the modules are placed below the thread pointer, at the
+// end of the image, which points to itself (variant II of the ELF TLS ABI)
+fn load_tls(
+    mapper: &mut OffsetPageTable,
+    modules: &mut [Module],
+    end: &mut u64,
+) -> Result<(u64, Tls), ()> {
+    let segments: Vec<_> = modules.iter().map(|m| {
+        tls_segment(&m.elf)
+    }).collect();
+    let sizes: Vec<_> = segments.iter().map(|s| {
+        s.as_ref().map_or((0, 1), |(_, memsz, align)| (*memsz, *align))
+    }).collect();
+    let (offsets, size) = tls_offsets(&sizes);
+    if size == 0 {
+        return Ok((0, Tls::default()));
+    }
+
+    // The uninitialized part of each block is zeroed
+    let mut image = vec![0; size as usize];
+    for (i, module) in modules.iter_mut().enumerate() {
+        module.tls_offset = offsets[i];
+        if let Some((data, _, _)) = &segments[i] {
+            let j = (size - offsets[i]) as usize;
+            image[j..j + data.len()].copy_from_slice(data);
+        }
+    }
+    let align = sizes.iter().map(|(_, align)| *align).max().unwrap_or(1);
+    let tls = Tls { image, align };
+
+    let tp = map_tls(mapper, end.next_multiple_of(4096), &tls)?;
+    *end = (tp + TCB_SIZE).next_multiple_of(4096);
+    Ok((tp, tls))
+}
+
+/// Maps the TLS blocks and the TCB of a thread at the beginning of the pages
+/// at the given address, and returns its thread pointer.
+pub fn map_tls(
+    mapper: &mut OffsetPageTable,
+    addr: u64,
+    tls: &Tls,
+) -> Result<u64, ()> {
+    let size = tls.image.len() as u64;
+    let tp = (addr + size).next_multiple_of(tls.align.max(16));
+    let flags = PageTableFlags::PRESENT
+              | PageTableFlags::WRITABLE
+              | PageTableFlags::USER_ACCESSIBLE
+              | PageTableFlags::NO_EXECUTE;
+    let len = (tp + TCB_SIZE - addr) as usize;
+    load_binary(mapper, addr, len, &[], flags)?;
+    write_pages(mapper, tp - size, &tls.image)?;
+    write_pages(mapper, tp, &tp.to_le_bytes())?;
+    Ok(tp)
+}
+
+// Return the offsets below the thread pointer of the TLS blocks of the
+// modules given by their size and alignment, and the total size
+fn tls_offsets(sizes: &[(u64, u64)]) -> (Vec<u64>, u64) {
+    let mut offset = 0;
+    let offsets = sizes.iter().map(|(size, align)| {
+        if *size > 0 {
+            offset = (offset + size).next_multiple_of(*align);
+        }
+        offset
+    }).collect();
+    (offsets, offset)
+}
+
+// Return the initialization image, the size in memory, and the alignment of
+// the TLS segment of a module
+fn tls_segment<'a>(elf: &ElfFile64<'a>) -> Option<(&'a [u8], u64, u64)> {
+    let endian = elf.endian();
+    elf.elf_program_headers().iter().find(|ph| {
+        ph.p_type(endian) == PT_TLS
+    }).and_then(|ph| {
+        let data = ph.data(endian, elf.data()).ok()?;
+        let align = ph.p_align(endian).max(1);
+        Some((data, ph.p_memsz(endian), align))
+    })
+}
+
+// Return the size of the pages spanned by the segments of a module
+fn span(elf: &ElfFile64) -> u64 {
+    elf.segments().map(|segment| {
+        segment.address() + segment.size()
+    }).max().unwrap_or(0).next_multiple_of(4096)
+}
+
+// Return the names of the libraries needed by a binary
+fn needed(bin: &[u8]) -> Result<Vec<String>, ()> {
+    if bin.get(0..4) != Some(&ELF_MAGIC) {
+        return Ok(Vec::new());
+    }
+    let elf: ElfFile64 = ElfFile64::parse(bin).map_err(|_| ())?;
+    let endian = elf.endian();
+    let strings = elf.elf_dynamic_symbol_table().strings();
+    let mut names = Vec::new();
+    for ph in elf.elf_program_headers() {
+        if let Some(entries) = ph.dynamic(endian, bin).map_err(|_| ())? {
+            for entry in entries {
+                if entry.tag32(endian) == Some(DT_NEEDED) {
+                    let offset = entry.d_val(endian) as u32;
+                    let name = strings.get(offset)?.to_vec();
+                    names.push(String::from_utf8(name).map_err(|_| ())?);
+                }
+            }
+        }
+    }
+    Ok(names)
+}
+
+// Apply the dynamic relocations of a module, with the symbols resolved in the
+// order of the modules
+fn relocate(
+    mapper: &mut OffsetPageTable,
+    modules: &[Module],
+    i: usize,
+    symbols: &BTreeMap<&[u8], Definition>,
+) -> Result<(), ()> {
+    let module = &modules[i];
+    let relocations = match module.elf.dynamic_relocations() {
+        Some(relocations) => relocations,
+        None => return Ok(()),
+    };
+    for (offset, relocation) in relocations {
+        let def = match relocation.target() {
+            RelocationTarget::Symbol(index) => {
+                let table = module.elf.dynamic_symbol_table().ok_or(())?;
+                let sym = table.symbol_by_index(index).map_err(|_| ())?;
+                let name = sym.name_bytes().map_err(|_| ())?;
+                match symbols.get(name) {
+                    Some(def) => *def,
+                    None if sym.is_weak() => Definition { module: i, value: 0 },
+                    None => return Err(()),
+                }
+            }
+            _ => Definition { module: i, value: 0 },
+        };
+        let a = relocation.addend() as u64;
+        let s = modules[def.module].bias + def.value;
+        let value = match relocation.flags() {
+            RelocationFlags::Elf { r_type } => match r_type {
+                R_X86_64_RELATIVE => module.bias.wrapping_add(a),
+                R_X86_64_64 => s.wrapping_add(a),
+                R_X86_64_GLOB_DAT | R_X86_64_JUMP_SLOT => s,
+                R_X86_64_DTPMOD64 => def.module as u64 + 1,
+                R_X86_64_DTPOFF64 => def.value.wrapping_add(a),
+                R_X86_64_TPOFF64 => {
+                    let offset = modules[def.module].tls_offset;
+                    def.value.wrapping_add(a).wrapping_sub(offset)
+                }
+                _ => return Err(()),
+            },
+            _ => return Err(()),
+        };
+        write_pages(mapper, module.bias + offset, &value.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+pub fn segment_flags(flags: SegmentFlags) -> PageTableFlags {
+    let mut res = PageTableFlags::PRESENT | PageTableFlags::USER_ACCESSIBLE;
+    if let SegmentFlags::Elf { p_flags } = flags {
+        if p_flags & PF_W != 0 {
+            res |= PageTableFlags::WRITABLE;
+        }
+        if p_flags & PF_X == 0 {
+            res |= PageTableFlags::NO_EXECUTE;
+        }
+    }
+    res
+}
+
+pub fn load_binary(
+    mapper: &mut OffsetPageTable,
+    addr: u64,
+    size: usize,
+    buf: &[u8],
+    flags: PageTableFlags,
+) -> Result<(), ()> {
+    debug_assert!(size >= buf.len());
+    sys::mem::map_pages(mapper, addr, size, flags)?;
+    write_pages(mapper, addr, buf)
+}
+
+// The pages might not be writable and they are not in the current address
+// space, so the buffer is copied through the physical memory mapping of the
+// kernel. The pages are zeroed beyond the buffer when they are mapped.
+pub fn write_pages(
+    mapper: &mut OffsetPageTable,
+    addr: u64,
+    buf: &[u8],
+) -> Result<(), ()> {
+    let mut i = 0;
+    while i < buf.len() {
+        let virt_addr = VirtAddr::new(addr + i as u64);
+        let phys_addr = mapper.translate_addr(virt_addr).ok_or(())?;
+        let offset = (virt_addr.as_u64() % 4096) as usize;
+        let n = (4096 - offset).min(buf.len() - i);
+        let dst: *mut u8 = sys::mem::phys_to_virt(phys_addr).as_mut_ptr();
+        unsafe {
+            core::ptr::copy_nonoverlapping(buf[i..].as_ptr(), dst, n);
+        }
+        i += n;
+    }
+    Ok(())
+}
+
+pub fn is_pie(bin: &[u8]) -> bool {
+    let is_elf = bin.get(0..4) == Some(&ELF_MAGIC);
+    is_elf && object::File::parse(bin).is_ok_and(|obj| {
+        obj.kind() == ObjectKind::Dynamic
+    })
+}
+
+#[test_case]
+fn test_tls_offsets() {
+    let (offsets, size) = tls_offsets(&[(12, 8), (0, 1), (4, 4)]);
+    assert_eq!(offsets, vec![16, 16, 20]);
+    assert_eq!(size, 20);
+
+    let (offsets, size) = tls_offsets(&[]);
+    assert!(offsets.is_empty());
+    assert_eq!(size, 0);
+}
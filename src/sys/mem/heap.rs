@@ -1,5 +1,3 @@
-use crate::sys;
-
 use core::cmp;
 use linked_list_allocator::LockedHeap;
 use x86_64::structures::paging::{
@@ -20,7 +18,6 @@ pub fn init_heap() -> Result<(), MapToError<Size4KiB>> {
     // because the allocator is slow.
     let heap_size = (cmp::min(super::memory_size(), heap_max()) / 2) as u64;
     let heap_start = VirtAddr::new(HEAP_START);
-    sys::process::init_process_addr(HEAP_START + heap_size);
 
     let pages = {
         let heap_end = heap_start + heap_size - 1u64;
@@ -2,17 +2,22 @@ mod heap;
 mod paging;
 mod phys;
 
-pub use paging::{alloc_pages, free_pages, active_page_table, create_page_table};
+pub use paging::{
+    alloc_pages, map_pages, free_pages, active_page_table, create_page_table,
+    share_pages, copy_on_write, map_contiguous_physical_region,
+};
 
 // DmaPhysBuf for framebuffer, Modified by shshi102
 pub use phys::{phys_addr, PhysBuf, DmaPhysBuf};
+pub use crate::api::syscall::MapFlag;
 
 use crate::sys;
 use bootloader::bootinfo::{BootInfo, MemoryMap, MemoryRegionType};
 use core::sync::atomic::{AtomicUsize, Ordering};
 use spin::Once;
 use x86_64::structures::paging::{
-    FrameAllocator, OffsetPageTable, PhysFrame, Size4KiB, Translate, PageSize,
+    FrameAllocator, FrameDeallocator, OffsetPageTable, PhysFrame, Size4KiB,
+    Translate, PageSize,
 };
 use x86_64::{PhysAddr, VirtAddr};
 
@@ -33,6 +38,15 @@ unsafe impl FrameAllocator<Size4KiB> for GlobalFrameAllocatorRef {
     }
 }
 
+impl FrameDeallocator<Size4KiB> for GlobalFrameAllocatorRef {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        let mut allocator_guard = GLOBAL_FRAME_ALLOCATOR.get()
+            .expect("Global Frame Allocator not initialized!")
+            .lock();
+        allocator_guard.deallocate_frame(frame)
+    }
+}
+
 // Modified by shshi102
 pub fn frame_allocator() -> GlobalFrameAllocatorRef {
     GlobalFrameAllocatorRef
@@ -55,6 +69,7 @@ pub fn dma_framebuffer() -> &'static DmaPhysBuf {
     DMA_FRAMEBUFFER_REGION.get().expect("DMA Framebuffer not initialized!")
 }
 const FRAMEBUFFER_SIZE_IN_BYTES: usize = 8 * 1024 * 1024; // 8 MB
+const LOW_MEMORY_END: u64 = 1 << 20; // 1 MB
 const FRAMEBUFFER_VIRT_START: VirtAddr = VirtAddr::new(0xFFFF_FF00_0000_0000);
 
 pub fn init(boot_info: &'static BootInfo) {
@@ -186,11 +201,28 @@ pub fn virt_to_phys(addr: VirtAddr) -> Option<PhysAddr> {
     mapper().translate_addr(addr)
 }
 
+// Return true if the frame at the given address of the low memory is not used
+// by the firmware or the kernel
+pub fn is_low_frame_free(addr: PhysAddr) -> bool {
+    if addr.as_u64() >= LOW_MEMORY_END {
+        return false;
+    }
+    let memory_map = unsafe { MEMORY_MAP.get_unchecked() };
+    memory_map.iter().any(|region| {
+        let range = region.range.start_addr()..region.range.end_addr();
+        range.contains(&addr.as_u64()) && matches!(
+            region.region_type,
+            MemoryRegionType::Usable | MemoryRegionType::Bootloader
+        )
+    })
+}
+
 // Modified by shshi102
 pub struct BootInfoFrameAllocator {
     memory_map: &'static MemoryMap,
     current_region_idx: usize,
     current_frame_offset: u64,
+    free_frames: Option<PhysFrame>, // Last deallocated frame
 }
 
 // Modified by shshi102
@@ -200,10 +232,16 @@ impl BootInfoFrameAllocator {
             memory_map,
             current_region_idx: 0,
             current_frame_offset: 0,
+            free_frames: None,
         }
     }
 
     fn is_frame_usable(&self, frame_addr: PhysAddr) -> bool {
+        // Keep the low memory for the real mode trampoline of the APs
+        if frame_addr.as_u64() < LOW_MEMORY_END {
+            return false;
+        }
+
         let reserved_range_guard = FRAMEBUFFER_PHYS_RANGE.lock();
         let reserved_range_copy = *reserved_range_guard;
 
@@ -218,6 +256,19 @@ impl BootInfoFrameAllocator {
 // Memory allocation logic has changed to adjust DMA for Framebuffer, Modified by shshi102
 unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
     fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        // The deallocated frames are reused first, each of them holding the
+        // address of the frame deallocated before it
+        if let Some(frame) = self.free_frames {
+            let ptr: *const u64 = phys_to_virt(frame.start_address()).as_ptr();
+            let next = unsafe { ptr.read() };
+            self.free_frames = if next > 0 {
+                Some(PhysFrame::containing_address(PhysAddr::new(next)))
+            } else {
+                None
+            };
+            return Some(frame);
+        }
+
         loop {
             let current_region_option = self.memory_map.iter().nth(self.current_region_idx);
             let current_region = match current_region_option {
@@ -262,4 +313,32 @@ unsafe impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+impl FrameDeallocator<Size4KiB> for BootInfoFrameAllocator {
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        // Only the frames that could have been allocated are reused
+        let addr = frame.start_address();
+        let is_usable = self.memory_map.iter().any(|region| {
+            region.region_type == MemoryRegionType::Usable &&
+                region.range.start_addr() <= addr.as_u64() &&
+                addr.as_u64() < region.range.end_addr()
+        });
+        if !is_usable || !self.is_frame_usable(addr) {
+            return;
+        }
+        let next = self.free_frames.map_or(0, |f| f.start_address().as_u64());
+        let ptr: *mut u64 = phys_to_virt(addr).as_mut_ptr();
+        ptr.write(next);
+        self.free_frames = Some(frame);
+    }
+}
+
+#[test_case]
+fn test_deallocate_frame() {
+    let mut allocator = frame_allocator();
+    let frame = allocator.allocate_frame().unwrap();
+    unsafe { allocator.deallocate_frame(frame) };
+    assert_eq!(allocator.allocate_frame(), Some(frame));
+    unsafe { allocator.deallocate_frame(frame) };
+}
@@ -1,15 +1,26 @@
+use x86_64::instructions::tlb;
 use x86_64::registers::control::Cr3;
+use alloc::collections::BTreeMap;
+use spin::Mutex;
 use x86_64::structures::paging::{
     page::PageRangeInclusive,
     OffsetPageTable, PageTable, PhysFrame, Size4KiB,
-    Page, PageTableFlags, Mapper, FrameAllocator,
-    PageSize,
+    Page, PageTableFlags, Mapper, FrameAllocator, FrameDeallocator,
+    PageSize, Translate,
 };
+use x86_64::structures::paging::mapper::{MappedFrame, TranslateResult};
 
 // Modified by shshi102
 use x86_64::{VirtAddr, PhysAddr};
 use x86_64::structures::paging::mapper::MapToError;
 
+// Flag set on the pages shared by a fork that must be copied on write
+const COPY_ON_WRITE: PageTableFlags = PageTableFlags::BIT_9;
+
+// Number of page tables mapping the frames shared by a fork, which are
+// deallocated when they are no longer mapped by any of them
+static SHARED_FRAMES: Mutex<BTreeMap<PhysFrame, usize>> = Mutex::new(BTreeMap::new());
+
 pub unsafe fn active_page_table() -> &'static mut PageTable {
     let (frame, _) = Cr3::read();
     let phys_addr = frame.start_address();
@@ -30,6 +41,20 @@ pub fn alloc_pages(
     mapper: &mut OffsetPageTable,
     addr: u64,
     size: usize,
+) -> Result<(), ()> {
+    let flags = PageTableFlags::PRESENT
+                  | PageTableFlags::WRITABLE
+                  | PageTableFlags::USER_ACCESSIBLE;
+
+    map_pages(mapper, addr, size, flags)
+}
+
+// Map zeroed frames to the pages of the given range with the given flags
+pub fn map_pages(
+    mapper: &mut OffsetPageTable,
+    addr: u64,
+    size: usize,
+    flags: PageTableFlags,
 ) -> Result<(), ()> {
     let size = size.saturating_sub(1) as u64;
     let mut frame_allocator_ref = super::frame_allocator();
@@ -40,12 +65,14 @@ pub fn alloc_pages(
         Page::range_inclusive(start_page, end_page)
     };
 
-    let flags = PageTableFlags::PRESENT
-                  | PageTableFlags::WRITABLE
-                  | PageTableFlags::USER_ACCESSIBLE;
-
     for page in pages {
         if let Some(frame) = frame_allocator_ref.allocate_frame() {
+            // Do not leak the content of the frame to the process
+            let addr = super::phys_to_virt(frame.start_address());
+            let ptr: *mut u8 = addr.as_mut_ptr();
+            unsafe {
+                ptr.write_bytes(0, Size4KiB::SIZE as usize);
+            }
             let res = unsafe {
                 mapper.map_to(page, frame, flags, &mut frame_allocator_ref)
             };
@@ -67,6 +94,23 @@ pub fn alloc_pages(
     Ok(())
 }
 
+// Deallocate a frame unmapped from a page table unless it is still mapped by
+// another one
+fn release_frame(frame: PhysFrame) {
+    let mut shared = SHARED_FRAMES.lock();
+    match shared.get_mut(&frame) {
+        Some(count) if *count > 2 => {
+            *count -= 1;
+        }
+        Some(_) => {
+            shared.remove(&frame);
+        }
+        None => unsafe {
+            super::frame_allocator().deallocate_frame(frame);
+        },
+    }
+}
+
 // TODO: Replace `free` by `dealloc`
 pub fn free_pages(mapper: &mut OffsetPageTable, addr: u64, size: usize) {
     let size = size.saturating_sub(1) as u64;
@@ -78,14 +122,104 @@ pub fn free_pages(mapper: &mut OffsetPageTable, addr: u64, size: usize) {
     };
 
     for page in pages {
-        if let Ok((_, mapping)) = mapper.unmap(page) {
+        if let Ok((frame, mapping)) = mapper.unmap(page) {
             mapping.flush();
+            release_frame(frame);
         } else {
             //debug!("Could not unmap {:?}", page);
         }
     }
 }
 
+// Map the pages of the given range to the same frames in another page table,
+// where the writable pages become read-only in both tables and are marked to
+// be copied when they are written to.
+pub fn share_pages(
+    mapper: &mut OffsetPageTable,
+    other: &mut OffsetPageTable,
+    addr: u64,
+    size: usize,
+) -> Result<(), ()> {
+    let size = size.saturating_sub(1) as u64;
+    let mut frame_allocator_ref = super::frame_allocator();
+    let table_flags = PageTableFlags::PRESENT
+                    | PageTableFlags::WRITABLE
+                    | PageTableFlags::USER_ACCESSIBLE;
+
+    let pages: PageRangeInclusive<Size4KiB> = {
+        let start_page = Page::containing_address(VirtAddr::new(addr));
+        let end_page = Page::containing_address(VirtAddr::new(addr + size));
+        Page::range_inclusive(start_page, end_page)
+    };
+
+    for page in pages {
+        let (frame, mut flags) = match mapper.translate(page.start_address()) {
+            TranslateResult::Mapped {
+                frame: MappedFrame::Size4KiB(frame), flags, ..
+            } => (frame, flags),
+            _ => continue,
+        };
+        if flags.contains(PageTableFlags::WRITABLE) {
+            flags = (flags - PageTableFlags::WRITABLE) | COPY_ON_WRITE;
+            unsafe {
+                mapper.update_flags(page, flags).map_err(|_| ())?.ignore();
+            }
+        }
+        unsafe {
+            other.map_to_with_table_flags(
+                page, frame, flags, table_flags, &mut frame_allocator_ref
+            ).map_err(|_| ())?.ignore();
+        }
+        *SHARED_FRAMES.lock().entry(frame).or_insert(1) += 1;
+    }
+    tlb::flush_all();
+
+    Ok(())
+}
+
+// Give a writable copy of a page shared by a fork to the process writing to
+// it, or the page itself when the other processes have already got their own
+// copy of it.
+pub fn copy_on_write(
+    mapper: &mut OffsetPageTable,
+    addr: u64,
+) -> Result<(), ()> {
+    let page: Page<Size4KiB> = Page::containing_address(VirtAddr::new(addr));
+    let (frame, flags) = match mapper.translate(page.start_address()) {
+        TranslateResult::Mapped {
+            frame: MappedFrame::Size4KiB(frame), flags, ..
+        } => (frame, flags),
+        _ => return Err(()),
+    };
+    if !flags.contains(COPY_ON_WRITE) {
+        return Err(());
+    }
+
+    let flags = (flags - COPY_ON_WRITE) | PageTableFlags::WRITABLE;
+    if !SHARED_FRAMES.lock().contains_key(&frame) {
+        unsafe {
+            mapper.update_flags(page, flags).map_err(|_| ())?.flush();
+        }
+        return Ok(());
+    }
+
+    let mut frame_allocator_ref = super::frame_allocator();
+    let copy = frame_allocator_ref.allocate_frame().ok_or(())?;
+    let src: *const u8 = super::phys_to_virt(frame.start_address()).as_ptr();
+    let dst: *mut u8 = super::phys_to_virt(copy.start_address()).as_mut_ptr();
+    unsafe {
+        core::ptr::copy_nonoverlapping(src, dst, Size4KiB::SIZE as usize);
+    }
+
+    mapper.unmap(page).map_err(|_| ())?.1.flush();
+    release_frame(frame);
+    unsafe {
+        mapper.map_to(page, copy, flags, &mut frame_allocator_ref).
+            map_err(|_| ())?.flush();
+    }
+    Ok(())
+}
+
 
 // Added to create physically contiguous region for DMA for Framebuffer, Modified by shshi102
 pub unsafe fn map_contiguous_physical_region(
@@ -39,13 +39,19 @@ macro_rules! log {
 
 pub mod acpi;
 pub mod ata;
+pub mod backtrace;
 pub mod clk;
 pub mod console;
+pub mod coredump;
 pub mod cpu;
 pub mod fs;
+pub mod gdb;
 pub mod gdt;
 pub mod idt;
+pub mod ioapic;
 pub mod keyboard;
+pub mod lapic;
+pub mod loader;
 pub mod log;
 pub mod mem;
 pub mod net;
@@ -54,6 +60,7 @@ pub mod pic;
 pub mod process;
 pub mod rng;
 pub mod serial;
+pub mod smp;
 pub mod speaker;
 pub mod syscall;
 pub mod vga;
\ No newline at end of file
@@ -5,7 +5,8 @@ pub mod mac;
 pub mod usage;
 pub mod socket;
 
-use crate::{sys, usr};
+use crate::api::hex::print_hex;
+use crate::sys;
 use crate::sys::pci::DeviceConfig;
 
 use alloc::format;
@@ -111,7 +112,7 @@ impl<'a> smoltcp::phy::Device for EthernetDevice {
         if let Some(buffer) = self.receive_packet() {
             if self.config().is_debug_enabled() {
                 debug!("NET Packet Received");
-                usr::hex::print_hex(&buffer);
+                print_hex(&buffer);
             }
             self.stats().rx_add(buffer.len() as u64);
             let rx = RxToken { buffer };
@@ -163,7 +164,7 @@ impl smoltcp::phy::TxToken for TxToken {
         let res = f(buf);
         if config.is_debug_enabled() {
             debug!("NET Packet Transmitted");
-            usr::hex::print_hex(buf);
+            print_hex(buf);
         }
         self.device.transmit_packet(len);
         self.device.stats().tx_add(len as u64);
@@ -5,7 +5,7 @@ pub mod udp;
 
 use alloc::vec;
 use lazy_static::lazy_static;
-use smoltcp::iface::SocketSet;
+use smoltcp::iface::{Interface, SocketSet};
 use smoltcp::time::Duration;
 use spin::Mutex;
 
@@ -22,3 +22,32 @@ fn random_port() -> u16 {
 fn wait(duration: Duration) {
     sys::clk::sleep((duration.total_micros() as f64) / 1000000.0);
 }
+
+// Poll the interface and call the function with the sockets until it returns
+// a result or until the timeout, releasing the locks of the network stack
+// while waiting for the next poll so that other processes can use it
+fn poll_until<T, F>(timeout: f64, mut f: F) -> Result<T, ()>
+where
+    F: FnMut(&mut Interface, &mut SocketSet) -> Option<Result<T, ()>>,
+{
+    let started = sys::clk::epoch_time();
+    loop {
+        if sys::clk::epoch_time() - started > timeout {
+            return Err(());
+        }
+        let delay = {
+            let mut net = sys::net::NET.lock();
+            let (iface, device) = net.as_mut().ok_or(())?;
+            let mut sockets = SOCKETS.lock();
+            iface.poll(sys::net::time(), device, &mut sockets);
+            if let Some(res) = f(iface, &mut sockets) {
+                return res;
+            }
+            iface.poll_delay(sys::net::time(), &sockets)
+        };
+        if let Some(d) = delay {
+            wait(d);
+        }
+        sys::clk::block();
+    }
+}
@@ -4,7 +4,7 @@ use crate::api::fs::{FileIO, IO};
 use crate::sys::net::SocketStatus;
 
 use super::SOCKETS;
-use super::{random_port, wait};
+use super::{poll_until, random_port};
 
 use alloc::vec;
 use bit_field::BitField;
@@ -56,186 +56,109 @@ impl TcpSocket {
     pub fn connect(&mut self, addr: IpAddress, port: u16) -> Result<(), ()> {
         let mut connecting = false;
         let timeout = 5.0;
-        let started = sys::clk::epoch_time();
-        if let Some((ref mut iface, ref mut device)) = *sys::net::NET.lock() {
-            loop {
-                if sys::clk::epoch_time() - started > timeout {
-                    return Err(());
-                }
-                let mut sockets = SOCKETS.lock();
-                iface.poll(sys::net::time(), device, &mut sockets);
-                let socket = sockets.get_mut::<tcp::Socket>(self.handle);
-
-                match socket.state() {
-                    tcp::State::Closed => {
-                        if connecting {
-                            return Err(());
-                        }
-                        let cx = iface.context();
-                        let dest = (addr, port);
-                        if socket.connect(cx, dest, random_port()).is_err() {
-                            return Err(());
-                        }
-                        connecting = true;
-                    }
-                    tcp::State::SynSent => {}
-                    tcp::State::Established => {
-                        break;
+        poll_until(timeout, |iface, sockets| {
+            let socket = sockets.get_mut::<tcp::Socket>(self.handle);
+
+            match socket.state() {
+                tcp::State::Closed => {
+                    if connecting {
+                        return Some(Err(()));
                     }
-                    _ => {
-                        // Did something get sent before the connection closed?
-                        return if socket.can_recv() { Ok(()) } else { Err(()) };
+                    let cx = iface.context();
+                    let dest = (addr, port);
+                    if socket.connect(cx, dest, random_port()).is_err() {
+                        return Some(Err(()));
                     }
+                    connecting = true;
+                    None
                 }
-
-                if let Some(d) = iface.poll_delay(sys::net::time(), &sockets) {
-                    wait(d);
+                tcp::State::SynSent => None,
+                tcp::State::Established => Some(Ok(())),
+                _ => {
+                    // Did something get sent before the connection closed?
+                    Some(if socket.can_recv() { Ok(()) } else { Err(()) })
                 }
-                sys::clk::halt();
             }
-        }
-        Ok(())
+        })
     }
 
     pub fn listen(&mut self, port: u16) -> Result<(), ()> {
-        if let Some((ref mut iface, ref mut device)) = *sys::net::NET.lock() {
-            let mut sockets = SOCKETS.lock();
-            iface.poll(sys::net::time(), device, &mut sockets);
+        let mut listening = false;
+        let timeout = 5.0;
+        poll_until(timeout, |_, sockets| {
+            if listening {
+                return Some(Ok(()));
+            }
             let socket = sockets.get_mut::<tcp::Socket>(self.handle);
-
             if socket.listen(port).is_err() {
-                return Err(());
-            }
-
-            if let Some(d) = iface.poll_delay(sys::net::time(), &sockets) {
-                wait(d);
+                return Some(Err(()));
             }
-            sys::clk::halt();
-            Ok(())
-        } else {
-            Err(())
-        }
+            listening = true; // Return after next poll
+            None
+        })
     }
 
     pub fn accept(&mut self) -> Result<IpAddress, ()> {
         let timeout = 5.0;
-        let started = sys::clk::epoch_time();
-        if let Some((ref mut iface, ref mut device)) = *sys::net::NET.lock() {
-            loop {
-                if sys::clk::epoch_time() - started > timeout {
-                    return Err(());
-                }
-                let mut sockets = SOCKETS.lock();
-                iface.poll(sys::net::time(), device, &mut sockets);
-                let socket = sockets.get_mut::<tcp::Socket>(self.handle);
-
-                if let Some(endpoint) = socket.remote_endpoint() {
-                    return Ok(endpoint.addr);
-                }
-
-                if let Some(d) = iface.poll_delay(sys::net::time(), &sockets) {
-                    wait(d);
-                }
-                sys::clk::halt();
-            }
-        } else {
-            Err(())
-        }
+        poll_until(timeout, |_, sockets| {
+            let socket = sockets.get_mut::<tcp::Socket>(self.handle);
+            socket.remote_endpoint().map(|endpoint| Ok(endpoint.addr))
+        })
     }
 }
 
 impl FileIO for TcpSocket {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
         let timeout = 5.0;
-        let started = sys::clk::epoch_time();
-        let mut bytes = 0;
-        if let Some((ref mut iface, ref mut device)) = *sys::net::NET.lock() {
-            let mut sockets = SOCKETS.lock();
-            loop {
-                if sys::clk::epoch_time() - started > timeout {
-                    return Err(());
-                }
-                iface.poll(sys::net::time(), device, &mut sockets);
-                let socket = sockets.get_mut::<tcp::Socket>(self.handle);
+        poll_until(timeout, |_, sockets| {
+            let socket = sockets.get_mut::<tcp::Socket>(self.handle);
 
-                if buf.len() == 1 {
-                    // 1 byte status read
-                    buf[0] = tcp_socket_status(socket);
-                    return Ok(1);
-                }
+            if buf.len() == 1 {
+                // 1 byte status read
+                buf[0] = tcp_socket_status(socket);
+                return Some(Ok(1));
+            }
 
-                if socket.can_recv() {
-                    bytes = socket.recv_slice(buf).map_err(|_| ())?;
-                    break;
-                }
-                if !socket.may_recv() {
-                    break;
-                }
-                if let Some(d) = iface.poll_delay(sys::net::time(), &sockets) {
-                    wait(d);
-                }
-                sys::clk::halt();
+            if socket.can_recv() {
+                return Some(socket.recv_slice(buf).map_err(|_| ()));
             }
-            Ok(bytes)
-        } else {
-            Err(())
-        }
+            if !socket.may_recv() {
+                return Some(Ok(0));
+            }
+            None
+        })
     }
 
     fn write(&mut self, buf: &[u8]) -> Result<usize, ()> {
         let timeout = 5.0;
-        let started = sys::clk::epoch_time();
         let mut sent = false;
-        if let Some((ref mut iface, ref mut device)) = *sys::net::NET.lock() {
-            let mut sockets = SOCKETS.lock();
-            loop {
-                if sys::clk::epoch_time() - started > timeout {
-                    return Err(());
-                }
-                iface.poll(sys::net::time(), device, &mut sockets);
-                let socket = sockets.get_mut::<tcp::Socket>(self.handle);
-
-                if sent {
-                    break;
-                }
-                if socket.can_send() {
-                    if socket.send_slice(buf.as_ref()).is_err() {
-                        return Err(());
-                    }
-                    sent = true; // Break after next poll
-                }
-
-                if let Some(d) = iface.poll_delay(sys::net::time(), &sockets) {
-                    wait(d);
+        poll_until(timeout, |_, sockets| {
+            if sent {
+                return Some(Ok(buf.len()));
+            }
+            let socket = sockets.get_mut::<tcp::Socket>(self.handle);
+            if socket.can_send() {
+                if socket.send_slice(buf.as_ref()).is_err() {
+                    return Some(Err(()));
                 }
-                sys::clk::halt();
+                sent = true; // Return after next poll
             }
-            Ok(buf.len())
-        } else {
-            Err(())
-        }
+            None
+        })
     }
 
     fn close(&mut self) {
         let mut closed = false;
-        if let Some((ref mut iface, ref mut device)) = *sys::net::NET.lock() {
-            let mut sockets = SOCKETS.lock();
-            loop {
-                iface.poll(sys::net::time(), device, &mut sockets);
-                let socket = sockets.get_mut::<tcp::Socket>(self.handle);
-
-                if closed {
-                    break;
-                }
-                socket.close();
-                closed = true;
-
-                if let Some(d) = iface.poll_delay(sys::net::time(), &sockets) {
-                    wait(d);
-                }
-                sys::clk::halt();
+        let timeout = 5.0;
+        poll_until(timeout, |_, sockets| {
+            if closed {
+                return Some(Ok(()));
             }
-        }
+            let socket = sockets.get_mut::<tcp::Socket>(self.handle);
+            socket.close();
+            closed = true; // Return after next poll
+            None
+        }).ok();
     }
 
     fn poll(&mut self, event: IO) -> bool {
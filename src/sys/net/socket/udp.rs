@@ -4,7 +4,7 @@ use crate::api::fs::{FileIO, IO};
 use crate::sys::net::SocketStatus;
 
 use super::SOCKETS;
-use super::{random_port, wait};
+use super::{poll_until, random_port};
 
 use alloc::vec;
 use bit_field::BitField;
@@ -59,29 +59,18 @@ impl UdpSocket {
     }
 
     pub fn connect(&mut self, addr: IpAddress, port: u16) -> Result<(), ()> {
+        let handle = self.handle;
         let timeout = 5.0;
-        let started = sys::clk::epoch_time();
-        if let Some((ref mut iface, ref mut device)) = *sys::net::NET.lock() {
-            loop {
-                if sys::clk::epoch_time() - started > timeout {
-                    return Err(());
-                }
-                let mut sockets = SOCKETS.lock();
-                iface.poll(sys::net::time(), device, &mut sockets);
-                let socket = sockets.get_mut::<udp::Socket>(self.handle);
-
-                if !socket.is_open() {
-                    let local_endpoint = IpListenEndpoint::from(random_port());
-                    socket.bind(local_endpoint).unwrap();
-                    break;
-                }
+        poll_until(timeout, |_, sockets| {
+            let socket = sockets.get_mut::<udp::Socket>(handle);
 
-                if let Some(d) = iface.poll_delay(sys::net::time(), &sockets) {
-                    wait(d);
-                }
-                sys::clk::halt();
+            if !socket.is_open() {
+                let local_endpoint = IpListenEndpoint::from(random_port());
+                socket.bind(local_endpoint).unwrap();
+                return Some(Ok(()));
             }
-        }
+            None
+        })?;
         self.remote_endpoint = Some(IpEndpoint::new(addr, port));
         Ok(())
     }
@@ -98,96 +87,57 @@ impl UdpSocket {
 impl FileIO for UdpSocket {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, ()> {
         let timeout = 5.0;
-        let started = sys::clk::epoch_time();
-        if let Some((ref mut iface, ref mut device)) = *sys::net::NET.lock() {
-            let bytes;
-            let mut sockets = SOCKETS.lock();
-            loop {
-                if sys::clk::epoch_time() - started > timeout {
-                    return Err(());
-                }
-                iface.poll(sys::net::time(), device, &mut sockets);
-                let socket = sockets.get_mut::<udp::Socket>(self.handle);
+        poll_until(timeout, |_, sockets| {
+            let socket = sockets.get_mut::<udp::Socket>(self.handle);
 
-                if buf.len() == 1 {
-                    // 1 byte status read
-                    buf[0] = udp_socket_status(socket);
-                    return Ok(1);
-                }
+            if buf.len() == 1 {
+                // 1 byte status read
+                buf[0] = udp_socket_status(socket);
+                return Some(Ok(1));
+            }
 
-                if socket.can_recv() {
-                    (bytes, _) = socket.recv_slice(buf).map_err(|_| ())?;
-                    break;
-                }
-                if let Some(d) = iface.poll_delay(sys::net::time(), &sockets) {
-                    wait(d);
-                }
-                sys::clk::halt();
+            if socket.can_recv() {
+                let res = socket.recv_slice(buf).map_err(|_| ());
+                return Some(res.map(|(bytes, _)| bytes));
             }
-            Ok(bytes)
-        } else {
-            Err(())
-        }
+            None
+        })
     }
 
     fn write(&mut self, buf: &[u8]) -> Result<usize, ()> {
         let timeout = 5.0;
-        let started = sys::clk::epoch_time();
         let mut sent = false;
-        if let Some((ref mut iface, ref mut device)) = *sys::net::NET.lock() {
-            let mut sockets = SOCKETS.lock();
-            loop {
-                if sys::clk::epoch_time() - started > timeout {
-                    return Err(());
-                }
-                iface.poll(sys::net::time(), device, &mut sockets);
-                let socket = sockets.get_mut::<udp::Socket>(self.handle);
-
-                if sent {
-                    break;
-                }
-                if socket.can_send() {
-                    if let Some(endpoint) = self.remote_endpoint {
-                        if socket.send_slice(buf.as_ref(), endpoint).is_err() {
-                            return Err(());
-                        }
-                    } else {
-                        return Err(());
+        poll_until(timeout, |_, sockets| {
+            if sent {
+                return Some(Ok(buf.len()));
+            }
+            let socket = sockets.get_mut::<udp::Socket>(self.handle);
+            if socket.can_send() {
+                if let Some(endpoint) = self.remote_endpoint {
+                    if socket.send_slice(buf.as_ref(), endpoint).is_err() {
+                        return Some(Err(()));
                     }
-                    sent = true; // Break after next poll
+                } else {
+                    return Some(Err(()));
                 }
-
-                if let Some(d) = iface.poll_delay(sys::net::time(), &sockets) {
-                    wait(d);
-                }
-                sys::clk::halt();
+                sent = true; // Return after next poll
             }
-            Ok(buf.len())
-        } else {
-            Err(())
-        }
+            None
+        })
     }
 
     fn close(&mut self) {
         let mut closed = false;
-        if let Some((ref mut iface, ref mut device)) = *sys::net::NET.lock() {
-            let mut sockets = SOCKETS.lock();
-            loop {
-                iface.poll(sys::net::time(), device, &mut sockets);
-                let socket = sockets.get_mut::<udp::Socket>(self.handle);
-
-                if closed {
-                    break;
-                }
-                socket.close();
-                closed = true;
-
-                if let Some(d) = iface.poll_delay(sys::net::time(), &sockets) {
-                    wait(d);
-                }
-                sys::clk::halt();
+        let timeout = 5.0;
+        poll_until(timeout, |_, sockets| {
+            if closed {
+                return Some(Ok(()));
             }
-        }
+            let socket = sockets.get_mut::<udp::Socket>(self.handle);
+            socket.close();
+            closed = true; // Return after next poll
+            None
+        }).ok();
     }
 
     fn poll(&mut self, event: IO) -> bool {
@@ -1,3 +1,5 @@
+use crate::sys;
+
 use pic8259::ChainedPics;
 use spin::Mutex;
 
@@ -14,3 +16,25 @@ pub fn init() {
     }
     x86_64::instructions::interrupts::enable();
 }
+
+// Mask all the IRQs of the PIC when they are routed through the I/O APIC and
+// return the masks that were set
+pub fn disable() -> [u8; 2] {
+    let mut pics = PICS.lock();
+    unsafe {
+        let masks = pics.read_masks();
+        pics.disable();
+        masks
+    }
+}
+
+// Signal the end of an IRQ to the controller that sent it
+pub fn notify_end_of_interrupt(irq: u8) {
+    if sys::ioapic::is_enabled() {
+        sys::lapic::eoi();
+    } else {
+        unsafe {
+            PICS.lock().notify_end_of_interrupt(PIC_1_OFFSET + irq);
+        }
+    }
+}
@@ -1,9 +1,12 @@
-use crate::api::process::ExitCode;
+use crate::api::process::{ExitCode, SIGCHLD, SIGINT, SIGKILL};
+use crate::api::process::{SIG_DFL, SIG_IGN};
 use crate::sys::console::Console;
 use crate::sys::fs::{Device, Resource};
 use crate::sys;
 use crate::sys::gdt::GDT;
-use crate::sys::mem::phys_mem_offset;
+use crate::sys::loader::{is_pie, load_binary, map_tls, Image, Tls};
+use crate::sys::mem::{phys_mem_offset, MapFlag};
+use crate::sys::smp::MAX_CPUS;
 
 use alloc::boxed::Box;
 use alloc::collections::btree_map::BTreeMap;
@@ -11,63 +14,320 @@ use alloc::string::{String, ToString};
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use core::alloc::{GlobalAlloc, Layout};
-use core::arch::asm;
-use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use core::convert::TryInto;
+use core::ops::{Index, IndexMut};
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 use lazy_static::lazy_static;
 use linked_list_allocator::LockedHeap;
-use object::{Object, ObjectSegment};
+use object::{Object, ObjectSection, ObjectSegment};
 use spin::RwLock;
+use x86_64::instructions::interrupts;
 use x86_64::registers::control::Cr3;
+use x86_64::registers::model_specific::FsBase;
+use x86_64::registers::rflags::RFlags;
 use x86_64::structures::idt::InterruptStackFrameValue;
+use x86_64::structures::paging::mapper::TranslateResult;
 use x86_64::structures::paging::{
-    FrameAllocator, OffsetPageTable, PageTable, PhysFrame,
+    FrameAllocator, FrameDeallocator, OffsetPageTable, PageTable, PhysFrame,
     Translate, PageTableFlags, // Page, Size4KiB,
-    mapper::TranslateResult
 };
 use x86_64::VirtAddr;
 
 const ELF_MAGIC: [u8; 4] = [0x7F, b'E', b'L', b'F'];
 const BIN_MAGIC: [u8; 4] = [0x7F, b'B', b'I', b'N'];
 
-const MAX_HANDLES: usize = 64;
-const MAX_PROCS: usize = 4; // TODO: Increase this
-const MAX_PROC_SIZE: usize = 10 << 20; // 10 MB
-
-// TODO: Remove this when the kernel is no longer at 0x200000 in userspace.
-// Currently this address must be used by the linker for user programs that
-// need to allocate memory to avoid using kernel memory.
-static USER_ADDR: u64 = 0x800000;
-
-static CODE_ADDR: AtomicU64 = AtomicU64::new(0);
-pub static PID: AtomicUsize = AtomicUsize::new(0);
-pub static MAX_PID: AtomicUsize = AtomicUsize::new(1);
+pub const MAX_HANDLES: usize = 64;
+const MAX_PROCS: usize = 128;
+pub const MAX_PROC_SIZE: usize = 256 << 20; // 256 MB
+const DEFAULT_PROC_SIZE: usize = 10 << 20; // 10 MB
+const KERNEL_STACK_SIZE: usize = 64 << 10; // 64 KB
+const TASK_STACK_SIZE: usize = 1 << 20; // 1 MB
+const MAX_SIGNALS: usize = 32;
+
+// Name of the ELF section where a binary can set the size of its process
+const PROC_SIZE_SECTION: &str = ".proc_size";
+
+// Every process has its own address space where its code, heap, and stack are
+// mapped in a window of the second GB, outside of the first GB used by the
+// kernel. The window starts at this address for the programs that must be
+// loaded at a fixed address, and at a random address for the others.
+const USER_ADDR: u64 = 0x4000_0000;
+const USER_SIZE: u64 = 1 << 30; // 1 GB
+
+// The current process of each CPU
+static PIDS: [AtomicUsize; MAX_CPUS] = [
+    const { AtomicUsize::new(0) }; MAX_CPUS
+];
+static NEXT_PID: AtomicUsize = AtomicUsize::new(1);
+
+// The foreground process is the last one waited for by the foreground
+// process, starting with the kernel, and it is interrupted by Ctrl-C
+static FOREGROUND: AtomicUsize = AtomicUsize::new(0);
+static INTERRUPT: AtomicBool = AtomicBool::new(false);
+
+// Function run by a kernel task, returning its exit code
+type Task = Box<dyn FnOnce() -> ExitCode>;
 
 lazy_static! {
-    pub static ref PROCESS_TABLE: RwLock<[Box<Process>; MAX_PROCS]> = {
-        RwLock::new([(); MAX_PROCS].map(|_| Box::new(Process::new())))
+    pub static ref PROCESS_TABLE: RwLock<ProcessTable> = {
+        RwLock::new(ProcessTable::new())
     };
 }
 
-// Called during kernel heap initialization
-pub fn init_process_addr(addr: u64) {
-    sys::process::CODE_ADDR.store(addr, Ordering::SeqCst);
+// Processes indexed by PID, starting with the kernel
+pub struct ProcessTable {
+    procs: BTreeMap<usize, Box<Process>>,
+    orphans: Vec<usize>, // Processes adopted by the kernel
+
+    // The kernel stack of the last process that exited on each CPU is kept
+    // until the CPU has finished to switch from it to the next process
+    exited: [Option<Arc<KernelStack>>; MAX_CPUS],
+}
+
+impl ProcessTable {
+    fn new() -> Self {
+        // The kernel is pinned to the BSP
+        let kernel = Process {
+            cpu: Some(0),
+            affinity: Some(0),
+            ..Process::new()
+        };
+        let mut procs = BTreeMap::new();
+        procs.insert(0, Box::new(kernel));
+        let exited = [const { None }; MAX_CPUS];
+        Self { procs, orphans: Vec::new(), exited }
+    }
+
+    pub fn len(&self) -> usize {
+        self.procs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.procs.is_empty()
+    }
+
+    pub fn contains(&self, pid: usize) -> bool {
+        self.procs.contains_key(&pid)
+    }
+
+    pub fn get(&self, pid: usize) -> Option<&Process> {
+        self.procs.get(&pid).map(|proc| proc.as_ref())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Box<Process>> {
+        self.procs.values()
+    }
+
+    fn iter_mut(&mut self) -> impl Iterator<Item = &mut Box<Process>> {
+        self.procs.values_mut()
+    }
+
+    fn insert(&mut self, proc: Process) {
+        self.procs.insert(proc.id, Box::new(proc));
+    }
+
+    // The threads of a process are removed with it, and the frames of its
+    // page table are given back once its pages have been freed
+    fn remove(&mut self, pid: usize) {
+        self.orphans.retain(|&orphan| orphan != pid);
+        if let Some(proc) = self.procs.remove(&pid) {
+            if !proc.is_thread() && !proc.is_kernel() {
+                free_page_table(proc.page_table_frame);
+            }
+        }
+        self.procs.retain(|_, proc| proc.leader_id != pid);
+    }
+
+    // Return the main thread of the process of a thread, which holds the
+    // data shared by the threads
+    fn leader(&self, id: usize) -> &Process {
+        &self[self[id].leader_id]
+    }
+
+    fn leader_mut(&mut self, id: usize) -> &mut Process {
+        let id = self[id].leader_id;
+        &mut self[id]
+    }
+
+    // Return true if the process of the first thread is the process of the
+    // second one or one of its descendants, which are all descendants of the
+    // kernel once adopted
+    fn is_descendant(&self, id: usize, ancestor_id: usize) -> bool {
+        let ancestor_id = self[ancestor_id].leader_id;
+        let mut id = self[id].leader_id;
+        while id != ancestor_id {
+            if id == 0 {
+                return false;
+            }
+            id = self[id].parent_id;
+        }
+        true
+    }
+
+    // Make the threads of a process blocked in a syscall check again if
+    // they can continue
+    fn wake_threads(&mut self, leader_id: usize) {
+        for proc in self.iter_mut() {
+            let is_blocked = proc.state == ProcessState::Blocked;
+            if proc.leader_id == leader_id && is_blocked {
+                proc.state = ProcessState::Ready;
+            }
+        }
+    }
+
+    // Return the CPU running a thread of a process. The threads of a user
+    // process are never run by two CPUs at the same time, so that the TLB
+    // of the other CPUs doesn't have to be flushed when its pages change.
+    fn cpu(&self, leader_id: usize) -> Option<usize> {
+        self.iter().filter(|proc| proc.leader_id == leader_id).
+            find_map(|proc| proc.cpu)
+    }
+
+    // Check if a process can be run by the scheduler of the given CPU
+    fn is_runnable(&self, proc: &Process, cpu: usize) -> bool {
+        let is_free = |id: Option<usize>| id.is_none_or(|id| id == cpu);
+        proc.state == ProcessState::Ready
+            && proc.stack_frame.is_some()
+            && proc.cpu.is_none()
+            && is_free(proc.affinity)
+            && (proc.is_kernel() || is_free(self.cpu(proc.leader_id)))
+    }
+
+    // Find the next process after the given PID that satisfies the
+    // predicate, wrapping around to the beginning of the table
+    fn next<P>(&self, pid: usize, predicate: P) -> Option<usize>
+    where
+        P: Fn(&Process) -> bool,
+    {
+        let after = self.procs.range((pid + 1)..);
+        let before = self.procs.range(..pid);
+        after.chain(before).find(|(_, proc)| predicate(proc)).map(|(&i, _)| i)
+    }
+}
+
+impl Index<usize> for ProcessTable {
+    type Output = Box<Process>;
+
+    fn index(&self, pid: usize) -> &Self::Output {
+        &self.procs[&pid]
+    }
+}
+
+impl IndexMut<usize> for ProcessTable {
+    fn index_mut(&mut self, pid: usize) -> &mut Self::Output {
+        self.procs.get_mut(&pid).expect("process not found")
+    }
 }
 
 #[repr(align(8), C)]
 #[derive(Debug, Clone, Copy, Default)]
 pub struct Registers {
+    // Saved preserved registers
+    pub r15: usize,
+    pub r14: usize,
+    pub r13: usize,
+    pub r12: usize,
     // Saved scratch registers
     pub r11: usize,
     pub r10: usize,
     pub r9: usize,
     pub r8: usize,
+    pub rbp: usize,
     pub rdi: usize,
     pub rsi: usize,
     pub rdx: usize,
     pub rcx: usize,
+    pub rbx: usize,
     pub rax: usize,
 }
 
+// The syscalls of a traced process are written to the kernel log or to one
+// of its handles, and the children of the process are traced like it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trace {
+    Log,
+    Handle(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProcessState {
+    Ready,
+    Running,
+    Blocked,
+    Zombie,
+}
+
+// Stack used by the CPU when a process is interrupted in userspace. Each
+// process has its own kernel stack to be able to switch to another process
+// while the kernel is waiting in a syscall, and a kernel task runs on it.
+struct KernelStack {
+    data: Box<[u8]>,
+}
+
+impl KernelStack {
+    fn new(size: usize) -> Self {
+        let data = alloc::vec![0; size].into_boxed_slice();
+        Self { data }
+    }
+
+    fn top(&self) -> VirtAddr {
+        let addr = self.data.as_ptr() as u64 + self.data.len() as u64;
+        VirtAddr::new(addr).align_down(16u64)
+    }
+}
+
+// Signals sent to a process are pending until it goes back to user mode,
+// where they are delivered unless they are blocked. A signal either calls a
+// handler through the trampoline given with it or triggers its default
+// action, which is to terminate the process except for SIGCHLD.
+#[derive(Clone, Debug)]
+struct Signals {
+    pending: u32,
+    blocked: u32,
+    handlers: [usize; MAX_SIGNALS],
+    trampoline: usize,
+    saved: Option<(InterruptStackFrameValue, Registers, u32)>,
+}
+
+impl Signals {
+    fn new() -> Self {
+        Self {
+            pending: 0,
+            blocked: 0,
+            handlers: [SIG_DFL; MAX_SIGNALS],
+            trampoline: 0,
+            saved: None,
+        }
+    }
+
+    // Take the first pending signal that is not blocked
+    fn take(&mut self) -> Option<usize> {
+        let signals = self.pending & !self.blocked;
+        if signals == 0 {
+            return None;
+        }
+        let signal = signals.trailing_zeros() as usize;
+        self.pending &= !(1 << signal);
+        Some(signal)
+    }
+
+    // Check if a pending signal would interrupt the process
+    fn is_pending(&self) -> bool {
+        let signals = self.pending & !self.blocked;
+        (0..MAX_SIGNALS).any(|signal| {
+            signals & (1 << signal) != 0 && !self.is_ignored(signal)
+        })
+    }
+
+    fn is_ignored(&self, signal: usize) -> bool {
+        match self.handlers[signal] {
+            SIG_IGN => true,
+            SIG_DFL => signal == SIGCHLD,
+            _ => false,
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct ProcessData {
     env: BTreeMap<String, String>,
@@ -97,58 +357,81 @@ impl ProcessData {
 }
 
 pub fn id() -> usize {
-    PID.load(Ordering::SeqCst)
+    PIDS[sys::smp::cpu_id()].load(Ordering::SeqCst)
 }
 
 pub fn set_id(id: usize) {
-    PID.store(id, Ordering::SeqCst)
+    PIDS[sys::smp::cpu_id()].store(id, Ordering::SeqCst)
+}
+
+// Return the current process of a CPU
+pub fn id_on(cpu: usize) -> usize {
+    PIDS.get(cpu).map_or(0, |pid| pid.load(Ordering::SeqCst))
+}
+
+// Add the idle process of an AP, running the kernel on the stack of the AP
+// when there is nothing else to run. It is pinned to the AP and never ready,
+// to only be switched to after the exit of the current process of the AP.
+pub fn init_idle(cpu: usize) {
+    let id = NEXT_PID.fetch_add(1, Ordering::SeqCst);
+    let proc = Process {
+        id,
+        leader_id: id,
+        name: "idle".to_string(),
+        cpu: Some(cpu),
+        affinity: Some(cpu),
+        state: ProcessState::Blocked,
+        ..Process::new()
+    };
+    PROCESS_TABLE.write().insert(proc);
+    PIDS[cpu].store(id, Ordering::SeqCst);
 }
 
 pub fn env(key: &str) -> Option<String> {
     let table = PROCESS_TABLE.read();
-    let proc = &table[id()];
+    let proc = table.leader(id());
     proc.data.env.get(key).cloned()
 }
 
 pub fn envs() -> BTreeMap<String, String> {
     let table = PROCESS_TABLE.read();
-    let proc = &table[id()];
+    let proc = table.leader(id());
     proc.data.env.clone()
 }
 
 pub fn dir() -> String {
     let table = PROCESS_TABLE.read();
-    let proc = &table[id()];
+    let proc = table.leader(id());
     proc.data.dir.clone()
 }
 
 pub fn user() -> Option<String> {
     let table = PROCESS_TABLE.read();
-    let proc = &table[id()];
+    let proc = table.leader(id());
     proc.data.user.clone()
 }
 
 pub fn set_env(key: &str, val: &str) {
     let mut table = PROCESS_TABLE.write();
-    let proc = &mut table[id()];
+    let proc = table.leader_mut(id());
     proc.data.env.insert(key.into(), val.into());
 }
 
 pub fn set_dir(dir: &str) {
     let mut table = PROCESS_TABLE.write();
-    let proc = &mut table[id()];
+    let proc = table.leader_mut(id());
     proc.data.dir = dir.into();
 }
 
 pub fn set_user(user: &str) {
     let mut table = PROCESS_TABLE.write();
-    let proc = &mut table[id()];
+    let proc = table.leader_mut(id());
     proc.data.user = Some(user.into())
 }
 
 pub fn create_handle(file: Resource) -> Result<usize, ()> {
     let mut table = PROCESS_TABLE.write();
-    let proc = &mut table[id()];
+    let proc = table.leader_mut(id());
     let min = 4; // The first 4 handles are reserved
     let max = MAX_HANDLES;
     for handle in min..max {
@@ -163,25 +446,25 @@ pub fn create_handle(file: Resource) -> Result<usize, ()> {
 
 pub fn update_handle(handle: usize, file: Resource) {
     let mut table = PROCESS_TABLE.write();
-    let proc = &mut table[id()];
+    let proc = table.leader_mut(id());
     proc.data.handles[handle] = Some(Box::new(file));
 }
 
 pub fn delete_handle(handle: usize) {
     let mut table = PROCESS_TABLE.write();
-    let proc = &mut table[id()];
+    let proc = table.leader_mut(id());
     proc.data.handles[handle] = None;
 }
 
 pub fn handle(handle: usize) -> Option<Box<Resource>> {
     let table = PROCESS_TABLE.read();
-    let proc = &table[id()];
-    proc.data.handles[handle].clone()
+    let proc = table.leader(id());
+    proc.data.handles.get(handle).and_then(|file| file.clone())
 }
 
 pub fn handles() -> Vec<Option<Box<Resource>>> {
     let table = PROCESS_TABLE.read();
-    let proc = &table[id()];
+    let proc = table.leader(id());
     proc.data.handles.to_vec()
 }
 
@@ -230,24 +513,673 @@ pub fn set_stack_frame(stack_frame: InterruptStackFrameValue) {
     proc.stack_frame = Some(stack_frame);
 }
 
-// TODO: Remove this when the kernel is no longer at 0x200000 in userspace
-pub fn is_userspace(addr: u64) -> bool {
-    USER_ADDR <= addr && addr <= USER_ADDR + MAX_PROC_SIZE as u64
+pub fn trace() -> Option<Trace> {
+    let table = PROCESS_TABLE.read();
+    let proc = &table[id()];
+    proc.trace
 }
 
-pub fn exit() {
+pub fn set_trace(trace: Option<Trace>) {
+    let mut table = PROCESS_TABLE.write();
+    let proc = &mut table[id()];
+    proc.trace = trace;
+}
+
+pub fn state() -> ProcessState {
     let table = PROCESS_TABLE.read();
     let proc = &table[id()];
+    proc.state
+}
 
-    MAX_PID.fetch_sub(1, Ordering::SeqCst);
-    set_id(proc.parent_id);
+pub fn set_state(state: ProcessState) {
+    let mut table = PROCESS_TABLE.write();
+    let proc = &mut table[id()];
+    proc.state = state;
+}
 
-    unsafe {
-        let (_, flags) = Cr3::read();
-        Cr3::write(page_table_frame(), flags);
+pub fn is_user() -> bool {
+    let table = PROCESS_TABLE.read();
+    !table.leader(id()).is_kernel()
+}
+
+// Check if the address is in the heap or the stack of the current process,
+// where the pages are allocated on demand
+pub fn is_demand_paged(addr: u64) -> bool {
+    let table = PROCESS_TABLE.read();
+    table.leader(id()).is_demand_paged(addr)
+}
+
+// Check that a buffer given to a syscall by a user process is in its memory
+// window, in pages that are either mapped for userspace or allocated on
+// demand, while the kernel and its tasks can give any buffer
+pub fn is_user_range(addr: u64, len: usize) -> bool {
+    let table = PROCESS_TABLE.read();
+    let proc = table.leader(id());
+    if proc.is_kernel() {
+        return true;
+    }
+    let end = match addr.checked_add(len as u64) {
+        Some(end) => end,
+        None => return false,
+    };
+    if addr < proc.code_addr || end > proc.code_addr + proc.size as u64 {
+        return false;
+    }
+    let mapper = proc.mapper();
+    (addr & !0xFFF..end).step_by(4096).all(|page| {
+        match mapper.translate(VirtAddr::new(page)) {
+            TranslateResult::Mapped { flags, .. } => {
+                flags.contains(PageTableFlags::USER_ACCESSIBLE)
+            }
+            _ => proc.is_demand_paged(page),
+        }
+    })
+}
+
+// Snapshot of a process used to generate the files of the /proc directory
+#[derive(Clone, Debug)]
+pub struct ProcessInfo {
+    pub id: usize,
+    pub parent_id: usize,
+    pub name: String,
+    pub state: ProcessState,
+    pub env: BTreeMap<String, String>,
+    pub dir: String,
+    pub user: Option<String>,
+    pub handles: Vec<(usize, Box<Resource>)>,
+    pub regions: Vec<Region>,
+    pub size: usize,
+    pub heap_size: usize,
+    pub heap_used: usize,
+}
+
+// Area of the memory window of a process with the number of bytes that are
+// currently mapped in it
+#[derive(Clone, Debug)]
+pub struct Region {
+    pub addr: u64,
+    pub size: usize,
+    pub mapped: usize,
+    pub name: &'static str,
+}
+
+pub fn pids() -> Vec<usize> {
+    PROCESS_TABLE.read().procs.keys().copied().collect()
+}
+
+pub fn snapshot(pid: usize) -> Option<ProcessInfo> {
+    let table = PROCESS_TABLE.read();
+    if !table.contains(pid) {
+        return None;
+    }
+    let proc = &table[pid];
+    let leader = table.leader(pid);
+    let handles = leader.data.handles.iter().enumerate().filter_map(|(i, h)| {
+        h.clone().map(|h| (i, h))
+    }).collect();
+
+    // The allocator of the process could be locked by its own code
+    let (heap_size, heap_used) = proc.allocator.try_lock().map_or(
+        (0, 0), |heap| (heap.size(), heap.used())
+    );
+    Some(ProcessInfo {
+        id: proc.id,
+        parent_id: proc.parent_id,
+        name: proc.name.clone(),
+        state: proc.state,
+        env: leader.data.env.clone(),
+        dir: leader.data.dir.clone(),
+        user: leader.data.user.clone(),
+        handles,
+        regions: leader.regions(),
+        size: proc.size,
+        heap_size,
+        heap_used,
+    })
+}
+
+// Make a blocked process ready to run again, unless the process table is
+// locked by the code interrupted by the timer calling this function
+pub fn wake(pid: usize) -> Result<(), ()> {
+    let mut table = PROCESS_TABLE.try_write().ok_or(())?;
+    if table.contains(pid) && table[pid].state == ProcessState::Blocked {
+        table[pid].state = ProcessState::Ready;
+    }
+    Ok(())
+}
+
+// NOTE: The process table is not locked if it is already locked by the
+// interrupted code, in which case the process is not considered blocked.
+pub fn is_blocked() -> bool {
+    PROCESS_TABLE.try_read().is_some_and(|table| {
+        table[id()].state == ProcessState::Blocked
+    })
+}
+
+// Round-robin scheduler saving the context of the current process and
+// returning the context of the next ready process after switching to its
+// address space and kernel stack.
+pub fn schedule(
+    stack_frame: InterruptStackFrameValue,
+    regs: Registers
+) -> Option<(InterruptStackFrameValue, Registers)> {
+    // The process table could be locked by the interrupted code or by
+    // another CPU
+    let mut table = PROCESS_TABLE.try_write()?;
+
+    // The CPU goes back to the process pinned to it, the kernel or an idle
+    // process, when nothing else is ready after the exit of the current one
+    let cpu = sys::smp::cpu_id();
+    let current = id();
+    let next = table.next(current, |proc| table.is_runnable(proc, cpu));
+    let next = match next {
+        Some(next) => next,
+        None if table[current].state == ProcessState::Zombie => {
+            table.iter().find(|proc| proc.affinity == Some(cpu))?.id
+        }
+        None => return None,
+    };
+
+    let proc = &mut table[current];
+    proc.stack_frame = Some(stack_frame);
+    proc.registers = regs;
+    proc.cpu = None;
+    if proc.state == ProcessState::Running {
+        proc.state = ProcessState::Ready;
+    }
+
+    // The pages of a process are freed once its last thread running on a
+    // CPU has exited
+    if proc.state == ProcessState::Zombie {
+        table.exited[cpu] = table[current].kernel_stack.clone();
+        let leader = table.leader(current);
+        let is_done = leader.state == ProcessState::Zombie;
+        if is_done && !leader.is_kernel() && table.cpu(leader.id).is_none() {
+            leader.free_pages();
+        }
+    }
+
+    let proc = &mut table[next];
+    if proc.state == ProcessState::Ready {
+        proc.state = ProcessState::Running;
+    }
+    proc.cpu = Some(cpu);
+    set_id(next);
+    proc.switch();
+    proc.stack_frame.map(|stack_frame| (stack_frame, proc.registers))
+}
+
+// Collect the exit code of a child process, blocking the current thread
+// until the child exits if it is still running and `block` is true.
+fn reap(pid: usize, block: bool) -> Result<Option<ExitCode>, ()> {
+    let mut table = PROCESS_TABLE.write();
+    let id = id();
+    let leader_id = table[id].leader_id;
+    let proc = match table.get(pid) {
+        Some(proc) if pid > 0 && proc.parent_id == leader_id => proc,
+        _ => return Err(()),
+    };
+    if proc.is_thread() {
+        return Err(());
+    }
+    match proc.state {
+        ProcessState::Zombie if table.cpu(pid).is_none() => {
+            let code = proc.exit_code;
+            table.remove(pid);
+            table[id].state = ProcessState::Running;
+            Ok(code)
+        }
+        // The child is still switching from its kernel stack on another CPU
+        ProcessState::Zombie => Ok(None),
+        _ => {
+            if block {
+                table[id].state = ProcessState::Blocked;
+            }
+            Ok(None)
+        }
+    }
+}
+
+pub fn wait(pid: usize) -> Result<ExitCode, ()> {
+    let id = id();
+    let is_foreground = FOREGROUND.load(Ordering::SeqCst) == id;
+    if is_foreground {
+        FOREGROUND.store(pid, Ordering::SeqCst);
+    }
+    let res = loop {
+        match reap(pid, true) {
+            Ok(Some(code)) => break Ok(code),
+            Ok(None) => {}
+            Err(()) => break Err(()),
+        }
+        // The scheduler will switch to another process while we are waiting
+        sys::clk::block();
+    };
+    if is_foreground {
+        FOREGROUND.store(id, Ordering::SeqCst);
+    }
+    res
+}
+
+pub fn try_wait(pid: usize) -> Result<Option<ExitCode>, ()> {
+    reap(pid, false)
+}
+
+// Send a signal to a process, which is terminated right away by SIGKILL,
+// or just checked to exist with the null signal
+pub fn kill(pid: usize, signal: usize) -> Result<(), ()> {
+    let mut table = PROCESS_TABLE.write();
+    let state = match table.get(pid) {
+        Some(proc) if proc.affinity.is_none() && signal < MAX_SIGNALS => {
+            proc.state
+        }
+        _ => return Err(()),
+    };
+    if !table.is_descendant(pid, id()) {
+        return Err(());
+    }
+    match (state, signal) {
+        (ProcessState::Zombie, _) => Err(()),
+        (_, 0) => Ok(()),
+        (_, SIGKILL) => {
+            terminate(&mut table, pid, ExitCode::Killed);
+            Ok(())
+        }
+        // A kernel task has no handler and takes the default action
+        _ if table[pid].is_kernel() => {
+            if !table[pid].signals.is_ignored(signal) {
+                terminate(&mut table, pid, ExitCode::Killed);
+            }
+            Ok(())
+        }
+        _ => {
+            table[pid].signals.pending |= 1 << signal;
+            Ok(())
+        }
+    }
+}
+
+// Set the handler of a signal with the trampoline calling it, and return the
+// previous handler
+pub fn sigaction(
+    signal: usize,
+    handler: usize,
+    trampoline: usize
+) -> Result<usize, ()> {
+    if signal == 0 || signal == SIGKILL || signal >= MAX_SIGNALS {
+        return Err(());
+    }
+    let mut table = PROCESS_TABLE.write();
+    let proc = &mut table[id()];
+    let old = proc.signals.handlers[signal];
+    proc.signals.handlers[signal] = handler;
+    if handler != SIG_DFL && handler != SIG_IGN {
+        proc.signals.trampoline = trampoline;
+    }
+    Ok(old)
+}
+
+// Update the mask of blocked signals and return the previous one
+pub fn sigprocmask(how: usize, mask: u32) -> Result<u32, ()> {
+    let mut table = PROCESS_TABLE.write();
+    let proc = &mut table[id()];
+    let old = proc.signals.blocked;
+    let blocked = match how {
+        0 => old | mask,
+        1 => old & !mask,
+        2 => mask,
+        _ => return Err(()),
+    };
+    proc.signals.blocked = blocked & !(1 << SIGKILL);
+    Ok(old)
+}
+
+// Ctrl-C interrupts the foreground process the next time a process goes
+// back to user mode, because the process table cannot be written by the
+// keyboard interrupt handler. The kernel and its tasks read it as a key.
+pub fn interrupt() -> bool {
+    let pid = FOREGROUND.load(Ordering::SeqCst);
+    let is_user = PROCESS_TABLE.try_read().is_some_and(|table| {
+        table.contains(pid) && !table[pid].is_kernel()
+    });
+    if !is_user {
+        return false;
+    }
+    INTERRUPT.store(true, Ordering::SeqCst);
+    true
+}
+
+// Check if the current process has a signal to handle, to stop waiting in
+// a blocking syscall
+pub fn has_signal() -> bool {
+    let id = id();
+    if id == 0 {
+        return false;
+    }
+    let is_foreground = FOREGROUND.load(Ordering::SeqCst) == id;
+    if is_foreground && INTERRUPT.load(Ordering::SeqCst) {
+        return true;
+    }
+    PROCESS_TABLE.read()[id].signals.is_pending()
+}
+
+// Return the context where the current process should go back to user mode
+// to run the handler of its next signal. The process is terminated instead
+// if the default action of the signal is taken.
+pub fn deliver_signal(
+    stack_frame: InterruptStackFrameValue,
+    regs: Registers
+) -> Option<(InterruptStackFrameValue, Registers)> {
+    // The process table could be locked by the interrupted code
+    let mut table = PROCESS_TABLE.try_write()?;
+
+    if INTERRUPT.swap(false, Ordering::SeqCst) {
+        let pid = FOREGROUND.load(Ordering::SeqCst);
+        if pid != 0 && table.contains(pid) {
+            table[pid].signals.pending |= 1 << SIGINT;
+        }
+    }
+
+    let id = id();
+    if id == 0 || table[id].state == ProcessState::Zombie {
+        return None;
+    }
+    let proc = &mut table[id];
+    let signal = proc.signals.take()?;
+    if proc.signals.is_ignored(signal) {
+        return None;
+    }
+    match proc.signals.handlers[signal] {
+        SIG_DFL => {
+            terminate(&mut table, id, ExitCode::Killed);
+            None
+        }
+        handler => {
+            // Other signals are blocked until the handler returns
+            let blocked = proc.signals.blocked;
+            proc.signals.saved = Some((stack_frame, regs, blocked));
+            proc.signals.blocked = u32::MAX;
+
+            // The trampoline is called below the red zone of the stack
+            let addr = stack_frame.stack_pointer.as_u64() - 128;
+            let stack_addr = (addr & !0xF) - 8;
+            let stack_frame = InterruptStackFrameValue::new(
+                VirtAddr::new(proc.signals.trampoline as u64),
+                stack_frame.code_segment,
+                stack_frame.cpu_flags,
+                VirtAddr::new(stack_addr),
+                stack_frame.stack_segment,
+            );
+            let regs = Registers {
+                rdi: signal,
+                rsi: handler,
+                ..Default::default()
+            };
+            Some((stack_frame, regs))
+        }
+    }
+}
+
+// Return the context saved before the signal handler of the current process
+pub fn sigreturn() -> Option<(InterruptStackFrameValue, Registers)> {
+    let mut table = PROCESS_TABLE.write();
+    let proc = &mut table[id()];
+    let (stack_frame, regs, blocked) = proc.signals.saved.take()?;
+    proc.signals.blocked = blocked;
+    Some((stack_frame, regs))
+}
+
+pub fn exit(code: ExitCode) {
+    let id = id();
+    if id == 0 {
+        return; // The kernel cannot exit
+    }
+    let mut table = PROCESS_TABLE.write();
+    terminate(&mut table, id, code);
+}
+
+// Turn a process into a zombie until its exit code is collected by its
+// parent, which is woken up so that the scheduler always has a process to
+// switch to after the exit of the current one.
+fn terminate(table: &mut ProcessTable, id: usize, code: ExitCode) {
+    // The threads of the process are terminated with it
+    let id = table[id].leader_id;
+    for proc in table.iter_mut() {
+        if proc.leader_id == id {
+            proc.state = ProcessState::Zombie;
+            proc.futex_addr = None;
+        }
+    }
+    table[id].exit_code = Some(code);
+
+    // Closing the handles of the process will give an EOF to the readers of
+    // the pipes it was writing to
+    for handle in table[id].data.handles.iter_mut() {
+        *handle = None;
+    }
+
+    // The zombie children of the process are reaped and the others are
+    // adopted by the kernel
+    let zombies: Vec<usize> = table.iter().filter(|proc| {
+        proc.parent_id == id && proc.state == ProcessState::Zombie
+    }).filter(|proc| {
+        !proc.is_thread() && table.cpu(proc.id).is_none()
+    }).map(|proc| proc.id).collect();
+    for pid in zombies {
+        table.remove(pid);
+    }
+    let mut orphans = Vec::new();
+    for proc in table.iter_mut() {
+        if proc.parent_id == id && !proc.is_thread() {
+            proc.parent_id = 0;
+            orphans.push(proc.id);
+        }
+    }
+    table.orphans.extend(orphans);
+
+    let parent_id = table[id].parent_id;
+    table.wake_threads(parent_id);
+    table[parent_id].signals.pending |= 1 << SIGCHLD;
+
+    // The pages of a process running on a CPU are freed by the scheduler of
+    // the CPU when it switches to another process
+    if !table[id].is_kernel() && table.cpu(id).is_none() {
+        table[id].free_pages();
+    }
+
+    // The kernel doesn't wait for the orphans, which are reaped here once
+    // they have exited, except the ones whose threads are still running on
+    // their kernel stacks
+    let zombies: Vec<usize> = table.orphans.iter().copied().filter(|&pid| {
+        table[pid].state == ProcessState::Zombie && table.cpu(pid).is_none()
+    }).collect();
+    for pid in zombies {
+        table.remove(pid);
+    }
+}
+
+// Terminate the current thread, or its process if it is the main thread, and
+// wake the threads of the process that could be waiting to join it
+pub fn exit_thread(code: ExitCode) {
+    let id = id();
+    if id == 0 {
+        return; // The kernel cannot exit
+    }
+    let mut table = PROCESS_TABLE.write();
+    if !table[id].is_thread() {
+        terminate(&mut table, id, code);
+        return;
     }
+    table[id].state = ProcessState::Zombie;
+    table[id].exit_code = Some(code);
+    let leader_id = table[id].leader_id;
+    table.wake_threads(leader_id);
+}
+
+// Collect the exit code of another thread of the current process, blocking
+// the current thread until it exits or until a signal interrupts it
+pub fn join(tid: usize) -> Result<ExitCode, ()> {
+    let id = id();
+    loop {
+        {
+            let mut table = PROCESS_TABLE.write();
+            let leader_id = table[id].leader_id;
+            let thread = match table.get(tid) {
+                Some(proc) if tid != id && proc.is_thread() => proc,
+                _ => return Err(()),
+            };
+            if thread.leader_id != leader_id {
+                return Err(());
+            }
+            if thread.state == ProcessState::Zombie {
+                let code = thread.exit_code.ok_or(())?;
+                let fs_base = thread.fs_base;
+                table.remove(tid);
+                table.leader_mut(id).unmap_tls(fs_base);
+                table[id].state = ProcessState::Running;
+                return Ok(code);
+            }
+            table[id].state = ProcessState::Blocked;
+        }
+        if has_signal() {
+            set_state(ProcessState::Running);
+            return Err(());
+        }
+        // The scheduler will switch to another thread while we are waiting
+        sys::clk::block();
+    }
+}
+
+// Block the current thread until another thread of the process wakes it up
+// with the address of the futex, unless the value at this address has
+// already changed or a signal interrupts it
+pub fn futex_wait(addr: u64, val: u32) -> Result<(), ()> {
+    let id = id();
+    if !is_futex_addr(addr) {
+        return Err(());
+    }
+    // The page could be allocated on demand by the page fault handler, which
+    // needs to read the process table
+    let ptr = addr as *const u32;
+    unsafe { core::ptr::read_volatile(ptr) };
+    {
+        let mut table = PROCESS_TABLE.write();
+        if unsafe { core::ptr::read_volatile(ptr) } != val {
+            return Err(());
+        }
+        table[id].futex_addr = Some(addr);
+    }
+    loop {
+        {
+            let mut table = PROCESS_TABLE.write();
+            if table[id].futex_addr.is_none() {
+                table[id].state = ProcessState::Running;
+                return Ok(());
+            }
+            table[id].state = ProcessState::Blocked;
+        }
+        if has_signal() {
+            let mut table = PROCESS_TABLE.write();
+            table[id].futex_addr = None;
+            table[id].state = ProcessState::Running;
+            return Err(());
+        }
+        sys::clk::block();
+    }
+}
+
+// Wake up to the given number of threads of the current process waiting on
+// the futex at the address, and return the number of threads woken up
+pub fn futex_wake(addr: u64, n: usize) -> usize {
+    let mut table = PROCESS_TABLE.write();
+    let leader_id = table[id()].leader_id;
+    let mut count = 0;
+    for proc in table.iter_mut() {
+        if count == n {
+            break;
+        }
+        if proc.leader_id == leader_id && proc.futex_addr == Some(addr) {
+            proc.futex_addr = None;
+            if proc.state == ProcessState::Blocked {
+                proc.state = ProcessState::Ready;
+            }
+            count += 1;
+        }
+    }
+    count
+}
+
+// Check that a futex is an aligned word of the memory window of the current
+// process that can be read without faulting
+fn is_futex_addr(addr: u64) -> bool {
+    if addr % 4 != 0 {
+        return false;
+    }
+    if is_demand_paged(addr) {
+        return true;
+    }
+    let table = PROCESS_TABLE.read();
+    let proc = table.leader(id());
+    let end = proc.code_addr + proc.size as u64;
+    let is_window = proc.code_addr <= addr && addr + 4 <= end;
+    is_window && proc.mapper().translate_addr(VirtAddr::new(addr)).is_some()
+}
+
+// The scheduler starts a kernel task with this function, which exits the
+// process with the code returned by the task
+extern "sysv64" fn start_task(arg: usize) -> ! {
+    let task = unsafe { Box::from_raw(arg as *mut Task) };
+    let code = task();
+    crate::api::syscall::exit(code);
+    unreachable!("kernel task resumed after its exit");
+}
+
+// Context of a process killed by an exception, which is given to the function
+// writing its core file
+pub struct Crash {
+    pub stack_frame: InterruptStackFrameValue,
+    pub registers: Registers,
+    pub addr: u64,
+    pub code: ExitCode,
+    pub dump: bool,
+}
 
-    proc.free_pages();
+// Return the context resuming the current process in the kernel on the top of
+// its own stack to write its core file and exit, because the handler of the
+// exception must not use the filesystem or the scheduler on its stack
+pub fn crash(crash: Crash) -> Option<(InterruptStackFrameValue, Registers)> {
+    let table = PROCESS_TABLE.read();
+    let stack = table[id()].kernel_stack.as_ref()?.top();
+    let stack_frame = InterruptStackFrameValue::new(
+        VirtAddr::new(exit_crashed as usize as u64),
+        GDT.1.code,
+        RFlags::INTERRUPT_FLAG,
+        stack - 8u64,
+        GDT.1.data,
+    );
+    let registers = Registers {
+        rdi: Box::into_raw(Box::new(crash)) as usize,
+        ..Default::default()
+    };
+    Some((stack_frame, registers))
+}
+
+extern "sysv64" fn exit_crashed(arg: usize) -> ! {
+    let crash = unsafe { Box::from_raw(arg as *mut Crash) };
+    if crash.dump {
+        let res = sys::coredump::write(
+            &crash.stack_frame, &crash.registers, crash.addr
+        );
+        if res.is_err() {
+            let csi_color = crate::api::console::Style::color("red");
+            let csi_reset = crate::api::console::Style::reset();
+            printk!(
+                "{}Error:{} Could not write core file\n", csi_color, csi_reset
+            );
+        }
+    }
+    crate::api::syscall::exit(crash.code);
+    unreachable!("crashed process resumed after its exit");
 }
 
 unsafe fn page_table_frame() -> PhysFrame {
@@ -280,18 +1212,126 @@ pub unsafe fn free(ptr: *mut u8, layout: Layout) {
     }
 }
 
+// Map pages in the mapping area of the current process, at the given address
+// or at the first free range if the address is null
+pub fn mmap(addr: u64, size: usize, flags: u8) -> Result<u64, ()> {
+    let size = size.div_ceil(4096) * 4096;
+    if size == 0 || addr % 4096 != 0 {
+        return Err(());
+    }
+    let mut table = PROCESS_TABLE.write();
+    let proc = table.leader_mut(id());
+    let (start, end) = (proc.mmap_addr, proc.guard_addr);
+    let addr = find_free_range(&proc.mappings, start, end, addr, size).
+        ok_or(())?;
+    let mut mapper = proc.mapper();
+    if sys::mem::map_pages(&mut mapper, addr, size, map_flags(flags)).is_err() {
+        sys::mem::free_pages(&mut mapper, addr, size);
+        return Err(());
+    }
+    proc.mappings.insert(addr, size);
+    Ok(addr)
+}
+
+// Copy a buffer to the pages of the current process at the given address,
+// which might not be writable by the process
+pub fn write_pages(addr: u64, buf: &[u8]) -> Result<(), ()> {
+    let table = PROCESS_TABLE.read();
+    let proc = table.leader(id());
+    sys::loader::write_pages(&mut proc.mapper(), addr, buf)
+}
+
+// Unmap the pages of a mapping of the current process, which must be given
+// with its address and size
+pub fn munmap(addr: u64, size: usize) -> Result<(), ()> {
+    let size = size.div_ceil(4096) * 4096;
+    let mut table = PROCESS_TABLE.write();
+    let proc = table.leader_mut(id());
+    if proc.mappings.get(&addr) != Some(&size) {
+        return Err(());
+    }
+    proc.mappings.remove(&addr);
+    sys::mem::free_pages(&mut proc.mapper(), addr, size);
+    Ok(())
+}
+
+// Find a free range of the given size between the mappings, at the given
+// address or at the lowest possible address if the address is null
+fn find_free_range(
+    mappings: &BTreeMap<u64, usize>,
+    start: u64,
+    end: u64,
+    addr: u64,
+    size: usize,
+) -> Option<u64> {
+    let size = size as u64;
+    let is_free = |addr: u64| {
+        addr.checked_add(size).is_some_and(|top| {
+            start <= addr && top <= end && mappings.iter().all(|(&a, &n)| {
+                top <= a || a + n as u64 <= addr
+            })
+        })
+    };
+    if addr != 0 {
+        return Some(addr).filter(|&addr| is_free(addr));
+    }
+    let mut addr = start;
+    for (&a, &n) in mappings {
+        if addr + size <= a {
+            break;
+        }
+        addr = addr.max(a + n as u64);
+    }
+    Some(addr).filter(|&addr| is_free(addr))
+}
+
+fn map_flags(flags: u8) -> PageTableFlags {
+    let mut res = PageTableFlags::PRESENT;
+    let is_readable = MapFlag::Read.is_set(flags);
+    let is_writable = MapFlag::Write.is_set(flags);
+    let is_executable = MapFlag::Exec.is_set(flags);
+    if is_readable || is_writable || is_executable {
+        res |= PageTableFlags::USER_ACCESSIBLE;
+    }
+    if is_writable {
+        res |= PageTableFlags::WRITABLE;
+    }
+    if !is_executable {
+        res |= PageTableFlags::NO_EXECUTE;
+    }
+    res
+}
+
 #[derive(Clone)]
 pub struct Process {
     id: usize,
     parent_id: usize,
+    leader_id: usize,
+    name: String,
     code_addr: u64,
     stack_addr: u64,
     entry_point_addr: u64,
+    size: usize,
+    heap_addr: u64,
+    mmap_addr: u64,
+    guard_addr: u64,
+    mappings: BTreeMap<u64, usize>,
+    aslr: bool,
+    fs_base: u64,
+    tls: Tls,
+    futex_addr: Option<u64>,
     page_table_frame: PhysFrame,
     stack_frame: Option<InterruptStackFrameValue>,
     registers: Registers,
     data: ProcessData,
     allocator: Arc<LockedHeap>,
+    signals: Signals,
+    kernel_stack: Option<Arc<KernelStack>>,
+    trace: Option<Trace>,
+    cpu: Option<usize>, // CPU running the process
+    affinity: Option<usize>, // CPU where the process is pinned
+    state: ProcessState,
+    exit_code: Option<ExitCode>,
 }
 
 impl Process {
@@ -299,196 +1339,528 @@ impl Process {
         Self {
             id: 0,
             parent_id: 0,
+            leader_id: 0,
+            name: "kernel".to_string(),
             code_addr: 0,
             stack_addr: 0,
             entry_point_addr: 0,
+            size: 0,
+            heap_addr: 0,
+            mmap_addr: 0,
+            guard_addr: 0,
+            mappings: BTreeMap::new(),
+            aslr: false,
+            fs_base: 0,
+            tls: Tls::default(),
+            futex_addr: None,
             stack_frame: None,
             page_table_frame: Cr3::read().0,
             registers: Registers::default(),
             data: ProcessData::new("/", None),
             allocator: Arc::new(LockedHeap::empty()),
+            signals: Signals::new(),
+            kernel_stack: None,
+            trace: None,
+            cpu: None,
+            affinity: None,
+            state: ProcessState::Running,
+            exit_code: None,
         }
     }
 
+    // Create a child process that will be executed by the scheduler and
+    // return its PID without waiting for it to exit
     pub fn spawn(
+        path: &str,
         bin: &[u8],
         args_ptr: usize,
         args_len: usize
-    ) -> Result<(), ExitCode> {
-        if let Ok(id) = Self::create(bin) {
+    ) -> Result<usize, ExitCode> {
+        if PROCESS_TABLE.read().len() >= MAX_PROCS {
+            return Err(ExitCode::ExecError);
+        }
+        let id = NEXT_PID.fetch_add(1, Ordering::SeqCst);
+        if Self::create(id, path, bin).is_ok() {
             let proc = {
                 let table = PROCESS_TABLE.read();
                 table[id].clone()
             };
-            proc.exec(args_ptr, args_len);
-            unreachable!(); // The kernel switched to the child process
+            if let Err(code) = proc.exec(args_ptr, args_len) {
+                proc.free_pages();
+                PROCESS_TABLE.write().remove(id);
+                return Err(code);
+            }
+            Ok(id)
         } else {
             Err(ExitCode::ExecError)
         }
     }
 
-    fn create(bin: &[u8]) -> Result<usize, ()> {
-        if MAX_PID.load(Ordering::SeqCst) >= MAX_PROCS {
+    // Create a copy of the current process sharing its pages until one of
+    // them writes to it, and return the PID of the child that will resume
+    // from the syscall with a null result
+    pub fn fork() -> Result<usize, ()> {
+        if PROCESS_TABLE.read().len() >= MAX_PROCS || id() == 0 {
             return Err(());
         }
+        let id = NEXT_PID.fetch_add(1, Ordering::SeqCst);
 
-        let page_table_frame = sys::mem::frame_allocator().allocate_frame().
-            expect("frame allocation failed");
+        // A thread forks with the data and the mappings of its process
+        let parent = {
+            let process_table = PROCESS_TABLE.read();
+            let leader = process_table.leader(sys::process::id());
+            let mut parent = process_table[sys::process::id()].clone();
+            parent.data = leader.data.clone();
+            parent.mappings = leader.mappings.clone();
+            parent
+        };
 
+        let page_table_frame = create_page_table()?;
         let page_table = unsafe {
             sys::mem::create_page_table(page_table_frame)
         };
+        let mut mapper = unsafe {
+            OffsetPageTable::new(page_table, VirtAddr::new(phys_mem_offset()))
+        };
+        let addr = parent.code_addr;
+        let size = parent.size;
+        if let Err(()) = sys::mem::share_pages(
+            &mut parent.mapper(), &mut mapper, addr, size
+        ) {
+            sys::mem::free_pages(&mut mapper, addr, size);
+            return Err(());
+        }
+
+        // The metadata of the heap allocator is copied as it is because
+        // the holes of the heap are at the same addresses in the child
+        let allocator = Arc::new(LockedHeap::empty());
+        unsafe {
+            let heap = core::ptr::read(&*parent.allocator.lock());
+            *allocator.lock() = heap;
+        }
+
+        let registers = Registers { rax: 0, ..parent.registers };
+        let proc = Process {
+            id,
+            parent_id: parent.leader_id,
+            leader_id: id,
+            page_table_frame,
+            registers,
+            allocator,
+            signals: Signals { pending: 0, ..parent.signals.clone() },
+            kernel_stack: Some(Arc::new(KernelStack::new(KERNEL_STACK_SIZE))),
+            cpu: None,
+            affinity: None,
+            state: ProcessState::Ready,
+            exit_code: None,
+            ..*parent
+        };
+
+        let mut process_table = PROCESS_TABLE.write();
+        process_table.insert(proc);
+
+        Ok(id)
+    }
+
+    // Create a thread of the current process sharing its address space and
+    // its handles, which starts to run the function at the entry point with
+    // the argument on the given stack, and return its TID
+    pub fn thread(entry: u64, stack: u64, arg: usize) -> Result<usize, ()> {
+        if PROCESS_TABLE.read().len() >= MAX_PROCS || id() == 0 {
+            return Err(());
+        }
+        let mut table = PROCESS_TABLE.write();
+        let leader = table.leader_mut(sys::process::id());
+        let start = leader.code_addr;
+        let end = start + leader.size as u64;
+        if entry < start || entry >= end || stack <= start || stack > end {
+            return Err(());
+        }
+
+        // Each thread has its own TLS blocks and TCB, mapped like the pages
+        // of `mmap` and unmapped when it is joined
+        let fs_base = if leader.tls.is_empty() {
+            0
+        } else {
+            let size = leader.tls.size();
+            let (start, end) = (leader.mmap_addr, leader.guard_addr);
+            let addr = find_free_range(&leader.mappings, start, end, 0, size).
+                ok_or(())?;
+            let mut mapper = leader.mapper();
+            match map_tls(&mut mapper, addr, &leader.tls) {
+                Ok(tp) => {
+                    leader.mappings.insert(addr, size);
+                    tp
+                }
+                Err(()) => {
+                    sys::mem::free_pages(&mut mapper, addr, size);
+                    return Err(());
+                }
+            }
+        };
+
+        let leader = table.leader(sys::process::id());
+        let id = NEXT_PID.fetch_add(1, Ordering::SeqCst);
+
+        let stack_frame = InterruptStackFrameValue::new(
+            VirtAddr::new(entry),
+            GDT.1.user_code,
+            RFlags::INTERRUPT_FLAG,
+            VirtAddr::new(stack),
+            GDT.1.user_data,
+        );
+        let registers = Registers {
+            rdi: arg,
+            ..Default::default()
+        };
+        let mut proc = Process {
+            id,
+            parent_id: leader.id,
+            leader_id: leader.id,
+            mappings: BTreeMap::new(),
+            stack_frame: Some(stack_frame),
+            registers,
+            signals: Signals { pending: 0, ..leader.signals.clone() },
+            kernel_stack: Some(Arc::new(KernelStack::new(KERNEL_STACK_SIZE))),
+            fs_base,
+            futex_addr: None,
+            cpu: None,
+            affinity: None,
+            state: ProcessState::Ready,
+            exit_code: None,
+            ..leader.clone()
+        };
+
+        // The handles of the process are only held by its main thread
+        for handle in proc.data.handles.iter_mut() {
+            *handle = None;
+        }
+
+        table.insert(proc);
+        Ok(id)
+    }
 
-        let kernel_page_table = unsafe {
-            sys::mem::active_page_table()
+    // Create a child process running a task in the kernel with the standard
+    // handles of the current process, replaced by some of its handles given
+    // as pairs of handles of the child and of the current process, and
+    // return its PID without waiting for it to exit
+    pub fn spawn_task<F>(
+        name: &str,
+        handles: &[(usize, usize)],
+        task: F
+    ) -> Result<usize, ExitCode>
+    where
+        F: FnOnce() -> ExitCode + Send + 'static,
+    {
+        if PROCESS_TABLE.read().len() >= MAX_PROCS {
+            return Err(ExitCode::ExecError);
+        }
+        let mut table = PROCESS_TABLE.write();
+        let parent = table.leader(sys::process::id());
+        let mut data = parent.data.clone();
+        for handle in data.handles.iter_mut().skip(4) {
+            *handle = None;
+        }
+        for &(i, h) in handles {
+            let handle = parent.data.handles.get(h).cloned().flatten();
+            if let Some(entry) = data.handles.get_mut(i) {
+                *entry = handle;
+            }
+        }
+        let id = NEXT_PID.fetch_add(1, Ordering::SeqCst);
+
+        // The task is boxed twice to be given as a thin pointer to the
+        // function starting it, which is called on the stack of the process
+        let task: Box<Task> = Box::new(Box::new(task));
+        let stack = Arc::new(KernelStack::new(TASK_STACK_SIZE));
+        let stack_frame = InterruptStackFrameValue::new(
+            VirtAddr::new(start_task as usize as u64),
+            GDT.1.code,
+            RFlags::INTERRUPT_FLAG,
+            stack.top() - 8u64,
+            GDT.1.data,
+        );
+        let registers = Registers {
+            rdi: Box::into_raw(task) as usize,
+            ..Default::default()
         };
+        let proc = Process {
+            id,
+            parent_id: parent.id,
+            leader_id: id,
+            name: name.to_string(),
+            page_table_frame: table[0].page_table_frame,
+            stack_frame: Some(stack_frame),
+            registers,
+            data,
+            kernel_stack: Some(stack),
+            trace: parent.trace,
+            state: ProcessState::Ready,
+            ..Process::new()
+        };
+        table.insert(proc);
+        Ok(id)
+    }
 
-        // FIXME: for now we just copy everything
-        let pages = page_table.iter_mut().zip(kernel_page_table.iter());
-        for (user_page, kernel_page) in pages {
-            *user_page = kernel_page.clone();
+    fn create(id: usize, path: &str, bin: &[u8]) -> Result<(), ()> {
+        // The libraries are loaded after the program, below the args copied
+        // in the middle of the window, which is enlarged twice their size
+        let libs = sys::loader::libraries(bin)?;
+        let mut size = proc_size(bin)?;
+        if bin[0..4] == ELF_MAGIC {
+            size += 2 * sys::loader::libraries_size(bin, &libs)?;
+        }
+        if size > MAX_PROC_SIZE {
+            return Err(());
         }
 
+        let page_table_frame = create_page_table()?;
+        let page_table = unsafe {
+            sys::mem::create_page_table(page_table_frame)
+        };
         let mut mapper = unsafe {
             OffsetPageTable::new(page_table, VirtAddr::new(phys_mem_offset()))
         };
 
-        let proc_size = MAX_PROC_SIZE as u64;
-        let code_addr = CODE_ADDR.fetch_add(proc_size, Ordering::SeqCst);
-        let stack_addr = code_addr + proc_size - 4096;
-
-        let mut entry_point_addr = 0;
-
-        //debug!("Process memory:");
-        if bin[0..4] == ELF_MAGIC { // ELF binary
-            if let Ok(obj) = object::File::parse(bin) {
-                entry_point_addr = obj.entry();
-
-                for segment in obj.segments() {
-                    if let Ok(data) = segment.data() {
-                        // NOTE: The size of the segment in memory can be
-                        // larger than on the disk because the object can
-                        // contain uninitialized sections like ".bss" that has
-                        // a length but no data.
-                        let addr = code_addr + segment.address();
-                        let size = segment.size() as usize;
-                        /*
-                        debug!(
-                            "{:#X}..{:#X}: {} bytes for a code segment ({:#X}..{:#X}: {} bytes)",
-                            addr, addr + data.len() as u64, data.len(),
-                            segment.address(), segment.address() + segment.size(), segment.size(),
-                        );
-                        */
-                        load_binary(&mut mapper, addr, size, data)?;
-                    }
-                }
-            }
-        } else if bin[0..4] == BIN_MAGIC { // Flat binary
-            load_binary(&mut mapper, code_addr, bin.len() - 4, &bin[4..])?;
+        // The layout of the memory window is randomized for ELF binaries,
+        // but the flat binaries keep the fixed layout
+        let aslr = bin[0..4] == ELF_MAGIC;
+        let code_addr = if is_pie(bin) {
+            USER_ADDR + random_offset(USER_SIZE - size as u64 + 4096, 4096)
         } else {
-            return Err(());
+            USER_ADDR
+        };
+        let mut stack_addr = code_addr + size as u64 - 4096;
+        if aslr {
+            stack_addr -= random_offset(size as u64 / 64, 16);
         }
 
+        let args_addr = code_addr + (stack_addr - code_addr) / 2;
+        let image = match load_program(&mut mapper, code_addr, bin, &libs) {
+            Ok(image) if image.end <= args_addr => image,
+            _ => {
+                sys::mem::free_pages(&mut mapper, code_addr, size);
+                return Err(());
+            }
+        };
+
         let parent = {
             let process_table = PROCESS_TABLE.read();
-            process_table[id()].clone()
+            process_table.leader(sys::process::id()).clone()
         };
 
         let data = parent.data.clone();
-        let registers = parent.registers;
-        let stack_frame = parent.stack_frame;
+        let registers = Registers::default();
+        let stack_frame = None;
 
         let allocator = Arc::new(LockedHeap::empty());
+        let kernel_stack = Some(Arc::new(KernelStack::new(KERNEL_STACK_SIZE)));
+        let state = ProcessState::Blocked; // Until the process is executed
+        let exit_code = None;
 
-        let id = MAX_PID.fetch_add(1, Ordering::SeqCst);
         let parent_id = parent.id;
         let proc = Process {
             id,
             parent_id,
+            leader_id: id,
+            name: path.to_string(),
             code_addr,
             stack_addr,
-            entry_point_addr,
+            entry_point_addr: image.entry,
+            size,
+            heap_addr: 0,
+            mmap_addr: 0,
+            guard_addr: 0,
+            mappings: BTreeMap::new(),
+            aslr,
+            fs_base: image.fs_base,
+            tls: image.tls,
+            futex_addr: None,
             page_table_frame,
             data,
             stack_frame,
             registers,
             allocator,
+            signals: Signals::new(),
+            kernel_stack,
+            trace: parent.trace,
+            cpu: None,
+            affinity: None,
+            state,
+            exit_code,
         };
 
         let mut process_table = PROCESS_TABLE.write();
-        process_table[id] = Box::new(proc);
+        process_table.insert(proc);
 
-        Ok(id)
+        Ok(())
     }
 
-    // Switch to user mode and execute the program
-    fn exec(&self, args_ptr: usize, args_len: usize) {
-        let page_table = unsafe { sys::process::page_table() };
-        let mut mapper = unsafe {
-            OffsetPageTable::new(page_table, VirtAddr::new(phys_mem_offset()))
-        };
+    // Prepare the context of the program for the scheduler that will switch
+    // to user mode to execute it
+    fn exec(&self, args_ptr: usize, args_len: usize) -> Result<(), ExitCode> {
+        let mut mapper = self.mapper();
 
-        // Copy args to user memory
-        let args_addr = self.code_addr + (self.stack_addr - self.code_addr) / 2;
-        sys::mem::alloc_pages(&mut mapper, args_addr, 1).
-            expect("proc args alloc");
+        // Copy args from the memory of the parent before switching to the
+        // address space of the process
         let args: &[&str] = unsafe {
             let ptr = ptr_from_addr(args_ptr as u64) as usize;
             core::slice::from_raw_parts(ptr as *const &str, args_len)
         };
-        let mut addr = args_addr;
-        let vec: Vec<&str> = args.iter().map(|arg| {
-            let ptr = addr as *mut u8;
-            addr += arg.len() as u64;
+        let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+
+        let align = core::mem::align_of::<&str>() as u64;
+        let args_addr = self.args_addr();
+        let args_size = args.iter().map(|arg| arg.len()).sum::<usize>()
+            + args.len() * core::mem::size_of::<&str>() + align as usize;
+        sys::mem::alloc_pages(&mut mapper, args_addr, args_size).
+            map_err(|_| ExitCode::ExecError)?;
+
+        // The space between the args and the stack is shared by the heap and
+        // the area where pages can be mapped by the process
+        let args_end = args_addr + args_size as u64;
+        let mut heap_addr = args_end.div_ceil(4096) * 4096;
+        if self.aslr {
+            let limit = (self.stack_addr - heap_addr) / 16;
+            heap_addr += random_offset(limit, 4096);
+        }
+        let free_size = self.stack_addr - heap_addr;
+        let heap_size = (free_size / 2) & !0xFFF;
+        let mmap_addr = heap_addr + heap_size;
+        sys::mem::alloc_pages(&mut mapper, heap_addr, 1).
+            map_err(|_| ExitCode::ExecError)?;
+
+        // The guard page below the stack is mapped for the kernel only to
+        // catch stack overflows in the process
+        let guard_addr = (self.stack_addr - free_size / 4) & !0xFFF;
+        let flags = PageTableFlags::PRESENT;
+        sys::mem::map_pages(&mut mapper, guard_addr, 1, flags).
+            map_err(|_| ExitCode::ExecError)?;
+        let heap_size = heap_size as usize;
+
+        //debug!("{:#X}..{:#X}: {} bytes for the args", args_addr, args_addr + args_size as u64, args_size);
+        //debug!("{:#X}..{:#X}: {} bytes for the heap", heap_addr, heap_addr + heap_size as u64, heap_size);
+        //debug!("{:#X}..{:#X}: {} bytes for the mappings", mmap_addr, guard_addr, guard_addr - mmap_addr);
+        //debug!("{:#X}..{:#X}: {} bytes for the stack", guard_addr + 4096, self.stack_addr, self.stack_addr - guard_addr - 4096);
+
+        // The args and the metadata of the heap allocator are written in the
+        // address space of the process
+        let args_ptr = interrupts::without_interrupts(|| {
+            let (frame, flags) = Cr3::read();
             unsafe {
-                let s = core::slice::from_raw_parts_mut(ptr, arg.len());
-                s.copy_from_slice(arg.as_bytes());
-                core::str::from_utf8_unchecked(s)
+                Cr3::write(self.page_table_frame, flags);
             }
-        }).collect();
-        let align = core::mem::align_of::<&str>() as u64;
-        addr += align - (addr % align);
-        let args = vec.as_slice();
-        let ptr = addr as *mut &str;
-        let args: &[&str] = unsafe {
-            let s = core::slice::from_raw_parts_mut(ptr, args.len());
-            s.copy_from_slice(args);
-            s
+
+            let mut addr = args_addr;
+            let vec: Vec<&str> = args.iter().map(|arg| {
+                let ptr = addr as *mut u8;
+                addr += arg.len() as u64;
+                unsafe {
+                    let s = core::slice::from_raw_parts_mut(ptr, arg.len());
+                    s.copy_from_slice(arg.as_bytes());
+                    core::str::from_utf8_unchecked(s)
+                }
+            }).collect();
+            addr += align - (addr % align);
+            let args = vec.as_slice();
+            let ptr = addr as *mut &str;
+            let args: &[&str] = unsafe {
+                let s = core::slice::from_raw_parts_mut(ptr, args.len());
+                s.copy_from_slice(args);
+                s
+            };
+
+            unsafe {
+                self.allocator.lock().init(heap_addr as *mut u8, heap_size);
+                Cr3::write(frame, flags);
+            }
+            args.as_ptr() as u64
+        });
+
+        let stack_frame = InterruptStackFrameValue::new(
+            VirtAddr::new(self.entry_point_addr),
+            GDT.1.user_code,
+            RFlags::INTERRUPT_FLAG,
+            VirtAddr::new(self.stack_addr),
+            GDT.1.user_data,
+        );
+        let registers = Registers {
+            rdi: args_ptr as usize,
+            rsi: args.len(),
+            ..Default::default()
         };
-        let args_ptr = args.as_ptr() as u64;
 
-        let heap_addr = addr + 4096;
-        let heap_size = ((self.stack_addr - heap_addr) / 2) as usize;
-        unsafe {
-            self.allocator.lock().init(heap_addr as *mut u8, heap_size);
-        }
+        let mut table = PROCESS_TABLE.write();
+        let proc = &mut table[self.id];
+        proc.stack_frame = Some(stack_frame);
+        proc.registers = registers;
+        proc.heap_addr = heap_addr;
+        proc.mmap_addr = mmap_addr;
+        proc.guard_addr = guard_addr;
+        proc.state = ProcessState::Ready;
+        Ok(())
+    }
 
-        //debug!("{:#X}..{:#X}: {} bytes for the args", args_addr, args_addr + 4096, 4096); // FIXME: args size
-        //debug!("{:#X}..{:#X}: {} bytes for the heap", heap_addr, heap_addr + heap_size as u64, heap_size);
-        //debug!("{:#X}..{:#X}: {} bytes for the stack", self.stack_addr - heap_size as u64, self.stack_addr, heap_size);
+    fn is_thread(&self) -> bool {
+        self.id != self.leader_id
+    }
+
+    // The kernel and its tasks have no memory window
+    fn is_kernel(&self) -> bool {
+        self.size == 0
+    }
+
+    // The heap and the stack are allocated on demand by the page fault
+    // handler, except the guard page below the stack
+    fn is_demand_paged(&self, addr: u64) -> bool {
+        let stack_end = self.code_addr + self.size as u64;
+        let is_heap = self.heap_addr <= addr && addr < self.mmap_addr;
+        let is_stack = self.guard_addr + 4096 <= addr && addr < stack_end;
+        is_heap || is_stack
+    }
+
+    // The args are copied in the middle of the memory window
+    fn args_addr(&self) -> u64 {
+        self.code_addr + (self.stack_addr - self.code_addr) / 2
+    }
 
-        set_id(self.id); // Change PID
+    // Areas of the memory window, which are only known once the program has
+    // been executed and until its pages are freed when it exits
+    fn regions(&self) -> Vec<Region> {
+        if self.heap_addr == 0 || self.state == ProcessState::Zombie {
+            return Vec::new();
+        }
+        let stack_end = self.code_addr + self.size as u64;
+        let mut areas = alloc::vec![
+            (self.code_addr, self.args_addr(), "code"),
+            (self.args_addr(), self.heap_addr, "args"),
+            (self.heap_addr, self.mmap_addr, "heap"),
+        ];
+        for (&addr, &size) in self.mappings.iter() {
+            areas.push((addr, addr + size as u64, "mmap"));
+        }
+        areas.push((self.guard_addr, self.guard_addr + 4096, "guard"));
+        areas.push((self.guard_addr + 4096, stack_end, "stack"));
+
+        let mapper = self.mapper();
+        areas.into_iter().map(|(start, end, name)| {
+            let mapped = (start..end).step_by(4096).filter(|&addr| {
+                mapper.translate_addr(VirtAddr::new(addr)).is_some()
+            }).count() * 4096;
+            let size = (end - start) as usize;
+            Region { addr: start, size, mapped, name }
+        }).collect()
+    }
 
+    // Switch to the address space and the kernel stack of the process
+    fn switch(&self) {
         unsafe {
             let (_, flags) = Cr3::read();
             Cr3::write(self.page_table_frame, flags);
-
-            asm!(
-                "cli",        // Disable interrupts
-                "push {:r}",  // Stack segment (SS)
-                "push {:r}",  // Stack pointer (RSP)
-                "push 0x200", // RFLAGS with interrupts enabled
-                "push {:r}",  // Code segment (CS)
-                "push {:r}",  // Instruction pointer (RIP)
-                "iretq",
-                in(reg) GDT.1.user_data.0,
-                in(reg) self.stack_addr,
-                in(reg) GDT.1.user_code.0,
-                in(reg) self.code_addr + self.entry_point_addr,
-                in("rdi") args_ptr,
-                in("rsi") args_len,
-            );
         }
+        FsBase::write(VirtAddr::new(self.fs_base)); // Thread pointer
+        let stack = self.kernel_stack.as_ref().map(|stack| stack.top());
+        sys::gdt::set_kernel_stack(stack);
     }
 
     fn mapper(&self) -> OffsetPageTable {
@@ -500,36 +1872,361 @@ impl Process {
         }
     }
 
+    // Unmap the pages of the TLS blocks of a thread given by its thread
+    // pointer, unless it is the one of the main thread
+    fn unmap_tls(&mut self, fs_base: u64) {
+        if fs_base == 0 || fs_base == self.fs_base {
+            return;
+        }
+        let mapping = self.mappings.range(..=fs_base).next_back();
+        if let Some((&addr, &size)) = mapping {
+            if fs_base < addr + size as u64 {
+                self.mappings.remove(&addr);
+                sys::mem::free_pages(&mut self.mapper(), addr, size);
+            }
+        }
+    }
+
     fn free_pages(&self) {
         let mut mapper = self.mapper();
+        sys::mem::free_pages(&mut mapper, self.code_addr, self.size);
+    }
+}
 
-        let size = MAX_PROC_SIZE;
-        sys::mem::free_pages(&mut mapper, self.code_addr, size);
+// Return the size of the process that can be set by a binary in a dedicated
+// section, after checking that its segments fit in it
+fn proc_size(bin: &[u8]) -> Result<usize, ()> {
+    if bin[0..4] == ELF_MAGIC {
+        let obj = object::File::parse(bin).map_err(|_| ())?;
+        let size = match obj.section_by_name(PROC_SIZE_SECTION) {
+            Some(section) => {
+                let data = section.data().map_err(|_| ())?;
+                let buf = data.get(0..8).ok_or(())?;
+                u64::from_le_bytes(buf.try_into().map_err(|_| ())?) as usize
+            }
+            None => DEFAULT_PROC_SIZE,
+        };
+        let size = size.div_ceil(4096) * 4096;
+        let base = if is_pie(bin) { 0 } else { USER_ADDR };
+        let mut end = 0;
+        for segment in obj.segments() {
+            let addr = segment.address().checked_sub(base).ok_or(())?;
+            end = end.max(addr + segment.size());
+        }
+        if size > MAX_PROC_SIZE || end + 4096 > size as u64 {
+            return Err(());
+        }
+        Ok(size)
+    } else if bin[0..4] == BIN_MAGIC {
+        Ok(DEFAULT_PROC_SIZE)
+    } else {
+        Err(())
+    }
+}
 
-        let addr = USER_ADDR;
-        match mapper.translate(VirtAddr::new(addr)) {
-            TranslateResult::Mapped { frame: _, offset: _, flags } => {
-                if flags.contains(PageTableFlags::USER_ACCESSIBLE) {
-                    sys::mem::free_pages(&mut mapper, addr, size);
+// Load the program in the memory of the process with its libraries and
+// return its image
+fn load_program(
+    mapper: &mut OffsetPageTable,
+    code_addr: u64,
+    bin: &[u8],
+    libs: &[Vec<u8>],
+) -> Result<Image, ()> {
+    if bin[0..4] == ELF_MAGIC { // ELF binary
+        sys::loader::load(mapper, code_addr, bin, libs)
+    } else if bin[0..4] == BIN_MAGIC { // Flat binary
+        let flags = PageTableFlags::PRESENT
+                  | PageTableFlags::WRITABLE
+                  | PageTableFlags::USER_ACCESSIBLE;
+        let size = bin.len() - 4;
+        load_binary(mapper, code_addr, size, &bin[4..], flags)?;
+        let end = code_addr + size as u64;
+        let tls = Tls::default();
+        Ok(Image { entry: code_addr, end, fs_base: 0, tls })
+    } else {
+        Err(())
+    }
+}
+
+// Return a random multiple of the alignment below the limit
+fn random_offset(limit: u64, align: u64) -> u64 {
+    if limit < align {
+        return 0;
+    }
+    (sys::rng::get_u64() % (limit / align)) * align
+}
+
+// Create the page table of a process with an empty user space and with the
+// mappings of the kernel, which are not accessible from user mode
+fn create_page_table() -> Result<PhysFrame, ()> {
+    let kernel_frame = PROCESS_TABLE.read()[0].page_table_frame;
+    let kernel_table = unsafe { sys::mem::create_page_table(kernel_frame) };
+    let user_addr = VirtAddr::new(USER_ADDR);
+    let kernel_flags = !PageTableFlags::USER_ACCESSIBLE;
+    let user_flags = PageTableFlags::PRESENT
+                   | PageTableFlags::WRITABLE
+                   | PageTableFlags::USER_ACCESSIBLE;
+
+    let frame = alloc_page_table()?;
+    let table = unsafe { sys::mem::create_page_table(frame) };
+    for (entry, kernel_entry) in table.iter_mut().zip(kernel_table.iter()) {
+        if !kernel_entry.is_unused() {
+            let flags = kernel_entry.flags() & kernel_flags;
+            entry.set_addr(kernel_entry.addr(), flags);
+        }
+    }
+
+    // The user space shares its level 4 entry with the kernel, so the level 3
+    // table is copied without the entry of the user space
+    let i = user_addr.p4_index();
+    let kernel_l3_frame = kernel_table[i].frame().map_err(|_| ())?;
+    let kernel_l3_table = unsafe {
+        sys::mem::create_page_table(kernel_l3_frame)
+    };
+    let l3_frame = alloc_page_table()?;
+    let l3_table = unsafe { sys::mem::create_page_table(l3_frame) };
+    let j = user_addr.p3_index();
+    let entries = l3_table.iter_mut().zip(kernel_l3_table.iter()).enumerate();
+    for (k, (entry, kernel_entry)) in entries {
+        if !kernel_entry.is_unused() && k != usize::from(j) {
+            let flags = kernel_entry.flags() & kernel_flags;
+            entry.set_addr(kernel_entry.addr(), flags);
+        }
+    }
+    table[i].set_addr(l3_frame.start_address(), user_flags);
+
+    Ok(frame)
+}
+
+// Give back the frames of a page table created by `create_page_table` and of
+// the tables of its user space, once the pages of the process have been freed
+fn free_page_table(frame: PhysFrame) {
+    let user_addr = VirtAddr::new(USER_ADDR);
+    let mut allocator = sys::mem::frame_allocator();
+    let table = unsafe { sys::mem::create_page_table(frame) };
+    if let Ok(l3_frame) = table[user_addr.p4_index()].frame() {
+        let l3_table = unsafe { sys::mem::create_page_table(l3_frame) };
+        if let Ok(l2_frame) = l3_table[user_addr.p3_index()].frame() {
+            let l2_table = unsafe { sys::mem::create_page_table(l2_frame) };
+            for entry in l2_table.iter() {
+                if let Ok(l1_frame) = entry.frame() {
+                    unsafe { allocator.deallocate_frame(l1_frame) };
                 }
             }
-            _ => {}
+            unsafe { allocator.deallocate_frame(l2_frame) };
         }
+        unsafe { allocator.deallocate_frame(l3_frame) };
     }
+    unsafe { allocator.deallocate_frame(frame) };
 }
 
-fn load_binary(
-    mapper: &mut OffsetPageTable, addr: u64, size: usize, buf: &[u8]
-) -> Result<(), ()> {
-    debug_assert!(size >= buf.len());
-    sys::mem::alloc_pages(mapper, addr, size)?;
-    let src = buf.as_ptr();
-    let dst = addr as *mut u8;
+fn alloc_page_table() -> Result<PhysFrame, ()> {
+    let frame = sys::mem::frame_allocator().allocate_frame().ok_or(())?;
     unsafe {
-        core::ptr::copy_nonoverlapping(src, dst, buf.len());
-        if size > buf.len() {
-            core::ptr::write_bytes(dst.add(buf.len()), 0, size - buf.len());
-        }
+        sys::mem::create_page_table(frame).zero();
     }
-    Ok(())
+    Ok(frame)
+}
+
+// Run a flat binary made of the given instructions in a process and return
+// its exit code
+#[cfg(test)]
+fn run_flat_binary(code: &[&[u8]]) -> Result<ExitCode, ()> {
+    let mut bin = BIN_MAGIC.to_vec();
+    for instruction in code {
+        bin.extend_from_slice(instruction);
+    }
+    let args: [&str; 0] = [];
+    let args_ptr = args.as_ptr() as usize;
+    let pid = Process::spawn("test", &bin, args_ptr, 0).map_err(|_| ())?;
+    wait(pid)
+}
+
+#[test_case]
+fn test_user_write_to_kernel() {
+    let data = Box::new(0u8);
+    let addrs = [
+        0x200000, // Kernel code
+        &*data as *const u8 as u64, // Kernel heap
+    ];
+    for addr in addrs {
+        // Flat binary writing a byte at the address before exiting
+        let res = run_flat_binary(&[
+            &[0x48, 0xB8], // mov rax, addr
+            &addr.to_le_bytes(),
+            &[0xC6, 0x00, 0x2A], // mov byte [rax], 42
+            &[0xB8, 0x01, 0x00, 0x00, 0x00], // mov eax, 1
+            &[0x31, 0xFF], // xor edi, edi
+            &[0xCD, 0x80], // int 0x80
+        ]);
+        assert_eq!(res, Ok(ExitCode::PageFaultError));
+    }
+    assert_eq!(*data, 0);
+}
+
+#[test_case]
+fn test_fork() {
+    // Flat binary where the child writes a byte and exits with it, and the
+    // parent checks the exit code of the child before exiting with its own
+    // copy of the byte
+    let res = run_flat_binary(&[
+        &[0xB8, 0x19, 0x00, 0x00, 0x00], // mov eax, FORK
+        &[0xCD, 0x80], // int 0x80
+        &[0x48, 0x85, 0xC0], // test rax, rax
+        &[0x75, 0x15], // jnz parent
+
+        // Child
+        &[0xC6, 0x05, 0x3B, 0, 0, 0, 0x40], // mov [d], 64
+        &[0xB8, 0x01, 0x00, 0x00, 0x00], // mov eax, EXIT
+        &[0x0F, 0xB6, 0x3D, 0x2F, 0, 0, 0], // movzx edi, [d]
+        &[0xCD, 0x80], // int 0x80
+
+        // Parent
+        &[0x48, 0x89, 0xC7], // mov rdi, rax
+        &[0xBE, 0x01, 0x00, 0x00, 0x00], // mov esi, 1
+        &[0xB8, 0x14, 0x00, 0x00, 0x00], // mov eax, WAIT
+        &[0xCD, 0x80], // int 0x80
+        &[0x3C, 0x40], // cmp al, 64
+        &[0x75, 0x0E], // jne fail
+        &[0x0F, 0xB6, 0x3D, 0x13, 0, 0, 0], // movzx edi, [d]
+        &[0xB8, 0x01, 0x00, 0x00, 0x00], // mov eax, EXIT
+        &[0xCD, 0x80], // int 0x80
+
+        // Fail
+        &[0xBF, 0x01, 0x00, 0x00, 0x00], // mov edi, 1
+        &[0xB8, 0x01, 0x00, 0x00, 0x00], // mov eax, EXIT
+        &[0xCD, 0x80], // int 0x80
+
+        // Data (d)
+        &[0],
+    ]);
+    assert_eq!(res, Ok(ExitCode::Success));
+}
+
+#[test_case]
+fn test_thread() {
+    // Flat binary mapping a stack for a thread that exits with a code, which
+    // is collected by the main thread to exit with it
+    let res = run_flat_binary(&[
+        &[0xB8, 0x17, 0x00, 0x00, 0x00], // mov eax, MMAP
+        &[0x31, 0xFF], // xor edi, edi
+        &[0xBE, 0x00, 0x10, 0x00, 0x00], // mov esi, 4096
+        &[0xBA, 0x03, 0x00, 0x00, 0x00], // mov edx, RW
+        &[0x45, 0x31, 0xC0], // xor r8d, r8d
+        &[0xCD, 0x80], // int 0x80
+        &[0x48, 0x8D, 0xB0], // lea rsi, [rax + 4088]
+        &4088u32.to_le_bytes(),
+        &[0x48, 0x8D, 0x3D, 0x1D, 0, 0, 0], // lea rdi, [t]
+        &[0x31, 0xD2], // xor edx, edx
+        &[0xB8, 0x1D, 0x00, 0x00, 0x00], // mov eax, THREAD
+        &[0xCD, 0x80], // int 0x80
+        &[0x48, 0x89, 0xC7], // mov rdi, rax
+        &[0xB8, 0x1E, 0x00, 0x00, 0x00], // mov eax, JOIN
+        &[0xCD, 0x80], // int 0x80
+        &[0x48, 0x89, 0xC7], // mov rdi, rax
+        &[0xB8, 0x01, 0x00, 0x00, 0x00], // mov eax, EXIT
+        &[0xCD, 0x80], // int 0x80
+
+        // Thread (t)
+        &[0xBF, 0x40, 0x00, 0x00, 0x00], // mov edi, 64
+        &[0xB8, 0x1F, 0x00, 0x00, 0x00], // mov eax, EXIT_THREAD
+        &[0xCD, 0x80], // int 0x80
+    ]);
+    assert_eq!(res, Ok(ExitCode::UsageError));
+}
+
+#[test_case]
+fn test_signals() {
+    let mut signals = Signals::new();
+    signals.pending = (1 << SIGCHLD) | (1 << SIGINT);
+    signals.blocked = 1 << SIGINT;
+    assert!(!signals.is_pending());
+    assert_eq!(signals.take(), Some(SIGCHLD));
+    assert_eq!(signals.take(), None);
+
+    signals.blocked = 0;
+    assert!(signals.is_pending());
+    signals.handlers[SIGINT] = SIG_IGN;
+    assert!(!signals.is_pending());
+    assert_eq!(signals.take(), Some(SIGINT));
+    assert_eq!(signals.pending, 0);
+}
+
+#[test_case]
+fn test_is_descendant() {
+    let mut table = ProcessTable::new();
+    let proc = |id, parent_id, leader_id| {
+        Process { id, parent_id, leader_id, ..Process::new() }
+    };
+    table.insert(proc(1, 0, 1));
+    table.insert(proc(2, 1, 2));
+    table.insert(proc(3, 1, 1)); // Thread of the first process
+    table.insert(proc(4, 0, 4));
+    assert!(table.is_descendant(2, 1));
+    assert!(table.is_descendant(2, 3));
+    assert!(table.is_descendant(1, 1));
+    assert!(table.is_descendant(4, 0));
+    assert!(!table.is_descendant(1, 2));
+    assert!(!table.is_descendant(4, 1));
+}
+
+#[test_case]
+fn test_is_runnable() {
+    let mut table = ProcessTable::new();
+    let stack_frame = InterruptStackFrameValue::new(
+        VirtAddr::new(USER_ADDR),
+        GDT.1.user_code,
+        RFlags::INTERRUPT_FLAG,
+        VirtAddr::new(USER_ADDR),
+        GDT.1.user_data,
+    );
+    let proc = |id, leader_id, cpu| Process {
+        id,
+        leader_id,
+        size: 4096,
+        cpu,
+        stack_frame: Some(stack_frame),
+        state: ProcessState::Ready,
+        ..Process::new()
+    };
+    table.insert(proc(1, 1, Some(1)));
+    table.insert(proc(2, 1, None)); // Thread of the first process
+    table.insert(proc(3, 3, None));
+    assert!(!table.is_runnable(&table[0], 0)); // Running kernel
+    assert!(!table.is_runnable(&table[1], 0));
+    assert!(!table.is_runnable(&table[2], 0));
+    assert!(table.is_runnable(&table[2], 1));
+    assert!(table.is_runnable(&table[3], 0));
+    table[1].cpu = None;
+    assert!(table.is_runnable(&table[2], 0));
+    table[3].affinity = Some(1);
+    assert!(!table.is_runnable(&table[3], 0));
+    assert!(table.is_runnable(&table[3], 1));
+}
+
+#[test_case]
+fn test_find_free_range() {
+    let mut mappings = BTreeMap::new();
+    mappings.insert(0x10000, 0x2000);
+    mappings.insert(0x13000, 0x1000);
+    let find = |addr, size| {
+        find_free_range(&mappings, 0x10000, 0x20000, addr, size)
+    };
+    assert_eq!(find(0, 0x1000), Some(0x12000));
+    assert_eq!(find(0, 0x2000), Some(0x14000));
+    assert_eq!(find(0, 0x10000), None);
+    assert_eq!(find(0x11000, 0x1000), None);
+    assert_eq!(find(0x1F000, 0x1000), Some(0x1F000));
+    assert_eq!(find(0x1F000, 0x2000), None);
+}
+
+#[test_case]
+fn test_random_offset() {
+    let limit = USER_SIZE - DEFAULT_PROC_SIZE as u64 + 4096;
+    for _ in 0..10 {
+        let offset = random_offset(limit, 4096);
+        assert_eq!(offset % 4096, 0);
+        assert!(offset < limit);
+    }
+    assert_eq!(random_offset(8, 16), 0);
 }
@@ -1,4 +1,5 @@
 use crate::sys;
+use crate::sys::smp::IrqMutex;
 
 use core::fmt;
 use core::fmt::Write;
@@ -9,7 +10,7 @@ use vte::{Params, Parser, Perform};
 use x86_64::instructions::interrupts;
 
 lazy_static! {
-    pub static ref SERIAL: Mutex<Serial> = Mutex::new(Serial::new(0x3F8));
+    pub static ref SERIAL: IrqMutex<Serial> = IrqMutex::new(Serial::new(0x3F8));
     pub static ref PARSER: Mutex<Parser> = Mutex::new(Parser::new());
 }
 
@@ -0,0 +1,322 @@
+use crate::{hlt_loop, sys};
+
+use acpi::platform::{Processor, ProcessorState};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::arch::global_asm;
+use core::mem::ManuallyDrop;
+use core::ops::{Deref, DerefMut};
+use core::ptr::addr_of;
+use core::sync::atomic::{AtomicBool, Ordering};
+use spin::{Mutex, MutexGuard, Once};
+use x86_64::instructions::interrupts;
+use x86_64::registers::control::Cr3;
+use x86_64::{PhysAddr, VirtAddr};
+
+// The APs start in real mode at the beginning of a page of the low memory
+// where the trampoline is copied to switch to long mode
+const TRAMPOLINE_ADDR: u64 = 0x8000;
+const AP_STACK_SIZE: usize = 64 << 10; // 64 KB
+
+// The state of the kernel that depends on the CPU, like its current process,
+// is kept in arrays indexed by the ID of the CPU
+pub const MAX_CPUS: usize = 16;
+
+// The APs run the processes like the BSP with their own timer, GDT, TSS and
+// stacks, but they share its IDT and the process table, where the scheduler
+// of each CPU picks the next ready process that is not running on another
+// CPU. The interrupts of the devices are only sent to the BSP.
+pub struct Cpu {
+    pub id: usize,
+    pub apic_id: u32,
+    is_online: AtomicBool,
+}
+
+impl Cpu {
+    fn new(id: usize, apic_id: u32) -> Self {
+        Self {
+            id,
+            apic_id,
+            is_online: AtomicBool::new(false),
+        }
+    }
+
+    pub fn is_bsp(&self) -> bool {
+        self.id == 0
+    }
+
+    pub fn is_online(&self) -> bool {
+        self.is_online.load(Ordering::SeqCst)
+    }
+}
+
+static CPUS: Once<Vec<Cpu>> = Once::new();
+
+pub fn cpus() -> &'static [Cpu] {
+    CPUS.get().map_or(&[], |cpus| cpus.as_slice())
+}
+
+// Return the index of the current CPU, the BSP being the first one
+pub fn cpu_id() -> usize {
+    let apic_id = sys::lapic::id();
+    cpus().iter().position(|cpu| cpu.apic_id == apic_id).unwrap_or(0)
+}
+
+pub fn is_bsp() -> bool {
+    cpu_id() == 0
+}
+
+// Start the APs that are waiting for a startup IPI, one after the other
+pub fn init(bsp: &Processor, aps: &[Processor]) {
+    if !sys::lapic::is_enabled() {
+        return;
+    }
+
+    let mut cpus = Vec::new();
+    cpus.push(Cpu::new(0, sys::lapic::id()));
+    cpus[0].is_online.store(true, Ordering::SeqCst);
+    debug_assert_eq!(bsp.local_apic_id, cpus[0].apic_id);
+    for ap in aps {
+        if cpus.len() == MAX_CPUS {
+            break;
+        }
+        if ap.state == ProcessorState::WaitingForSipi {
+            cpus.push(Cpu::new(cpus.len(), ap.local_apic_id));
+        }
+    }
+    let cpus = CPUS.call_once(|| cpus);
+    if cpus.len() == 1 {
+        return;
+    }
+
+    let addr = PhysAddr::new(TRAMPOLINE_ADDR);
+    if !sys::mem::is_low_frame_free(addr) {
+        debug!("SMP: Could not use {:#X} for the trampoline", TRAMPOLINE_ADDR);
+        return;
+    }
+
+    // The trampoline is identity mapped because it is still running from
+    // the same address once paging is enabled
+    let virt_addr = VirtAddr::new(TRAMPOLINE_ADDR);
+    let mapper = sys::mem::mapper();
+    if sys::mem::virt_to_phys(virt_addr).is_some() {
+        sys::mem::free_pages(mapper, TRAMPOLINE_ADDR, 4096);
+    }
+    let res = unsafe {
+        sys::mem::map_contiguous_physical_region(mapper, addr, virt_addr, 4096)
+    };
+    if res.is_err() {
+        debug!("SMP: Could not map the trampoline");
+        return;
+    }
+    let start = addr_of!(ap_trampoline_start) as usize;
+    let end = addr_of!(ap_trampoline_end) as usize;
+    let data = addr_of!(ap_trampoline_data) as usize;
+    let code = unsafe {
+        core::slice::from_raw_parts(start as *const u8, end - start)
+    };
+    let trampoline = unsafe {
+        core::slice::from_raw_parts_mut(TRAMPOLINE_ADDR as *mut u8, 4096)
+    };
+    trampoline[0..code.len()].copy_from_slice(code);
+    let data_ptr = (TRAMPOLINE_ADDR as usize + data - start) as *mut ApData;
+
+    for cpu in cpus.iter().skip(1) {
+        let stack = Box::leak(alloc::vec![0u8; AP_STACK_SIZE].into_boxed_slice());
+        let stack_top = (stack.as_ptr() as u64 + AP_STACK_SIZE as u64) & !0xF;
+        let ap_data = ApData {
+            page_table: Cr3::read().0.start_address().as_u64(),
+            stack: stack_top,
+            entry: ap_main as usize as u64,
+            cpu: cpu.id as u64,
+        };
+        unsafe {
+            core::ptr::write_volatile(data_ptr, ap_data);
+        }
+
+        // INIT-SIPI-SIPI sequence
+        sys::lapic::send_init(cpu.apic_id);
+        sys::clk::sleep(0.01);
+        let page = (TRAMPOLINE_ADDR >> 12) as u8;
+        for _ in 0..2 {
+            sys::lapic::send_startup(cpu.apic_id, page);
+            if wait_online(cpu, 0.1) {
+                break;
+            }
+        }
+        if cpu.is_online() {
+            log!("CPU AP:{} online", cpu.apic_id);
+        } else {
+            debug!("SMP: Could not start CPU {}", cpu.apic_id);
+        }
+    }
+
+    sys::mem::free_pages(mapper, TRAMPOLINE_ADDR, 4096);
+}
+
+fn wait_online(cpu: &Cpu, seconds: f64) -> bool {
+    let start = sys::clk::boot_time();
+    while sys::clk::boot_time() - start < seconds {
+        if cpu.is_online() {
+            return true;
+        }
+        core::hint::spin_loop();
+    }
+    cpu.is_online()
+}
+
+// Entry point of the APs in long mode with their own stack, which becomes
+// the stack of their idle process. The scheduler of an AP switches from it
+// to the ready processes on the ticks of its timer, and back to it when
+// there is nothing else to run.
+extern "sysv64" fn ap_main(id: usize) -> ! {
+    sys::gdt::init_ap(id);
+    sys::idt::init();
+    sys::lapic::enable();
+
+    // The interrupts are still disabled when the timer is started, until
+    // the idle process is the current process of the AP
+    if sys::lapic::start_timer(sys::pic::PIC_1_OFFSET).is_err() {
+        hlt_loop();
+    }
+    sys::process::init_idle(id);
+
+    let cpu = &cpus()[id];
+    cpu.is_online.store(true, Ordering::SeqCst);
+    interrupts::enable();
+    loop {
+        sys::clk::block();
+    }
+}
+
+// Data written at the end of the trampoline for the next AP to start
+#[repr(C)]
+struct ApData {
+    page_table: u64,
+    stack: u64,
+    entry: u64,
+    cpu: u64,
+}
+
+extern "C" {
+    static ap_trampoline_start: u8;
+    static ap_trampoline_data: u8;
+    static ap_trampoline_end: u8;
+}
+
+// The trampoline switches from real mode to long mode with a temporary GDT,
+// the page table of the kernel, and the flags set by the bootloader on the
+// BSP (PAE, long mode, no-execute, and write protection), before calling the
+// entry point of the APs.
+global_asm!(
+    ".global ap_trampoline_start",
+    ".global ap_trampoline_data",
+    ".global ap_trampoline_end",
+    ".code16",
+    "ap_trampoline_start:",
+    "    cli",
+    "    cld",
+    "    xorw %ax, %ax",
+    "    movw %ax, %ds",
+    "    lgdtl 0x8000 + ap_gdt_ptr - ap_trampoline_start",
+    "    movl %cr0, %eax",
+    "    orl $1, %eax",
+    "    movl %eax, %cr0",
+    "    ljmpl $0x08, $(0x8000 + ap_protected_mode - ap_trampoline_start)",
+    ".code32",
+    "ap_protected_mode:",
+    "    movw $0x10, %ax",
+    "    movw %ax, %ds",
+    "    movw %ax, %es",
+    "    movw %ax, %ss",
+    "    movl %cr4, %eax",
+    "    orl $(1 << 5), %eax",
+    "    movl %eax, %cr4",
+    "    movl 0x8000 + ap_trampoline_data - ap_trampoline_start, %eax",
+    "    movl %eax, %cr3",
+    "    movl $0xC0000080, %ecx",
+    "    rdmsr",
+    "    orl $((1 << 8) | (1 << 11)), %eax",
+    "    wrmsr",
+    "    movl %cr0, %eax",
+    "    orl $((1 << 31) | (1 << 16)), %eax",
+    "    movl %eax, %cr0",
+    "    ljmpl $0x18, $(0x8000 + ap_long_mode - ap_trampoline_start)",
+    ".code64",
+    "ap_long_mode:",
+    "    movq 0x8000 + ap_trampoline_data - ap_trampoline_start + 8, %rsp",
+    "    movq 0x8000 + ap_trampoline_data - ap_trampoline_start + 24, %rdi",
+    "    movq 0x8000 + ap_trampoline_data - ap_trampoline_start + 16, %rax",
+    "    callq *%rax",
+    "1:",
+    "    hlt",
+    "    jmp 1b",
+    ".p2align 3",
+    "ap_gdt:",
+    "    .quad 0",
+    "    .quad 0x00CF9A000000FFFF",
+    "    .quad 0x00CF92000000FFFF",
+    "    .quad 0x00AF9A000000FFFF",
+    "ap_gdt_ptr:",
+    "    .word ap_gdt_ptr - ap_gdt - 1",
+    "    .long 0x8000 + ap_gdt - ap_trampoline_start",
+    ".p2align 3",
+    "ap_trampoline_data:",
+    "    .quad 0",
+    "    .quad 0",
+    "    .quad 0",
+    "    .quad 0",
+    "ap_trampoline_end:",
+    options(att_syntax)
+);
+
+// Spin lock disabling the interrupts of the current CPU while it is held, to
+// be used by the code running in interrupt context: the other CPUs can wait
+// for the lock without the risk of a deadlock with an interrupt handler of
+// the CPU holding it.
+pub struct IrqMutex<T> {
+    inner: Mutex<T>,
+}
+
+pub struct IrqMutexGuard<'a, T> {
+    guard: ManuallyDrop<MutexGuard<'a, T>>,
+    were_enabled: bool,
+}
+
+impl<T> IrqMutex<T> {
+    pub const fn new(value: T) -> Self {
+        Self { inner: Mutex::new(value) }
+    }
+
+    pub fn lock(&self) -> IrqMutexGuard<T> {
+        let were_enabled = interrupts::are_enabled();
+        interrupts::disable();
+        let guard = ManuallyDrop::new(self.inner.lock());
+        IrqMutexGuard { guard, were_enabled }
+    }
+}
+
+impl<T> Deref for IrqMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.guard
+    }
+}
+
+impl<T> DerefMut for IrqMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.guard
+    }
+}
+
+impl<T> Drop for IrqMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        unsafe {
+            ManuallyDrop::drop(&mut self.guard);
+        }
+        if self.were_enabled {
+            interrupts::enable();
+        }
+    }
+}
@@ -49,8 +49,7 @@ const SPEAKER_PORT: u16 = 0x61;
 
 fn start_sound(freq: f64) {
     let divider = (clk::pit_frequency() / freq) as u16;
-    let channel = 2; // PC Speaker
-    clk::set_pit_frequency(divider, channel);
+    clk::set_speaker_frequency(divider);
 
     let mut speaker: Port<u8> = Port::new(SPEAKER_PORT);
     let tmp = unsafe { speaker.read() };
@@ -1,19 +1,44 @@
-pub mod number;
 pub mod service;
+mod trace;
+
+pub use crate::api::syscall::number;
 
 use crate::api::process::ExitCode;
 use crate::sys;
 use crate::sys::fs::FileInfo;
 
-use core::arch::asm;
 use core::convert::TryInto;
 use smoltcp::wire::IpAddress;
 use smoltcp::wire::Ipv4Address;
 
-fn utf8_from_raw_parts(ptr: *mut u8, len: usize) -> &'static str {
-    unsafe {
-        let slice = core::slice::from_raw_parts(ptr, len);
-        core::str::from_utf8_unchecked(slice)
+// Return the slice given by the calling process at an address of its memory,
+// unless it is outside of the pages of a user process
+pub(super) fn slice_from_addr<T>(addr: usize, len: usize) -> Option<&'static mut [T]> {
+    let ptr = sys::process::ptr_from_addr(addr as u64);
+    let size = len.checked_mul(core::mem::size_of::<T>())?;
+    let is_aligned = (ptr as usize) % core::mem::align_of::<T>() == 0;
+    if is_aligned && sys::process::is_user_range(ptr as u64, size) {
+        Some(unsafe { core::slice::from_raw_parts_mut(ptr as *mut T, len) })
+    } else {
+        None
+    }
+}
+
+pub(super) fn utf8_from_addr(addr: usize, len: usize) -> Option<&'static str> {
+    core::str::from_utf8(slice_from_addr(addr, len)?).ok()
+}
+
+// The strings of the args given to a new process must also be in the memory
+// of the calling process
+fn args_from_addr(addr: usize, len: usize) -> Option<usize> {
+    let args: &[&str] = slice_from_addr(addr, len)?;
+    let is_user = args.iter().all(|arg| {
+        sys::process::is_user_range(arg.as_ptr() as u64, arg.len())
+    });
+    if is_user {
+        Some(args.as_ptr() as usize)
+    } else {
+        None
     }
 }
 
@@ -23,6 +48,26 @@ pub fn dispatcher(
     arg2: usize,
     arg3: usize,
     arg4: usize
+) -> usize {
+    // The kernel is never traced but its children inherit its trace flag
+    let trace = match sys::process::id() {
+        0 => None,
+        _ => sys::process::trace(),
+    };
+    let call = trace.map(|_| trace::call(n, [arg1, arg2, arg3, arg4]));
+    let res = dispatch(n, arg1, arg2, arg3, arg4);
+    if let (Some(trace), Some(call)) = (trace, call) {
+        trace::write(trace, &call, n, res);
+    }
+    res
+}
+
+fn dispatch(
+    n: usize,
+    arg1: usize,
+    arg2: usize,
+    arg3: usize,
+    arg4: usize
 ) -> usize {
     match n {
         number::EXIT => service::exit(ExitCode::from(arg1)) as usize,
@@ -31,16 +76,21 @@ pub fn dispatcher(
             0
         }
         number::DELETE => {
-            let ptr = sys::process::ptr_from_addr(arg1 as u64);
-            let len = arg2;
-            let path = utf8_from_raw_parts(ptr, len);
+            let path = match utf8_from_addr(arg1, arg2) {
+                Some(path) => path,
+                None => return -1_isize as usize,
+            };
             service::delete(path) as usize
         }
         number::INFO => {
-            let ptr = sys::process::ptr_from_addr(arg1 as u64);
-            let len = arg2;
-            let path = utf8_from_raw_parts(ptr, len);
-            let info = unsafe { &mut *(arg3 as *mut FileInfo) };
+            let path = match utf8_from_addr(arg1, arg2) {
+                Some(path) => path,
+                None => return -1_isize as usize,
+            };
+            let info = match slice_from_addr::<FileInfo>(arg3, 1) {
+                Some(info) => &mut info[0],
+                None => return -1_isize as usize,
+            };
             service::info(path, info) as usize
         }
         number::KIND => {
@@ -48,27 +98,26 @@ pub fn dispatcher(
             service::kind(handle) as usize
         }
         number::OPEN => {
-            let ptr = sys::process::ptr_from_addr(arg1 as u64);
-            let len = arg2;
-            let path = utf8_from_raw_parts(ptr, len);
+            let path = match utf8_from_addr(arg1, arg2) {
+                Some(path) => path,
+                None => return -1_isize as usize,
+            };
             let flags = arg3 as u8;
             service::open(path, flags) as usize
         }
         number::READ => {
             let handle = arg1;
-            let ptr = sys::process::ptr_from_addr(arg2 as u64);
-            let len = arg3;
-            let buf = unsafe {
-                core::slice::from_raw_parts_mut(ptr, len)
+            let buf = match slice_from_addr(arg2, arg3) {
+                Some(buf) => buf,
+                None => return -1_isize as usize,
             };
             service::read(handle, buf) as usize
         }
         number::WRITE => {
             let handle = arg1;
-            let ptr = sys::process::ptr_from_addr(arg2 as u64);
-            let len = arg3;
-            let buf = unsafe {
-                core::slice::from_raw_parts_mut(ptr, len) // TODO: Remove mut
+            let buf = match slice_from_addr(arg2, arg3) {
+                Some(buf) => buf, // TODO: Remove mut
+                None => return -1_isize as usize,
             };
             service::write(handle, buf) as usize
         }
@@ -82,35 +131,89 @@ pub fn dispatcher(
             let new_handle = arg2;
             service::dup(old_handle, new_handle) as usize
         }
+        number::PIPE => {
+            let capacity = arg1;
+            let handles = match slice_from_addr(arg2, 2) {
+                Some(buf) => buf.try_into().unwrap(),
+                None => return -1_isize as usize,
+            };
+            service::pipe(capacity, handles) as usize
+        }
         number::SPAWN => {
-            let path_ptr = sys::process::ptr_from_addr(arg1 as u64);
-            let path_len = arg2;
-            let path = utf8_from_raw_parts(path_ptr, path_len);
-            let args_ptr = arg3;
+            let path = match utf8_from_addr(arg1, arg2) {
+                Some(path) => path,
+                None => return -1_isize as usize,
+            };
+            let args_ptr = match args_from_addr(arg3, arg4) {
+                Some(ptr) => ptr,
+                None => return -1_isize as usize,
+            };
             let args_len = arg4;
             service::spawn(path, args_ptr, args_len) as usize
         }
+        number::START => {
+            let path = match utf8_from_addr(arg1, arg2) {
+                Some(path) => path,
+                None => return -1_isize as usize,
+            };
+            let args_ptr = match args_from_addr(arg3, arg4) {
+                Some(ptr) => ptr,
+                None => return -1_isize as usize,
+            };
+            let args_len = arg4;
+            match service::start(path, args_ptr, args_len) {
+                Ok(pid) => pid,
+                Err(code) => -(code as isize) as usize,
+            }
+        }
+        number::FORK => service::fork() as usize,
+        number::WAIT => {
+            let pid = arg1;
+            let block = arg2 != 0;
+            service::wait(pid, block) as usize
+        }
+        number::KILL => {
+            let pid = arg1;
+            let signal = arg2;
+            service::kill(pid, signal) as usize
+        }
+        number::SIGACTION => {
+            let signal = arg1;
+            let handler = arg2;
+            let trampoline = arg3;
+            service::sigaction(signal, handler, trampoline) as usize
+        }
+        number::SIGPROCMASK => {
+            let how = arg1;
+            let mask = arg2 as u32;
+            service::sigprocmask(how, mask) as usize
+        }
+        number::SIGRETURN => {
+            0 // The context is restored by the syscall handler
+        }
         number::STOP => {
             let code = arg1;
             service::stop(code)
         }
         number::POLL => {
-            let ptr = sys::process::ptr_from_addr(arg1 as u64) as *const _;
-            let len = arg2;
-            let list = unsafe { core::slice::from_raw_parts(ptr, len) };
+            let list = match slice_from_addr(arg1, arg2) {
+                Some(list) => list,
+                None => return -1_isize as usize,
+            };
             service::poll(list) as usize
         }
         number::CONNECT => {
             let handle = arg1;
-            let ptr = sys::process::ptr_from_addr(arg2 as u64);
-            let len = arg3;
-            let buf = unsafe { core::slice::from_raw_parts(ptr, len) };
+            let buf: &[u8] = match slice_from_addr(arg2, arg3) {
+                Some(buf) => buf,
+                None => return -1_isize as usize,
+            };
             if let Ok(buf) = buf.try_into() {
                 let addr = IpAddress::from(Ipv4Address::from_octets(buf));
                 let port = arg4 as u16;
                 service::connect(handle, addr, port) as usize
             } else {
-                -1 as isize as usize
+                -1_isize as usize
             }
         }
         number::LISTEN => {
@@ -120,14 +223,15 @@ pub fn dispatcher(
         }
         number::ACCEPT => {
             let handle = arg1;
-            let ptr = sys::process::ptr_from_addr(arg2 as u64);
-            let len = arg3;
-            let buf = unsafe { core::slice::from_raw_parts_mut(ptr, len) };
+            let buf = match slice_from_addr(arg2, arg3) {
+                Some(buf) if buf.len() == 4 => buf,
+                _ => return -1_isize as usize,
+            };
             if let Ok(IpAddress::Ipv4(addr)) = service::accept(handle) {
-                buf[0..len].clone_from_slice(&addr.octets());
+                buf.clone_from_slice(&addr.octets());
                 0
             } else {
-                -1 as isize as usize
+                -1_isize as usize
             }
         }
         number::ALLOC => {
@@ -142,103 +246,39 @@ pub fn dispatcher(
             service::free(ptr, size, align);
             0
         }
+        number::MMAP => {
+            let addr = arg1;
+            let size = arg2;
+            let flags = arg3 as u8;
+            let handle = arg4;
+            service::mmap(addr, size, flags, handle) as usize
+        }
+        number::MUNMAP => {
+            let addr = arg1;
+            let size = arg2;
+            service::munmap(addr, size) as usize
+        }
+        number::THREAD => {
+            let entry = arg1 as u64;
+            let stack = arg2 as u64;
+            let arg = arg3;
+            service::thread(entry, stack, arg) as usize
+        }
+        number::JOIN => {
+            let tid = arg1;
+            service::join(tid) as usize
+        }
+        number::EXIT_THREAD => {
+            service::exit_thread(ExitCode::from(arg1)) as usize
+        }
+        number::FUTEX => {
+            let addr = sys::process::ptr_from_addr(arg1 as u64) as u64;
+            let op = arg2;
+            let val = arg3;
+            service::futex(addr, op, val) as usize
+        }
         _ => {
             unimplemented!();
         }
     }
 }
-
-#[doc(hidden)]
-pub unsafe fn syscall0(n: usize) -> usize {
-    let res: usize;
-    asm!(
-        "int 0x80", in("rax") n,
-        lateout("rax") res
-    );
-    res
-}
-
-#[doc(hidden)]
-pub unsafe fn syscall1(n: usize, arg1: usize) -> usize {
-    let res: usize;
-    asm!(
-        "int 0x80", in("rax") n,
-        in("rdi") arg1,
-        lateout("rax") res
-    );
-    res
-}
-
-#[doc(hidden)]
-pub unsafe fn syscall2(n: usize, arg1: usize, arg2: usize) -> usize {
-    let res: usize;
-    asm!(
-        "int 0x80", in("rax") n,
-        in("rdi") arg1, in("rsi") arg2,
-        lateout("rax") res
-    );
-    res
-}
-
-#[doc(hidden)]
-pub unsafe fn syscall3(
-    n: usize,
-    arg1: usize,
-    arg2: usize,
-    arg3: usize
-) -> usize {
-    let res: usize;
-    asm!(
-        "int 0x80", in("rax") n,
-        in("rdi") arg1, in("rsi") arg2, in("rdx") arg3,
-        lateout("rax") res
-    );
-    res
-}
-
-#[doc(hidden)]
-pub unsafe fn syscall4(
-    n: usize,
-    arg1: usize,
-    arg2: usize,
-    arg3: usize,
-    arg4: usize
-) -> usize {
-    let res: usize;
-    asm!(
-        "int 0x80", in("rax") n,
-        in("rdi") arg1, in("rsi") arg2, in("rdx") arg3, in("r8") arg4,
-        lateout("rax") res
-    );
-    res
-}
-
-#[macro_export]
-macro_rules! syscall {
-    ($n:expr) => {
-        $crate::sys::syscall::syscall0($n as usize)
-    };
-    ($n:expr, $a1:expr) => {
-        $crate::sys::syscall::syscall1($n as usize, $a1 as usize)
-    };
-    ($n:expr, $a1:expr, $a2:expr) => {
-        $crate::sys::syscall::syscall2($n as usize, $a1 as usize, $a2 as usize)
-    };
-    ($n:expr, $a1:expr, $a2:expr, $a3:expr) => {
-        $crate::sys::syscall::syscall3(
-            $n as usize,
-            $a1 as usize,
-            $a2 as usize,
-            $a3 as usize,
-        )
-    };
-    ($n:expr, $a1:expr, $a2:expr, $a3:expr, $a4:expr) => {
-        $crate::sys::syscall::syscall4(
-            $n as usize,
-            $a1 as usize,
-            $a2 as usize,
-            $a3 as usize,
-            $a4 as usize,
-        )
-    };
-}
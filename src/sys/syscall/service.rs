@@ -1,9 +1,11 @@
 use crate::api::fs::{FileIO, IO};
-use crate::api::process::ExitCode;
+use crate::api::process::{ExitCode, FUTEX_WAIT, FUTEX_WAKE};
 use crate::sys;
 use crate::sys::fs::Device;
 use crate::sys::fs::FileInfo;
+use crate::sys::fs::Pipe;
 use crate::sys::fs::Resource;
+use crate::sys::mem::MapFlag;
 use crate::sys::process::Process;
 
 use alloc::vec;
@@ -12,7 +14,7 @@ use core::arch::asm;
 use smoltcp::wire::IpAddress;
 
 pub fn exit(code: ExitCode) -> ExitCode {
-    sys::process::exit();
+    sys::process::exit(code);
     code
 }
 
@@ -63,6 +65,9 @@ pub fn open(path: &str, flags: u8) -> isize {
 }
 
 pub fn dup(old_handle: usize, new_handle: usize) -> isize {
+    if new_handle >= sys::process::MAX_HANDLES {
+        return -1;
+    }
     if let Some(file) = sys::process::handle(old_handle) {
         sys::process::update_handle(new_handle, *file);
         return 0;
@@ -70,6 +75,18 @@ pub fn dup(old_handle: usize, new_handle: usize) -> isize {
     -1
 }
 
+pub fn pipe(capacity: usize, handles: &mut [usize; 2]) -> isize {
+    let (reader, writer) = Pipe::new(capacity);
+    if let Ok(handle) = sys::process::create_handle(Resource::Pipe(reader)) {
+        if let Ok(other) = sys::process::create_handle(Resource::Pipe(writer)) {
+            *handles = [handle, other];
+            return 0;
+        }
+        sys::process::delete_handle(handle);
+    }
+    -1
+}
+
 pub fn read(handle: usize, buf: &mut [u8]) -> isize {
     if let Some(mut file) = sys::process::handle(handle) {
         if let Ok(bytes) = file.read(buf) {
@@ -98,24 +115,108 @@ pub fn close(handle: usize) {
 }
 
 pub fn spawn(path: &str, args_ptr: usize, args_len: usize) -> ExitCode {
+    match start(path, args_ptr, args_len) {
+        Ok(pid) => sys::process::wait(pid).unwrap_or(ExitCode::ExecError),
+        Err(code) => code,
+    }
+}
+
+pub fn start(
+    path: &str,
+    args_ptr: usize,
+    args_len: usize
+) -> Result<usize, ExitCode> {
     let path = match sys::fs::canonicalize(path) {
         Ok(path) => path,
-        Err(_) => return ExitCode::OpenError,
+        Err(_) => return Err(ExitCode::OpenError),
     };
     if let Some(mut file) = sys::fs::File::open(&path) {
         let mut buf = vec![0; file.size()];
         if let Ok(bytes) = file.read(&mut buf) {
             buf.resize(bytes, 0);
-            if let Err(code) = Process::spawn(&buf, args_ptr, args_len) {
-                code
+            Process::spawn(&path, &buf, args_ptr, args_len)
+        } else {
+            Err(ExitCode::ReadError)
+        }
+    } else {
+        Err(ExitCode::OpenError)
+    }
+}
+
+pub fn fork() -> isize {
+    match Process::fork() {
+        Ok(pid) => pid as isize,
+        Err(()) => -1,
+    }
+}
+
+pub fn wait(pid: usize, block: bool) -> isize {
+    let res = if block {
+        sys::process::wait(pid).map(Some)
+    } else {
+        sys::process::try_wait(pid)
+    };
+    match res {
+        Ok(Some(code)) => code as isize,
+        Ok(None) => -2, // The process is still running
+        Err(()) => -1,
+    }
+}
+
+pub fn thread(entry: u64, stack: u64, arg: usize) -> isize {
+    match Process::thread(entry, stack, arg) {
+        Ok(tid) => tid as isize,
+        Err(()) => -1,
+    }
+}
+
+pub fn join(tid: usize) -> isize {
+    match sys::process::join(tid) {
+        Ok(code) => code as isize,
+        Err(()) => -1,
+    }
+}
+
+pub fn exit_thread(code: ExitCode) -> ExitCode {
+    sys::process::exit_thread(code);
+    code
+}
+
+// Wait on a futex while it has the given value, or wake up to the given
+// number of threads waiting on it
+pub fn futex(addr: u64, op: usize, val: usize) -> isize {
+    match op {
+        FUTEX_WAIT => {
+            if sys::process::futex_wait(addr, val as u32).is_ok() {
+                0
             } else {
-                unreachable!(); // The kernel switched to the child process
+                -1
             }
-        } else {
-            ExitCode::ReadError
         }
+        FUTEX_WAKE => sys::process::futex_wake(addr, val) as isize,
+        _ => -1,
+    }
+}
+
+pub fn kill(pid: usize, signal: usize) -> isize {
+    if sys::process::kill(pid, signal).is_ok() {
+        0
     } else {
-        ExitCode::OpenError
+        -1
+    }
+}
+
+pub fn sigaction(signal: usize, handler: usize, trampoline: usize) -> isize {
+    match sys::process::sigaction(signal, handler, trampoline) {
+        Ok(old) => old as isize,
+        Err(()) => -1,
+    }
+}
+
+pub fn sigprocmask(how: usize, mask: u32) -> isize {
+    match sys::process::sigprocmask(how, mask) {
+        Ok(old) => old as isize,
+        Err(()) => -1,
     }
 }
 
@@ -127,7 +228,7 @@ pub fn stop(code: usize) -> usize {
             }
         }
         0xDEAD => { // Halt
-            sys::process::exit();
+            sys::process::exit(ExitCode::Success);
             sys::acpi::shutdown();
         }
         _ => {
@@ -205,3 +306,54 @@ pub fn free(ptr: *mut u8, size: usize, align: usize) {
         unsafe { sys::process::free(ptr, layout) };
     }
 }
+
+pub fn mmap(addr: usize, size: usize, flags: u8, handle: usize) -> isize {
+    if size > sys::process::MAX_PROC_SIZE {
+        return -1;
+    }
+    let mut file = None;
+    if MapFlag::File.is_set(flags) {
+        // The pages of a file are copies that cannot be written back
+        if MapFlag::Write.is_set(flags) {
+            return -1;
+        }
+        file = sys::process::handle(handle);
+        if file.is_none() {
+            return -1;
+        }
+    }
+    let addr = match sys::process::mmap(addr as u64, size, flags) {
+        Ok(addr) => addr,
+        Err(()) => return -1,
+    };
+    if let Some(mut file) = file {
+        // The file is copied one page at a time into the mapped pages
+        let mut buf = vec![0; 4096];
+        let mut i = 0;
+        while i < size {
+            let n = buf.len().min(size - i);
+            let res = file.read(&mut buf[0..n]).and_then(|bytes| {
+                let addr = addr + i as u64;
+                sys::process::write_pages(addr, &buf[0..bytes]).map(|_| bytes)
+            });
+            match res {
+                Ok(0) => break,
+                Ok(bytes) => i += bytes,
+                Err(()) => {
+                    sys::process::munmap(addr, size).ok();
+                    return -1;
+                }
+            }
+        }
+        sys::process::update_handle(handle, *file);
+    }
+    addr as isize
+}
+
+pub fn munmap(addr: usize, size: usize) -> isize {
+    if sys::process::munmap(addr as u64, size).is_ok() {
+        0
+    } else {
+        -1
+    }
+}
@@ -0,0 +1,129 @@
+use super::{number, slice_from_addr, utf8_from_addr};
+use crate::sys;
+use crate::sys::fs::FileIO;
+use crate::sys::process::Trace;
+
+use alloc::format;
+use alloc::string::String;
+
+// Describe a syscall with its decoded arguments, before it is dispatched
+// because the memory of the process is freed if it exits
+pub fn call(n: usize, args: [usize; 4]) -> String {
+    let [a1, a2, a3, a4] = args;
+    let args = match n {
+        number::EXIT | number::KIND | number::CLOSE | number::ACCEPT |
+        number::JOIN | number::EXIT_THREAD => format!("{}", a1),
+        number::STOP => format!("{:#X}", a1),
+        number::SLEEP => format!("{}", f64::from_bits(a1 as u64)),
+        number::DELETE => path(a1, a2),
+        number::INFO | number::OPEN => {
+            format!("{}, {:#X}", path(a1, a2), a3)
+        }
+        number::READ | number::WRITE => format!("{}, {:#X}, {}", a1, a2, a3),
+        number::DUP | number::WAIT | number::KILL | number::LISTEN => {
+            format!("{}, {}", a1, a2)
+        }
+        number::SPAWN | number::START => {
+            format!("{}, {:#X}, {}", path(a1, a2), a3, a4)
+        }
+        number::FORK | number::SIGRETURN => String::new(),
+        number::SIGACTION => format!("{}, {:#X}, {:#X}", a1, a2, a3),
+        number::SIGPROCMASK | number::PIPE => format!("{}, {:#X}", a1, a2),
+        number::POLL => format!("{:#X}, {}", a1, a2),
+        number::CONNECT => format!("{}, {}, {}", a1, addr(a2, a3), a4),
+        number::ALLOC => format!("{}, {}", a1, a2),
+        number::FREE => format!("{:#X}, {}, {}", a1, a2, a3),
+        number::MMAP => format!("{:#X}, {}, {:#X}, {}", a1, a2, a3, a4),
+        number::MUNMAP => format!("{:#X}, {}", a1, a2),
+        number::THREAD => format!("{:#X}, {:#X}, {}", a1, a2, a3),
+        number::FUTEX => format!("{:#X}, {}, {}", a1, a2, a3),
+        _ => format!("{:#X}, {:#X}, {:#X}, {:#X}", a1, a2, a3, a4),
+    };
+    format!("{}({})", name(n), args)
+}
+
+// Write the call with its result to the kernel log or to a handle of the
+// traced process
+pub fn write(trace: Trace, call: &str, n: usize, res: usize) {
+    let pid = sys::process::id();
+    let res = match n {
+        number::ALLOC | number::MMAP => format!("{:#X}", res),
+        _ => format!("{}", res as isize),
+    };
+    match trace {
+        Trace::Log => {
+            log!("TRACE [{}] {} = {}", pid, call, res);
+        }
+        Trace::Handle(handle) => {
+            let line = format!("[{}] {} = {}\n", pid, call, res);
+            if let Some(mut file) = sys::process::handle(handle) {
+                if file.write(line.as_bytes()).is_ok() {
+                    sys::process::update_handle(handle, *file);
+                }
+            }
+        }
+    }
+}
+
+fn name(n: usize) -> &'static str {
+    match n {
+        number::EXIT        => "exit",
+        number::SPAWN       => "spawn",
+        number::READ        => "read",
+        number::WRITE       => "write",
+        number::OPEN        => "open",
+        number::CLOSE       => "close",
+        number::INFO        => "info",
+        number::DUP         => "dup",
+        number::DELETE      => "delete",
+        number::STOP        => "stop",
+        number::SLEEP       => "sleep",
+        number::POLL        => "poll",
+        number::CONNECT     => "connect",
+        number::LISTEN      => "listen",
+        number::ACCEPT      => "accept",
+        number::ALLOC       => "alloc",
+        number::FREE        => "free",
+        number::KIND        => "kind",
+        number::START       => "start",
+        number::WAIT        => "wait",
+        number::KILL        => "kill",
+        number::PIPE        => "pipe",
+        number::MMAP        => "mmap",
+        number::MUNMAP      => "munmap",
+        number::FORK        => "fork",
+        number::SIGACTION   => "sigaction",
+        number::SIGPROCMASK => "sigprocmask",
+        number::SIGRETURN   => "sigreturn",
+        number::THREAD      => "thread",
+        number::JOIN        => "join",
+        number::EXIT_THREAD => "exit_thread",
+        number::FUTEX       => "futex",
+        _                   => "unknown",
+    }
+}
+
+fn path(ptr: usize, len: usize) -> String {
+    match utf8_from_addr(ptr, len) {
+        Some(path) => format!("{:?}", path),
+        None => format!("{:#X}", ptr),
+    }
+}
+
+fn addr(ptr: usize, len: usize) -> String {
+    let buf: &[u8] = match slice_from_addr(ptr, len) {
+        Some(buf) if len == 4 => buf,
+        _ => return format!("{:#X}", ptr),
+    };
+    format!("{}.{}.{}.{}", buf[0], buf[1], buf[2], buf[3])
+}
+
+#[test_case]
+fn test_call() {
+    assert_eq!(call(number::CLOSE, [4, 0, 0, 0]), "close(4)");
+    assert_eq!(call(number::FORK, [0, 0, 0, 0]), "fork()");
+    assert_eq!(
+        call(number::MMAP, [0, 4096, 3, 0]),
+        "mmap(0x0, 4096, 0x3, 0)"
+    );
+}
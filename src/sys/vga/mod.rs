@@ -9,6 +9,7 @@ pub use font::VgaFont;
 pub use screen::VgaMode;
 pub use palette::Palette as VgaPalette;
 pub use buffer::Buffer as VgaBuffer;
+pub use crate::api::vga::is_printable;
 
 use color::Color;
 use palette::Palette;
@@ -46,15 +47,6 @@ pub fn print_fmt(args: fmt::Arguments) {
     )
 }
 
-// ASCII Printable
-// Backspace
-// New Line
-// Carriage Return
-// Extended ASCII Printable
-pub fn is_printable(c: u8) -> bool {
-    matches!(c, 0x20..=0x7E | 0x08 | 0x0A | 0x0D | 0x80..=0xFF)
-}
-
 // 0x00 -> top
 // 0x0F -> bottom
 // 0x1F -> max (invisible)
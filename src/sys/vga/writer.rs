@@ -4,6 +4,7 @@ use buffer::Buffer;
 
 use crate::api::font::Font;
 use crate::sys;
+use crate::sys::smp::IrqMutex;
 
 //use core::fmt::Write;
 use lazy_static::lazy_static;
@@ -51,7 +52,7 @@ struct ScreenBuffer {
 
 lazy_static! {
     pub static ref PARSER: Mutex<Parser> = Mutex::new(Parser::new());
-    pub static ref WRITER: Mutex<Writer> = Mutex::new(Writer {
+    pub static ref WRITER: IrqMutex<Writer> = IrqMutex::new(Writer {
         cursor: [0; 2],
         writer: [0; 2],
         color_code: ColorCode::new(FG, BG),
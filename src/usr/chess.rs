@@ -3,16 +3,21 @@ use crate::api::fs;
 use crate::api::process::ExitCode;
 use crate::api::prompt::Prompt;
 use crate::api::rng;
-use crate::{api, sys};
+use crate::canvas::{Canvas, FONT_HEIGHT, FONT_WIDTH};
+use crate::sys::console;
+use crate::{api, gpu, mouse, sys};
 
 use alloc::format;
 use alloc::string::String;
 use alloc::sync::Arc;
+use alloc::vec;
 use alloc::vec::Vec;
 
 use lazy_static::lazy_static;
 use littlewing::chess::*;
 use littlewing::color::*;
+use littlewing::piece::{PieceAttr, PieceChar, EMPTY};
+use littlewing::square::{Square, SquareExt};
 use spin::Mutex;
 
 lazy_static! {
@@ -52,6 +57,110 @@ fn update_autocomplete(prompt: &mut Prompt, game: &mut Game) {
     prompt.completion.set(&chess_completer);
 }
 
+const LIGHT_SQUARE_COLOR: u32 = 0xFFF0D9B5;
+const DARK_SQUARE_COLOR: u32 = 0xFFB58863;
+const SELECTED_COLOR: u32 = 0xFF70A040;
+const BACKGROUND_COLOR: u32 = 0xFF202020;
+const WHITE_PIECE_COLOR: u32 = 0xFFFFFFFF;
+const BLACK_PIECE_COLOR: u32 = 0xFF000000;
+const TEXT_COLOR: u32 = 0xFFFFFFFF;
+
+// Graphical board drawn on the screen of the VirtIO GPU
+struct GpuBoard {
+    screen: Canvas,
+    x: i32,
+    y: i32,
+    square_size: u32,
+    selected: Option<Square>,
+    flipped: bool,
+}
+
+impl GpuBoard {
+    fn new(width: u32, height: u32) -> Self {
+        // Keep two lines of text below the board
+        let size = width.min(height.saturating_sub(2 * FONT_HEIGHT));
+        let square_size = size / 8;
+        let x = ((width - 8 * square_size) / 2) as i32;
+        let y = ((height - 2 * FONT_HEIGHT - 8 * square_size) / 2) as i32;
+        Self {
+            screen: Canvas::new(width, height),
+            x,
+            y,
+            square_size,
+            selected: None,
+            flipped: false,
+        }
+    }
+
+    fn square_position(&self, square: Square) -> (i32, i32) {
+        let (file, rank) = (square.file() as i32, square.rank() as i32);
+        let (col, row) = if self.flipped {
+            (7 - file, rank)
+        } else {
+            (file, 7 - rank)
+        };
+        let size = self.square_size as i32;
+        (self.x + col * size, self.y + row * size)
+    }
+
+    fn square_at(&self, x: u32, y: u32) -> Option<Square> {
+        let size = self.square_size as i32;
+        let col = (x as i32 - self.x).div_euclid(size);
+        let row = (y as i32 - self.y).div_euclid(size);
+        if !(0..8).contains(&col) || !(0..8).contains(&row) {
+            return None;
+        }
+        let (file, rank) = if self.flipped {
+            (7 - col, row)
+        } else {
+            (col, 7 - row)
+        };
+        Some((rank * 8 + file) as Square)
+    }
+
+    fn draw(&mut self, game: &Game, status: &str) {
+        self.screen.clear(BACKGROUND_COLOR);
+        let size = self.square_size;
+        for square in 0..64 {
+            let (x, y) = self.square_position(square);
+            let is_light = (square.file() + square.rank()) % 2 == 1;
+            let color = if self.selected == Some(square) {
+                SELECTED_COLOR
+            } else if is_light {
+                LIGHT_SQUARE_COLOR
+            } else {
+                DARK_SQUARE_COLOR
+            };
+            self.screen.fill_rect(x, y, size, size, color);
+
+            let piece = game.board[square as usize];
+            if piece != EMPTY {
+                // Scale the letter of the piece to the size of the square
+                let scale = (size / (2 * FONT_HEIGHT)).max(1);
+                let w = FONT_WIDTH * scale;
+                let h = FONT_HEIGHT * scale;
+                let px = x + ((size - w) / 2) as i32;
+                let py = y + ((size - h) / 2) as i32;
+                let (fg, bg) = if piece.color() == WHITE {
+                    (WHITE_PIECE_COLOR, BLACK_PIECE_COLOR)
+                } else {
+                    (BLACK_PIECE_COLOR, WHITE_PIECE_COLOR)
+                };
+                let c = piece.to_char().to_ascii_uppercase();
+                // Outline the letter to make it visible on every square
+                for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+                    self.screen.scaled_char(px + dx, py + dy, c, bg, scale);
+                }
+                self.screen.scaled_char(px, py, c, fg, scale);
+            }
+        }
+        let x = self.x;
+        let y = self.y + 8 * size as i32 + FONT_HEIGHT as i32 / 2;
+        self.screen.text(x, y, status, TEXT_COLOR);
+        self.screen.present(0, 0);
+    }
+}
+
 fn system_time() -> u128 {
     (api::clock::epoch_time() * 1000.0) as u128
 }
@@ -75,14 +184,7 @@ impl Chess {
         }
     }
 
-    fn run(&mut self) {
-        println!("MOROS Chess v0.2.0\n");
-        let prompt_string = format!("{}>{} ", self.csi_color, self.csi_reset);
-
-        let mut prompt = Prompt::new();
-        let history_file = "~/.chess-history";
-        prompt.history.load(history_file);
-
+    fn setup(&mut self) {
         // 40 moves in 5 minutes
         self.game.clock = Clock::new(40, 5 * 60 * 1000);
         self.game.clock.system_time = Arc::new(system_time);
@@ -91,6 +193,16 @@ impl Chess {
         self.game.tt_resize(size);
         self.game.load_fen(FEN).unwrap();
         println!("{}", self.game);
+    }
+
+    fn run(&mut self) {
+        println!("MOROS Chess v0.2.0\n");
+        let prompt_string = format!("{}>{} ", self.csi_color, self.csi_reset);
+
+        let mut prompt = Prompt::new();
+        let history_file = "~/.chess-history";
+        prompt.history.load(history_file);
+        self.setup();
 
         update_autocomplete(&mut prompt, &mut self.game);
         while let Some(cmd) = prompt.input(&prompt_string) {
@@ -121,6 +233,101 @@ impl Chess {
         }
     }
 
+    // Play with the mouse on a graphical board, the moves are still printed
+    // on the console.
+    fn run_gpu(&mut self, width: u32, height: u32) {
+        println!("MOROS Chess v0.2.0\n");
+        self.setup();
+        let mut board = GpuBoard::new(width, height);
+        board.flipped = self.side == WHITE;
+        let status = self.status();
+        board.draw(&self.game, &status);
+
+        let hot_x = gpu::CURSOR_WIDTH / 2;
+        let hot_y = gpu::CURSOR_HEIGHT / 2;
+        gpu::set_pointer(
+            &gpu::CURSOR_DATA, gpu::CURSOR_WIDTH, gpu::CURSOR_HEIGHT, hot_x, hot_y
+        );
+        let state = mouse::state();
+        gpu::move_pointer(state.x, state.y);
+
+        console::disable_echo();
+        console::enable_raw();
+        let mut was_pressed = state.is_left_pressed();
+        loop {
+            sys::clk::block();
+            let mut dirty = false;
+
+            if mouse::poll() {
+                let state = mouse::state();
+                gpu::move_pointer(state.x, state.y);
+                let is_pressed = state.is_left_pressed();
+                if is_pressed && !was_pressed {
+                    if let Some(square) = board.square_at(state.x, state.y) {
+                        self.click(&mut board, square);
+                        dirty = true;
+                    }
+                }
+                was_pressed = is_pressed;
+            }
+
+            while let Some(c) = console::try_read_char() {
+                match c {
+                    'q' | console::ETX_KEY | '\x11' => { // Ctrl C or Ctrl Q
+                        console::enable_echo();
+                        console::disable_raw();
+                        return;
+                    }
+                    'i' => self.cmd_init(vec!["init"]),
+                    'u' => self.cmd_undo(vec!["undo"]),
+                    'p' => self.cmd_play(vec!["play"]),
+                    _ => continue,
+                }
+                board.selected = None;
+                dirty = true;
+            }
+
+            if dirty {
+                board.flipped = self.side == WHITE;
+                let status = self.status();
+        board.draw(&self.game, &status);
+            }
+        }
+    }
+
+    // Selects a piece of the side to move with a first click and moves it
+    // with a second click on its destination.
+    fn click(&mut self, board: &mut GpuBoard, square: Square) {
+        let piece = self.game.board[square as usize];
+        if piece != EMPTY && piece.color() == self.game.side() {
+            board.selected = Some(square);
+            return;
+        }
+        if let Some(from) = board.selected.take() {
+            let mut m = format!("{}{}", from.to_coord(), square.to_coord());
+            let rank = square.rank();
+            if self.game.board[from as usize].is_pawn() && (rank == 0 || rank == 7) {
+                m.push('q'); // Promote to a queen
+            }
+            self.cmd_move(vec!["move", &m]);
+        }
+    }
+
+    fn status(&mut self) -> String {
+        if self.game.is_mate() {
+            if self.game.is_check(color::WHITE) {
+                "Black mates".into()
+            } else if self.game.is_check(color::BLACK) {
+                "White mates".into()
+            } else {
+                "Draw".into()
+            }
+        } else {
+            let side = if self.game.side() == WHITE { "White" } else { "Black" };
+            format!("{} to move (i: init, u: undo, p: play, q: quit)", side)
+        }
+    }
+
     fn cmd_help(&mut self, _args: Vec<&str>) {
         println!("{}Commands:{}", self.csi_notif, self.csi_reset);
         let cmds = [
@@ -393,14 +600,19 @@ fn is_move(m: &str) -> bool {
 }
 
 pub fn main(args: &[&str]) -> Result<(), ExitCode> {
+    let mut text = false;
     for &arg in args {
         match arg {
             "-h" | "--help" => return help(),
+            "-t" | "--text" => text = true,
             _ => {}
         }
     }
     let mut chess = Chess::new();
-    chess.run();
+    match gpu::get_resolution() {
+        Some((width, height)) if !text => chess.run_gpu(width, height),
+        _ => chess.run(),
+    }
     Ok(())
 }
 
@@ -409,8 +621,14 @@ pub fn help() -> Result<(), ExitCode> {
     let csi_title = Style::color("yellow");
     let csi_reset = Style::reset();
     println!(
-        "{}Usage:{} chess {}{}",
+        "{}Usage:{} chess {}<options>{}",
         csi_title, csi_reset, csi_option, csi_reset
     );
+    println!();
+    println!("{}Options:{}", csi_title, csi_reset);
+    println!(
+        "  {0}-t{1}, {0}--text{1}    Play in the console instead of the screen",
+        csi_option, csi_reset
+    );
     Ok(())
 }
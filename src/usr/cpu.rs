@@ -0,0 +1,73 @@
+use crate::api::console::Style;
+use crate::api::process::ExitCode;
+use crate::sys;
+
+const TIMEOUT: f64 = 0.1;
+
+pub fn main(args: &[&str]) -> Result<(), ExitCode> {
+    match *args.get(1).unwrap_or(&"list") {
+        "list" => list(),
+        "-h" | "--help" => {
+            help();
+            Ok(())
+        }
+        _ => {
+            help();
+            Err(ExitCode::UsageError)
+        }
+    }
+}
+
+fn list() -> Result<(), ExitCode> {
+    let cpus = sys::smp::cpus();
+    if cpus.is_empty() {
+        error!("Could not find the Local APIC");
+        return Err(ExitCode::Failure);
+    }
+    let color = Style::color("aqua");
+    let reset = Style::reset();
+    let current = sys::smp::cpu_id();
+    for cpu in cpus {
+        let kind = if cpu.is_bsp() { "BSP" } else { "AP" };
+        let state = if is_active(cpu.id) { "active" } else { "offline" };
+        let mark = if cpu.id == current { "*" } else { " " };
+        let pid = sys::process::id_on(cpu.id);
+        println!(
+            "{}CPU {}{}{} APIC:{:<3} {:<3} {:<7} pid:{}",
+            color, cpu.id, mark, reset, cpu.apic_id, kind, state, pid
+        );
+    }
+    Ok(())
+}
+
+// A CPU is active if its timer is ticking, to run its scheduler
+fn is_active(id: usize) -> bool {
+    if id == sys::smp::cpu_id() {
+        return true;
+    }
+    let ticks = sys::clk::cpu_ticks(id);
+    let start = sys::clk::boot_time();
+    while sys::clk::boot_time() - start < TIMEOUT {
+        if sys::clk::cpu_ticks(id) != ticks {
+            return true;
+        }
+        sys::clk::block();
+    }
+    false
+}
+
+fn help() {
+    let csi_option = Style::color("aqua");
+    let csi_title = Style::color("yellow");
+    let csi_reset = Style::reset();
+    println!(
+        "{}Usage:{} cpu {}<command>{}",
+        csi_title, csi_reset, csi_option, csi_reset
+    );
+    println!();
+    println!("{}Commands:{}", csi_title, csi_reset);
+    println!(
+        "  {}list{}   List CPUs and check that they are active",
+        csi_option, csi_reset
+    );
+}
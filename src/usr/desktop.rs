@@ -0,0 +1,471 @@
+use crate::api::console::Style;
+use crate::api::process::{self, ExitCode, SIGKILL};
+use crate::canvas::{Canvas, FONT_HEIGHT, FONT_WIDTH};
+use crate::compositor::{self, COMPOSITOR};
+use crate::sys::console;
+use crate::sys::fs::{FileIO, Pipe, Resource, IO};
+use crate::sys::process::Process;
+use crate::{gpu, mouse, sys, usr};
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use vte::{Params, Parser, Perform};
+
+const COLS: usize = 80;
+const ROWS: usize = 25;
+const FG: u32 = 0xFFC0C0C0;
+const BG: u32 = 0xFF000000;
+
+// The ANSI colors in the order of their codes, with the bright colors last
+const COLORS: [u32; 16] = [
+    0xFF000000, // Black
+    0xFF800000, // Red
+    0xFF008000, // Green
+    0xFF808000, // Yellow
+    0xFF000080, // Blue
+    0xFF800080, // Magenta
+    0xFF008080, // Cyan
+    0xFFC0C0C0, // White
+    0xFF808080, // Bright Black
+    0xFFFF0000, // Bright Red
+    0xFF00FF00, // Bright Green
+    0xFFFFFF00, // Bright Yellow
+    0xFF0000FF, // Bright Blue
+    0xFFFF00FF, // Bright Magenta
+    0xFF00FFFF, // Bright Cyan
+    0xFFFFFFFF, // Bright White
+];
+
+#[derive(Clone, Copy)]
+struct Cell {
+    c: char,
+    fg: u32,
+    bg: u32,
+}
+
+// A grid of characters updated by the text and the escape sequences written
+// by the program running in a terminal window
+struct Screen {
+    cells: Vec<Cell>,
+    x: usize,
+    y: usize,
+    fg: u32,
+    bg: u32,
+}
+
+impl Screen {
+    fn new() -> Self {
+        let blank = Cell { c: ' ', fg: FG, bg: BG };
+        Self { cells: vec![blank; COLS * ROWS], x: 0, y: 0, fg: FG, bg: BG }
+    }
+
+    fn blank(&self) -> Cell {
+        Cell { c: ' ', fg: self.fg, bg: self.bg }
+    }
+
+    // Erase the cells between two positions of the grid
+    fn erase(&mut self, from: usize, to: usize) {
+        let blank = self.blank();
+        for cell in &mut self.cells[from..to] {
+            *cell = blank;
+        }
+    }
+
+    fn new_line(&mut self) {
+        self.x = 0;
+        if self.y < ROWS - 1 {
+            self.y += 1;
+        } else {
+            // Scroll the grid up by one line
+            self.cells.drain(0..COLS);
+            let blank = self.blank();
+            self.cells.resize(COLS * ROWS, blank);
+        }
+    }
+
+    fn draw(&self, canvas: &mut Canvas, is_running: bool) {
+        for (i, cell) in self.cells.iter().enumerate() {
+            let x = ((i % COLS) as u32 * FONT_WIDTH) as i32;
+            let y = ((i / COLS) as u32 * FONT_HEIGHT) as i32;
+            canvas.fill_rect(x, y, FONT_WIDTH, FONT_HEIGHT, cell.bg);
+            canvas.char(x, y, cell.c, cell.fg);
+        }
+        if is_running {
+            let x = (self.x.min(COLS - 1) as u32 * FONT_WIDTH) as i32;
+            let y = (self.y as u32 * FONT_HEIGHT) as i32;
+            canvas.char(x, y, '_', self.fg);
+        }
+    }
+}
+
+// Return the last parameter of an escape sequence
+fn last_param(params: &Params, default: usize) -> usize {
+    let mut n = default;
+    for param in params.iter() {
+        n = param[0] as usize;
+    }
+    n
+}
+
+impl Perform for Screen {
+    fn print(&mut self, c: char) {
+        if self.x >= COLS {
+            self.new_line();
+        }
+        let i = self.y * COLS + self.x;
+        self.cells[i] = Cell { c, fg: self.fg, bg: self.bg };
+        self.x += 1;
+    }
+
+    fn execute(&mut self, byte: u8) {
+        match byte {
+            b'\n' => self.new_line(),
+            b'\r' => self.x = 0,
+            0x08 => { // Backspace
+                if self.x > 0 {
+                    self.x -= 1;
+                    let i = self.y * COLS + self.x;
+                    self.erase(i, i + 1);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn csi_dispatch(&mut self, params: &Params, _: &[u8], _: bool, c: char) {
+        match c {
+            'm' => {
+                for param in params.iter() {
+                    match param[0] as usize {
+                        0 => {
+                            self.fg = FG;
+                            self.bg = BG;
+                        }
+                        n @ 30..=37 => self.fg = COLORS[n - 30],
+                        n @ 90..=97 => self.fg = COLORS[n - 90 + 8],
+                        n @ 40..=47 => self.bg = COLORS[n - 40],
+                        n @ 100..=107 => self.bg = COLORS[n - 100 + 8],
+                        _ => {}
+                    }
+                }
+            }
+            'A' => { // Cursor Up
+                self.y = self.y.saturating_sub(last_param(params, 1));
+            }
+            'B' => { // Cursor Down
+                self.y = (self.y + last_param(params, 1)).min(ROWS - 1);
+            }
+            'C' => { // Cursor Forward
+                self.x = (self.x + last_param(params, 1)).min(COLS - 1);
+            }
+            'D' => { // Cursor Backward
+                self.x = self.x.saturating_sub(last_param(params, 1));
+            }
+            'G' => { // Cursor Horizontal Absolute
+                let x = last_param(params, 1); // 1-indexed value
+                if 0 < x && x <= COLS {
+                    self.x = x - 1;
+                }
+            }
+            'H' => { // Move cursor
+                let mut pos = [1, 1]; // 1-indexed values
+                for (i, param) in params.iter().take(2).enumerate() {
+                    pos[i] = param[0] as usize;
+                }
+                let [y, x] = pos;
+                if 0 < x && x <= COLS && 0 < y && y <= ROWS {
+                    self.x = x - 1;
+                    self.y = y - 1;
+                }
+            }
+            'J' => { // Erase in Display
+                if last_param(params, 0) == 2 {
+                    self.erase(0, COLS * ROWS);
+                    self.x = 0;
+                    self.y = 0;
+                }
+            }
+            'K' => { // Erase in Line
+                let i = self.y * COLS;
+                match last_param(params, 0) {
+                    0 => self.erase(i + self.x.min(COLS), i + COLS),
+                    2 => self.erase(i, i + COLS),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// A window running a command in a kernel task with its standard input and
+// outputs connected to the window by pipes
+struct Terminal {
+    id: usize,
+    pid: usize,
+    title: String,
+    stdin: Option<Pipe>,
+    stdout: Pipe,
+    keys: Vec<u8>,
+    parser: Parser,
+    screen: Screen,
+}
+
+impl Terminal {
+    fn spawn(id: usize, title: &str, cmd: &str) -> Result<Self, ExitCode> {
+        let (stdin_reader, stdin_writer) = Pipe::new(0);
+        let (stdout_reader, stdout_writer) = Pipe::new(0);
+
+        // The pipe ends of the command are given to the task through the
+        // handles of the desktop, and closed once it has been spawned
+        let r = sys::process::create_handle(Resource::Pipe(stdin_reader));
+        let w = sys::process::create_handle(Resource::Pipe(stdout_writer));
+        let (r, w) = match (r, w) {
+            (Ok(r), Ok(w)) => (r, w),
+            (r, w) => {
+                for handle in r.iter().chain(w.iter()) {
+                    sys::process::delete_handle(*handle);
+                }
+                return Err(ExitCode::Failure);
+            }
+        };
+        let handles = [(0, r), (1, w), (2, w)];
+        let cmd = cmd.to_string();
+        let res = Process::spawn_task(title, &handles, move || {
+            let res = if cmd.is_empty() {
+                usr::shell::main(&["shell"])
+            } else {
+                usr::shell::exec(&cmd)
+            };
+            match res {
+                Ok(()) => ExitCode::Success,
+                Err(code) => code,
+            }
+        });
+        sys::process::delete_handle(r);
+        sys::process::delete_handle(w);
+
+        Ok(Self {
+            id,
+            pid: res?,
+            title: title.to_string(),
+            stdin: Some(stdin_writer),
+            stdout: stdout_reader,
+            keys: Vec::new(),
+            parser: Parser::new(),
+            screen: Screen::new(),
+        })
+    }
+
+    fn is_running(&self) -> bool {
+        self.stdin.is_some()
+    }
+
+    // Send the keys of the window to the command and read its output, and
+    // return true if the window must be drawn again
+    fn update(&mut self) -> bool {
+        let mut changed = false;
+        if let Some(window) = COMPOSITOR.lock().window_mut(self.id) {
+            while let Some(c) = window.read_key() {
+                let mut buf = [0; 4];
+                self.keys.extend_from_slice(c.encode_utf8(&mut buf).as_bytes());
+            }
+        }
+
+        // The keys are sent one by one because a read of the standard input
+        // only keeps the first one
+        if let Some(stdin) = self.stdin.as_mut() {
+            if !self.keys.is_empty() && stdin.is_empty() {
+                let n = match self.keys[0] {
+                    b if b < 0x80 => 1,
+                    b if b < 0xE0 => 2,
+                    b if b < 0xF0 => 3,
+                    _ => 4,
+                }.min(self.keys.len());
+                let key: Vec<u8> = self.keys.drain(..n).collect();
+                stdin.write(&key).ok();
+            }
+        }
+
+        let mut buf = [0; 1024];
+        while self.stdout.poll(IO::Read) {
+            match self.stdout.read(&mut buf) {
+                Ok(n) if n > 0 => {
+                    for b in &buf[0..n] {
+                        self.parser.advance(&mut self.screen, *b);
+                    }
+                    changed = true;
+                }
+                _ => break,
+            }
+        }
+
+        if self.is_running() {
+            if let Ok(Some(code)) = process::try_wait(self.pid) {
+                self.stdin = None;
+                let title = format!("{} ({})", self.title, code as u8);
+                if let Some(window) = COMPOSITOR.lock().window_mut(self.id) {
+                    window.set_title(&title);
+                }
+                changed = true;
+            }
+        }
+        changed
+    }
+
+    fn draw(&self) {
+        let mut compositor = COMPOSITOR.lock();
+        if let Some(window) = compositor.window_mut(self.id) {
+            self.screen.draw(window.canvas_mut(), self.is_running());
+        }
+    }
+
+    fn close(&mut self) {
+        if self.is_running() {
+            process::kill(self.pid, SIGKILL).ok();
+            process::wait(self.pid).ok();
+        }
+        COMPOSITOR.lock().close_window(self.id);
+    }
+}
+
+pub fn main(args: &[&str]) -> Result<(), ExitCode> {
+    let mut n: usize = 2;
+    let mut cmd = Vec::new();
+    let mut i = 1;
+    while i < args.len() {
+        match args[i] {
+            "-h" | "--help" => {
+                help();
+                return Ok(());
+            }
+            "-n" | "--windows" if i + 1 < args.len() => {
+                i += 1;
+                n = args[i].parse().unwrap_or(n);
+            }
+            arg if arg.starts_with('-') && cmd.is_empty() => {
+                error!("Invalid option '{}'", arg);
+                return Err(ExitCode::UsageError);
+            }
+            arg => {
+                cmd.push(arg);
+            }
+        }
+        i += 1;
+    }
+    let cmd = cmd.join(" ");
+
+    let (width, height) = match gpu::get_resolution() {
+        Some(resolution) => resolution,
+        None => {
+            error!("Could not find a VirtIO GPU");
+            return Err(ExitCode::Failure);
+        }
+    };
+
+    let w = COLS as u32 * FONT_WIDTH;
+    let h = ROWS as u32 * FONT_HEIGHT;
+    let mut terminals = Vec::new();
+    for i in 0..n {
+        let offset = 40 * i as i32;
+        let x = (width.saturating_sub(w) / 4) as i32 + offset;
+        let y = (height.saturating_sub(h) / 4) as i32 + offset;
+        let title = if cmd.is_empty() {
+            format!("Shell {}", i + 1)
+        } else {
+            cmd.clone()
+        };
+        let id = COMPOSITOR.lock().create_window(&title, x, y, w, h);
+        match Terminal::spawn(id, &title, &cmd) {
+            Ok(terminal) => {
+                terminal.draw();
+                terminals.push(terminal);
+            }
+            Err(code) => {
+                error!("Could not run '{}'", title);
+                COMPOSITOR.lock().close_window(id);
+                for terminal in terminals.iter_mut() {
+                    terminal.close();
+                }
+                return Err(code);
+            }
+        }
+    }
+
+    let hot_x = gpu::CURSOR_WIDTH / 2;
+    let hot_y = gpu::CURSOR_HEIGHT / 2;
+    gpu::set_pointer(
+        &gpu::CURSOR_DATA, gpu::CURSOR_WIDTH, gpu::CURSOR_HEIGHT, hot_x, hot_y
+    );
+    let state = mouse::state();
+    gpu::move_pointer(state.x, state.y);
+
+    console::disable_echo();
+    console::enable_raw();
+    let mut dirty = true;
+    loop {
+        if dirty {
+            compositor::render();
+            dirty = false;
+        }
+        sys::clk::block();
+
+        if mouse::poll() {
+            let state = mouse::state();
+            gpu::move_pointer(state.x, state.y);
+            dirty |= COMPOSITOR.lock().handle_mouse(state);
+        }
+
+        while let Some(c) = console::try_read_char() {
+            match c {
+                '\x11' => { // Ctrl Q
+                    for terminal in terminals.iter_mut() {
+                        terminal.close();
+                    }
+                    console::enable_echo();
+                    console::disable_raw();
+                    return Ok(());
+                }
+                '\x0E' => { // Ctrl N
+                    COMPOSITOR.lock().focus_next();
+                    dirty = true;
+                }
+                c => {
+                    COMPOSITOR.lock().handle_key(c);
+                }
+            }
+        }
+
+        for terminal in terminals.iter_mut() {
+            if terminal.update() {
+                terminal.draw();
+                dirty = true;
+            }
+        }
+    }
+}
+
+fn help() {
+    let csi_option = Style::color("aqua");
+    let csi_title = Style::color("yellow");
+    let csi_reset = Style::reset();
+    println!(
+        "{}Usage:{} desktop {}<options> [<cmd>]{1}",
+        csi_title, csi_reset, csi_option
+    );
+    println!();
+    println!("Run a shell session or a command in each window");
+    println!();
+    println!("{}Options:{}", csi_title, csi_reset);
+    println!(
+        "  {0}-n{1}, {0}--windows <num>{1}   Open {0}<num>{1} windows",
+        csi_option, csi_reset
+    );
+    println!();
+    println!("{}Keys:{}", csi_title, csi_reset);
+    println!("  Drag a title bar with the mouse to move a window");
+    println!("  Click on a window or press Ctrl N to change the focus");
+    println!("  Press Ctrl Q to quit");
+}
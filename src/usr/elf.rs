@@ -1,11 +1,25 @@
 use crate::api::console::Style;
 use crate::api::fs;
+use crate::api::hex::print_hex_at;
 use crate::api::process::ExitCode;
+use crate::sys::coredump::{
+    NT_PRPSINFO, NT_PRSTATUS, NT_SIGINFO, PRPSINFO_PSARGS, PRSTATUS_REG,
+    REGISTERS, SIGINFO_ADDR,
+};
 
-use crate::usr;
-use object::{Object, ObjectSection};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::convert::TryInto;
+use object::elf::ET_CORE;
+use object::read::elf::{ElfFile64, FileHeader, ProgramHeader};
+use object::{Endianness, Object, ObjectSection, ObjectSegment, ObjectSymbol};
+
+const MAX_FRAMES: usize = 32;
 
 pub fn main(args: &[&str]) -> Result<(), ExitCode> {
+    if args.len() == 3 && args[1] == "core" {
+        return print_core(args[2]);
+    }
     if args.len() != 2 {
         help();
         return Err(ExitCode::UsageError);
@@ -36,7 +50,7 @@ pub fn main(args: &[&str]) -> Result<(), ExitCode> {
                         color, name, reset, addr, size, align
                     );
                     if let Ok(data) = section.data() {
-                        usr::hex::print_hex_at(data, addr);
+                        print_hex_at(data, addr);
                     }
                 }
             }
@@ -51,6 +65,161 @@ pub fn main(args: &[&str]) -> Result<(), ExitCode> {
     }
 }
 
+// Summarize the core file of a process killed by an exception
+fn print_core(pathname: &str) -> Result<(), ExitCode> {
+    let buf = match fs::read_to_bytes(pathname) {
+        Ok(buf) => buf,
+        Err(_) => {
+            error!("Could not read file '{}'", pathname);
+            return Err(ExitCode::Failure);
+        }
+    };
+    let core = match Core::parse(&buf) {
+        Some(core) => core,
+        None => {
+            error!("Could not parse core file");
+            return Err(ExitCode::Failure);
+        }
+    };
+
+    let color = Style::color("yellow");
+    let reset = Style::reset();
+    println!("{}Process:{} {} (pid {})", color, reset, core.name, core.pid);
+    println!("{}Fault address:{} {:#X}", color, reset, core.addr);
+    println!();
+    println!("{}Registers:{}", color, reset);
+    for (i, name) in REGISTERS.iter().enumerate().take(21) {
+        if *name != "orig_rax" {
+            println!("  {:6} {:#018X}", name, core.regs[i]);
+        }
+    }
+
+    // Look up the names of the functions if the binary has not been stripped
+    let symbols = fs::read_to_bytes(&core.name).map(|bin| {
+        symbols(&bin)
+    }).unwrap_or_default();
+    println!();
+    println!("{}Backtrace:{}", color, reset);
+    for addr in core.backtrace() {
+        print!("  {:#018X}", addr);
+        let i = symbols.partition_point(|(a, _)| *a <= addr);
+        if let Some((a, name)) = i.checked_sub(1).map(|i| &symbols[i]) {
+            print!(" {}+{:#X}", name, addr - a);
+        }
+        println!();
+    }
+    Ok(())
+}
+
+struct Core<'a> {
+    name: String,
+    pid: u32,
+    addr: u64,
+    regs: [u64; 27],
+    segments: Vec<(u64, &'a [u8])>,
+}
+
+impl<'a> Core<'a> {
+    fn parse(buf: &'a [u8]) -> Option<Self> {
+        let obj = ElfFile64::<Endianness>::parse(buf).ok()?;
+        let endian = obj.endian();
+        if obj.elf_header().e_type(endian) != ET_CORE {
+            return None;
+        }
+        let mut core = Self {
+            name: String::new(),
+            pid: 0,
+            addr: 0,
+            regs: [0; 27],
+            segments: Vec::new(),
+        };
+        for ph in obj.elf_program_headers() {
+            let mut notes = match ph.notes(endian, buf) {
+                Ok(Some(notes)) => notes,
+                _ => continue,
+            };
+            while let Ok(Some(note)) = notes.next() {
+                let desc = note.desc();
+                match note.n_type(endian) {
+                    NT_PRSTATUS => {
+                        core.pid = read_u32(desc, 32)?;
+                        for (i, reg) in core.regs.iter_mut().enumerate() {
+                            *reg = read_u64(desc, PRSTATUS_REG + i * 8)?;
+                        }
+                    }
+                    NT_PRPSINFO => {
+                        let psargs = desc.get(PRPSINFO_PSARGS..)?;
+                        let n = psargs.iter().position(|&b| b == 0)?;
+                        let name = String::from_utf8_lossy(&psargs[..n]);
+                        core.name = name.into();
+                    }
+                    NT_SIGINFO => {
+                        core.addr = read_u64(desc, SIGINFO_ADDR)?;
+                    }
+                    _ => {}
+                }
+            }
+        }
+        for segment in obj.segments() {
+            let data = segment.data().ok()?;
+            if !data.is_empty() {
+                core.segments.push((segment.address(), data));
+            }
+        }
+        Some(core)
+    }
+
+    // Read a word of the memory of the process saved in the core file
+    fn read(&self, addr: u64) -> Option<u64> {
+        self.segments.iter().find_map(|(start, data)| {
+            let offset = addr.checked_sub(*start)? as usize;
+            read_u64(data, offset)
+        })
+    }
+
+    // Follow the frame pointers like the kernel does for its own backtraces
+    fn backtrace(&self) -> Vec<u64> {
+        let mut res = alloc::vec![self.regs[16]]; // RIP
+        let mut rbp = self.regs[4];
+        for _ in 0..MAX_FRAMES {
+            let (next, addr) = match (self.read(rbp), self.read(rbp + 8)) {
+                (Some(next), Some(addr)) if addr != 0 => (next, addr),
+                _ => break,
+            };
+            res.push(addr);
+            if next <= rbp {
+                break;
+            }
+            rbp = next;
+        }
+        res
+    }
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> Option<u32> {
+    let bytes = buf.get(offset..offset + 4)?;
+    Some(u32::from_le_bytes(bytes.try_into().ok()?))
+}
+
+fn read_u64(buf: &[u8], offset: usize) -> Option<u64> {
+    let bytes = buf.get(offset..offset + 8)?;
+    Some(u64::from_le_bytes(bytes.try_into().ok()?))
+}
+
+// Return the text symbols of a binary sorted by address
+fn symbols(bin: &[u8]) -> Vec<(u64, String)> {
+    let mut res = Vec::new();
+    if let Ok(obj) = object::File::parse(bin) {
+        for symbol in obj.symbols().filter(|s| s.is_definition()) {
+            if let Ok(name) = symbol.name() {
+                res.push((symbol.address(), name.into()));
+            }
+        }
+    }
+    res.sort_by_key(|(addr, _)| *addr);
+    res
+}
+
 fn help() {
     let csi_option = Style::color("aqua");
     let csi_title = Style::color("yellow");
@@ -59,4 +228,16 @@ fn help() {
         "{}Usage:{} elf {}<binary>{}",
         csi_title, csi_reset, csi_option, csi_reset
     );
+    println!(
+        "{}Usage:{} elf core {}<file>{}",
+        csi_title, csi_reset, csi_option, csi_reset
+    );
+}
+
+#[test_case]
+fn test_read() {
+    let buf = [1, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0];
+    assert_eq!(read_u32(&buf, 0), Some(1));
+    assert_eq!(read_u64(&buf, 4), Some(2));
+    assert_eq!(read_u64(&buf, 8), None);
 }
@@ -1,5 +1,6 @@
 use crate::api::console::Style;
 use crate::api::fs;
+use crate::api::io;
 use crate::api::process::ExitCode;
 use crate::api::regex::Regex;
 use crate::sys;
@@ -73,6 +74,13 @@ pub fn main(args: &[&str]) -> Result<(), ExitCode> {
         i += 1;
     }
 
+    // Search lines piped or redirected to stdin
+    // read log.txt -> find -l error
+    if path.is_empty() && io::is_redirected(0) && !options.line.is_empty() {
+        print_matching_lines("", &mut options);
+        return Ok(());
+    }
+
     if path.is_empty() {
         path = sys::process::dir();
         options.trim = format!("{}/", path);
@@ -125,7 +133,7 @@ fn is_matching_file(path: &str, pattern: &str) -> bool {
 }
 
 fn print_matching_lines(path: &str, options: &mut Options) {
-    if !fs::is_file(path) {
+    if !path.is_empty() && !fs::is_file(path) {
         return;
     }
 
@@ -135,7 +143,13 @@ fn print_matching_lines(path: &str, options: &mut Options) {
     let reset = Style::reset();
 
     let re = Regex::new(&options.line);
-    if let Ok(contents) = fs::read_to_string(path) {
+    // The lines piped or redirected to stdin are given with an empty path
+    let contents = if path.is_empty() {
+        Ok(io::stdin().read_to_string())
+    } else {
+        fs::read_to_string(path)
+    };
+    if let Ok(contents) = contents {
         let mut matches = Vec::new();
         for (i, line) in contents.lines().enumerate() {
             let line: Vec<char> = line.chars().collect();
@@ -0,0 +1,58 @@
+use crate::api::console::Style;
+use crate::api::process::ExitCode;
+use crate::sys;
+
+use x86_64::instructions::interrupts;
+
+pub fn main(args: &[&str]) -> Result<(), ExitCode> {
+    let mut addr = sys::gdb::COM2;
+    let mut i = 1;
+    let n = args.len();
+    while i < n {
+        match args[i] {
+            "--com1" => addr = sys::gdb::COM1,
+            "--com2" => addr = sys::gdb::COM2,
+            "-h" | "--help" => {
+                help();
+                return Ok(());
+            }
+            _ => {
+                help();
+                return Err(ExitCode::UsageError);
+            }
+        }
+        i += 1;
+    }
+    if sys::gdb::is_enabled() {
+        error!("Debugger already enabled");
+        return Err(ExitCode::Failure);
+    }
+
+    let port = if addr == sys::gdb::COM1 { "COM1" } else { "COM2" };
+    println!("Waiting for the debugger on {}", port);
+    sys::gdb::init(addr);
+
+    // Stop here until the debugger is connected and continues the execution
+    interrupts::int3();
+    Ok(())
+}
+
+fn help() {
+    let csi_option = Style::color("aqua");
+    let csi_title = Style::color("yellow");
+    let csi_reset = Style::reset();
+    println!(
+        "{}Usage:{} gdb {}<options>{}",
+        csi_title, csi_reset, csi_option, csi_reset
+    );
+    println!();
+    println!("{}Options:{}", csi_title, csi_reset);
+    println!(
+        "  {0}--com1{1}  Take over the serial console",
+        csi_option, csi_reset
+    );
+    println!(
+        "  {0}--com2{1}  Use the second serial port (default)",
+        csi_option, csi_reset
+    );
+}
@@ -16,6 +16,7 @@ macro_rules! copy_file {
 
 pub fn copy_files(verbose: bool) {
     create_dir("/bin", verbose); // Binaries
+    create_dir("/boot", verbose); // Kernel symbols
     create_dir("/dev", verbose); // Devices
     create_dir("/ini", verbose); // Initializers
     create_dir("/lib", verbose); // Libraries
@@ -48,6 +49,7 @@ pub fn copy_files(verbose: bool) {
     create_dir("/dev/ata/0", verbose);
     create_dir("/dev/ata/1", verbose);
     create_dir("/dev/clk", verbose); // Clock
+    create_dir("/dev/gpu", verbose); // VirtIO GPU
     create_dir("/dev/net", verbose); // Network
     create_dir("/dev/vga", verbose);
 
@@ -59,6 +61,9 @@ pub fn copy_files(verbose: bool) {
     create_dev("/dev/clk/epoch", "clk-epoch", verbose);
     create_dev("/dev/clk/rtc", "clk-rtc", verbose);
     create_dev("/dev/console", "console", verbose);
+    create_dev("/dev/gpu/buffer", "gpu-buffer", verbose);
+    create_dev("/dev/gpu/mode", "gpu-mode", verbose);
+    create_dev("/dev/mouse", "mouse", verbose);
     create_dev("/dev/net/tcp", "net-tcp", verbose);
     create_dev("/dev/net/udp", "net-udp", verbose);
     create_dev("/dev/net/gw", "net-gw", verbose);
@@ -112,6 +117,7 @@ pub fn copy_files(verbose: bool) {
     //copy_file!("/tmp/lisp/factorial.lsp", verbose);
     //copy_file!("/tmp/lisp/fibonacci.lsp", verbose);
     //copy_file!("/tmp/lisp/geotime.lsp", verbose);
+    //copy_file!("/tmp/lisp/paint.lsp", verbose);
     //copy_file!("/tmp/lisp/pi.lsp", verbose);
     //copy_file!("/tmp/lisp/sum.lsp", verbose);
     //copy_file!("/tmp/lisp/tak.lsp", verbose);
@@ -142,6 +148,14 @@ pub fn copy_files(verbose: bool) {
     create_dir("/var/pkg", verbose);
 }
 
+// The shared library is not embedded in the kernel but installed from the
+// disk image where it has been written by `make image`
+fn install_library(verbose: bool) {
+    if let Some(buf) = sys::fs::read_library() {
+        copy_file("/lib/libmoros.so", &buf, verbose);
+    }
+}
+
 pub fn main(args: &[&str]) -> Result<(), ExitCode> {
     let csi_color = Style::color("yellow");
     let csi_reset = Style::reset();
@@ -187,6 +201,7 @@ pub fn main(args: &[&str]) -> Result<(), ExitCode> {
         println!("{}Populating filesystem...{}", csi_color, csi_reset);
         let verbose = true;
         copy_files(verbose);
+        install_library(verbose);
 
         if sys::process::user().is_none() {
             println!();
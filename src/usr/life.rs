@@ -3,6 +3,8 @@ use crate::api::console::Style;
 use crate::api::fs;
 use crate::api::process::ExitCode;
 use crate::api::rng;
+use crate::canvas::{Canvas, FONT_HEIGHT, FONT_WIDTH};
+use crate::gpu;
 use crate::sys::console;
 
 use alloc::collections::BTreeSet;
@@ -21,8 +23,20 @@ struct Game {
     quiet: bool,
     seed_interval: usize,
     seed_population: usize,
+    screen: Option<Canvas>,
+    cell_size: u32,
 }
 
+// Number of columns of the grid on the screen of the VirtIO GPU, used to
+// scale the size of the cells to the resolution.
+const GPU_COLS: u32 = 160;
+
+const CELL_COLOR: u32 = 0xFFFFFFFF;
+const EMPTY_COLOR: u32 = 0xFF000000;
+const STATUS_COLOR: u32 = 0xFFFFFFFF;
+const GAME_OVER_COLOR: u32 = 0xFFFFFF00;
+const STATUS_TEXT_COLOR: u32 = 0xFF000000;
+
 impl Game {
     pub fn new(cols: usize, rows: usize) -> Self {
         Self {
@@ -34,6 +48,77 @@ impl Game {
             quiet: false,
             seed_interval: 1,
             seed_population: 30,
+            screen: None,
+            cell_size: 1,
+        }
+    }
+
+    // Draws the grid on the screen of the VirtIO GPU with cells scaled to
+    // its resolution instead of the text console.
+    pub fn with_gpu(width: u32, height: u32) -> Self {
+        let cell_size = (width / GPU_COLS).max(1);
+        let cols = (width / cell_size) as usize;
+        let rows = (height.saturating_sub(FONT_HEIGHT) / cell_size) as usize;
+        let mut game = Self::new(cols, rows);
+        game.screen = Some(Canvas::new(width, height));
+        game.cell_size = cell_size;
+        game
+    }
+
+    fn set_quiet(&mut self) {
+        self.quiet = true;
+        if let Some(screen) = &self.screen {
+            self.rows = (screen.height() / self.cell_size) as usize;
+        } else {
+            self.rows += 1;
+        }
+    }
+
+    fn draw(&mut self) {
+        if self.screen.is_none() {
+            print!("{}", self);
+            return;
+        }
+        let cell_size = self.cell_size;
+        let quiet = self.quiet;
+        let status = if self.is_game_over() {
+            ("GAME OVER", GAME_OVER_COLOR)
+        } else {
+            ("GAME OF LIFE", STATUS_COLOR)
+        };
+        let stats = format!(
+            "GEN: {:04} | POP: {:04}", self.generation(), self.population()
+        );
+        let screen = self.screen.as_mut().unwrap();
+        screen.clear(EMPTY_COLOR);
+        for &(x, y) in &self.grid {
+            if x < 0 || y < 0 {
+                continue;
+            }
+            let x = x as u32 * cell_size;
+            let y = y as u32 * cell_size;
+            // Leave a one pixel gap between cells when they are big enough
+            let size = if cell_size > 2 { cell_size - 1 } else { cell_size };
+            screen.fill_rect(x as i32, y as i32, size, size, CELL_COLOR);
+        }
+        if !quiet {
+            let (title, color) = status;
+            let w = screen.width();
+            let y = (screen.height() - FONT_HEIGHT) as i32;
+            screen.fill_rect(0, y, w, FONT_HEIGHT, color);
+            screen.text(0, y, title, STATUS_TEXT_COLOR);
+            let x = w as i32 - (stats.len() as u32 * FONT_WIDTH) as i32;
+            screen.text(x, y, &stats, STATUS_TEXT_COLOR);
+        }
+        screen.present(0, 0);
+    }
+
+    fn clear(&mut self) {
+        if let Some(screen) = self.screen.as_mut() {
+            screen.clear(EMPTY_COLOR);
+            screen.present(0, 0);
+        } else {
+            print!("\x1b[2J\x1b[1;1H"); // Clear screen and move to top
         }
     }
 
@@ -57,10 +142,10 @@ impl Game {
                 self.seed();
             }
             if console::end_of_text() || (self.is_game_over() && self.quiet) {
-                print!("\x1b[2J\x1b[1;1H"); // Clear screen and move to top
+                self.clear();
                 return;
             }
-            print!("{}", self);
+            self.draw();
             sys::clk::sleep(1.0 / self.speed);
             if self.is_game_over() {
                 continue; // Display the screen until ^C is received
@@ -170,7 +255,10 @@ impl fmt::Display for Game {
 }
 
 pub fn main(args: &[&str]) -> Result<(), ExitCode> {
-    let mut game = Game::new(api::console::cols(), api::console::rows() - 1);
+    let mut game = match gpu::get_resolution() {
+        Some((width, height)) => Game::with_gpu(width, height),
+        None => Game::new(api::console::cols(), api::console::rows() - 1),
+    };
     let mut i = 0;
     let n = args.len();
     while i < n {
@@ -210,8 +298,7 @@ pub fn main(args: &[&str]) -> Result<(), ExitCode> {
                 }
             }
             "-q" | "--quiet" => {
-                game.quiet = true;
-                game.rows += 1;
+                game.set_quiet();
             }
             arg => {
                 if arg.starts_with('-') {
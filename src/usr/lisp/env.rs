@@ -251,6 +251,42 @@ pub fn default_env() -> Rc<RefCell<Env>> {
         "date".to_string(),
         Exp::Primitive(primitive::lisp_date),
     );
+    data.insert(
+        "gfx/size".to_string(),
+        Exp::Primitive(primitive::lisp_gfx_size),
+    );
+    data.insert(
+        "gfx/clear".to_string(),
+        Exp::Primitive(primitive::lisp_gfx_clear),
+    );
+    data.insert(
+        "gfx/pixel".to_string(),
+        Exp::Primitive(primitive::lisp_gfx_pixel),
+    );
+    data.insert(
+        "gfx/line".to_string(),
+        Exp::Primitive(primitive::lisp_gfx_line),
+    );
+    data.insert(
+        "gfx/rect".to_string(),
+        Exp::Primitive(primitive::lisp_gfx_rect),
+    );
+    data.insert(
+        "gfx/text".to_string(),
+        Exp::Primitive(primitive::lisp_gfx_text),
+    );
+    data.insert(
+        "gfx/image".to_string(),
+        Exp::Primitive(primitive::lisp_gfx_image),
+    );
+    data.insert(
+        "gfx/flush".to_string(),
+        Exp::Primitive(primitive::lisp_gfx_flush),
+    );
+    data.insert(
+        "gfx/event".to_string(),
+        Exp::Primitive(primitive::lisp_gfx_event),
+    );
 
     // Setup autocompletion
     *FUNCTIONS.lock() = data.keys().cloned().
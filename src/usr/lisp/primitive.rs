@@ -6,7 +6,10 @@ use super::{Err, Exp, Number};
 use crate::api;
 use crate::api::regex::Regex;
 use crate::api::syscall;
+use crate::canvas::{Canvas, FONT_HEIGHT};
+use crate::sys::console;
 use crate::sys::fs::OpenFlag;
+use crate::{gpu, mouse};
 use crate::usr::host;
 use crate::usr::shell;
 use crate::{could_not, ensure_length_eq, ensure_length_gt, expected};
@@ -21,8 +24,10 @@ use core::cmp::Ordering::Equal;
 use core::convert::TryFrom;
 use core::convert::TryInto;
 use core::str::FromStr;
+use lazy_static::lazy_static;
 use num_bigint::BigInt;
 use smoltcp::wire::IpAddress;
+use spin::Mutex;
 
 pub fn lisp_eq(args: &[Exp]) -> Result<Exp, Err> {
     Ok(Exp::Bool(
@@ -670,3 +675,184 @@ pub fn lisp_date(args: &[Exp]) -> Result<Exp, Err> {
     let date = api::time::from_timestamp_utc(ts).format(fmt);
     Ok(Exp::Str(date))
 }
+
+// Graphics module
+
+lazy_static! {
+    // Off-screen canvas drawn on the screen of the VirtIO GPU by `gfx/flush`
+    static ref GFX: Mutex<Option<Canvas>> = Mutex::new(None);
+}
+
+fn with_canvas<F>(f: F) -> Result<Exp, Err>
+where
+    F: FnOnce(&mut Canvas) -> Result<Exp, Err>,
+{
+    let (width, height) = match gpu::get_resolution() {
+        Some(resolution) => resolution,
+        None => return could_not!("find a VirtIO GPU"),
+    };
+    let mut gfx = GFX.lock();
+    let is_resized = gfx.as_ref().is_none_or(|canvas|
+        canvas.width() != width || canvas.height() != height
+    );
+    if is_resized {
+        *gfx = Some(Canvas::new(width, height));
+    }
+    f(gfx.as_mut().unwrap())
+}
+
+// Colors are given as 0xRRGGBB or 0xAARRGGBB numbers
+fn color(exp: &Exp) -> Result<u32, Err> {
+    let color = u32::try_from(number(exp)?)?;
+    if color > 0xFFFFFF {
+        Ok(color)
+    } else {
+        Ok(0xFF000000 | color)
+    }
+}
+
+fn coord(exp: &Exp) -> Result<i32, Err> {
+    Ok(float(exp)? as i32)
+}
+
+pub fn lisp_gfx_size(args: &[Exp]) -> Result<Exp, Err> {
+    ensure_length_eq!(args, 0);
+    with_canvas(|canvas| Ok(Exp::List(vec![
+        Exp::Num(Number::from(canvas.width() as usize)),
+        Exp::Num(Number::from(canvas.height() as usize)),
+    ])))
+}
+
+pub fn lisp_gfx_clear(args: &[Exp]) -> Result<Exp, Err> {
+    let c = match args.len() {
+        0 => 0xFF000000,
+        1 => color(&args[0])?,
+        _ => return expected!("0 or 1 argument"),
+    };
+    with_canvas(|canvas| {
+        canvas.clear(c);
+        Ok(Exp::List(vec![]))
+    })
+}
+
+pub fn lisp_gfx_pixel(args: &[Exp]) -> Result<Exp, Err> {
+    match args.len() {
+        2 => {
+            let (x, y) = (coord(&args[0])?, coord(&args[1])?);
+            with_canvas(|canvas| match canvas.pixel(x, y) {
+                Some(c) => Ok(Exp::Num(Number::from((c & 0xFFFFFF) as usize))),
+                None => Ok(Exp::List(vec![])),
+            })
+        }
+        3 => {
+            let (x, y, c) = (coord(&args[0])?, coord(&args[1])?, color(&args[2])?);
+            with_canvas(|canvas| {
+                canvas.set_pixel(x, y, c);
+                Ok(Exp::List(vec![]))
+            })
+        }
+        _ => expected!("2 or 3 arguments"),
+    }
+}
+
+pub fn lisp_gfx_line(args: &[Exp]) -> Result<Exp, Err> {
+    ensure_length_eq!(args, 5);
+    let x0 = coord(&args[0])?;
+    let y0 = coord(&args[1])?;
+    let x1 = coord(&args[2])?;
+    let y1 = coord(&args[3])?;
+    let c = color(&args[4])?;
+    with_canvas(|canvas| {
+        canvas.line(x0, y0, x1, y1, c);
+        Ok(Exp::List(vec![]))
+    })
+}
+
+pub fn lisp_gfx_rect(args: &[Exp]) -> Result<Exp, Err> {
+    let fill = match args.len() {
+        5 => false,
+        6 => matches!(args[5], Exp::Bool(true)),
+        _ => return expected!("5 or 6 arguments"),
+    };
+    let x = coord(&args[0])?;
+    let y = coord(&args[1])?;
+    let w = u32::try_from(number(&args[2])?)?;
+    let h = u32::try_from(number(&args[3])?)?;
+    let c = color(&args[4])?;
+    with_canvas(|canvas| {
+        if fill {
+            canvas.fill_rect(x, y, w, h, c);
+        } else {
+            canvas.rect(x, y, w, h, c);
+        }
+        Ok(Exp::List(vec![]))
+    })
+}
+
+pub fn lisp_gfx_text(args: &[Exp]) -> Result<Exp, Err> {
+    ensure_length_eq!(args, 4);
+    let x = coord(&args[0])?;
+    let y = coord(&args[1])?;
+    let s = string(&args[2])?;
+    let c = color(&args[3])?;
+    with_canvas(|canvas| {
+        for (i, line) in s.lines().enumerate() {
+            canvas.text(x, y + (i as u32 * FONT_HEIGHT) as i32, line, c);
+        }
+        Ok(Exp::List(vec![]))
+    })
+}
+
+pub fn lisp_gfx_image(args: &[Exp]) -> Result<Exp, Err> {
+    ensure_length_eq!(args, 4);
+    let x = coord(&args[0])?;
+    let y = coord(&args[1])?;
+    let w = u32::try_from(number(&args[2])?)?;
+    let pixels = match &args[3] {
+        Exp::List(list) => list.iter().map(color).collect::<Result<Vec<u32>, Err>>()?,
+        _ => return expected!("fourth argument to be a list"),
+    };
+    if w == 0 || pixels.len() % w as usize != 0 {
+        return expected!("a list of pixels with a multiple of the width");
+    }
+    let h = (pixels.len() / w as usize) as u32;
+    let mut image = Canvas::new(w, h);
+    image.pixels_mut().copy_from_slice(&pixels);
+    with_canvas(|canvas| {
+        canvas.blit(&image, x, y);
+        Ok(Exp::List(vec![]))
+    })
+}
+
+pub fn lisp_gfx_flush(args: &[Exp]) -> Result<Exp, Err> {
+    ensure_length_eq!(args, 0);
+    with_canvas(|canvas| {
+        if canvas.present(0, 0) {
+            Ok(Exp::List(vec![]))
+        } else {
+            could_not!("flush display")
+        }
+    })
+}
+
+// Returns the next keyboard or mouse event without waiting:
+// ("key" <char>), ("mouse" <x> <y> <buttons>), or () if there is none
+pub fn lisp_gfx_event(args: &[Exp]) -> Result<Exp, Err> {
+    ensure_length_eq!(args, 0);
+    if let Some(c) = console::try_read_char() {
+        return Ok(Exp::List(vec![
+            Exp::Str("key".to_string()),
+            Exp::Str(c.to_string()),
+        ]));
+    }
+    if mouse::poll() {
+        let state = mouse::state();
+        return Ok(Exp::List(vec![
+            Exp::Str("mouse".to_string()),
+            Exp::Num(Number::from(state.x as usize)),
+            Exp::Num(Number::from(state.y as usize)),
+            Exp::Num(Number::from(state.buttons)),
+        ]));
+    }
+    Ok(Exp::List(vec![]))
+}
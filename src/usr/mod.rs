@@ -2,8 +2,10 @@
 pub mod calc;
 pub mod chess;
 pub mod copy;
+pub mod cpu;
 pub mod date;
 pub mod decode;
+pub mod desktop;
 pub mod delete;
 pub mod dhcp;
 pub mod diff;
@@ -13,6 +15,7 @@ pub mod elf;
 pub mod encode;
 pub mod env;
 pub mod find;
+pub mod gdb;
 //pub mod geodate; // TODO: Remove file
 pub mod hash;
 pub mod help;
@@ -30,6 +33,7 @@ pub mod net;
 pub mod pci;
 pub mod pi;
 pub mod pow;
+pub mod ps;
 pub mod r#move;
 pub mod read;
 pub mod render;
@@ -37,6 +41,8 @@ pub mod shell;
 pub mod socket;
 pub mod tcp;
 pub mod time;
+pub mod top;
+pub mod trace;
 pub mod user;
 pub mod view;
 pub mod write;
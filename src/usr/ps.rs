@@ -0,0 +1,110 @@
+use crate::api::console::Style;
+use crate::api::fs;
+use crate::api::process::ExitCode;
+use crate::api::unit::SizeUnit;
+use crate::sys::fs::PROC_DIR;
+
+use alloc::collections::btree_map::BTreeMap;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+pub fn main(args: &[&str]) -> Result<(), ExitCode> {
+    let mut unit = SizeUnit::Binary;
+    for arg in &args[1..] {
+        match *arg {
+            "-b" | "--binary-size" => {
+                unit = SizeUnit::Binary;
+            }
+            "-d" | "--decimal-size" => {
+                unit = SizeUnit::Decimal;
+            }
+            "-h" | "--help" => {
+                help();
+                return Ok(());
+            }
+            _ => {
+                help();
+                return Err(ExitCode::UsageError);
+            }
+        }
+    }
+    for line in table(&unit)? {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+// Return the lines of the table of processes read from the /proc directory,
+// starting with the header
+pub fn table(unit: &SizeUnit) -> Result<Vec<String>, ExitCode> {
+    let entries = match fs::read_dir(PROC_DIR) {
+        Ok(entries) => entries,
+        Err(()) => {
+            error!("Could not read '{}'", PROC_DIR);
+            return Err(ExitCode::Failure);
+        }
+    };
+    let mut pids: Vec<usize> = entries.iter().filter_map(|entry| {
+        entry.name().parse().ok()
+    }).collect();
+    pids.sort();
+
+    let color = Style::color("yellow");
+    let reset = Style::reset();
+    let mut res = Vec::new();
+    res.push(format!(
+        "{}{:>5} {:>6} {:<7} {:>6} {:>6} {:>7} {}{}",
+        color, "PID", "PARENT", "STATE", "MEMORY", "SIZE", "HANDLES", "BINARY",
+        reset
+    ));
+    for pid in pids {
+        // The process could have exited since the directory was read
+        let path = format!("{}/{}/status", PROC_DIR, pid);
+        if let Ok(contents) = fs::read_to_string(&path) {
+            let status = parse_status(&contents);
+            let get = |key| status.get(key).cloned().unwrap_or_default();
+            let size = |key| unit.format(get(key).parse().unwrap_or(0));
+            res.push(format!(
+                "{:>5} {:>6} {:<7} {:>6} {:>6} {:>7} {}",
+                pid, get("parent"), get("state"), size("memory"),
+                size("size"), get("handles"), get("name")
+            ));
+        }
+    }
+    Ok(res)
+}
+
+fn parse_status(contents: &str) -> BTreeMap<String, String> {
+    contents.lines().filter_map(|line| line.split_once(": ")).map(|(k, v)| {
+        (k.to_string(), v.to_string())
+    }).collect()
+}
+
+fn help() {
+    let csi_option = Style::color("aqua");
+    let csi_title = Style::color("yellow");
+    let csi_reset = Style::reset();
+    println!(
+        "{}Usage:{} ps {}<options>{}",
+        csi_title, csi_reset, csi_option, csi_reset
+    );
+    println!();
+    println!("{}Options:{}", csi_title, csi_reset);
+    println!(
+        "  {0}-b{1}, {0}--binary-size{1}   Use binary size",
+        csi_option, csi_reset
+    );
+    println!(
+        "  {0}-d{1}, {0}--decimal-size{1}  Use decimal size",
+        csi_option, csi_reset
+    );
+}
+
+#[test_case]
+fn test_parse_status() {
+    let status = parse_status("pid: 1\nname: /bin/sleep\nstate: Ready\n");
+    assert_eq!(status.get("pid"), Some(&"1".to_string()));
+    assert_eq!(status.get("name"), Some(&"/bin/sleep".to_string()));
+    assert_eq!(status.get("user"), None);
+}
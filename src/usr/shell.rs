@@ -4,9 +4,11 @@ use crate::api::process::ExitCode;
 use crate::api::prompt::Prompt;
 use crate::api::regex::Regex;
 use crate::api::syscall;
-use crate::sys::fs::FileType;
+use crate::sys::fs::{FileType, Resource};
+use crate::sys::process::Process;
 use crate::{api, sys, usr};
 
+use alloc::boxed::Box;
 use alloc::collections::btree_map::BTreeMap;
 use alloc::format;
 use alloc::string::{String, ToString};
@@ -14,22 +16,32 @@ use alloc::vec::Vec;
 use core::sync::atomic::{fence, Ordering};
 
 // TODO: Scan /bin
-const AUTOCOMPLETE_COMMANDS: [&str; 40] = [
-    "2048", "calc", "chess", "copy", "date", "decode", "delete", "dhcp",
-    "diff", "disk", "edit", "elf", "encode", "env", "goto", "hash", "help",
-    "hex", "host", "http", "httpd", "install", "keyboard", "life", "lisp",
-    "list", "memory", "move", "net", "pci", "quit", "read", "render", "shell",
-    "socket", "tcp", "time", "user", "view", "write",
+const AUTOCOMPLETE_COMMANDS: [&str; 49] = [
+    "2048", "calc", "chess", "copy", "cpu", "date", "decode", "delete",
+    "desktop", "dhcp", "diff", "disk", "edit", "elf", "encode", "env", "gdb",
+    "goto", "hash", "help", "hex", "host", "http", "httpd", "install", "jobs",
+    "keyboard", "kill", "life", "lisp", "list", "memory", "move", "net", "pci",
+    "ps", "quit", "read", "render", "shell", "socket", "tcp", "time", "top",
+    "trace", "user", "view", "wait", "write",
 ];
 
+#[derive(Clone)]
+struct Job {
+    pid: usize,
+    cmd: String,
+}
+
+#[derive(Clone)]
 struct Config {
     env: BTreeMap<String, String>,
     aliases: BTreeMap<String, String>,
+    jobs: BTreeMap<usize, Job>,
 }
 
 impl Config {
     fn new() -> Config {
         let aliases = BTreeMap::new();
+        let jobs = BTreeMap::new();
         let mut env = BTreeMap::new();
         for (key, val) in sys::process::envs() {
             // Copy the process environment to the shell environment
@@ -37,7 +49,7 @@ impl Config {
         }
         env.insert("DIR".to_string(), sys::process::dir());
         env.insert("status".to_string(), "0".to_string());
-        Config { env, aliases }
+        Config { env, aliases, jobs }
     }
 }
 
@@ -371,6 +383,149 @@ fn cmd_unset(args: &[&str], config: &mut Config) -> Result<(), ExitCode> {
     Ok(())
 }
 
+fn job_status(code: ExitCode) -> String {
+    match code {
+        ExitCode::Success => "Done".to_string(),
+        ExitCode::Killed => "Killed".to_string(),
+        code => format!("Exit {}", code as u8),
+    }
+}
+
+// Print and forget the background jobs that are done
+fn update_jobs(config: &mut Config) {
+    let mut done = Vec::new();
+    for (id, job) in config.jobs.iter() {
+        match api::process::try_wait(job.pid) {
+            Ok(Some(code)) => {
+                println!("[{}] {} {}", id, job_status(code), job.cmd);
+                done.push(*id);
+            }
+            Ok(None) => {}
+            Err(()) => done.push(*id),
+        }
+    }
+    for id in done {
+        config.jobs.remove(&id);
+    }
+}
+
+// Find the PID of a job given as `%<id>` or a PID
+fn job_pid(arg: &str, config: &Config) -> Option<usize> {
+    if let Some(id) = arg.strip_prefix('%') {
+        let id = id.parse().ok()?;
+        config.jobs.get(&id).map(|job| job.pid)
+    } else {
+        arg.parse().ok()
+    }
+}
+
+fn start_job(
+    args: &[&str],
+    handles: &[(usize, usize)],
+    config: &mut Config
+) -> Result<(), ExitCode> {
+    let mut path = fs::realpath(args[0]);
+    if syscall::info(&path).map(|info| info.kind()) != Some(FileType::File) {
+        path = format!("/bin/{}", args[0]);
+    }
+    // A job with redirections is a kernel task waiting for the binary
+    let res = if handles.is_empty() {
+        api::process::start(&path, args)
+    } else {
+        spawn_task(args, handles, config)
+    };
+    match res {
+        Ok(pid) => {
+            let id = config.jobs.keys().last().map_or(1, |id| id + 1);
+            let cmd = args.join(" ");
+            println!("[{}] {}", id, pid);
+            config.jobs.insert(id, Job { pid, cmd });
+            Ok(())
+        }
+        Err(ExitCode::OpenError) => {
+            error!("Could not open '{}'", args[0]);
+            Err(ExitCode::OpenError)
+        }
+        Err(code) => {
+            error!("Could not execute '{}'", args[0]);
+            Err(code)
+        }
+    }
+}
+
+fn cmd_jobs(config: &mut Config) -> Result<(), ExitCode> {
+    update_jobs(config);
+    for (id, job) in config.jobs.iter() {
+        println!("[{}] {} Running {}", id, job.pid, job.cmd);
+    }
+    Ok(())
+}
+
+fn cmd_wait(args: &[&str], config: &mut Config) -> Result<(), ExitCode> {
+    let ids: Vec<usize> = if args.len() > 1 {
+        let mut ids = Vec::new();
+        for arg in &args[1..] {
+            if let Some(pid) = job_pid(arg, config) {
+                ids.push(pid);
+            } else {
+                error!("Could not find job '{}'", arg);
+                return Err(ExitCode::Failure);
+            }
+        }
+        ids
+    } else {
+        config.jobs.values().map(|job| job.pid).collect()
+    };
+
+    let mut res = Ok(());
+    for pid in ids {
+        match api::process::wait(pid) {
+            Ok(ExitCode::Success) => res = Ok(()),
+            Ok(code) => res = Err(code),
+            Err(()) => {
+                error!("Could not wait for process {}", pid);
+                res = Err(ExitCode::Failure);
+            }
+        }
+        config.jobs.retain(|_, job| job.pid != pid);
+    }
+    res
+}
+
+fn cmd_kill(args: &[&str], config: &mut Config) -> Result<(), ExitCode> {
+    let (signal, targets) = match args.get(1) {
+        Some(arg) if arg.starts_with('-') => {
+            match arg[1..].parse() {
+                Ok(signal) => (signal, &args[2..]),
+                Err(_) => (0, &args[0..0]),
+            }
+        }
+        _ => (api::process::SIGKILL, &args[1..]),
+    };
+    if targets.is_empty() {
+        let csi_option = Style::color("aqua");
+        let csi_title = Style::color("yellow");
+        let csi_reset = Style::reset();
+        eprintln!(
+            "{}Usage:{} kill {}[-<signal>] <pid>|%<job>{1}",
+            csi_title, csi_reset, csi_option
+        );
+        return Err(ExitCode::UsageError);
+    }
+
+    let mut res = Ok(());
+    for arg in targets {
+        let killed = job_pid(arg, config).map(|pid| {
+            api::process::kill(pid, signal)
+        });
+        if killed != Some(Ok(())) {
+            error!("Could not kill '{}'", arg);
+            res = Err(ExitCode::Failure);
+        }
+    }
+    res
+}
+
 fn cmd_logs() -> Result<(), ExitCode> {
     print!("{}", sys::log::read());
     Ok(())
@@ -401,8 +556,200 @@ fn exec_with_config(cmd: &str, config: &mut Config) -> Result<(), ExitCode> {
 
     let mut args: Vec<&str> = args.iter().map(String::as_str).collect();
 
+    // Background job
+    // sleep 5 &
+    let is_background = args.len() > 1 && args[args.len() - 1] == "&";
+    if is_background {
+        args.pop();
+    }
+
+    // Pipes
+    // read foo.txt --> write bar.txt
+    // read foo.txt -> write bar.txt
+    // read foo.txt [2]-> write /dev/null
+    let mut stages = Vec::new();
+    let mut stage = Vec::new();
+    for arg in args {
+        if Regex::new("^[?\\d*]?-+>$").is_match(arg) {
+            let num: String = arg.chars().filter(char::is_ascii_digit).collect();
+            let handle = num.parse().unwrap_or(1);
+            stages.push((stage, handle));
+            stage = Vec::new();
+        } else {
+            stage.push(arg);
+        }
+    }
+    stages.push((stage, 1));
+    if stages.len() > 1 {
+        if is_background {
+            error!("Running a pipeline in the background is not supported");
+            return Err(ExitCode::Failure);
+        }
+        if stages.iter().any(|(stage, _)| stage.is_empty()) {
+            error!("Could not parse pipeline");
+            return Err(ExitCode::Failure);
+        }
+        return exec_pipeline(stages, config);
+    }
+    let (args, _) = stages.pop().unwrap();
+    exec_args(args, is_background, config)
+}
+
+// Connect the output of each stage of a pipeline to the input of the next
+// one. The stages run concurrently in kernel tasks, which run the builtins
+// or wait for the binaries, and we wait for all of them at the end.
+fn exec_pipeline(
+    stages: Vec<(Vec<&str>, usize)>,
+    config: &Config
+) -> Result<(), ExitCode> {
+    let n = stages.len();
+    let mut res = Ok(());
+    let mut pids = Vec::new();
+    let mut reader = None;
+    for (i, (args, handle)) in stages.into_iter().enumerate() {
+        let pipe = if i < n - 1 {
+            match syscall::pipe(0) {
+                Some(pipe) => Some(pipe),
+                None => {
+                    error!("Could not create pipe");
+                    res = Err(ExitCode::Failure);
+                    break;
+                }
+            }
+        } else {
+            None
+        };
+        let mut handles = Vec::new();
+        if let Some(r) = reader {
+            handles.push((0, r));
+        }
+        if let Some((_, w)) = pipe {
+            handles.push((handle, w));
+        }
+        let stage = spawn_task(&args, &handles, config);
+
+        // Close the copies of the pipe ends held by the shell to let the
+        // next stage get an EOF
+        if let Some(r) = reader.take() {
+            syscall::close(r);
+        }
+        if let Some((r, w)) = pipe {
+            syscall::close(w);
+            reader = Some(r);
+        }
+        match stage {
+            Ok(pid) => pids.push(pid),
+            Err(code) => {
+                error!("Could not run '{}'", args[0]);
+                res = Err(code);
+                break;
+            }
+        }
+    }
+    if let Some(r) = reader {
+        syscall::close(r);
+    }
+
+    let last_pid = if res.is_ok() { pids.last().copied() } else { None };
+    for pid in pids {
+        let code = api::process::wait(pid).unwrap_or(ExitCode::Failure);
+        if Some(pid) == last_pid {
+            res = match code {
+                ExitCode::Success => Ok(()),
+                code => Err(code),
+            };
+        }
+    }
+    res
+}
+
+// Run a command in a kernel task with a copy of the shell config and with
+// the given handles of the shell, and return its PID
+fn spawn_task(
+    args: &[&str],
+    handles: &[(usize, usize)],
+    config: &Config
+) -> Result<usize, ExitCode> {
+    let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    let mut config = config.clone();
+    let name = args[0].clone();
+    Process::spawn_task(&name, handles, move || {
+        let args = args.iter().map(String::as_str).collect();
+        match exec_args(args, false, &mut config) {
+            Ok(()) => ExitCode::Success,
+            Err(code) => code,
+        }
+    })
+}
+
+// Open the file of a redirection as a new handle of the shell, which will be
+// closed once the command has been started
+fn open_redirection(path: &str, append: bool) -> Option<usize> {
+    match syscall::info(path) {
+        Some(info) if info.is_device() => api::fs::open_device(path),
+        Some(_) if append => api::fs::append_file(path),
+        Some(_) => api::fs::open_file(path),
+        None => api::fs::create_file(path),
+    }
+}
+
+// Add a redirection of a handle of the command to a handle of the shell,
+// replacing a previous redirection of the same handle
+fn redirect(handle: usize, shell_handle: usize, map: &mut Vec<(usize, usize)>) {
+    map.retain(|(h, _)| *h != handle);
+    map.push((handle, shell_handle));
+}
+
+// Return true if the command changes the state of the shell, like its
+// aliases, variables, jobs or directory
+fn is_shell_builtin(args: &[&str]) -> bool {
+    match args[0] {
+        "alias" | "goto" | "jobs" | "kill" | "quit" | "set" | "unalias" |
+        "unset" | "wait" => true,
+        _ => {
+            let path = fs::realpath(args[0]);
+            syscall::info(&path).is_some_and(|info| info.is_dir())
+        }
+    }
+}
+
+// Run a command in the process of the shell with the redirected handles
+// given to it, and restore the handles of the shell once it returns
+fn dispatch_with_handles(
+    args: &[&str],
+    handles: &[(usize, usize)],
+    config: &mut Config
+) -> Result<(), ExitCode> {
+    let saved = handles.iter().map(|&(i, _)| {
+        (i, sys::process::handle(i))
+    }).collect();
+    let files = handles.iter().map(|&(i, h)| {
+        (i, sys::process::handle(h))
+    }).collect();
+    set_handles(files);
+    let res = dispatch(args, config);
+    set_handles(saved);
+    res
+}
+
+fn set_handles(files: Vec<(usize, Option<Box<Resource>>)>) {
+    for (i, file) in files {
+        match file {
+            Some(file) => sys::process::update_handle(i, *file),
+            None => sys::process::delete_handle(i),
+        }
+    }
+}
+
+fn exec_args(
+    mut args: Vec<&str>,
+    is_background: bool,
+    config: &mut Config
+) -> Result<(), ExitCode> {
     // Redirections
-    let mut restore_handles = false;
+    let mut res = Ok(());
+    let mut handles = Vec::new(); // Handles of the command and of the shell
+    let mut opened = Vec::new(); // Handles opened by the shell for the command
     let mut n = args.len();
     let mut i = 0;
     loop {
@@ -410,19 +757,9 @@ fn exec_with_config(cmd: &str, config: &mut Config) -> Result<(), ExitCode> {
             break;
         }
 
-        let mut is_fat_arrow = false;
-        let mut is_thin_arrow = false;
         let mut head_count = 0;
         let mut left_handle;
-        if Regex::new("^[?\\d*]?-+>$").is_match(args[i]) {
-            // Pipes
-            // read foo.txt --> write bar.txt
-            // read foo.txt -> write bar.txt
-            // read foo.txt [2]-> write /dev/null
-            is_thin_arrow = true;
-            left_handle = 1;
-        } else if Regex::new("^<=*>+$").is_match(args[i]) {
-            is_fat_arrow = true;
+        if Regex::new("^<=*>+$").is_match(args[i]) {
             left_handle = 0;
             n += 2;
             args.insert(i + 2, args[i + 1]);
@@ -435,14 +772,12 @@ fn exec_with_config(cmd: &str, config: &mut Config) -> Result<(), ExitCode> {
             // read foo.txt > bar.txt
             // read foo.txt [1]=> /dev/null
             // read foo.txt [1]=>[3]
-            is_fat_arrow = true;
             left_handle = 1;
         } else if Regex::new("^<=*$").is_match(args[i]) {
             // Redirections from
             // write bar.txt <== foo.txt
             // write bar.txt <= foo.txt
             // write bar.txt < foo.txt
-            is_fat_arrow = true;
             left_handle = 0;
         } else {
             i += 1;
@@ -469,45 +804,73 @@ fn exec_with_config(cmd: &str, config: &mut Config) -> Result<(), ExitCode> {
             }
         }
 
-        if is_fat_arrow {
-            // Redirections
-            restore_handles = true;
-            if !num.is_empty() {
-                // if let Ok(right_handle) = num.parse() {}
-                error!("Redirecting to a handle has not been implemented yet");
-                return Err(ExitCode::Failure);
-            } else {
-                if i == n - 1 {
-                    error!("Could not parse path for redirection");
-                    return Err(ExitCode::Failure);
+        // The redirections are not applied to the handles of the shell but
+        // given to the command that will be spawned with them
+        if !num.is_empty() {
+            // Redirections to a handle
+            // read foo.txt [2]=>[1]
+            match num.parse::<usize>() {
+                Ok(h) => {
+                    // The handle could itself have been redirected before
+                    let h = match handles.iter().find(|(k, _)| *k == h) {
+                        Some((_, v)) => *v,
+                        None => h,
+                    };
+                    redirect(left_handle, h, &mut handles);
                 }
-                let path = args[i + 1];
-                let append_mode = head_count > 1;
-                if api::fs::reopen(path, left_handle, append_mode).is_err() {
-                    error!("Could not open path for redirection");
-                    return Err(ExitCode::Failure);
+                Err(_) => {
+                    error!("Could not redirect handle {}", left_handle);
+                    res = Err(ExitCode::Failure);
+                    break;
                 }
-                args.remove(i); // Remove path from args
-                n -= 1;
             }
+        } else {
+            if i == n - 1 {
+                error!("Could not parse path for redirection");
+                res = Err(ExitCode::Failure);
+                break;
+            }
+            let path = args[i + 1];
+            let append_mode = head_count > 1;
+            if let Some(h) = open_redirection(path, append_mode) {
+                opened.push(h);
+                redirect(left_handle, h, &mut handles);
+            } else {
+                error!("Could not open path for redirection");
+                res = Err(ExitCode::Failure);
+                break;
+            }
+            args.remove(i); // Remove path from args
             n -= 1;
-            args.remove(i); // Remove redirection from args
-        } else if is_thin_arrow {
-            error!("Piping has not been implemented yet");
-            return Err(ExitCode::Failure);
         }
+        n -= 1;
+        args.remove(i); // Remove redirection from args
     }
 
-    fence(Ordering::SeqCst);
-    let res = dispatch(&args, config);
-
-    // TODO: Remove this when redirections are done in spawned process
-    if restore_handles {
-        for i in 0..3 {
-            api::fs::reopen("/dev/console", i, false).ok();
-        }
+    if res.is_ok() {
+        fence(Ordering::SeqCst);
+        res = if is_background {
+            start_job(&args, &handles, config)
+        } else if handles.is_empty() {
+            dispatch(&args, config)
+        } else if is_shell_builtin(&args) {
+            dispatch_with_handles(&args, &handles, config)
+        } else {
+            // The command is run in a kernel task with the redirected handles
+            // and the binaries it spawns will get a copy of them
+            spawn_task(&args, &handles, config).and_then(|pid| {
+                match api::process::wait(pid) {
+                    Ok(ExitCode::Success) => Ok(()),
+                    Ok(code) => Err(code),
+                    Err(()) => Err(ExitCode::Failure),
+                }
+            })
+        };
     }
 
+    for handle in opened {
+        syscall::close(handle);
+    }
     res
 }
 
@@ -520,9 +883,11 @@ fn dispatch(args: &[&str], config: &mut Config) -> Result<(), ExitCode> {
         "calc"     => usr::calc::main(args),
         "chess"    => usr::chess::main(args),
         "copy"     => usr::copy::main(args),
+        "cpu"      => usr::cpu::main(args),
         "date"     => usr::date::main(args),
         "decode"   => usr::decode::main(args),
         "delete"   => usr::delete::main(args),
+        "desktop"  => usr::desktop::main(args),
         "dhcp"     => usr::dhcp::main(args),
         "diff"     => usr::diff::main(args),
         "disk"     => usr::disk::main(args),
@@ -531,6 +896,7 @@ fn dispatch(args: &[&str], config: &mut Config) -> Result<(), ExitCode> {
         "encode"   => usr::encode::main(args),
         "env"      => usr::env::main(args),
         "find"     => usr::find::main(args),
+        "gdb"      => usr::gdb::main(args),
         //"geodate"  => usr::geodate::main(args),
         "goto"     => cmd_change_dir(args, config), // TODO: Remove this
         "hash"     => usr::hash::main(args),
@@ -540,7 +906,9 @@ fn dispatch(args: &[&str], config: &mut Config) -> Result<(), ExitCode> {
         "http"     => usr::http::main(args),
         "httpd"    => usr::httpd::main(args),
         "install"  => usr::install::main(args),
+        "jobs"     => cmd_jobs(config),
         "keyboard" => usr::keyboard::main(args),
+        "kill"     => cmd_kill(args, config),
         "life"     => usr::life::main(args),
         "lisp"     => usr::lisp::main(args),
         "list"     => usr::list::main(args),
@@ -550,6 +918,7 @@ fn dispatch(args: &[&str], config: &mut Config) -> Result<(), ExitCode> {
         "net"      => usr::net::main(args),
         "pci"      => usr::pci::main(args),
         "pi"       => usr::pi::main(args),
+        "ps"       => usr::ps::main(args),
         "quit"     => Err(ExitCode::ShellExit),
         "read"     => usr::read::main(args),
         "render"   => usr::render::main(args),
@@ -558,11 +927,14 @@ fn dispatch(args: &[&str], config: &mut Config) -> Result<(), ExitCode> {
         "socket"   => usr::socket::main(args),
         "tcp"      => usr::tcp::main(args),
         "time"     => usr::time::main(args),
+        "top"      => usr::top::main(args),
+        "trace"    => usr::trace::main(args),
         "unalias"  => cmd_unalias(args, config),
         "unset"    => cmd_unset(args, config),
         "version"  => cmd_version(),
         "user"     => usr::user::main(args),
         "view"     => usr::view::main(args),
+        "wait"     => cmd_wait(args, config),
         "write"    => usr::write::main(args),
         "panic"    => panic!("{}", args[1..].join(" ")),
         _ => {
@@ -641,6 +1013,7 @@ fn repl(config: &mut Config) -> Result<(), ExitCode> {
             Ok(()) => ExitCode::Success,
         };
         config.env.insert("status".to_string(), format!("{}", code as u8));
+        update_jobs(config);
         prompt.history.add(&cmd);
         prompt.history.save(history_file);
         sys::console::drain();
@@ -737,6 +1110,10 @@ fn test_shell() {
     exec_with_config("print a $b $c d => /test", &mut config).ok();
     assert_eq!(api::fs::read_to_string("/test"), Ok("a 42 d\n".to_string()));
 
+    // Redirect a builtin changing the state of the shell
+    exec_with_config("alias ll list => /tmp/test4", &mut config).ok();
+    assert_eq!(config.aliases.get("ll"), Some(&"list".to_string()));
+
     sys::fs::dismount();
 }
 
@@ -0,0 +1,75 @@
+use crate::api::clock;
+use crate::api::console::Style;
+use crate::api::process::ExitCode;
+use crate::api::unit::SizeUnit;
+use crate::sys;
+use crate::sys::console;
+use crate::usr;
+
+pub fn main(args: &[&str]) -> Result<(), ExitCode> {
+    let mut interval = 1.0;
+    let mut i = 1;
+    let n = args.len();
+    while i < n {
+        match args[i] {
+            "-i" | "--interval" if i + 1 < n => {
+                i += 1;
+                match args[i].parse::<f64>() {
+                    Ok(seconds) if seconds > 0.0 => interval = seconds,
+                    _ => {
+                        error!("Could not parse interval");
+                        return Err(ExitCode::UsageError);
+                    }
+                }
+            }
+            "-h" | "--help" => {
+                help();
+                return Ok(());
+            }
+            _ => {
+                help();
+                return Err(ExitCode::UsageError);
+            }
+        }
+        i += 1;
+    }
+
+    let unit = SizeUnit::Binary;
+    loop {
+        let lines = usr::ps::table(&unit)?;
+        print!("\x1b[2J\x1b[1;1H"); // Clear screen and move to top
+        println!(
+            "uptime: {:.2}s, processes: {}\n",
+            clock::boot_time(), lines.len() - 1
+        );
+        for line in lines {
+            println!("{}", line);
+        }
+
+        // Wait for the next refresh unless the command is interrupted
+        let start = clock::boot_time();
+        while clock::boot_time() - start < interval {
+            if console::end_of_text() || console::end_of_transmission() {
+                println!();
+                return Ok(());
+            }
+            sys::clk::sleep(0.1_f64.min(interval));
+        }
+    }
+}
+
+fn help() {
+    let csi_option = Style::color("aqua");
+    let csi_title = Style::color("yellow");
+    let csi_reset = Style::reset();
+    println!(
+        "{}Usage:{} top {}<options>{}",
+        csi_title, csi_reset, csi_option, csi_reset
+    );
+    println!();
+    println!("{}Options:{}", csi_title, csi_reset);
+    println!(
+        "  {0}-i{1}, {0}--interval <seconds>{1}  Refresh interval",
+        csi_option, csi_reset
+    );
+}
@@ -0,0 +1,58 @@
+use crate::api::console::Style;
+use crate::api::process::ExitCode;
+use crate::sys;
+use crate::sys::process::Trace;
+use crate::usr;
+
+pub fn main(args: &[&str]) -> Result<(), ExitCode> {
+    let mut trace = Trace::Log;
+    let mut i = 1;
+    let n = args.len();
+    while i < n {
+        match args[i] {
+            "-o" | "--output" if i + 1 < n => {
+                i += 1;
+                match args[i].parse() {
+                    Ok(handle) => trace = Trace::Handle(handle),
+                    Err(_) => {
+                        error!("Could not parse handle");
+                        return Err(ExitCode::UsageError);
+                    }
+                }
+            }
+            "-h" | "--help" => {
+                help();
+                return Ok(());
+            }
+            _ => break,
+        }
+        i += 1;
+    }
+    if i == n {
+        help();
+        return Err(ExitCode::UsageError);
+    }
+
+    // The children spawned by the command inherit the trace flag
+    let cmd = args[i..].join(" ");
+    sys::process::set_trace(Some(trace));
+    let res = usr::shell::exec(&cmd);
+    sys::process::set_trace(None);
+    res
+}
+
+fn help() {
+    let csi_option = Style::color("aqua");
+    let csi_title = Style::color("yellow");
+    let csi_reset = Style::reset();
+    println!(
+        "{}Usage:{} trace {}<options> <cmd>{}",
+        csi_title, csi_reset, csi_option, csi_reset
+    );
+    println!();
+    println!("{}Options:{}", csi_title, csi_reset);
+    println!(
+        "  {0}-o{1}, {0}--output <handle>{1}  Write the trace to a handle",
+        csi_option, csi_reset
+    );
+}